@@ -155,6 +155,10 @@ impl From<SystemMetadata> for RenderMetadata {
         let mut smart_speaker_service = m.hap.services.get_mut("smart-speaker").unwrap();
         smart_speaker_service.name = "Smart Speaker".to_string();
 
+        // add units the metadata lacks but the HAP specification defines
+        let rotation_speed_characteristic = m.hap.characteristics.get_mut("rotation.speed").unwrap();
+        rotation_speed_characteristic.units = Some("percentage".to_string());
+
         let mut sorted_categories = m.homekit.categories.iter().map(|(_, v)| v.clone()).collect::<Vec<_>>();
         sorted_categories.sort_by(|a, b| a.number.partial_cmp(&b.number).unwrap());
 
@@ -368,7 +372,7 @@ fn unit_helper(
                 out.write("Unit::Celsius")?;
             },
             "fahrenheit" => {
-                out.write("Unit::Celsius")?;
+                out.write("Unit::Fahrenheit")?;
             },
             "percentage" => {
                 out.write("Unit::Percentage")?;
@@ -953,6 +957,10 @@ impl HapCharacteristic for {{pascal_case characteristic.DefaultDescription}}Char
     fn get_pid(&self) -> Option<u64> { HapCharacteristic::get_pid(&self.0) }
 
     fn set_pid(&mut self, pid: Option<u64>) { HapCharacteristic::set_pid(&mut self.0, pid) }
+
+    fn get_persistent(&self) -> bool { HapCharacteristic::get_persistent(&self.0) }
+
+    fn set_persistent(&mut self, persistent: bool) { HapCharacteristic::set_persistent(&mut self.0, persistent) }
 }
 
 impl HapCharacteristicSetup for {{pascal_case characteristic.DefaultDescription}}Characteristic {
@@ -1132,13 +1140,15 @@ impl HapService for {{pascal_case service.DefaultDescription}}Service {
 
 impl Serialize for {{pascal_case service.DefaultDescription}}Service {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut state = serializer.serialize_struct(\"HapService\", 5)?;
+        let mut state = serializer.serialize_struct(\"HapService\", 6)?;
         state.serialize_field(\"iid\", &self.get_id())?;
         state.serialize_field(\"type\", &self.get_type())?;
         state.serialize_field(\"hidden\", &self.get_hidden())?;
         state.serialize_field(\"primary\", &self.get_primary())?;
         state.serialize_field(\"characteristics\", &self.get_characteristics())?;
-        // linked services left out for now
+        if !self.linked_services.is_empty() {
+            state.serialize_field(\"linked\", &self.linked_services)?;
+        }
         state.end()
     }
 }
@@ -1168,6 +1178,8 @@ pub struct {{pascal_case service.DefaultDescription}}Accessory {
     pub accessory_information: AccessoryInformationService,
     /// {{service.DefaultDescription}} service.
     pub {{snake_case service.DefaultDescription}}: {{pascal_case service.DefaultDescription}}Service,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl {{pascal_case service.DefaultDescription}}Accessory {
@@ -1177,11 +1189,13 @@ impl {{pascal_case service.DefaultDescription}}Accessory {
         let {{snake_case service.DefaultDescription}}_id = accessory_information.get_characteristics().len() as u64;
         let mut {{snake_case service.DefaultDescription}} = {{pascal_case service.DefaultDescription}}Service::new(1 + {{snake_case service.DefaultDescription}}_id + 1, id);
         {{snake_case service.DefaultDescription}}.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &{{snake_case service.DefaultDescription}}], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             {{snake_case service.DefaultDescription}},
+            protocol_information,
         })
     }
 }
@@ -1217,6 +1231,7 @@ impl HapAccessory for {{pascal_case service.DefaultDescription}}Accessory {
         vec![
             &self.accessory_information,
             &self.{{snake_case service.DefaultDescription}},
+            &self.protocol_information,
         ]
     }
 
@@ -1224,6 +1239,7 @@ impl HapAccessory for {{pascal_case service.DefaultDescription}}Accessory {
         vec![
             &mut self.accessory_information,
             &mut self.{{snake_case service.DefaultDescription}},
+            &mut self.protocol_information,
         ]
     }
 }
@@ -1303,6 +1319,7 @@ const NON_IDIOMATIC_SERVICES: &'static [&'static str] = &[
     "accessory metrics",
     "access control",
     "accessory runtime information",
+    "air purifier",
     "asset update",
     "assistant",
     "audio stream management",
@@ -1348,6 +1365,7 @@ const SKIP_EXAMPLE_GENERATION: &'static [&'static str] = &["humidifier-dehumidif
 /// Example file names that are generated or edited manually and therefore shouldn't be overridden by codegen.
 const MANUALLY_GENERATED_EXAMPLES: &'static [&'static str] = &[
     "adding_accessories_dynamically.rs",
+    "air_purifier.rs",
     "async_callbacks.rs",
     "bridged_accessories.rs",
     "callbacks.rs",
@@ -2,6 +2,7 @@ use tokio;
 
 use hap::{
     accessory::{air_purifier::AirPurifierAccessory, AccessoryCategory, AccessoryInformation},
+    characteristic::CharacteristicCallbacks,
     server::{IpServer, Server},
     storage::{FileStorage, Storage},
     Config,
@@ -12,10 +13,31 @@ use hap::{
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let air_purifier = AirPurifierAccessory::new(1, AccessoryInformation {
+    let mut air_purifier = AirPurifierAccessory::new(1, AccessoryInformation {
         name: "Acme Air Purifier".into(),
         ..Default::default()
-    })?;
+    })?
+    .with_filter_maintenance()?
+    .with_air_quality_sensor()?;
+
+    air_purifier
+        .air_purifier
+        .active
+        .on_update(Some(|current_val: &u8, new_val: &u8| {
+            println!("active characteristic updated from {} to {}", current_val, new_val);
+            Ok(())
+        }));
+
+    if let Some(filter_reset_change_indication) = air_purifier
+        .filter_maintenance
+        .as_mut()
+        .and_then(|f| f.filter_reset_change_indication.as_mut())
+    {
+        filter_reset_change_indication.on_update(Some(|_: &u8, _: &u8| {
+            println!("filter was reset");
+            Ok(())
+        }));
+    }
 
     let mut storage = FileStorage::current_dir().await?;
 
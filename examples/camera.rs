@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use tokio;
+
+use hap::{
+    accessory::{camera::CameraAccessory, AccessoryCategory, AccessoryInformation},
+    camera::{
+        CameraStreamManager,
+        PrepareStreamRequest,
+        PrepareStreamResponse,
+        SessionCommand,
+        StreamConfiguration,
+        StreamDelegate,
+        StreamRequest,
+    },
+    server::{IpServer, Server},
+    storage::{FileStorage, Storage},
+    Config,
+    MacAddress,
+    Pin,
+    Result,
+};
+
+struct Ffmpeg {
+    address: std::net::IpAddr,
+}
+
+#[async_trait]
+impl StreamDelegate for Ffmpeg {
+    async fn prepare_stream(&self, request: PrepareStreamRequest) -> Result<PrepareStreamResponse> {
+        println!("preparing stream to {}", request.controller_address);
+
+        // reuse the ports and SRTP keys of the controller
+        Ok(PrepareStreamResponse {
+            address: self.address,
+            video_port: request.video_port,
+            audio_port: request.audio_port,
+            video_srtp: request.video_srtp,
+            audio_srtp: request.audio_srtp,
+            video_ssrc: rand::random(),
+            audio_ssrc: rand::random(),
+        })
+    }
+
+    async fn handle_stream_request(&self, request: StreamRequest) -> Result<()> {
+        match request.command {
+            SessionCommand::Start => println!("starting ffmpeg with {:?}", request.video),
+            SessionCommand::End => println!("stopping ffmpeg"),
+            command => println!("ignoring stream command {:?}", command),
+        }
+
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut camera = CameraAccessory::new(1, AccessoryInformation {
+        name: "Acme Camera".into(),
+        ..Default::default()
+    })?;
+
+    let mut storage = FileStorage::current_dir().await?;
+
+    let config = match storage.load_config().await {
+        Ok(mut config) => {
+            config.redetermine_local_ip();
+            storage.save_config(&config).await?;
+            config
+        },
+        Err(_) => {
+            let config = Config {
+                pin: Pin::new([1, 1, 1, 2, 2, 3, 3, 3])?,
+                name: "Acme Camera".into(),
+                device_id: MacAddress::from([10, 20, 30, 40, 50, 60]),
+                category: AccessoryCategory::IpCamera,
+                ..Default::default()
+            };
+            storage.save_config(&config).await?;
+            config
+        },
+    };
+
+    let stream_manager = CameraStreamManager::new(StreamConfiguration::default(), Ffmpeg { address: config.host });
+    stream_manager.attach(&mut camera.camera_stream_management).await?;
+
+    let server = IpServer::new(config, storage).await?;
+    server.add_accessory(camera).await?;
+
+    let handle = server.run_handle();
+
+    std::env::set_var("RUST_LOG", "hap=debug");
+    env_logger::init();
+
+    handle.await
+}
@@ -12,10 +12,15 @@ use hap::{
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let irrigation_system = IrrigationSystemAccessory::new(1, AccessoryInformation {
-        name: "Acme Irrigation-System".into(),
-        ..Default::default()
-    })?;
+    // an irrigation system with three zones
+    let irrigation_system = IrrigationSystemAccessory::with_zones(
+        1,
+        AccessoryInformation {
+            name: "Acme Irrigation-System".into(),
+            ..Default::default()
+        },
+        3,
+    )?;
 
     let mut storage = FileStorage::current_dir().await?;
 
@@ -41,10 +41,8 @@ async fn main() -> Result<()> {
     let server = IpServer::new(config, storage).await?;
     server.add_accessory(lightbulb).await?;
 
-    let handle = server.run_handle();
-
     std::env::set_var("RUST_LOG", "hap=debug");
     env_logger::init();
 
-    handle.await
+    server.run().await
 }
@@ -2,6 +2,7 @@ use tokio;
 
 use hap::{
     accessory::{lock::LockAccessory, AccessoryCategory, AccessoryInformation},
+    characteristic::CharacteristicCallbacks,
     server::{IpServer, Server},
     storage::{FileStorage, Storage},
     Config,
@@ -12,11 +13,21 @@ use hap::{
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let lock = LockAccessory::new(1, AccessoryInformation {
+    let mut lock = LockAccessory::new(1, AccessoryInformation {
         name: "Acme Lock".into(),
         ..Default::default()
     })?;
 
+    lock.lock_mechanism
+        .lock_target_state
+        .on_update(Some(|current_val: &u8, new_val: &u8| {
+            println!(
+                "lock_target_state characteristic updated from {} to {}",
+                current_val, new_val
+            );
+            Ok(())
+        }));
+
     let mut storage = FileStorage::current_dir().await?;
 
     let config = match storage.load_config().await {
@@ -2,6 +2,7 @@ use tokio;
 
 use hap::{
     accessory::{television::TelevisionAccessory, AccessoryCategory, AccessoryInformation},
+    characteristic::InputSourceType,
     server::{IpServer, Server},
     storage::{FileStorage, Storage},
     Config,
@@ -12,10 +13,18 @@ use hap::{
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let television = TelevisionAccessory::new(1, AccessoryInformation {
-        name: "Acme Television".into(),
-        ..Default::default()
-    })?;
+    let television = TelevisionAccessory::with_input_sources(
+        1,
+        AccessoryInformation {
+            name: "Acme Television".into(),
+            ..Default::default()
+        },
+        &[
+            ("HDMI 1", InputSourceType::Hdmi),
+            ("HDMI 2", InputSourceType::Hdmi),
+            ("Tuner", InputSourceType::Tuner),
+        ],
+    )?;
 
     let mut storage = FileStorage::current_dir().await?;
 
@@ -0,0 +1,149 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{
+    accessory::HapAccessory,
+    characteristic::HapCharacteristic,
+    service::{battery::BatteryService, HapService},
+    HapType,
+    Result,
+};
+
+/// Wraps any accessory, e.g. one of the predefined ones, and adds a Battery Service to it, so controllers can show its
+/// battery level and warn about a low battery.
+///
+/// # Examples
+///
+/// ```
+/// use hap::accessory::{leak_sensor::LeakSensorAccessory, AccessoryInformation, BatteryPowered};
+///
+/// let leak_sensor = LeakSensorAccessory::new(1, AccessoryInformation {
+///     name: "Acme Leak Sensor".into(),
+///     ..Default::default()
+/// })
+/// .unwrap();
+///
+/// let leak_sensor = BatteryPowered::new(leak_sensor);
+/// ```
+#[derive(Debug, Default)]
+pub struct BatteryPowered<A> {
+    /// The wrapped accessory.
+    pub accessory: A,
+    /// Battery Service.
+    pub battery: BatteryService,
+}
+
+impl<A: HapAccessory> BatteryPowered<A> {
+    /// Adds a Battery Service to the given accessory, numbered after its last service and characteristic.
+    pub fn new(accessory: A) -> Self {
+        let mut last_id = 0;
+        for service in accessory.get_services() {
+            last_id = last_id.max(service.get_id());
+            for characteristic in service.get_characteristics() {
+                last_id = last_id.max(characteristic.get_id());
+            }
+        }
+
+        let battery = BatteryService::new(last_id + 1, accessory.get_id());
+
+        Self { accessory, battery }
+    }
+
+    /// Sets the battery level in percent.
+    pub async fn set_battery_level(&mut self, battery_level: u8) -> Result<()> {
+        if let Some(c) = &mut self.battery.battery_level {
+            c.set_value(battery_level.into()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the Status Low Battery characteristic of the Battery Service and of every other service of the accessory
+    /// that has one, like the primary service of most sensors, which is what makes iOS flag the device.
+    pub async fn set_low_battery(&mut self, low_battery: bool) -> Result<()> {
+        for service in self.get_mut_services() {
+            if let Some(c) = service.get_mut_characteristic(HapType::StatusLowBattery) {
+                c.set_value((low_battery as u8).into()).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<A: HapAccessory> HapAccessory for BatteryPowered<A> {
+    fn get_id(&self) -> u64 { self.accessory.get_id() }
+
+    fn set_id(&mut self, id: u64) { self.accessory.set_id(id); }
+
+    fn get_service(&self, hap_type: HapType) -> Option<&dyn HapService> {
+        for service in self.get_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_mut_service(&mut self, hap_type: HapType) -> Option<&mut dyn HapService> {
+        for service in self.get_mut_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        let mut services = self.accessory.get_services();
+        services.push(&self.battery);
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
+        let mut services = self.accessory.get_mut_services();
+        services.push(&mut self.battery);
+        services
+    }
+}
+
+impl<A: HapAccessory> Serialize for BatteryPowered<A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapAccessory", 2)?;
+        state.serialize_field("aid", &self.get_id())?;
+        state.serialize_field("services", &self.get_services())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::accessory::{assert_unique_ids, leak_sensor::LeakSensorAccessory, AccessoryInformation};
+
+    #[tokio::test]
+    async fn test_low_battery() {
+        let leak_sensor = LeakSensorAccessory::new(1, AccessoryInformation::default()).unwrap();
+        let mut leak_sensor = BatteryPowered::new(leak_sensor);
+
+        assert_unique_ids(&leak_sensor);
+
+        leak_sensor.set_battery_level(15).await.unwrap();
+        leak_sensor.set_low_battery(true).await.unwrap();
+
+        let battery_level = leak_sensor.battery.battery_level.as_mut().unwrap();
+        assert_eq!(HapCharacteristic::get_value(battery_level).await.unwrap(), json!(15));
+        assert_eq!(
+            HapCharacteristic::get_value(&mut leak_sensor.battery.status_low_battery)
+                .await
+                .unwrap(),
+            json!(1)
+        );
+        let status_low_battery = leak_sensor.accessory.leak_sensor.status_low_battery.as_mut().unwrap();
+        assert_eq!(
+            HapCharacteristic::get_value(status_low_battery).await.unwrap(),
+            json!(1)
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    characteristic::HapCharacteristic,
+    service::{
+        accessory_information::AccessoryInformationService,
+        custom::CustomService,
+        protocol_information::ProtocolInformationService,
+        HapService,
+    },
+    HapType,
+    Result,
+};
+
+/// [`AccessoryBuilder`](AccessoryBuilder) builds accessories of arbitrary [`CustomService`](CustomService)s for
+/// devices that don't match any of the predefined accessories, numbering the services and characteristics on
+/// [`build`](AccessoryBuilder::build).
+///
+/// # Examples
+///
+/// ```
+/// use hap::{
+///     accessory::{AccessoryBuilder, AccessoryInformation},
+///     characteristic::{Characteristic, Format, Perm},
+///     service::custom::CustomService,
+///     HapType,
+/// };
+/// use uuid::Uuid;
+///
+/// let foo_number = Characteristic::<u8>::new(
+///     0,
+///     0,
+///     HapType::Custom(Uuid::parse_str("2db3ac3f-8b9c-4431-8d87-670351dc872a").unwrap()),
+///     Format::UInt8,
+///     vec![Perm::PairedRead, Perm::Events],
+///     Some("Foo Number".into()),
+///     None,
+///     42,
+///     None,
+///     None,
+///     None,
+///     None,
+///     None,
+///     None,
+///     None,
+///     None,
+///     None,
+///     None,
+/// );
+///
+/// let accessory = AccessoryBuilder::new(1, AccessoryInformation {
+///     name: "Acme Foo".into(),
+///     ..Default::default()
+/// })
+/// .add_service(
+///     CustomService::new(HapType::Custom(
+///         Uuid::parse_str("d5bb0a60-92f0-483e-811d-97d4b2b502ff").unwrap(),
+///     ))
+///     .primary()
+///     .add_characteristic(foo_number),
+/// )
+/// .build()
+/// .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct AccessoryBuilder {
+    id: u64,
+    information: AccessoryInformation,
+    services: Vec<CustomService>,
+}
+
+impl AccessoryBuilder {
+    /// Creates a new [`AccessoryBuilder`](AccessoryBuilder) for an accessory with the given ID and information.
+    pub fn new(id: u64, information: AccessoryInformation) -> Self {
+        Self {
+            id,
+            information,
+            services: Vec::new(),
+        }
+    }
+
+    /// Adds a service to the accessory.
+    pub fn add_service(mut self, service: CustomService) -> Self {
+        self.services.push(service);
+        self
+    }
+
+    /// Builds the accessory, assigning instance IDs to its services and characteristics in the order they were added.
+    /// A Protocol Information Service is numbered after the added services.
+    pub fn build(self) -> Result<CustomAccessory> {
+        let accessory_information = self.information.to_service(1, self.id)?;
+
+        let mut next_id = accessory_information
+            .get_characteristics()
+            .iter()
+            .map(|c| c.get_id())
+            .max()
+            .unwrap_or(1)
+            + 1;
+
+        let mut services = self.services;
+        for service in &mut services {
+            next_id = service.assign_ids(next_id, self.id);
+        }
+
+        let mut numbered_services: Vec<&dyn HapService> = vec![&accessory_information];
+        for service in &services {
+            numbered_services.push(service);
+        }
+        let protocol_information = protocol_information_service(&numbered_services, self.id)?;
+
+        Ok(CustomAccessory {
+            id: self.id,
+            accessory_information,
+            services,
+            protocol_information,
+        })
+    }
+}
+
+/// Accessory built by an [`AccessoryBuilder`](AccessoryBuilder).
+#[derive(Debug, Default)]
+pub struct CustomAccessory {
+    /// ID of the accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformationService,
+    /// Custom Services, in the order they were added to the builder.
+    pub services: Vec<CustomService>,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
+}
+
+impl HapAccessory for CustomAccessory {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_service(&self, hap_type: HapType) -> Option<&dyn HapService> {
+        for service in self.get_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_mut_service(&mut self, hap_type: HapType) -> Option<&mut dyn HapService> {
+        for service in self.get_mut_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        let mut services: Vec<&dyn HapService> = vec![&self.accessory_information];
+        for service in &self.services {
+            services.push(service);
+        }
+        services.push(&self.protocol_information);
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
+        let mut services: Vec<&mut dyn HapService> = vec![&mut self.accessory_information];
+        for service in &mut self.services {
+            services.push(service);
+        }
+        services.push(&mut self.protocol_information);
+        services
+    }
+}
+
+impl Serialize for CustomAccessory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapAccessory", 2)?;
+        state.serialize_field("aid", &self.get_id())?;
+        state.serialize_field("services", &self.get_services())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        accessory::assert_unique_ids,
+        characteristic::{custom_characteristic, Characteristic, Format, Perm},
+    };
+
+    fn characteristic(uuid: &str, value: u8) -> Characteristic<u8> {
+        custom_characteristic(uuid, Format::UInt8, vec![Perm::PairedRead, Perm::PairedWrite], value)
+    }
+
+    #[test]
+    fn test_build_custom_accessory() {
+        let foo_type = HapType::Custom(Uuid::parse_str("d5bb0a60-92f0-483e-811d-97d4b2b502ff").unwrap());
+        let bar_type = HapType::Custom(Uuid::parse_str("2db3ac3f-8b9c-4431-8d87-670351dc872a").unwrap());
+        let baz_type = HapType::Custom(Uuid::parse_str("497c968e-261f-445d-bcac-69ae7bb8979b").unwrap());
+
+        let mut accessory = AccessoryBuilder::new(3, AccessoryInformation {
+            name: "Acme Foo".into(),
+            firmware_revision: Some("1.0".into()),
+            ..Default::default()
+        })
+        .add_service(
+            CustomService::new(foo_type)
+                .primary()
+                .add_characteristic(characteristic("2db3ac3f-8b9c-4431-8d87-670351dc872a", 1))
+                .add_characteristic(characteristic("497c968e-261f-445d-bcac-69ae7bb8979b", 2)),
+        )
+        .add_service(
+            CustomService::new(HapType::Custom(Uuid::new_v4()))
+                .hidden()
+                .add_characteristic(characteristic("ba2a3c6b-5b29-4356-8b2b-bbbf3be05e18", 3)),
+        )
+        .build()
+        .unwrap();
+
+        assert_eq!(accessory.get_services().len(), 4);
+        assert!(accessory.services[0].get_primary());
+        assert!(accessory.services[1].get_hidden());
+
+        assert_unique_ids(&accessory);
+
+        let service = accessory.get_mut_service(foo_type).unwrap();
+        assert!(service.get_characteristic(bar_type).is_some());
+        let baz = service.get_mut_characteristic(baz_type).unwrap();
+        executor::block_on(baz.set_value(json!(7))).unwrap();
+        assert_eq!(executor::block_on(baz.get_value()).unwrap(), json!(7));
+
+        let json = serde_json::to_value(&accessory).unwrap();
+        assert_eq!(json["aid"], json!(3));
+        assert_eq!(
+            json["services"][1]["type"],
+            json!("d5bb0a60-92f0-483e-811d-97d4b2b502ff")
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    service::{
+        accessory_information::AccessoryInformationService,
+        air_purifier::AirPurifierService,
+        air_quality_sensor::AirQualitySensorService,
+        filter_maintenance::FilterMaintenanceService,
+        protocol_information::ProtocolInformationService,
+        HapService,
+    },
+    Error,
+    HapType,
+    Result,
+};
+
+/// Air Purifier Accessory.
+///
+/// The device is switched on and off with the `active` characteristic of the Air Purifier Service. A Filter
+/// Maintenance Service and an Air Quality Sensor Service can be added with
+/// [`with_filter_maintenance`](AirPurifierAccessory::with_filter_maintenance) and
+/// [`with_air_quality_sensor`](AirPurifierAccessory::with_air_quality_sensor), which link them to the Air Purifier
+/// Service.
+#[derive(Debug, Default)]
+pub struct AirPurifierAccessory {
+    /// ID of the Air Purifier Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformationService,
+    /// Air Purifier Service.
+    pub air_purifier: AirPurifierService,
+    /// Filter Maintenance Service linked to the Air Purifier Service.
+    ///
+    /// A controller resets the filter by writing to the write-only `filter_reset_change_indication` characteristic.
+    /// Set an `on_update` callback on it to reset the filter life counter of the device.
+    pub filter_maintenance: Option<FilterMaintenanceService>,
+    /// Air Quality Sensor Service linked to the Air Purifier Service.
+    pub air_quality_sensor: Option<AirQualitySensorService>,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
+}
+
+impl AirPurifierAccessory {
+    /// Creates a new Air Purifier Accessory.
+    pub fn new(id: u64, information: AccessoryInformation) -> Result<Self> {
+        let accessory_information = information.to_service(1, id)?;
+        let air_purifier_id = accessory_information.get_characteristics().len() as u64;
+        let mut air_purifier = AirPurifierService::new(1 + air_purifier_id + 1, id);
+        air_purifier.set_primary(true);
+
+        let protocol_information = protocol_information_service(&[&accessory_information, &air_purifier], id)?;
+
+        Ok(Self {
+            id,
+            accessory_information,
+            air_purifier,
+            filter_maintenance: None,
+            air_quality_sensor: None,
+            protocol_information,
+        })
+    }
+
+    /// Adds a Filter Maintenance Service to the Air Purifier Accessory and links it to the Air Purifier Service. Fails
+    /// with [`Error::DuplicateService`](crate::Error::DuplicateService) if the accessory already has one.
+    pub fn with_filter_maintenance(mut self) -> Result<Self> {
+        if self.filter_maintenance.is_some() {
+            return Err(Error::DuplicateService("Filter Maintenance"));
+        }
+
+        // the new service takes the place of the Protocol Information Service, which is renumbered after it
+        let filter_maintenance = FilterMaintenanceService::new(self.protocol_information.get_id(), self.id);
+        self.link_service(filter_maintenance.get_id());
+        self.filter_maintenance = Some(filter_maintenance);
+
+        self.renumber_protocol_information()?;
+
+        Ok(self)
+    }
+
+    /// Adds an Air Quality Sensor Service to the Air Purifier Accessory and links it to the Air Purifier Service.
+    /// Fails with [`Error::DuplicateService`](crate::Error::DuplicateService) if the accessory already has one.
+    pub fn with_air_quality_sensor(mut self) -> Result<Self> {
+        if self.air_quality_sensor.is_some() {
+            return Err(Error::DuplicateService("Air Quality Sensor"));
+        }
+
+        let air_quality_sensor = AirQualitySensorService::new(self.protocol_information.get_id(), self.id);
+        self.link_service(air_quality_sensor.get_id());
+        self.air_quality_sensor = Some(air_quality_sensor);
+
+        self.renumber_protocol_information()?;
+
+        Ok(self)
+    }
+
+    fn link_service(&mut self, service_id: u64) {
+        let mut linked_services = self.air_purifier.get_linked_services();
+        linked_services.push(service_id);
+        self.air_purifier.set_linked_services(linked_services);
+    }
+
+    fn renumber_protocol_information(&mut self) -> Result<()> {
+        let services: Vec<&dyn HapService> = self
+            .get_services()
+            .into_iter()
+            .filter(|service| service.get_type() != HapType::ProtocolInformation)
+            .collect();
+        self.protocol_information = protocol_information_service(&services, self.id)?;
+
+        Ok(())
+    }
+}
+
+impl HapAccessory for AirPurifierAccessory {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_service(&self, hap_type: HapType) -> Option<&dyn HapService> {
+        for service in self.get_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_mut_service(&mut self, hap_type: HapType) -> Option<&mut dyn HapService> {
+        for service in self.get_mut_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        let mut services: Vec<&dyn HapService> = vec![&self.accessory_information, &self.air_purifier];
+        if let Some(f) = &self.filter_maintenance {
+            services.push(f);
+        }
+        if let Some(a) = &self.air_quality_sensor {
+            services.push(a);
+        }
+        services.push(&self.protocol_information);
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
+        let mut services: Vec<&mut dyn HapService> = vec![&mut self.accessory_information, &mut self.air_purifier];
+        if let Some(f) = &mut self.filter_maintenance {
+            services.push(f);
+        }
+        if let Some(a) = &mut self.air_quality_sensor {
+            services.push(a);
+        }
+        services.push(&mut self.protocol_information);
+        services
+    }
+}
+
+impl Serialize for AirPurifierAccessory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapAccessory", 2)?;
+        state.serialize_field("aid", &self.get_id())?;
+        state.serialize_field("services", &self.get_services())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor;
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::{
+        accessory::assert_unique_ids,
+        characteristic::{CharacteristicCallbacks, HapCharacteristic},
+    };
+
+    #[test]
+    fn test_filter_maintenance_is_linked() {
+        let mut air_purifier = AirPurifierAccessory::new(1, AccessoryInformation::default())
+            .unwrap()
+            .with_filter_maintenance()
+            .unwrap();
+
+        let filter_maintenance_id = air_purifier.filter_maintenance.as_ref().unwrap().get_id();
+        assert_eq!(air_purifier.air_purifier.get_linked_services(), vec![
+            filter_maintenance_id
+        ]);
+
+        assert_unique_ids(&air_purifier);
+        assert_eq!(
+            air_purifier.get_services().last().unwrap().get_type(),
+            HapType::ProtocolInformation
+        );
+
+        let reset = Arc::new(AtomicBool::new(false));
+        let filter_reset_change_indication = air_purifier
+            .filter_maintenance
+            .as_mut()
+            .unwrap()
+            .filter_reset_change_indication
+            .as_mut()
+            .unwrap();
+        let r = reset.clone();
+        filter_reset_change_indication.on_update(Some(move |_: &u8, _: &u8| {
+            r.store(true, Ordering::SeqCst);
+            Ok(())
+        }));
+        executor::block_on(HapCharacteristic::set_value(filter_reset_change_indication, json!(1))).unwrap();
+
+        assert!(reset.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_air_quality_sensor_is_linked() {
+        let air_purifier = AirPurifierAccessory::new(1, AccessoryInformation::default())
+            .unwrap()
+            .with_air_quality_sensor()
+            .unwrap()
+            .with_filter_maintenance()
+            .unwrap();
+
+        assert_eq!(air_purifier.air_purifier.get_linked_services(), vec![
+            air_purifier.air_quality_sensor.as_ref().unwrap().get_id(),
+            air_purifier.filter_maintenance.as_ref().unwrap().get_id(),
+        ]);
+
+        assert_unique_ids(&air_purifier);
+        assert!(
+            air_purifier.protocol_information.get_id() > air_purifier.filter_maintenance.as_ref().unwrap().get_id()
+        );
+    }
+
+    #[test]
+    fn test_services_can_only_be_added_once() {
+        let air_purifier = || {
+            AirPurifierAccessory::new(1, AccessoryInformation::default())
+                .unwrap()
+                .with_filter_maintenance()
+                .unwrap()
+                .with_air_quality_sensor()
+                .unwrap()
+        };
+
+        assert!(matches!(
+            air_purifier().with_filter_maintenance(),
+            Err(Error::DuplicateService(_))
+        ));
+        assert!(matches!(
+            air_purifier().with_air_quality_sensor(),
+            Err(Error::DuplicateService(_))
+        ));
+    }
+}
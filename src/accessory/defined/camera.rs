@@ -0,0 +1,100 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    service::{
+        accessory_information::AccessoryInformationService,
+        camera_stream_management::CameraStreamManagementService,
+        protocol_information::ProtocolInformationService,
+        HapService,
+    },
+    HapType,
+    Result,
+};
+
+/// Camera Accessory.
+///
+/// The RTP stream negotiation of the Camera Stream Management service can be handled by a
+/// [`CameraStreamManager`](crate::camera::CameraStreamManager).
+#[derive(Debug, Default)]
+pub struct CameraAccessory {
+    /// ID of the Camera Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformationService,
+    /// Camera Stream Management Service.
+    pub camera_stream_management: CameraStreamManagementService,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
+}
+
+impl CameraAccessory {
+    /// Creates a new Camera Accessory.
+    pub fn new(id: u64, information: AccessoryInformation) -> Result<Self> {
+        let accessory_information = information.to_service(1, id)?;
+
+        let camera_stream_management_id = 2 + accessory_information.get_characteristics().len() as u64;
+        let mut camera_stream_management = CameraStreamManagementService::new(camera_stream_management_id, id);
+        camera_stream_management.set_primary(true);
+
+        let protocol_information =
+            protocol_information_service(&[&accessory_information, &camera_stream_management], id)?;
+
+        Ok(Self {
+            id,
+            accessory_information,
+            camera_stream_management,
+            protocol_information,
+        })
+    }
+}
+
+impl HapAccessory for CameraAccessory {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_service(&self, hap_type: HapType) -> Option<&dyn HapService> {
+        for service in self.get_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_mut_service(&mut self, hap_type: HapType) -> Option<&mut dyn HapService> {
+        for service in self.get_mut_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        vec![
+            &self.accessory_information,
+            &self.camera_stream_management,
+            &self.protocol_information,
+        ]
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
+        vec![
+            &mut self.accessory_information,
+            &mut self.camera_stream_management,
+            &mut self.protocol_information,
+        ]
+    }
+}
+
+impl Serialize for CameraAccessory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapAccessory", 2)?;
+        state.serialize_field("aid", &self.get_id())?;
+        state.serialize_field("services", &self.get_services())?;
+        state.end()
+    }
+}
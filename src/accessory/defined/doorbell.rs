@@ -0,0 +1,135 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    characteristic::HapCharacteristic,
+    service::{
+        accessory_information::AccessoryInformationService,
+        camera_stream_management::CameraStreamManagementService,
+        doorbell::DoorbellService,
+        protocol_information::ProtocolInformationService,
+        HapService,
+    },
+    HapType,
+    Result,
+};
+
+/// Doorbell Accessory.
+///
+/// A press of the doorbell is notified to controllers with
+/// [`Server::ring_doorbell`](crate::server::Server::ring_doorbell).
+#[derive(Debug, Default)]
+pub struct DoorbellAccessory {
+    /// ID of the Doorbell Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformationService,
+    /// Doorbell Service.
+    pub doorbell: DoorbellService,
+    /// Camera Stream Management Service of video doorbells.
+    pub camera_stream_management: Option<CameraStreamManagementService>,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
+}
+
+impl DoorbellAccessory {
+    /// Creates a new Doorbell Accessory.
+    pub fn new(id: u64, information: AccessoryInformation) -> Result<Self> {
+        let accessory_information = information.to_service(1, id)?;
+
+        let doorbell_id = 2 + accessory_information.get_characteristics().len() as u64;
+        let mut doorbell = DoorbellService::new(doorbell_id, id);
+        doorbell.set_primary(true);
+        // a doorbell only knows single presses
+        doorbell
+            .programmable_switch_event
+            .set_valid_values(Some(vec![0.into()]))?;
+
+        // TODO - figure out how to auto-set reasonable default values for tlv8 characteristics
+        doorbell.operating_state_response = None;
+
+        let protocol_information = protocol_information_service(&[&accessory_information, &doorbell], id)?;
+
+        Ok(Self {
+            id,
+            accessory_information,
+            doorbell,
+            camera_stream_management: None,
+            protocol_information,
+        })
+    }
+
+    /// Creates a new Doorbell Accessory with a Camera Stream Management Service, i.e. a video doorbell.
+    pub fn with_camera(id: u64, information: AccessoryInformation) -> Result<Self> {
+        let mut doorbell = Self::new(id, information)?;
+
+        let camera_stream_management_id =
+            3 + doorbell.doorbell.get_id() + doorbell.doorbell.get_characteristics().len() as u64;
+        let camera_stream_management = CameraStreamManagementService::new(camera_stream_management_id, id);
+
+        // renumber the Protocol Information Service after the Camera Stream Management Service
+        doorbell.protocol_information = protocol_information_service(
+            &[
+                &doorbell.accessory_information,
+                &doorbell.doorbell,
+                &camera_stream_management,
+            ],
+            id,
+        )?;
+        doorbell.camera_stream_management = Some(camera_stream_management);
+
+        Ok(doorbell)
+    }
+}
+
+impl HapAccessory for DoorbellAccessory {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_service(&self, hap_type: HapType) -> Option<&dyn HapService> {
+        for service in self.get_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_mut_service(&mut self, hap_type: HapType) -> Option<&mut dyn HapService> {
+        for service in self.get_mut_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        let mut services: Vec<&dyn HapService> = vec![&self.accessory_information, &self.doorbell];
+        if let Some(c) = &self.camera_stream_management {
+            services.push(c);
+        }
+        services.push(&self.protocol_information);
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
+        let mut services: Vec<&mut dyn HapService> = vec![&mut self.accessory_information, &mut self.doorbell];
+        if let Some(c) = &mut self.camera_stream_management {
+            services.push(c);
+        }
+        services.push(&mut self.protocol_information);
+        services
+    }
+}
+
+impl Serialize for DoorbellAccessory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapAccessory", 2)?;
+        state.serialize_field("aid", &self.get_id())?;
+        state.serialize_field("services", &self.get_services())?;
+        state.end()
+    }
+}
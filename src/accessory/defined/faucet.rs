@@ -2,9 +2,14 @@ use futures::executor;
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-    accessory::{AccessoryInformation, HapAccessory},
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
     characteristic::HapCharacteristic,
-    service::{accessory_information::AccessoryInformationService, valve::ValveService, HapService},
+    service::{
+        accessory_information::AccessoryInformationService,
+        protocol_information::ProtocolInformationService,
+        valve::ValveService,
+        HapService,
+    },
     HapType,
     Result,
 };
@@ -19,6 +24,8 @@ pub struct FaucetAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Valve Service.
     pub valve: ValveService,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl FaucetAccessory {
@@ -31,10 +38,13 @@ impl FaucetAccessory {
         valve.set_primary(true);
         executor::block_on(valve.valve_type.set_value(3.into()))?; // 3 is FAUCET
 
+        let protocol_information = protocol_information_service(&[&accessory_information, &valve], id)?;
+
         Ok(Self {
             id,
             accessory_information,
             valve,
+            protocol_information,
         })
     }
 }
@@ -62,10 +72,16 @@ impl HapAccessory for FaucetAccessory {
         None
     }
 
-    fn get_services(&self) -> Vec<&dyn HapService> { vec![&self.accessory_information, &self.valve] }
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        vec![&self.accessory_information, &self.valve, &self.protocol_information]
+    }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
-        vec![&mut self.accessory_information, &mut self.valve]
+        vec![
+            &mut self.accessory_information,
+            &mut self.valve,
+            &mut self.protocol_information,
+        ]
     }
 }
 
@@ -1,8 +1,13 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-    accessory::{AccessoryInformation, HapAccessory},
-    service::{accessory_information::AccessoryInformationService, heater_cooler::HeaterCoolerService, HapService},
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    service::{
+        accessory_information::AccessoryInformationService,
+        heater_cooler::HeaterCoolerService,
+        protocol_information::ProtocolInformationService,
+        HapService,
+    },
     HapType,
     Result,
 };
@@ -17,6 +22,8 @@ pub struct HeaterCoolerAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Heater-Cooler Service.
     pub heater_cooler: HeaterCoolerService,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl HeaterCoolerAccessory {
@@ -27,10 +34,13 @@ impl HeaterCoolerAccessory {
         let mut heater_cooler = HeaterCoolerService::new(1 + heater_cooler_id + 1, id);
         heater_cooler.set_primary(true);
 
+        let protocol_information = protocol_information_service(&[&accessory_information, &heater_cooler], id)?;
+
         Ok(Self {
             id,
             accessory_information,
             heater_cooler,
+            protocol_information,
         })
     }
 }
@@ -58,10 +68,20 @@ impl HapAccessory for HeaterCoolerAccessory {
         None
     }
 
-    fn get_services(&self) -> Vec<&dyn HapService> { vec![&self.accessory_information, &self.heater_cooler] }
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        vec![
+            &self.accessory_information,
+            &self.heater_cooler,
+            &self.protocol_information,
+        ]
+    }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
-        vec![&mut self.accessory_information, &mut self.heater_cooler]
+        vec![
+            &mut self.accessory_information,
+            &mut self.heater_cooler,
+            &mut self.protocol_information,
+        ]
     }
 }
 
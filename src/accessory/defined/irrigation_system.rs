@@ -2,9 +2,16 @@ use futures::executor;
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-    accessory::{AccessoryInformation, HapAccessory},
-    characteristic::HapCharacteristic,
-    service::{accessory_information::AccessoryInformationService, valve::ValveService, HapService},
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    characteristic::{HapCharacteristic, ValveType},
+    service::{
+        accessory_information::AccessoryInformationService,
+        irrigation_system::IrrigationSystemService,
+        protocol_information::ProtocolInformationService,
+        valve::ValveService,
+        HapService,
+    },
+    Error,
     HapType,
     Result,
 };
@@ -17,25 +24,67 @@ pub struct IrrigationSystemAccessory {
 
     /// Accessory Information Service.
     pub accessory_information: AccessoryInformationService,
-    /// Valve Service.
+    /// Irrigation-System Service, aggregating the state of the zones.
+    pub irrigation_system: IrrigationSystemService,
+    /// Valve Service of the first zone.
     pub valve: ValveService,
+    /// Valve Services of the further zones, if the accessory was created with more than one zone.
+    pub additional_valves: Vec<ValveService>,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl IrrigationSystemAccessory {
-    /// Creates a new Irrigation-System Accessory.
-    pub fn new(id: u64, information: AccessoryInformation) -> Result<Self> {
+    /// Creates a new Irrigation-System Accessory with a single zone.
+    pub fn new(id: u64, information: AccessoryInformation) -> Result<Self> { Self::with_zones(id, information, 1) }
+
+    /// Creates a new Irrigation-System Accessory with the given number of zones.
+    ///
+    /// Each zone is an irrigation valve linked to the primary Irrigation-System Service, with the Service Label
+    /// Indexes `1` to `zones`. Fails with [`Error::NoServices`](crate::Error::NoServices) if `zones` is `0`.
+    pub fn with_zones(id: u64, information: AccessoryInformation, zones: u8) -> Result<Self> {
+        if zones == 0 {
+            return Err(Error::NoServices("Valve"));
+        }
+
         let accessory_information = information.to_service(1, id)?;
 
-        // adding multiple valves here would result in an irrigation system with multiple "Zones"
-        let valve_id = 2 + accessory_information.get_characteristics().len() as u64;
-        let mut valve = ValveService::new(valve_id, id);
-        valve.set_primary(true);
-        executor::block_on(valve.valve_type.set_value(1.into()))?; // 1 is IRRIGATION/SPRINKLER
+        let irrigation_system_id = 2 + accessory_information.get_characteristics().len() as u64;
+        let mut irrigation_system = IrrigationSystemService::new(irrigation_system_id, id);
+        irrigation_system.set_primary(true);
+
+        let mut next_id = 3 + irrigation_system.get_id() + irrigation_system.get_characteristics().len() as u64;
+
+        let mut valves = Vec::new();
+        for index in 1..=zones {
+            let mut valve = ValveService::new(next_id, id);
+            executor::block_on(valve.valve_type.set_value((ValveType::Irrigation as u8).into()))?;
+            if let Some(label_index) = &mut valve.label_index {
+                executor::block_on(label_index.set_value(index.into()))?;
+            }
+            next_id = 3 + valve.get_id() + valve.get_characteristics().len() as u64;
+
+            valves.push(valve);
+        }
+
+        irrigation_system.set_linked_services(valves.iter().map(|valve| valve.get_id()).collect());
+
+        let mut services: Vec<&dyn HapService> = vec![&accessory_information, &irrigation_system];
+        for valve in &valves {
+            services.push(valve);
+        }
+        let protocol_information = protocol_information_service(&services, id)?;
+
+        let additional_valves = valves.split_off(1);
+        let valve = valves.remove(0);
 
         Ok(Self {
             id,
             accessory_information,
+            irrigation_system,
             valve,
+            additional_valves,
+            protocol_information,
         })
     }
 }
@@ -63,10 +112,27 @@ impl HapAccessory for IrrigationSystemAccessory {
         None
     }
 
-    fn get_services(&self) -> Vec<&dyn HapService> { vec![&self.accessory_information, &self.valve] }
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        let mut services: Vec<&dyn HapService> =
+            vec![&self.accessory_information, &self.irrigation_system, &self.valve];
+        for valve in &self.additional_valves {
+            services.push(valve);
+        }
+        services.push(&self.protocol_information);
+        services
+    }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
-        vec![&mut self.accessory_information, &mut self.valve]
+        let mut services: Vec<&mut dyn HapService> = vec![
+            &mut self.accessory_information,
+            &mut self.irrigation_system,
+            &mut self.valve,
+        ];
+        for valve in &mut self.additional_valves {
+            services.push(valve);
+        }
+        services.push(&mut self.protocol_information);
+        services
     }
 }
 
@@ -78,3 +144,70 @@ impl Serialize for IrrigationSystemAccessory {
         state.end()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accessory::assert_unique_ids;
+
+    #[test]
+    fn test_zones_are_linked() {
+        let mut irrigation_system =
+            IrrigationSystemAccessory::with_zones(1, AccessoryInformation::default(), 4).unwrap();
+
+        assert_eq!(irrigation_system.additional_valves.len(), 3);
+        assert_eq!(
+            irrigation_system.irrigation_system.get_linked_services(),
+            std::iter::once(&irrigation_system.valve)
+                .chain(&irrigation_system.additional_valves)
+                .map(|valve| valve.get_id())
+                .collect::<Vec<_>>()
+        );
+
+        assert_unique_ids(&irrigation_system);
+
+        let valves = std::iter::once(&mut irrigation_system.valve).chain(&mut irrigation_system.additional_valves);
+        for (index, valve) in valves.enumerate() {
+            let label_index = valve.label_index.as_mut().unwrap();
+            assert_eq!(
+                executor::block_on(label_index.get_value()).unwrap(),
+                serde_json::json!(index + 1)
+            );
+            assert_eq!(
+                executor::block_on(valve.valve_type.get_value()).unwrap(),
+                serde_json::json!(1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_linked_services_are_serialized() {
+        let irrigation_system = IrrigationSystemAccessory::with_zones(1, AccessoryInformation::default(), 2).unwrap();
+
+        let json = serde_json::to_value(&irrigation_system).unwrap();
+        let services = json["services"].as_array().unwrap();
+
+        let valve_ids = vec![
+            irrigation_system.valve.get_id(),
+            irrigation_system.additional_valves[0].get_id(),
+        ];
+        assert_eq!(services[1]["linked"], serde_json::json!(valve_ids));
+        // services without linked services don't announce an empty array
+        assert!(services[0].get("linked").is_none());
+        assert!(services[2].get("linked").is_none());
+    }
+
+    #[test]
+    fn test_zones_are_required() {
+        assert!(matches!(
+            IrrigationSystemAccessory::with_zones(1, AccessoryInformation::default(), 0),
+            Err(Error::NoServices(_))
+        ));
+
+        let irrigation_system = IrrigationSystemAccessory::new(1, AccessoryInformation::default()).unwrap();
+        assert!(irrigation_system.additional_valves.is_empty());
+        assert_eq!(irrigation_system.irrigation_system.get_linked_services(), vec![
+            irrigation_system.valve.get_id()
+        ]);
+    }
+}
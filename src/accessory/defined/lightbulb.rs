@@ -3,8 +3,13 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-    accessory::{AccessoryInformation, HapAccessory},
-    service::{accessory_information::AccessoryInformationService, lightbulb::LightbulbService, HapService},
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    service::{
+        accessory_information::AccessoryInformationService,
+        lightbulb::LightbulbService,
+        protocol_information::ProtocolInformationService,
+        HapService,
+    },
     HapType,
     Result,
 };
@@ -19,6 +24,8 @@ pub struct LightbulbAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Lightbulb Service.
     pub lightbulb: LightbulbService,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl LightbulbAccessory {
@@ -34,10 +41,13 @@ impl LightbulbAccessory {
         lightbulb.characteristic_value_transition_control = None;
         lightbulb.supported_characteristic_value_transition_configuration = None;
 
+        let protocol_information = protocol_information_service(&[&accessory_information, &lightbulb], id)?;
+
         Ok(Self {
             id,
             accessory_information,
             lightbulb,
+            protocol_information,
         })
     }
 }
@@ -65,10 +75,16 @@ impl HapAccessory for LightbulbAccessory {
         None
     }
 
-    fn get_services(&self) -> Vec<&dyn HapService> { vec![&self.accessory_information, &self.lightbulb] }
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        vec![&self.accessory_information, &self.lightbulb, &self.protocol_information]
+    }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
-        vec![&mut self.accessory_information, &mut self.lightbulb]
+        vec![
+            &mut self.accessory_information,
+            &mut self.lightbulb,
+            &mut self.protocol_information,
+        ]
     }
 }
 
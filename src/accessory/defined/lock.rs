@@ -1,11 +1,12 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-    accessory::{AccessoryInformation, HapAccessory},
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
     service::{
         accessory_information::AccessoryInformationService,
         lock_management::LockManagementService,
         lock_mechanism::LockMechanismService,
+        protocol_information::ProtocolInformationService,
         HapService,
     },
     HapType,
@@ -24,6 +25,8 @@ pub struct LockAccessory {
     pub lock_mechanism: LockMechanismService,
     /// Lock Management Service.
     pub lock_management: LockManagementService,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl LockAccessory {
@@ -42,11 +45,15 @@ impl LockAccessory {
         // TODO - figure out how to auto-set reasonable default values for tlv8 characteristics
         lock_management.logs = None;
 
+        let protocol_information =
+            protocol_information_service(&[&accessory_information, &lock_mechanism, &lock_management], id)?;
+
         Ok(Self {
             id,
             accessory_information,
             lock_mechanism,
             lock_management,
+            protocol_information,
         })
     }
 }
@@ -75,7 +82,12 @@ impl HapAccessory for LockAccessory {
     }
 
     fn get_services(&self) -> Vec<&dyn HapService> {
-        vec![&self.accessory_information, &self.lock_mechanism, &self.lock_management]
+        vec![
+            &self.accessory_information,
+            &self.lock_mechanism,
+            &self.lock_management,
+            &self.protocol_information,
+        ]
     }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
@@ -83,6 +95,7 @@ impl HapAccessory for LockAccessory {
             &mut self.accessory_information,
             &mut self.lock_mechanism,
             &mut self.lock_management,
+            &mut self.protocol_information,
         ]
     }
 }
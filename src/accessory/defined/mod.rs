@@ -1,5 +1,11 @@
+/// Air Purifier accessory definition.
+pub mod air_purifier;
 /// Bridge accessory definition.
 pub mod bridge;
+/// Camera accessory definition.
+pub mod camera;
+/// Doorbell accessory definition.
+pub mod doorbell;
 /// Faucet accessory definition.
 pub mod faucet;
 /// Heater-Cooler accessory definition.
@@ -10,7 +16,11 @@ pub mod irrigation_system;
 pub mod lightbulb;
 /// Lock accessory definition.
 pub mod lock;
+/// Programmable Switch accessory definition.
+pub mod programmable_switch;
 /// Shower Head accessory definition.
 pub mod shower_head;
 /// Television accessory definition.
 pub mod television;
+/// Valve accessory definition.
+pub mod valve;
@@ -0,0 +1,193 @@
+use futures::executor;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    characteristic::HapCharacteristic,
+    service::{
+        accessory_information::AccessoryInformationService,
+        label::LabelService,
+        protocol_information::ProtocolInformationService,
+        stateless_programmable_switch::StatelessProgrammableSwitchService,
+        HapService,
+    },
+    Error,
+    HapType,
+    Result,
+};
+
+/// Programmable Switch Accessory, i.e. a device with one or more stateless buttons that can be mapped to scenes.
+///
+/// Button presses are notified to controllers with
+/// [`Server::trigger_programmable_switch_event`](crate::server::Server::trigger_programmable_switch_event).
+#[derive(Debug, Default)]
+pub struct ProgrammableSwitchAccessory {
+    /// ID of the Programmable Switch Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformationService,
+    /// Label Service of devices with multiple buttons, announcing that the buttons are labeled with arabic numerals.
+    pub label: Option<LabelService>,
+    /// Stateless Programmable Switch Services, one per button.
+    pub buttons: Vec<StatelessProgrammableSwitchService>,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
+}
+
+impl ProgrammableSwitchAccessory {
+    /// Creates a new Programmable Switch Accessory with the given number of buttons.
+    ///
+    /// With more than one button, the buttons get the Service Label Indexes `1` to `buttons` and a Label Service is
+    /// added, so iOS can tell them apart. Fails with [`Error::NoServices`](crate::Error::NoServices) if `buttons` is
+    /// `0`.
+    pub fn new(id: u64, information: AccessoryInformation, buttons: u8) -> Result<Self> {
+        if buttons == 0 {
+            return Err(Error::NoServices("Stateless Programmable Switch"));
+        }
+
+        let accessory_information = information.to_service(1, id)?;
+
+        let mut next_id = 2 + accessory_information.get_characteristics().len() as u64;
+
+        let label = if buttons > 1 {
+            let mut label = LabelService::new(next_id, id);
+            executor::block_on(label.label_namespace.set_value(1.into()))?; // 1 is ARABIC_NUMERALS
+            next_id = 3 + label.get_id() + label.get_characteristics().len() as u64;
+
+            Some(label)
+        } else {
+            None
+        };
+
+        let mut services = Vec::new();
+        for index in 1..=buttons {
+            let mut button = StatelessProgrammableSwitchService::new(next_id, id);
+            button.set_primary(index == 1);
+            if buttons > 1 {
+                if let Some(label_index) = &mut button.label_index {
+                    executor::block_on(label_index.set_value(index.into()))?;
+                }
+            } else {
+                button.label_index = None;
+            }
+            next_id = 3 + button.get_id() + button.get_characteristics().len() as u64;
+
+            services.push(button);
+        }
+
+        let mut numbered_services: Vec<&dyn HapService> = vec![&accessory_information];
+        if let Some(l) = &label {
+            numbered_services.push(l);
+        }
+        for button in &services {
+            numbered_services.push(button);
+        }
+        let protocol_information = protocol_information_service(&numbered_services, id)?;
+
+        Ok(Self {
+            id,
+            accessory_information,
+            label,
+            buttons: services,
+            protocol_information,
+        })
+    }
+}
+
+impl HapAccessory for ProgrammableSwitchAccessory {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_service(&self, hap_type: HapType) -> Option<&dyn HapService> {
+        for service in self.get_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_mut_service(&mut self, hap_type: HapType) -> Option<&mut dyn HapService> {
+        for service in self.get_mut_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        let mut services: Vec<&dyn HapService> = vec![&self.accessory_information];
+        if let Some(l) = &self.label {
+            services.push(l);
+        }
+        for button in &self.buttons {
+            services.push(button);
+        }
+        services.push(&self.protocol_information);
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
+        let mut services: Vec<&mut dyn HapService> = vec![&mut self.accessory_information];
+        if let Some(l) = &mut self.label {
+            services.push(l);
+        }
+        for button in &mut self.buttons {
+            services.push(button);
+        }
+        services.push(&mut self.protocol_information);
+        services
+    }
+}
+
+impl Serialize for ProgrammableSwitchAccessory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapAccessory", 2)?;
+        state.serialize_field("aid", &self.get_id())?;
+        state.serialize_field("services", &self.get_services())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accessory::assert_unique_ids;
+
+    #[test]
+    fn test_multiple_buttons_are_labeled() {
+        let mut switch = ProgrammableSwitchAccessory::new(1, AccessoryInformation::default(), 3).unwrap();
+
+        assert!(switch.label.is_some());
+        assert_eq!(switch.buttons.len(), 3);
+
+        assert_unique_ids(&switch);
+
+        for (index, button) in switch.buttons.iter_mut().enumerate() {
+            let label_index = button.label_index.as_mut().unwrap();
+            assert_eq!(
+                executor::block_on(label_index.get_value()).unwrap(),
+                serde_json::json!(index + 1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_button_is_unlabeled() {
+        let switch = ProgrammableSwitchAccessory::new(1, AccessoryInformation::default(), 1).unwrap();
+
+        assert!(switch.label.is_none());
+        assert!(switch.buttons[0].label_index.is_none());
+    }
+
+    #[test]
+    fn test_buttons_are_required() {
+        assert!(matches!(
+            ProgrammableSwitchAccessory::new(1, AccessoryInformation::default(), 0),
+            Err(Error::NoServices(_))
+        ));
+    }
+}
@@ -1,9 +1,13 @@
+use futures::executor;
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-    accessory::{AccessoryInformation, HapAccessory},
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    characteristic::{HapCharacteristic, InputSourceType},
     service::{
         accessory_information::AccessoryInformationService,
+        input_source::InputSourceService,
+        protocol_information::ProtocolInformationService,
         speaker::SpeakerService,
         television::TelevisionService,
         HapService,
@@ -24,11 +28,28 @@ pub struct TelevisionAccessory {
     pub television: TelevisionService,
     /// Speaker Service.
     pub speaker: SpeakerService,
+    /// Input Source Services, linked to the Television Service.
+    pub input_sources: Vec<InputSourceService>,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl TelevisionAccessory {
-    /// Creates a new Television Accessory.
+    /// Creates a new Television Accessory without input sources.
     pub fn new(id: u64, information: AccessoryInformation) -> Result<Self> {
+        Self::with_input_sources(id, information, &[])
+    }
+
+    /// Creates a new Television Accessory with the given input sources, each given by its name and
+    /// [`InputSourceType`](InputSourceType).
+    ///
+    /// The input sources get the Identifiers `1` to `input_sources.len()`, are configured and shown, and are linked to
+    /// the Television Service. The Active Identifier of the Television Service is set to the first input source.
+    pub fn with_input_sources(
+        id: u64,
+        information: AccessoryInformation,
+        input_sources: &[(&str, InputSourceType)],
+    ) -> Result<Self> {
         let accessory_information = information.to_service(1, id)?;
 
         let television_id = 2 + accessory_information.get_characteristics().len() as u64;
@@ -41,11 +62,49 @@ impl TelevisionAccessory {
         let speaker_id = 3 + television_id + television.get_characteristics().len() as u64;
         let speaker = SpeakerService::new(speaker_id, id);
 
+        let mut next_id = 3 + speaker_id + speaker.get_characteristics().len() as u64;
+
+        let mut services = Vec::new();
+        for (index, &(name, input_source_type)) in input_sources.iter().enumerate() {
+            let mut input_source = InputSourceService::new(next_id, id);
+            if let Some(identifier) = &mut input_source.identifier {
+                executor::block_on(identifier.set_value((index as u32 + 1).into()))?;
+            }
+            executor::block_on(input_source.configured_name.set_value(name.into()))?;
+            executor::block_on(input_source.name.set_value(name.into()))?;
+            executor::block_on(
+                input_source
+                    .input_source_type
+                    .set_value((input_source_type as u8).into()),
+            )?;
+            executor::block_on(input_source.is_configured.set_value(1.into()))?; // 1 is CONFIGURED
+            executor::block_on(input_source.current_visibility_state.set_value(0.into()))?; // 0 is SHOWN
+            if let Some(target_visibility_state) = &mut input_source.target_visibility_state {
+                executor::block_on(target_visibility_state.set_value(0.into()))?;
+            }
+            next_id = 3 + input_source.get_id() + input_source.get_characteristics().len() as u64;
+
+            services.push(input_source);
+        }
+
+        if !services.is_empty() {
+            executor::block_on(television.active_identifier.set_value(1.into()))?;
+        }
+        television.set_linked_services(services.iter().map(|input_source| input_source.get_id()).collect());
+
+        let mut numbered_services: Vec<&dyn HapService> = vec![&accessory_information, &television, &speaker];
+        for input_source in &services {
+            numbered_services.push(input_source);
+        }
+        let protocol_information = protocol_information_service(&numbered_services, id)?;
+
         Ok(Self {
             id,
             accessory_information,
             television,
             speaker,
+            input_sources: services,
+            protocol_information,
         })
     }
 }
@@ -74,11 +133,22 @@ impl HapAccessory for TelevisionAccessory {
     }
 
     fn get_services(&self) -> Vec<&dyn HapService> {
-        vec![&self.accessory_information, &self.television, &self.speaker]
+        let mut services: Vec<&dyn HapService> = vec![&self.accessory_information, &self.television, &self.speaker];
+        for input_source in &self.input_sources {
+            services.push(input_source);
+        }
+        services.push(&self.protocol_information);
+        services
     }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
-        vec![&mut self.accessory_information, &mut self.television, &mut self.speaker]
+        let mut services: Vec<&mut dyn HapService> =
+            vec![&mut self.accessory_information, &mut self.television, &mut self.speaker];
+        for input_source in &mut self.input_sources {
+            services.push(input_source);
+        }
+        services.push(&mut self.protocol_information);
+        services
     }
 }
 
@@ -90,3 +160,49 @@ impl Serialize for TelevisionAccessory {
         state.end()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::accessory::assert_unique_ids;
+
+    #[test]
+    fn test_input_sources_are_linked() {
+        let mut television = TelevisionAccessory::with_input_sources(1, AccessoryInformation::default(), &[
+            ("HDMI 1", InputSourceType::Hdmi),
+            ("Netflix", InputSourceType::Application),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            television.television.get_linked_services(),
+            television
+                .input_sources
+                .iter()
+                .map(|input_source| input_source.get_id())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            executor::block_on(television.television.active_identifier.get_value()).unwrap(),
+            json!(1)
+        );
+
+        assert_unique_ids(&television);
+
+        let netflix = &mut television.input_sources[1];
+        assert_eq!(
+            executor::block_on(netflix.identifier.as_mut().unwrap().get_value()).unwrap(),
+            json!(2)
+        );
+        assert_eq!(
+            executor::block_on(netflix.configured_name.get_value()).unwrap(),
+            json!("Netflix")
+        );
+        assert_eq!(
+            executor::block_on(netflix.input_source_type.get_value()).unwrap(),
+            json!(InputSourceType::Application as u8)
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use futures::executor;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{
+    accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+    characteristic::{HapCharacteristic, ValveType},
+    service::{
+        accessory_information::AccessoryInformationService,
+        protocol_information::ProtocolInformationService,
+        valve::ValveService,
+        HapService,
+    },
+    HapType,
+    Result,
+};
+
+/// Valve Accessory.
+#[derive(Debug, Default)]
+pub struct ValveAccessory {
+    /// ID of the Valve Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformationService,
+    /// Valve Service.
+    pub valve: ValveService,
+    /// Protocol Information Service.
+    pub protocol_information: ProtocolInformationService,
+}
+
+impl ValveAccessory {
+    /// Creates a new Valve Accessory of the given [`ValveType`](ValveType).
+    ///
+    /// The optional Set Duration, Remaining Duration and Is Configured characteristics are included, so the Home app
+    /// offers a timer for the valve.
+    pub fn new(id: u64, information: AccessoryInformation, valve_type: ValveType) -> Result<Self> {
+        let accessory_information = information.to_service(1, id)?;
+
+        let valve_id = 2 + accessory_information.get_characteristics().len() as u64;
+        let mut valve = ValveService::new(valve_id, id);
+        valve.set_primary(true);
+        // a single valve isn't told apart from others
+        valve.label_index = None;
+        executor::block_on(valve.valve_type.set_value((valve_type as u8).into()))?;
+
+        let protocol_information = protocol_information_service(&[&accessory_information, &valve], id)?;
+
+        Ok(Self {
+            id,
+            accessory_information,
+            valve,
+            protocol_information,
+        })
+    }
+}
+
+impl HapAccessory for ValveAccessory {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_service(&self, hap_type: HapType) -> Option<&dyn HapService> {
+        for service in self.get_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_mut_service(&mut self, hap_type: HapType) -> Option<&mut dyn HapService> {
+        for service in self.get_mut_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_services(&self) -> Vec<&dyn HapService> {
+        vec![&self.accessory_information, &self.valve, &self.protocol_information]
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
+        vec![
+            &mut self.accessory_information,
+            &mut self.valve,
+            &mut self.protocol_information,
+        ]
+    }
+}
+
+impl Serialize for ValveAccessory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapAccessory", 2)?;
+        state.serialize_field("aid", &self.get_id())?;
+        state.serialize_field("services", &self.get_services())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valve_type() {
+        let mut valve = ValveAccessory::new(1, AccessoryInformation::default(), ValveType::WaterFaucet).unwrap();
+
+        assert_eq!(
+            executor::block_on(valve.valve.valve_type.get_value()).unwrap(),
+            serde_json::json!(3)
+        );
+        assert!(valve.valve.set_duration.is_some());
+        assert!(valve.valve.remaining_duration.is_some());
+        assert!(valve.valve.is_configured.is_some());
+        assert!(valve.valve.label_index.is_none());
+    }
+}
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, air_quality_sensor::AirQualitySensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, air_quality_sensor::AirQualitySensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct AirQualitySensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Air Quality Sensor service.
     pub air_quality_sensor: AirQualitySensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl AirQualitySensorAccessory {
@@ -28,11 +30,13 @@ impl AirQualitySensorAccessory {
         let air_quality_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut air_quality_sensor = AirQualitySensorService::new(1 + air_quality_sensor_id + 1, id);
         air_quality_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &air_quality_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             air_quality_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for AirQualitySensorAccessory {
         vec![
             &self.accessory_information,
             &self.air_quality_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for AirQualitySensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.air_quality_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
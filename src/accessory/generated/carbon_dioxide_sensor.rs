@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, carbon_dioxide_sensor::CarbonDioxideSensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, carbon_dioxide_sensor::CarbonDioxideSensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct CarbonDioxideSensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Carbon dioxide Sensor service.
     pub carbon_dioxide_sensor: CarbonDioxideSensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl CarbonDioxideSensorAccessory {
@@ -28,11 +30,13 @@ impl CarbonDioxideSensorAccessory {
         let carbon_dioxide_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut carbon_dioxide_sensor = CarbonDioxideSensorService::new(1 + carbon_dioxide_sensor_id + 1, id);
         carbon_dioxide_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &carbon_dioxide_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             carbon_dioxide_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for CarbonDioxideSensorAccessory {
         vec![
             &self.accessory_information,
             &self.carbon_dioxide_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for CarbonDioxideSensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.carbon_dioxide_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
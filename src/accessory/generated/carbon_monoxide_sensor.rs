@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, carbon_monoxide_sensor::CarbonMonoxideSensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, carbon_monoxide_sensor::CarbonMonoxideSensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct CarbonMonoxideSensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Carbon monoxide Sensor service.
     pub carbon_monoxide_sensor: CarbonMonoxideSensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl CarbonMonoxideSensorAccessory {
@@ -28,11 +30,13 @@ impl CarbonMonoxideSensorAccessory {
         let carbon_monoxide_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut carbon_monoxide_sensor = CarbonMonoxideSensorService::new(1 + carbon_monoxide_sensor_id + 1, id);
         carbon_monoxide_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &carbon_monoxide_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             carbon_monoxide_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for CarbonMonoxideSensorAccessory {
         vec![
             &self.accessory_information,
             &self.carbon_monoxide_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for CarbonMonoxideSensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.carbon_monoxide_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
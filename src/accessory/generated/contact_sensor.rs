@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, contact_sensor::ContactSensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, contact_sensor::ContactSensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct ContactSensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Contact Sensor service.
     pub contact_sensor: ContactSensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl ContactSensorAccessory {
@@ -28,11 +30,13 @@ impl ContactSensorAccessory {
         let contact_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut contact_sensor = ContactSensorService::new(1 + contact_sensor_id + 1, id);
         contact_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &contact_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             contact_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for ContactSensorAccessory {
         vec![
             &self.accessory_information,
             &self.contact_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for ContactSensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.contact_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, door::DoorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, door::DoorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct DoorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Door service.
     pub door: DoorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl DoorAccessory {
@@ -28,11 +30,13 @@ impl DoorAccessory {
         let door_id = accessory_information.get_characteristics().len() as u64;
         let mut door = DoorService::new(1 + door_id + 1, id);
         door.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &door], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             door,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for DoorAccessory {
         vec![
             &self.accessory_information,
             &self.door,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for DoorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.door,
+            &mut self.protocol_information,
         ]
     }
 }
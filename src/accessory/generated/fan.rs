@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, fan::FanService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, fan::FanService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct FanAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Fan service.
     pub fan: FanService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl FanAccessory {
@@ -28,11 +30,13 @@ impl FanAccessory {
         let fan_id = accessory_information.get_characteristics().len() as u64;
         let mut fan = FanService::new(1 + fan_id + 1, id);
         fan.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &fan], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             fan,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for FanAccessory {
         vec![
             &self.accessory_information,
             &self.fan,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for FanAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.fan,
+            &mut self.protocol_information,
         ]
     }
 }
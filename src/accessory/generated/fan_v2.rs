@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, fan_v2::FanV2Service},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, fan_v2::FanV2Service},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct FanV2Accessory {
     pub accessory_information: AccessoryInformationService,
     /// Fan v2 service.
     pub fan_v2: FanV2Service,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl FanV2Accessory {
@@ -28,11 +30,13 @@ impl FanV2Accessory {
         let fan_v2_id = accessory_information.get_characteristics().len() as u64;
         let mut fan_v2 = FanV2Service::new(1 + fan_v2_id + 1, id);
         fan_v2.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &fan_v2], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             fan_v2,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for FanV2Accessory {
         vec![
             &self.accessory_information,
             &self.fan_v2,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for FanV2Accessory {
         vec![
             &mut self.accessory_information,
             &mut self.fan_v2,
+            &mut self.protocol_information,
         ]
     }
 }
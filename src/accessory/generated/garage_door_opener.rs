@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, garage_door_opener::GarageDoorOpenerService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, garage_door_opener::GarageDoorOpenerService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct GarageDoorOpenerAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Garage Door Opener service.
     pub garage_door_opener: GarageDoorOpenerService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl GarageDoorOpenerAccessory {
@@ -28,11 +30,13 @@ impl GarageDoorOpenerAccessory {
         let garage_door_opener_id = accessory_information.get_characteristics().len() as u64;
         let mut garage_door_opener = GarageDoorOpenerService::new(1 + garage_door_opener_id + 1, id);
         garage_door_opener.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &garage_door_opener], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             garage_door_opener,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for GarageDoorOpenerAccessory {
         vec![
             &self.accessory_information,
             &self.garage_door_opener,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for GarageDoorOpenerAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.garage_door_opener,
+            &mut self.protocol_information,
         ]
     }
 }
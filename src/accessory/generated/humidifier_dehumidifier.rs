@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, humidifier_dehumidifier::HumidifierDehumidifierService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, humidifier_dehumidifier::HumidifierDehumidifierService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct HumidifierDehumidifierAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Humidifier-Dehumidifier service.
     pub humidifier_dehumidifier: HumidifierDehumidifierService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl HumidifierDehumidifierAccessory {
@@ -28,11 +30,13 @@ impl HumidifierDehumidifierAccessory {
         let humidifier_dehumidifier_id = accessory_information.get_characteristics().len() as u64;
         let mut humidifier_dehumidifier = HumidifierDehumidifierService::new(1 + humidifier_dehumidifier_id + 1, id);
         humidifier_dehumidifier.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &humidifier_dehumidifier], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             humidifier_dehumidifier,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for HumidifierDehumidifierAccessory {
         vec![
             &self.accessory_information,
             &self.humidifier_dehumidifier,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for HumidifierDehumidifierAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.humidifier_dehumidifier,
+            &mut self.protocol_information,
         ]
     }
 }
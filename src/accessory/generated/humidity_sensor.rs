@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, humidity_sensor::HumiditySensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, humidity_sensor::HumiditySensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct HumiditySensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Humidity Sensor service.
     pub humidity_sensor: HumiditySensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl HumiditySensorAccessory {
@@ -28,11 +30,13 @@ impl HumiditySensorAccessory {
         let humidity_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut humidity_sensor = HumiditySensorService::new(1 + humidity_sensor_id + 1, id);
         humidity_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &humidity_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             humidity_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for HumiditySensorAccessory {
         vec![
             &self.accessory_information,
             &self.humidity_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for HumiditySensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.humidity_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, leak_sensor::LeakSensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, leak_sensor::LeakSensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct LeakSensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Leak Sensor service.
     pub leak_sensor: LeakSensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl LeakSensorAccessory {
@@ -28,11 +30,13 @@ impl LeakSensorAccessory {
         let leak_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut leak_sensor = LeakSensorService::new(1 + leak_sensor_id + 1, id);
         leak_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &leak_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             leak_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for LeakSensorAccessory {
         vec![
             &self.accessory_information,
             &self.leak_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for LeakSensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.leak_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, light_sensor::LightSensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, light_sensor::LightSensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct LightSensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Light Sensor service.
     pub light_sensor: LightSensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl LightSensorAccessory {
@@ -28,11 +30,13 @@ impl LightSensorAccessory {
         let light_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut light_sensor = LightSensorService::new(1 + light_sensor_id + 1, id);
         light_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &light_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             light_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for LightSensorAccessory {
         vec![
             &self.accessory_information,
             &self.light_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for LightSensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.light_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
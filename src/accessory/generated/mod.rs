@@ -1,7 +1,5 @@
 // this file is auto-generated by hap-codegen
 
-/// Air Purifier accessory definition.
-pub mod air_purifier;
 /// Air Quality Sensor accessory definition.
 pub mod air_quality_sensor;
 /// Carbon dioxide Sensor accessory definition.
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, motion_sensor::MotionSensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, motion_sensor::MotionSensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct MotionSensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Motion Sensor service.
     pub motion_sensor: MotionSensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl MotionSensorAccessory {
@@ -28,11 +30,13 @@ impl MotionSensorAccessory {
         let motion_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut motion_sensor = MotionSensorService::new(1 + motion_sensor_id + 1, id);
         motion_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &motion_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             motion_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for MotionSensorAccessory {
         vec![
             &self.accessory_information,
             &self.motion_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for MotionSensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.motion_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, occupancy_sensor::OccupancySensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, occupancy_sensor::OccupancySensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct OccupancySensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Occupancy Sensor service.
     pub occupancy_sensor: OccupancySensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl OccupancySensorAccessory {
@@ -28,11 +30,13 @@ impl OccupancySensorAccessory {
         let occupancy_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut occupancy_sensor = OccupancySensorService::new(1 + occupancy_sensor_id + 1, id);
         occupancy_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &occupancy_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             occupancy_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for OccupancySensorAccessory {
         vec![
             &self.accessory_information,
             &self.occupancy_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for OccupancySensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.occupancy_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
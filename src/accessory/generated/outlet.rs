@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, outlet::OutletService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, outlet::OutletService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct OutletAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Outlet service.
     pub outlet: OutletService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl OutletAccessory {
@@ -28,11 +30,13 @@ impl OutletAccessory {
         let outlet_id = accessory_information.get_characteristics().len() as u64;
         let mut outlet = OutletService::new(1 + outlet_id + 1, id);
         outlet.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &outlet], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             outlet,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for OutletAccessory {
         vec![
             &self.accessory_information,
             &self.outlet,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for OutletAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.outlet,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, security_system::SecuritySystemService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, security_system::SecuritySystemService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct SecuritySystemAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Security System service.
     pub security_system: SecuritySystemService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl SecuritySystemAccessory {
@@ -28,11 +30,13 @@ impl SecuritySystemAccessory {
         let security_system_id = accessory_information.get_characteristics().len() as u64;
         let mut security_system = SecuritySystemService::new(1 + security_system_id + 1, id);
         security_system.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &security_system], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             security_system,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for SecuritySystemAccessory {
         vec![
             &self.accessory_information,
             &self.security_system,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for SecuritySystemAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.security_system,
+            &mut self.protocol_information,
         ]
     }
 }
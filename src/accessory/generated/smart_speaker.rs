@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, smart_speaker::SmartSpeakerService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, smart_speaker::SmartSpeakerService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct SmartSpeakerAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Smart Speaker service.
     pub smart_speaker: SmartSpeakerService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl SmartSpeakerAccessory {
@@ -28,11 +30,13 @@ impl SmartSpeakerAccessory {
         let smart_speaker_id = accessory_information.get_characteristics().len() as u64;
         let mut smart_speaker = SmartSpeakerService::new(1 + smart_speaker_id + 1, id);
         smart_speaker.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &smart_speaker], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             smart_speaker,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for SmartSpeakerAccessory {
         vec![
             &self.accessory_information,
             &self.smart_speaker,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for SmartSpeakerAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.smart_speaker,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, smoke_sensor::SmokeSensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, smoke_sensor::SmokeSensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct SmokeSensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Smoke Sensor service.
     pub smoke_sensor: SmokeSensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl SmokeSensorAccessory {
@@ -28,11 +30,13 @@ impl SmokeSensorAccessory {
         let smoke_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut smoke_sensor = SmokeSensorService::new(1 + smoke_sensor_id + 1, id);
         smoke_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &smoke_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             smoke_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for SmokeSensorAccessory {
         vec![
             &self.accessory_information,
             &self.smoke_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for SmokeSensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.smoke_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, stateful_programmable_switch::StatefulProgrammableSwitchService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, stateful_programmable_switch::StatefulProgrammableSwitchService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct StatefulProgrammableSwitchAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Stateful Programmable Switch service.
     pub stateful_programmable_switch: StatefulProgrammableSwitchService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl StatefulProgrammableSwitchAccessory {
@@ -28,11 +30,13 @@ impl StatefulProgrammableSwitchAccessory {
         let stateful_programmable_switch_id = accessory_information.get_characteristics().len() as u64;
         let mut stateful_programmable_switch = StatefulProgrammableSwitchService::new(1 + stateful_programmable_switch_id + 1, id);
         stateful_programmable_switch.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &stateful_programmable_switch], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             stateful_programmable_switch,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for StatefulProgrammableSwitchAccessory {
         vec![
             &self.accessory_information,
             &self.stateful_programmable_switch,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for StatefulProgrammableSwitchAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.stateful_programmable_switch,
+            &mut self.protocol_information,
         ]
     }
 }
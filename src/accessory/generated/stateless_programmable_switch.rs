@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, stateless_programmable_switch::StatelessProgrammableSwitchService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, stateless_programmable_switch::StatelessProgrammableSwitchService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct StatelessProgrammableSwitchAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Stateless Programmable Switch service.
     pub stateless_programmable_switch: StatelessProgrammableSwitchService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl StatelessProgrammableSwitchAccessory {
@@ -28,11 +30,13 @@ impl StatelessProgrammableSwitchAccessory {
         let stateless_programmable_switch_id = accessory_information.get_characteristics().len() as u64;
         let mut stateless_programmable_switch = StatelessProgrammableSwitchService::new(1 + stateless_programmable_switch_id + 1, id);
         stateless_programmable_switch.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &stateless_programmable_switch], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             stateless_programmable_switch,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for StatelessProgrammableSwitchAccessory {
         vec![
             &self.accessory_information,
             &self.stateless_programmable_switch,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for StatelessProgrammableSwitchAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.stateless_programmable_switch,
+            &mut self.protocol_information,
         ]
     }
 }
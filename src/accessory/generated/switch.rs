@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, switch::SwitchService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, switch::SwitchService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct SwitchAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Switch service.
     pub switch: SwitchService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl SwitchAccessory {
@@ -28,11 +30,13 @@ impl SwitchAccessory {
         let switch_id = accessory_information.get_characteristics().len() as u64;
         let mut switch = SwitchService::new(1 + switch_id + 1, id);
         switch.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &switch], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             switch,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for SwitchAccessory {
         vec![
             &self.accessory_information,
             &self.switch,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for SwitchAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.switch,
+            &mut self.protocol_information,
         ]
     }
 }
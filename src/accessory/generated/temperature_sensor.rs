@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, temperature_sensor::TemperatureSensorService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, temperature_sensor::TemperatureSensorService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct TemperatureSensorAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Temperature Sensor service.
     pub temperature_sensor: TemperatureSensorService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl TemperatureSensorAccessory {
@@ -28,11 +30,13 @@ impl TemperatureSensorAccessory {
         let temperature_sensor_id = accessory_information.get_characteristics().len() as u64;
         let mut temperature_sensor = TemperatureSensorService::new(1 + temperature_sensor_id + 1, id);
         temperature_sensor.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &temperature_sensor], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             temperature_sensor,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for TemperatureSensorAccessory {
         vec![
             &self.accessory_information,
             &self.temperature_sensor,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for TemperatureSensorAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.temperature_sensor,
+            &mut self.protocol_information,
         ]
     }
 }
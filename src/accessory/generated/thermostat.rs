@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, thermostat::ThermostatService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, thermostat::ThermostatService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct ThermostatAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Thermostat service.
     pub thermostat: ThermostatService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl ThermostatAccessory {
@@ -28,11 +30,13 @@ impl ThermostatAccessory {
         let thermostat_id = accessory_information.get_characteristics().len() as u64;
         let mut thermostat = ThermostatService::new(1 + thermostat_id + 1, id);
         thermostat.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &thermostat], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             thermostat,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for ThermostatAccessory {
         vec![
             &self.accessory_information,
             &self.thermostat,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for ThermostatAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.thermostat,
+            &mut self.protocol_information,
         ]
     }
 }
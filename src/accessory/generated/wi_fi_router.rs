@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, wi_fi_router::WiFiRouterService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, wi_fi_router::WiFiRouterService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct WiFiRouterAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Wi-Fi Router service.
     pub wi_fi_router: WiFiRouterService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl WiFiRouterAccessory {
@@ -28,11 +30,13 @@ impl WiFiRouterAccessory {
         let wi_fi_router_id = accessory_information.get_characteristics().len() as u64;
         let mut wi_fi_router = WiFiRouterService::new(1 + wi_fi_router_id + 1, id);
         wi_fi_router.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &wi_fi_router], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             wi_fi_router,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for WiFiRouterAccessory {
         vec![
             &self.accessory_information,
             &self.wi_fi_router,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for WiFiRouterAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.wi_fi_router,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, wi_fi_satellite::WiFiSatelliteService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, wi_fi_satellite::WiFiSatelliteService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct WiFiSatelliteAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Wi-Fi Satellite service.
     pub wi_fi_satellite: WiFiSatelliteService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl WiFiSatelliteAccessory {
@@ -28,11 +30,13 @@ impl WiFiSatelliteAccessory {
         let wi_fi_satellite_id = accessory_information.get_characteristics().len() as u64;
         let mut wi_fi_satellite = WiFiSatelliteService::new(1 + wi_fi_satellite_id + 1, id);
         wi_fi_satellite.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &wi_fi_satellite], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             wi_fi_satellite,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for WiFiSatelliteAccessory {
         vec![
             &self.accessory_information,
             &self.wi_fi_satellite,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for WiFiSatelliteAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.wi_fi_satellite,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, window::WindowService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, window::WindowService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct WindowAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Window service.
     pub window: WindowService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl WindowAccessory {
@@ -28,11 +30,13 @@ impl WindowAccessory {
         let window_id = accessory_information.get_characteristics().len() as u64;
         let mut window = WindowService::new(1 + window_id + 1, id);
         window.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &window], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             window,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for WindowAccessory {
         vec![
             &self.accessory_information,
             &self.window,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for WindowAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.window,
+            &mut self.protocol_information,
         ]
     }
 }
@@ -3,8 +3,8 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-	accessory::{AccessoryInformation, HapAccessory},
-	service::{HapService, accessory_information::AccessoryInformationService, window_covering::WindowCoveringService},
+	accessory::{protocol_information_service, AccessoryInformation, HapAccessory},
+	service::{HapService, accessory_information::AccessoryInformationService, protocol_information::ProtocolInformationService, window_covering::WindowCoveringService},
 	HapType,
 	Result,
 };
@@ -19,6 +19,8 @@ pub struct WindowCoveringAccessory {
     pub accessory_information: AccessoryInformationService,
     /// Window Covering service.
     pub window_covering: WindowCoveringService,
+    /// Protocol Information service.
+    pub protocol_information: ProtocolInformationService,
 }
 
 impl WindowCoveringAccessory {
@@ -28,11 +30,13 @@ impl WindowCoveringAccessory {
         let window_covering_id = accessory_information.get_characteristics().len() as u64;
         let mut window_covering = WindowCoveringService::new(1 + window_covering_id + 1, id);
         window_covering.set_primary(true);
+        let protocol_information = protocol_information_service(&[&accessory_information, &window_covering], id)?;
 
         Ok(Self {
             id,
             accessory_information,
             window_covering,
+            protocol_information,
         })
     }
 }
@@ -68,6 +72,7 @@ impl HapAccessory for WindowCoveringAccessory {
         vec![
             &self.accessory_information,
             &self.window_covering,
+            &self.protocol_information,
         ]
     }
 
@@ -75,6 +80,7 @@ impl HapAccessory for WindowCoveringAccessory {
         vec![
             &mut self.accessory_information,
             &mut self.window_covering,
+            &mut self.protocol_information,
         ]
     }
 }
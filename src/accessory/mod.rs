@@ -14,16 +14,33 @@ use crate::{
         HapCharacteristic,
     },
     pointer,
-    service::{accessory_information::AccessoryInformationService, HapService},
+    service::{
+        accessory_information::AccessoryInformationService,
+        protocol_information::ProtocolInformationService,
+        HapService,
+    },
+    Error,
     HapType,
     Result,
 };
 
+mod battery;
+mod builder;
 mod category;
 mod defined;
 mod generated;
 
-pub use crate::accessory::{category::AccessoryCategory, defined::*, generated::*};
+pub use crate::accessory::{
+    battery::BatteryPowered,
+    builder::{AccessoryBuilder, CustomAccessory},
+    category::AccessoryCategory,
+    defined::*,
+    generated::*,
+};
+
+/// Version of the HAP protocol implemented by the crate, reported by the Version characteristic of the Protocol
+/// Information Service the predefined accessories carry.
+pub const HAP_PROTOCOL_VERSION: &str = "1.1.0";
 
 /// [`HapAccessory`](HapAccessory) is implemented by every HAP accessory.
 pub trait HapAccessory: HapAccessorySetup + erased_serde::Serialize + Send + Sync {
@@ -92,11 +109,14 @@ pub struct AccessoryInformation {
     /// Describes the name of the accessory.
     pub name: String,
     /// Contains the manufacturer-specific serial number of the accessory, e.g. "1A2B3C4D5E6F".
-    /// The length must be greater than 1.
+    /// The length must be greater than 1. Accessories on the same server, e.g. the accessories of a bridge, need
+    /// unique serial numbers, otherwise iOS merges them; see [`Server::validate`](crate::server::Server::validate).
     pub serial_number: String,
     /// When set indicates accessory requires additional setup. Use of accessory flags requires
     /// written approval by Apple in advance.
     pub accessory_flags: Option<u32>,
+    /// Contains the bundle identifiers of the companion iOS apps of the accessory, encoded as TLV8 items, so iOS can
+    /// suggest installing them during setup.
     pub application_matching_identifier: Option<Vec<u8>>,
     pub configured_name: Option<String>,
     /// Describes a firmware revision string x[.y[.z]] (e.g. "100.1.1"):
@@ -129,15 +149,32 @@ pub struct AccessoryInformation {
     ///
     /// The value must change after every hardware update.
     pub hardware_revision: Option<String>,
+    /// Contains the 8 byte product data assigned to the product plan of the accessory by Apple.
     pub product_data: Option<Vec<u8>>,
     pub software_revision: Option<String>,
 }
 
 impl AccessoryInformation {
     /// Converts the `Information` struct to an Accessory Information Service.
+    ///
+    /// The service gets the instance ID `id`, its required characteristics the IDs `id + 1` to `id + 5` and the
+    /// optional characteristics that are set the following IDs in field order.
+    ///
+    /// Fails with [`Error::InvalidRevision`](Error::InvalidRevision) if the firmware or hardware revision isn't an
+    /// `x[.y[.z]]` version string.
     pub fn to_service(self, id: u64, accessory_id: u64) -> Result<AccessoryInformationService> {
+        for revision in [&self.firmware_revision, &self.hardware_revision].into_iter().flatten() {
+            if !is_valid_revision(revision) {
+                return Err(Error::InvalidRevision(revision.clone()));
+            }
+        }
+
         let mut i = AccessoryInformationService::new(id, accessory_id);
 
+        // the optional characteristics are numbered consecutively after the required ones, so the characteristics of
+        // the service occupy the IDs `id + 1` to `id + n`, which the accessories rely on to number the next service
+        let mut next_id = id + 6;
+
         executor::block_on(i.identify.set_value(serde_json::Value::Bool(false)))?;
         executor::block_on(i.manufacturer.set_value(serde_json::Value::String(self.manufacturer)))?;
         executor::block_on(i.model.set_value(serde_json::Value::String(self.model)))?;
@@ -145,7 +182,8 @@ impl AccessoryInformation {
         executor::block_on(i.serial_number.set_value(serde_json::Value::String(self.serial_number)))?;
 
         if let Some(v) = self.accessory_flags {
-            let mut c = AccessoryFlagsCharacteristic::new(id + 6, accessory_id);
+            let mut c = AccessoryFlagsCharacteristic::new(next_id, accessory_id);
+            next_id += 1;
             executor::block_on(c.set_value(v.into()))?;
             i.accessory_flags = Some(c);
         } else {
@@ -153,7 +191,8 @@ impl AccessoryInformation {
         }
 
         if let Some(v) = self.application_matching_identifier {
-            let mut c = ApplicationMatchingIdentifierCharacteristic::new(id + 7, accessory_id);
+            let mut c = ApplicationMatchingIdentifierCharacteristic::new(next_id, accessory_id);
+            next_id += 1;
             executor::block_on(c.set_value(v.into()))?;
             i.application_matching_identifier = Some(c);
         } else {
@@ -161,7 +200,8 @@ impl AccessoryInformation {
         }
 
         if let Some(v) = self.configured_name {
-            let mut c = ConfiguredNameCharacteristic::new(id + 8, accessory_id);
+            let mut c = ConfiguredNameCharacteristic::new(next_id, accessory_id);
+            next_id += 1;
             executor::block_on(c.set_value(v.into()))?;
             i.configured_name = Some(c);
         } else {
@@ -169,7 +209,8 @@ impl AccessoryInformation {
         }
 
         if let Some(v) = self.firmware_revision {
-            let mut c = FirmwareRevisionCharacteristic::new(id + 9, accessory_id);
+            let mut c = FirmwareRevisionCharacteristic::new(next_id, accessory_id);
+            next_id += 1;
             executor::block_on(c.set_value(v.into()))?;
             i.firmware_revision = Some(c);
         } else {
@@ -177,7 +218,8 @@ impl AccessoryInformation {
         }
 
         if let Some(v) = self.hardware_finish {
-            let mut c = HardwareFinishCharacteristic::new(id + 12, accessory_id);
+            let mut c = HardwareFinishCharacteristic::new(next_id, accessory_id);
+            next_id += 1;
             executor::block_on(c.set_value(v.into()))?;
             i.hardware_finish = Some(c);
         } else {
@@ -185,7 +227,8 @@ impl AccessoryInformation {
         }
 
         if let Some(v) = self.hardware_revision {
-            let mut c = HardwareRevisionCharacteristic::new(id + 10, accessory_id);
+            let mut c = HardwareRevisionCharacteristic::new(next_id, accessory_id);
+            next_id += 1;
             executor::block_on(c.set_value(v.into()))?;
             i.hardware_revision = Some(c);
         } else {
@@ -193,7 +236,8 @@ impl AccessoryInformation {
         }
 
         if let Some(v) = self.product_data {
-            let mut c = ProductDataCharacteristic::new(id + 12, accessory_id);
+            let mut c = ProductDataCharacteristic::new(next_id, accessory_id);
+            next_id += 1;
             executor::block_on(c.set_value(v.into()))?;
             i.product_data = Some(c);
         } else {
@@ -201,7 +245,7 @@ impl AccessoryInformation {
         }
 
         if let Some(v) = self.software_revision {
-            let mut c = SoftwareRevisionCharacteristic::new(id + 11, accessory_id);
+            let mut c = SoftwareRevisionCharacteristic::new(next_id, accessory_id);
             executor::block_on(c.set_value(v.into()))?;
             i.software_revision = Some(c);
         } else {
@@ -230,3 +274,166 @@ impl Default for AccessoryInformation {
         }
     }
 }
+
+/// Creates the Protocol Information Service of an accessory, reporting [`HAP_PROTOCOL_VERSION`](HAP_PROTOCOL_VERSION).
+///
+/// The service is numbered after the last service and characteristic of the accessory's other `services`, so adding it
+/// leaves their instance IDs unchanged.
+pub(crate) fn protocol_information_service(
+    services: &[&dyn HapService],
+    accessory_id: u64,
+) -> Result<ProtocolInformationService> {
+    let mut last_id = 0;
+    for service in services {
+        last_id = last_id.max(service.get_id());
+        for characteristic in service.get_characteristics() {
+            last_id = last_id.max(characteristic.get_id());
+        }
+    }
+
+    let mut protocol_information = ProtocolInformationService::new(last_id + 1, accessory_id);
+    executor::block_on(
+        protocol_information
+            .version
+            .set_value(serde_json::Value::String(HAP_PROTOCOL_VERSION.into())),
+    )?;
+
+    Ok(protocol_information)
+}
+
+/// Returns whether a revision string has the `x[.y[.z]]` format required for firmware and hardware revisions.
+pub(crate) fn is_valid_revision(revision: &str) -> bool {
+    let parts: Vec<&str> = revision.split('.').collect();
+    parts.len() <= 3
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Returns whether `revision` is newer than `other`. Missing components count as `0`, so `1.1` isn't newer than
+/// `1.1.0`. Revisions that aren't valid are never newer.
+pub(crate) fn is_newer_revision(revision: &str, other: &str) -> bool {
+    let components = |revision: &str| -> Option<Vec<u64>> {
+        if !is_valid_revision(revision) {
+            return None;
+        }
+        let mut components = revision
+            .split('.')
+            .map(|c| c.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        components.resize(3, 0);
+        Some(components)
+    };
+
+    match (components(revision), components(other)) {
+        (Some(revision), Some(other)) => revision > other,
+        _ => false,
+    }
+}
+
+/// Asserts that no two services or characteristics of an accessory share an instance ID.
+#[cfg(test)]
+pub(crate) fn assert_unique_ids(accessory: &dyn HapAccessory) {
+    let mut ids = Vec::new();
+    for service in accessory.get_services() {
+        ids.push(service.get_id());
+        for characteristic in service.get_characteristics() {
+            ids.push(characteristic.get_id());
+        }
+    }
+    let count = ids.len();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), count, "duplicate instance IDs");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer_revision() {
+        assert!(is_newer_revision("1.1", "1.0"));
+        assert!(is_newer_revision("1.10", "1.9"));
+        assert!(is_newer_revision("2", "1.9.9"));
+        assert!(!is_newer_revision("1.1", "1.1.0"));
+        assert!(!is_newer_revision("1.0", "1.1"));
+        assert!(!is_newer_revision("1.1-beta", "1.0"));
+    }
+
+    #[test]
+    fn test_is_valid_revision() {
+        assert!(is_valid_revision("1"));
+        assert!(is_valid_revision("1.2"));
+        assert!(is_valid_revision("100.1.1"));
+        assert!(!is_valid_revision(""));
+        assert!(!is_valid_revision("1."));
+        assert!(!is_valid_revision("1.2.3.4"));
+        assert!(!is_valid_revision("v1.2"));
+        assert!(!is_valid_revision("1.2-beta"));
+    }
+
+    #[test]
+    fn test_to_service_rejects_invalid_revision() {
+        let information = AccessoryInformation {
+            firmware_revision: Some("1.0-rc1".into()),
+            ..Default::default()
+        };
+
+        assert!(matches!(information.to_service(1, 1), Err(Error::InvalidRevision(_))));
+    }
+
+    #[test]
+    fn test_to_service_numbers_optional_characteristics() {
+        let information = AccessoryInformation {
+            accessory_flags: Some(1),
+            application_matching_identifier: Some(vec![0x01, 0x03, 0x61, 0x62, 0x63]),
+            configured_name: Some("Outlet".into()),
+            firmware_revision: Some("1.0".into()),
+            hardware_finish: Some(vec![0x01, 0x01, 0x00]),
+            hardware_revision: Some("1.0".into()),
+            product_data: Some(vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]),
+            software_revision: Some("1.0".into()),
+            ..Default::default()
+        };
+        let service = information.to_service(1, 1).unwrap();
+
+        let mut ids = service
+            .get_characteristics()
+            .iter()
+            .map(|c| c.get_id())
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        assert_eq!(ids, (2..=14).collect::<Vec<_>>());
+
+        assert_eq!(service.application_matching_identifier.as_ref().unwrap().get_id(), 7);
+        assert_eq!(service.product_data.as_ref().unwrap().get_id(), 13);
+    }
+
+    #[test]
+    fn test_protocol_information_service() {
+        let mut outlet = outlet::OutletAccessory::new(1, AccessoryInformation {
+            firmware_revision: Some("1.0".into()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            executor::block_on(outlet.protocol_information.version.get_value()).unwrap(),
+            serde_json::json!(HAP_PROTOCOL_VERSION)
+        );
+
+        let protocol_information = outlet.get_service(HapType::ProtocolInformation).unwrap();
+        let version = protocol_information.get_characteristic(HapType::Version).unwrap();
+
+        let mut ids = Vec::new();
+        for service in [&outlet.accessory_information as &dyn HapService, &outlet.outlet] {
+            ids.push(service.get_id());
+            for characteristic in service.get_characteristics() {
+                ids.push(characteristic.get_id());
+            }
+        }
+        assert_eq!(protocol_information.get_id(), ids.iter().max().unwrap() + 1);
+        assert_eq!(version.get_id(), protocol_information.get_id() + 1);
+    }
+}
@@ -0,0 +1,65 @@
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use std::{fmt, sync::Arc};
+
+use crate::Result;
+
+pub use stream::*;
+
+mod stream;
+
+/// A [`SnapshotRequest`](SnapshotRequest) is a controller asking for a still image of a camera, e.g. to show it in the
+/// room overview of the Home app.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SnapshotRequest {
+    /// ID of the camera accessory. Controllers may omit it for accessories that aren't bridged.
+    pub aid: Option<u64>,
+    /// Requested width of the image in pixels.
+    #[serde(rename = "image-width")]
+    pub width: u32,
+    /// Requested height of the image in pixels.
+    #[serde(rename = "image-height")]
+    pub height: u32,
+}
+
+/// A [`SnapshotHandler`](SnapshotHandler) takes snapshots of a camera for the `/resource` endpoint.
+///
+/// It's called with the requested resolution and returns the JPEG encoded image. Returning an error responds with a
+/// `500 Internal Server Error`.
+///
+/// # Examples
+/// ```
+/// use hap::{camera::SnapshotHandler, futures::FutureExt, Config};
+///
+/// let config = Config {
+///     snapshot_handler: Some(SnapshotHandler::new(|request| {
+///         async move {
+///             // capture a frame at `request.width` x `request.height` and encode it as JPEG
+///             let jpeg = Vec::new();
+///
+///             Ok(jpeg)
+///         }
+///         .boxed()
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct SnapshotHandler(Arc<dyn Fn(SnapshotRequest) -> BoxFuture<'static, Result<Vec<u8>>> + Send + Sync>);
+
+impl SnapshotHandler {
+    /// Creates a new [`SnapshotHandler`](SnapshotHandler) from a callback.
+    pub fn new<F>(take_snapshot: F) -> SnapshotHandler
+    where
+        F: Fn(SnapshotRequest) -> BoxFuture<'static, Result<Vec<u8>>> + Send + Sync + 'static,
+    {
+        SnapshotHandler(Arc::new(take_snapshot))
+    }
+
+    /// Calls the callback to take a snapshot.
+    pub(crate) async fn take_snapshot(&self, request: SnapshotRequest) -> Result<Vec<u8>> { (self.0)(request).await }
+}
+
+impl fmt::Debug for SnapshotHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("SnapshotHandler") }
+}
@@ -0,0 +1,888 @@
+use async_trait::async_trait;
+use futures::{future::FutureExt, lock::Mutex};
+use log::{debug, error};
+use serde_json::json;
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+use uuid::Uuid;
+
+use crate::{
+    characteristic::{AsyncCharacteristicCallbacks, Format, HapCharacteristic},
+    service::camera_stream_management::CameraStreamManagementService,
+    tlv,
+    Error,
+    Result,
+};
+
+/// H.264 profile of a video stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum H264Profile {
+    ConstrainedBaseline = 0,
+    Main = 1,
+    High = 2,
+}
+
+impl H264Profile {
+    fn from_u8(value: u8) -> Option<H264Profile> {
+        match value {
+            0 => Some(H264Profile::ConstrainedBaseline),
+            1 => Some(H264Profile::Main),
+            2 => Some(H264Profile::High),
+            _ => None,
+        }
+    }
+}
+
+/// H.264 level of a video stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum H264Level {
+    Level3_1 = 0,
+    Level3_2 = 1,
+    Level4 = 2,
+}
+
+impl H264Level {
+    fn from_u8(value: u8) -> Option<H264Level> {
+        match value {
+            0 => Some(H264Level::Level3_1),
+            1 => Some(H264Level::Level3_2),
+            2 => Some(H264Level::Level4),
+            _ => None,
+        }
+    }
+}
+
+/// Resolution and frame rate of a video stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VideoAttributes {
+    /// Width of the image in pixels.
+    pub width: u16,
+    /// Height of the image in pixels.
+    pub height: u16,
+    /// Frames per second.
+    pub frame_rate: u8,
+}
+
+/// Codec of an audio stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AudioCodec {
+    Pcmu = 0,
+    Pcma = 1,
+    AacEld = 2,
+    Opus = 3,
+    Msbc = 4,
+    Amr = 5,
+    AmrWb = 6,
+}
+
+impl AudioCodec {
+    fn from_u8(value: u8) -> Option<AudioCodec> {
+        match value {
+            0 => Some(AudioCodec::Pcmu),
+            1 => Some(AudioCodec::Pcma),
+            2 => Some(AudioCodec::AacEld),
+            3 => Some(AudioCodec::Opus),
+            4 => Some(AudioCodec::Msbc),
+            5 => Some(AudioCodec::Amr),
+            6 => Some(AudioCodec::AmrWb),
+            _ => None,
+        }
+    }
+}
+
+/// Bit rate mode of an audio stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AudioBitRate {
+    Variable = 0,
+    Constant = 1,
+}
+
+impl AudioBitRate {
+    fn from_u8(value: u8) -> Option<AudioBitRate> {
+        match value {
+            0 => Some(AudioBitRate::Variable),
+            1 => Some(AudioBitRate::Constant),
+            _ => None,
+        }
+    }
+}
+
+/// Sample rate of an audio stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AudioSampleRate {
+    Khz8 = 0,
+    Khz16 = 1,
+    Khz24 = 2,
+}
+
+impl AudioSampleRate {
+    fn from_u8(value: u8) -> Option<AudioSampleRate> {
+        match value {
+            0 => Some(AudioSampleRate::Khz8),
+            1 => Some(AudioSampleRate::Khz16),
+            2 => Some(AudioSampleRate::Khz24),
+            _ => None,
+        }
+    }
+}
+
+/// Audio codec supported by a camera.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AudioConfiguration {
+    pub codec: AudioCodec,
+    pub channels: u8,
+    pub bit_rate: AudioBitRate,
+    pub sample_rate: AudioSampleRate,
+}
+
+/// SRTP crypto suite of a stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SrtpCryptoSuite {
+    AesCm128HmacSha1_80 = 0,
+    AesCm256HmacSha1_80 = 1,
+    None = 2,
+}
+
+impl SrtpCryptoSuite {
+    fn from_u8(value: u8) -> Option<SrtpCryptoSuite> {
+        match value {
+            0 => Some(SrtpCryptoSuite::AesCm128HmacSha1_80),
+            1 => Some(SrtpCryptoSuite::AesCm256HmacSha1_80),
+            2 => Some(SrtpCryptoSuite::None),
+            _ => None,
+        }
+    }
+}
+
+/// Streaming capabilities of a camera, announced to controllers via the `Supported Video Stream Configuration`,
+/// `Supported Audio Stream Configuration` and `Supported RTP Configuration` characteristics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamConfiguration {
+    /// Supported H.264 profiles.
+    pub profiles: Vec<H264Profile>,
+    /// Supported H.264 levels.
+    pub levels: Vec<H264Level>,
+    /// Supported resolutions and frame rates.
+    pub resolutions: Vec<VideoAttributes>,
+    /// Supported audio codecs.
+    pub audio: Vec<AudioConfiguration>,
+    /// Whether comfort noise is supported on audio streams.
+    pub comfort_noise: bool,
+    /// Supported SRTP crypto suites.
+    pub srtp_crypto_suites: Vec<SrtpCryptoSuite>,
+}
+
+impl Default for StreamConfiguration {
+    fn default() -> StreamConfiguration {
+        StreamConfiguration {
+            profiles: vec![H264Profile::ConstrainedBaseline, H264Profile::Main, H264Profile::High],
+            levels: vec![H264Level::Level3_1, H264Level::Level3_2, H264Level::Level4],
+            resolutions: vec![
+                VideoAttributes {
+                    width: 1920,
+                    height: 1080,
+                    frame_rate: 30,
+                },
+                VideoAttributes {
+                    width: 1280,
+                    height: 720,
+                    frame_rate: 30,
+                },
+                VideoAttributes {
+                    width: 640,
+                    height: 360,
+                    frame_rate: 30,
+                },
+                VideoAttributes {
+                    width: 320,
+                    height: 240,
+                    frame_rate: 15,
+                },
+            ],
+            audio: vec![AudioConfiguration {
+                codec: AudioCodec::Opus,
+                channels: 1,
+                bit_rate: AudioBitRate::Variable,
+                sample_rate: AudioSampleRate::Khz16,
+            }],
+            comfort_noise: false,
+            srtp_crypto_suites: vec![SrtpCryptoSuite::AesCm128HmacSha1_80],
+        }
+    }
+}
+
+impl StreamConfiguration {
+    fn encode_supported_video_stream_configuration(&self) -> Vec<u8> {
+        let mut codec_parameters = Vec::new();
+        codec_parameters.extend(list(0x01, self.profiles.iter().map(|p| vec![*p as u8]).collect()));
+        codec_parameters.extend(list(0x02, self.levels.iter().map(|l| vec![*l as u8]).collect()));
+        // only the non-interleaved packetization mode is defined
+        codec_parameters.push((0x03, vec![0]));
+
+        let attributes = self.resolutions.iter().map(|r| r.encode()).collect();
+
+        let mut codec_configuration = vec![(0x01, vec![0]), (0x02, tlv::encode(codec_parameters))];
+        codec_configuration.extend(list(0x03, attributes));
+
+        tlv::encode(vec![(0x01, tlv::encode(codec_configuration))])
+    }
+
+    fn encode_supported_audio_stream_configuration(&self) -> Vec<u8> {
+        let codecs = self
+            .audio
+            .iter()
+            .map(|a| {
+                tlv::encode(vec![
+                    (0x01, vec![a.codec as u8]),
+                    (
+                        0x02,
+                        tlv::encode(vec![
+                            (0x01, vec![a.channels]),
+                            (0x02, vec![a.bit_rate as u8]),
+                            (0x03, vec![a.sample_rate as u8]),
+                        ]),
+                    ),
+                ])
+            })
+            .collect();
+
+        let mut configuration = list(0x01, codecs);
+        configuration.push((0x02, vec![self.comfort_noise as u8]));
+
+        tlv::encode(configuration)
+    }
+
+    fn encode_supported_rtp_configuration(&self) -> Vec<u8> {
+        tlv::encode(list(
+            0x02,
+            self.srtp_crypto_suites.iter().map(|s| vec![*s as u8]).collect(),
+        ))
+    }
+}
+
+impl VideoAttributes {
+    fn encode(&self) -> Vec<u8> {
+        tlv::encode(vec![
+            (0x01, self.width.to_le_bytes().to_vec()),
+            (0x02, self.height.to_le_bytes().to_vec()),
+            (0x03, vec![self.frame_rate]),
+        ])
+    }
+
+    fn decode(decoded: &HashMap<u8, Vec<u8>>) -> Result<VideoAttributes> {
+        Ok(VideoAttributes {
+            width: read_u16(decoded, 0x01)?,
+            height: read_u16(decoded, 0x02)?,
+            frame_rate: read_u8(decoded, 0x03)?,
+        })
+    }
+}
+
+/// SRTP key and salt of one direction of a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrtpParameters {
+    pub crypto_suite: SrtpCryptoSuite,
+    pub master_key: Vec<u8>,
+    pub master_salt: Vec<u8>,
+}
+
+impl SrtpParameters {
+    fn encode(&self) -> Vec<u8> {
+        tlv::encode(vec![
+            (0x01, vec![self.crypto_suite as u8]),
+            (0x02, self.master_key.clone()),
+            (0x03, self.master_salt.clone()),
+        ])
+    }
+
+    fn decode(decoded: &HashMap<u8, Vec<u8>>) -> Result<SrtpParameters> {
+        Ok(SrtpParameters {
+            crypto_suite: SrtpCryptoSuite::from_u8(read_u8(decoded, 0x01)?).ok_or(invalid_tlv())?,
+            master_key: decoded.get(&0x02).cloned().unwrap_or_default(),
+            master_salt: decoded.get(&0x03).cloned().unwrap_or_default(),
+        })
+    }
+}
+
+/// A controller setting up the endpoints of a new stream by writing the `Setup Endpoints` characteristic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrepareStreamRequest {
+    /// ID of the stream session.
+    pub session_id: Uuid,
+    /// Address of the controller the stream is sent to.
+    pub controller_address: IpAddr,
+    /// RTP port of the controller for video.
+    pub video_port: u16,
+    /// RTP port of the controller for audio.
+    pub audio_port: u16,
+    /// SRTP parameters of the controller for video.
+    pub video_srtp: SrtpParameters,
+    /// SRTP parameters of the controller for audio.
+    pub audio_srtp: SrtpParameters,
+}
+
+impl PrepareStreamRequest {
+    fn decode(decoded: &HashMap<u8, Vec<u8>>) -> Result<PrepareStreamRequest> {
+        let address = tlv::decode(decoded.get(&0x03).ok_or(invalid_tlv())?);
+
+        Ok(PrepareStreamRequest {
+            session_id: read_session_id(decoded)?,
+            controller_address: read_address(&address)?,
+            video_port: read_u16(&address, 0x03)?,
+            audio_port: read_u16(&address, 0x04)?,
+            video_srtp: SrtpParameters::decode(&tlv::decode(decoded.get(&0x04).ok_or(invalid_tlv())?))?,
+            audio_srtp: SrtpParameters::decode(&tlv::decode(decoded.get(&0x05).ok_or(invalid_tlv())?))?,
+        })
+    }
+}
+
+/// The endpoints of the accessory for a new stream, returned by
+/// [`StreamDelegate::prepare_stream`](StreamDelegate::prepare_stream).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrepareStreamResponse {
+    /// Address of the accessory the stream is sent from.
+    pub address: IpAddr,
+    /// RTP port of the accessory for video.
+    pub video_port: u16,
+    /// RTP port of the accessory for audio.
+    pub audio_port: u16,
+    /// SRTP parameters of the accessory for video.
+    pub video_srtp: SrtpParameters,
+    /// SRTP parameters of the accessory for audio.
+    pub audio_srtp: SrtpParameters,
+    /// Synchronization source of the video stream.
+    pub video_ssrc: u32,
+    /// Synchronization source of the audio stream.
+    pub audio_ssrc: u32,
+}
+
+impl PrepareStreamResponse {
+    fn encode(&self, session_id: Uuid) -> Vec<u8> {
+        let ip_version = if self.address.is_ipv4() { 0 } else { 1 };
+        let address = tlv::encode(vec![
+            (0x01, vec![ip_version]),
+            (0x02, self.address.to_string().into_bytes()),
+            (0x03, self.video_port.to_le_bytes().to_vec()),
+            (0x04, self.audio_port.to_le_bytes().to_vec()),
+        ]);
+
+        tlv::encode(vec![
+            (0x01, session_id.as_bytes().to_vec()),
+            (SETUP_ENDPOINTS_STATUS, vec![SetupEndpointsStatus::Success as u8]),
+            (0x03, address),
+            (0x04, self.video_srtp.encode()),
+            (0x05, self.audio_srtp.encode()),
+            (0x06, self.video_ssrc.to_le_bytes().to_vec()),
+            (0x07, self.audio_ssrc.to_le_bytes().to_vec()),
+        ])
+    }
+}
+
+/// TLV type of the status in the response of the `Setup Endpoints` characteristic.
+const SETUP_ENDPOINTS_STATUS: u8 = 0x02;
+
+#[derive(Debug, Copy, Clone)]
+enum SetupEndpointsStatus {
+    Success = 0,
+    Busy = 1,
+    Error = 2,
+}
+
+/// Command of a [`StreamRequest`](StreamRequest).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SessionCommand {
+    End = 0,
+    Start = 1,
+    Suspend = 2,
+    Resume = 3,
+    Reconfigure = 4,
+}
+
+impl SessionCommand {
+    fn from_u8(value: u8) -> Option<SessionCommand> {
+        match value {
+            0 => Some(SessionCommand::End),
+            1 => Some(SessionCommand::Start),
+            2 => Some(SessionCommand::Suspend),
+            3 => Some(SessionCommand::Resume),
+            4 => Some(SessionCommand::Reconfigure),
+            _ => None,
+        }
+    }
+}
+
+/// RTP parameters selected by the controller for one direction of a stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RtpParameters {
+    pub payload_type: u8,
+    pub ssrc: u32,
+    /// Maximum bit rate in kbit/s.
+    pub max_bit_rate: u16,
+    /// Minimum RTCP interval in seconds.
+    pub min_rtcp_interval: f32,
+    /// Maximum MTU in bytes. Only sent for video.
+    pub max_mtu: Option<u16>,
+    /// Payload type for comfort noise. Only sent for audio.
+    pub comfort_noise_payload_type: Option<u8>,
+}
+
+impl RtpParameters {
+    fn decode(decoded: &HashMap<u8, Vec<u8>>) -> Result<RtpParameters> {
+        Ok(RtpParameters {
+            payload_type: read_u8(decoded, 0x01)?,
+            ssrc: read_u32(decoded, 0x02)?,
+            max_bit_rate: read_u16(decoded, 0x03)?,
+            min_rtcp_interval: read_f32(decoded, 0x04)?,
+            max_mtu: read_u16(decoded, 0x05).ok(),
+            comfort_noise_payload_type: read_u8(decoded, 0x06).ok(),
+        })
+    }
+}
+
+/// Video parameters selected by the controller for a stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectedVideoParameters {
+    pub profile: H264Profile,
+    pub level: H264Level,
+    pub attributes: VideoAttributes,
+    pub rtp: RtpParameters,
+}
+
+impl SelectedVideoParameters {
+    fn decode(decoded: &HashMap<u8, Vec<u8>>) -> Result<SelectedVideoParameters> {
+        let codec_parameters = tlv::decode(decoded.get(&0x02).ok_or(invalid_tlv())?);
+
+        Ok(SelectedVideoParameters {
+            profile: H264Profile::from_u8(read_u8(&codec_parameters, 0x01)?).ok_or(invalid_tlv())?,
+            level: H264Level::from_u8(read_u8(&codec_parameters, 0x02)?).ok_or(invalid_tlv())?,
+            attributes: VideoAttributes::decode(&tlv::decode(decoded.get(&0x03).ok_or(invalid_tlv())?))?,
+            rtp: RtpParameters::decode(&tlv::decode(decoded.get(&0x04).ok_or(invalid_tlv())?))?,
+        })
+    }
+}
+
+/// Audio parameters selected by the controller for a stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectedAudioParameters {
+    pub codec: AudioCodec,
+    pub channels: u8,
+    pub bit_rate: AudioBitRate,
+    pub sample_rate: AudioSampleRate,
+    /// Duration of the audio in each RTP packet in milliseconds.
+    pub packet_time: u8,
+    pub rtp: RtpParameters,
+    pub comfort_noise: bool,
+}
+
+impl SelectedAudioParameters {
+    fn decode(decoded: &HashMap<u8, Vec<u8>>) -> Result<SelectedAudioParameters> {
+        let codec_parameters = tlv::decode(decoded.get(&0x02).ok_or(invalid_tlv())?);
+
+        Ok(SelectedAudioParameters {
+            codec: AudioCodec::from_u8(read_u8(decoded, 0x01)?).ok_or(invalid_tlv())?,
+            channels: read_u8(&codec_parameters, 0x01)?,
+            bit_rate: AudioBitRate::from_u8(read_u8(&codec_parameters, 0x02)?).ok_or(invalid_tlv())?,
+            sample_rate: AudioSampleRate::from_u8(read_u8(&codec_parameters, 0x03)?).ok_or(invalid_tlv())?,
+            packet_time: read_u8(&codec_parameters, 0x04)?,
+            rtp: RtpParameters::decode(&tlv::decode(decoded.get(&0x03).ok_or(invalid_tlv())?))?,
+            comfort_noise: read_u8(decoded, 0x04).map(|c| c == 1).unwrap_or(false),
+        })
+    }
+}
+
+/// A controller starting, reconfiguring or ending a stream by writing the `Selected RTP Stream Configuration`
+/// characteristic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamRequest {
+    /// ID of the stream session, as set up by the [`PrepareStreamRequest`](PrepareStreamRequest).
+    pub session_id: Uuid,
+    pub command: SessionCommand,
+    /// Selected video parameters. Sent with the `Start` and `Reconfigure` commands.
+    pub video: Option<SelectedVideoParameters>,
+    /// Selected audio parameters. Sent with the `Start` command.
+    pub audio: Option<SelectedAudioParameters>,
+}
+
+impl StreamRequest {
+    fn decode(decoded: &HashMap<u8, Vec<u8>>) -> Result<StreamRequest> {
+        let session_control = tlv::decode(decoded.get(&0x01).ok_or(invalid_tlv())?);
+
+        Ok(StreamRequest {
+            session_id: read_session_id(&session_control)?,
+            command: SessionCommand::from_u8(read_u8(&session_control, 0x02)?).ok_or(invalid_tlv())?,
+            video: decoded
+                .get(&0x02)
+                .map(|v| SelectedVideoParameters::decode(&tlv::decode(v)))
+                .transpose()?,
+            audio: decoded
+                .get(&0x03)
+                .map(|v| SelectedAudioParameters::decode(&tlv::decode(v)))
+                .transpose()?,
+        })
+    }
+}
+
+/// [`StreamDelegate`](StreamDelegate) is implemented by the application providing the camera streams, e.g. by piping a
+/// camera through `ffmpeg`.
+///
+/// # Examples
+/// ```
+/// use async_trait::async_trait;
+/// use hap::{
+///     camera::{
+///         PrepareStreamRequest,
+///         PrepareStreamResponse,
+///         SessionCommand,
+///         StreamDelegate,
+///         StreamRequest,
+///     },
+///     Result,
+/// };
+///
+/// struct Ffmpeg;
+///
+/// #[async_trait]
+/// impl StreamDelegate for Ffmpeg {
+///     async fn prepare_stream(
+///         &self,
+///         request: PrepareStreamRequest,
+///     ) -> Result<PrepareStreamResponse> {
+///         // reuse the ports and keys of the controller
+///         Ok(PrepareStreamResponse {
+///             address: "192.168.0.10".parse().unwrap(),
+///             video_port: request.video_port,
+///             audio_port: request.audio_port,
+///             video_srtp: request.video_srtp,
+///             audio_srtp: request.audio_srtp,
+///             video_ssrc: 1,
+///             audio_ssrc: 2,
+///         })
+///     }
+///
+///     async fn handle_stream_request(&self, request: StreamRequest) -> Result<()> {
+///         match request.command {
+///             SessionCommand::Start => { /* spawn ffmpeg */ },
+///             SessionCommand::End => { /* kill ffmpeg */ },
+///             _ => {},
+///         }
+///
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait StreamDelegate: Send + Sync {
+    /// Called when a controller sets up a new stream. Returns the endpoints of the accessory for the stream.
+    async fn prepare_stream(&self, request: PrepareStreamRequest) -> Result<PrepareStreamResponse>;
+    /// Called when a controller starts, suspends, resumes, reconfigures or ends a stream.
+    async fn handle_stream_request(&self, request: StreamRequest) -> Result<()>;
+}
+
+#[derive(Debug, Copy, Clone)]
+enum StreamingStatus {
+    Available = 0,
+    InUse = 1,
+}
+
+#[derive(Debug, Default)]
+struct StreamState {
+    /// Response to the last write of the `Setup Endpoints` characteristic.
+    setup_response: Vec<u8>,
+    /// ID of the currently running stream session.
+    active_session: Option<Uuid>,
+}
+
+/// [`CameraStreamManager`](CameraStreamManager) handles the RTP stream negotiation of a
+/// [`CameraStreamManagementService`](CameraStreamManagementService), passing the requests of controllers on to a
+/// [`StreamDelegate`](StreamDelegate). It supports one stream at a time.
+pub struct CameraStreamManager {
+    configuration: StreamConfiguration,
+    delegate: Arc<dyn StreamDelegate>,
+    state: Arc<Mutex<StreamState>>,
+}
+
+impl CameraStreamManager {
+    /// Creates a new [`CameraStreamManager`](CameraStreamManager).
+    pub fn new(configuration: StreamConfiguration, delegate: impl StreamDelegate + 'static) -> CameraStreamManager {
+        CameraStreamManager {
+            configuration,
+            delegate: Arc::new(delegate),
+            state: Arc::new(Mutex::new(StreamState::default())),
+        }
+    }
+
+    /// Sets the supported stream configurations on a [`CameraStreamManagementService`](CameraStreamManagementService)
+    /// and the callbacks handling its `Setup Endpoints` and `Selected RTP Stream Configuration` characteristics.
+    pub async fn attach(&self, service: &mut CameraStreamManagementService) -> Result<()> {
+        service
+            .supported_video_stream_configuration
+            .set_value(json!(self.configuration.encode_supported_video_stream_configuration()))
+            .await?;
+        service
+            .supported_audio_stream_configuration
+            .set_value(json!(self.configuration.encode_supported_audio_stream_configuration()))
+            .await?;
+        service
+            .supported_rtp_configuration
+            .set_value(json!(self.configuration.encode_supported_rtp_configuration()))
+            .await?;
+        service
+            .streaming_status
+            .set_value(json!(encode_streaming_status(StreamingStatus::Available)))
+            .await?;
+
+        let state = self.state.clone();
+        service.streaming_status.on_read_async(Some(move || {
+            let state = state.clone();
+            async move {
+                let status = match state.lock().await.active_session {
+                    Some(_) => StreamingStatus::InUse,
+                    None => StreamingStatus::Available,
+                };
+                Ok(Some(encode_streaming_status(status)))
+            }
+            .boxed()
+        }));
+
+        let state = self.state.clone();
+        service.setup_endpoint.on_read_async(Some(move || {
+            let state = state.clone();
+            async move {
+                let setup_response = state.lock().await.setup_response.clone();
+                Ok(if setup_response.is_empty() {
+                    None
+                } else {
+                    Some(setup_response)
+                })
+            }
+            .boxed()
+        }));
+
+        let state = self.state.clone();
+        let delegate = self.delegate.clone();
+        service
+            .setup_endpoint
+            .on_update_async(Some(move |_: Vec<u8>, request: Vec<u8>| {
+                let state = state.clone();
+                let delegate = delegate.clone();
+                async move {
+                    let decoded = tlv::decode(&request);
+                    // reading the setup response sets it as the value of the characteristic, which isn't a request
+                    if decoded.contains_key(&SETUP_ENDPOINTS_STATUS) {
+                        return Ok(());
+                    }
+
+                    let request = PrepareStreamRequest::decode(&decoded)?;
+                    let session_id = request.session_id;
+
+                    debug!("received prepare stream request: {:?}", &request);
+
+                    let setup_response = if state.lock().await.active_session.is_some() {
+                        encode_setup_endpoints_status(session_id, SetupEndpointsStatus::Busy)
+                    } else {
+                        match delegate.prepare_stream(request).await {
+                            Ok(response) => response.encode(session_id),
+                            Err(e) => {
+                                error!("error preparing stream: {:?}", e);
+                                encode_setup_endpoints_status(session_id, SetupEndpointsStatus::Error)
+                            },
+                        }
+                    };
+                    state.lock().await.setup_response = setup_response;
+
+                    Ok(())
+                }
+                .boxed()
+            }));
+
+        let state = self.state.clone();
+        let delegate = self.delegate.clone();
+        service
+            .selected_stream_configuration
+            .on_update_async(Some(move |_: Vec<u8>, request: Vec<u8>| {
+                let state = state.clone();
+                let delegate = delegate.clone();
+                async move {
+                    let request = StreamRequest::decode(&tlv::decode(&request))?;
+                    let session_id = request.session_id;
+                    let command = request.command;
+
+                    debug!("received stream request: {:?}", &request);
+
+                    delegate.handle_stream_request(request).await?;
+
+                    let mut state = state.lock().await;
+                    match command {
+                        SessionCommand::Start => state.active_session = Some(session_id),
+                        SessionCommand::End if state.active_session == Some(session_id) => state.active_session = None,
+                        _ => {},
+                    }
+
+                    Ok(())
+                }
+                .boxed()
+            }));
+
+        Ok(())
+    }
+}
+
+/// Encodes a list of values of the same type, separated by empty items.
+fn list(t: u8, values: Vec<Vec<u8>>) -> Vec<(u8, Vec<u8>)> {
+    let mut tlvs = Vec::new();
+    for (i, value) in values.into_iter().enumerate() {
+        if i > 0 {
+            tlvs.push((0x00, vec![]));
+        }
+        tlvs.push((t, value));
+    }
+    tlvs
+}
+
+fn encode_streaming_status(status: StreamingStatus) -> Vec<u8> { tlv::encode(vec![(0x01, vec![status as u8])]) }
+
+fn encode_setup_endpoints_status(session_id: Uuid, status: SetupEndpointsStatus) -> Vec<u8> {
+    tlv::encode(vec![
+        (0x01, session_id.as_bytes().to_vec()),
+        (SETUP_ENDPOINTS_STATUS, vec![status as u8]),
+    ])
+}
+
+fn invalid_tlv() -> Error { Error::InvalidValue(Format::Tlv8) }
+
+fn read_u8(decoded: &HashMap<u8, Vec<u8>>, t: u8) -> Result<u8> { tlv::read_u8(decoded, t).ok_or(invalid_tlv()) }
+
+fn read_u16(decoded: &HashMap<u8, Vec<u8>>, t: u8) -> Result<u16> { tlv::read_u16(decoded, t).ok_or(invalid_tlv()) }
+
+fn read_u32(decoded: &HashMap<u8, Vec<u8>>, t: u8) -> Result<u32> { tlv::read_u32(decoded, t).ok_or(invalid_tlv()) }
+
+fn read_f32(decoded: &HashMap<u8, Vec<u8>>, t: u8) -> Result<f32> { read_u32(decoded, t).map(f32::from_bits) }
+
+fn read_session_id(decoded: &HashMap<u8, Vec<u8>>) -> Result<Uuid> {
+    decoded
+        .get(&0x01)
+        .and_then(|id| Uuid::from_slice(id).ok())
+        .ok_or(invalid_tlv())
+}
+
+fn read_address(decoded: &HashMap<u8, Vec<u8>>) -> Result<IpAddr> {
+    let address = decoded.get(&0x02).ok_or(invalid_tlv())?;
+    std::str::from_utf8(address)?.parse().map_err(|_| invalid_tlv())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_prepare_stream_request() {
+        let session_id = Uuid::new_v4();
+        let srtp = SrtpParameters {
+            crypto_suite: SrtpCryptoSuite::AesCm128HmacSha1_80,
+            master_key: vec![1; 16],
+            master_salt: vec![2; 14],
+        };
+        let request = tlv::encode(vec![
+            (0x01, session_id.as_bytes().to_vec()),
+            (
+                0x03,
+                tlv::encode(vec![
+                    (0x01, vec![0]),
+                    (0x02, b"192.168.0.20".to_vec()),
+                    (0x03, 51000u16.to_le_bytes().to_vec()),
+                    (0x04, 51002u16.to_le_bytes().to_vec()),
+                ]),
+            ),
+            (0x04, srtp.encode()),
+            (0x05, srtp.encode()),
+        ]);
+
+        let request = PrepareStreamRequest::decode(&tlv::decode(&request)).unwrap();
+
+        assert_eq!(request, PrepareStreamRequest {
+            session_id,
+            controller_address: "192.168.0.20".parse().unwrap(),
+            video_port: 51000,
+            audio_port: 51002,
+            video_srtp: srtp.clone(),
+            audio_srtp: srtp,
+        });
+    }
+
+    #[test]
+    fn test_decode_stream_request() {
+        let session_id = Uuid::new_v4();
+        let request = tlv::encode(vec![
+            (
+                0x01,
+                tlv::encode(vec![(0x01, session_id.as_bytes().to_vec()), (0x02, vec![1])]),
+            ),
+            (
+                0x02,
+                tlv::encode(vec![
+                    (0x01, vec![0]),
+                    (
+                        0x02,
+                        tlv::encode(vec![(0x01, vec![2]), (0x02, vec![2]), (0x03, vec![0])]),
+                    ),
+                    (
+                        0x03,
+                        VideoAttributes {
+                            width: 1280,
+                            height: 720,
+                            frame_rate: 30,
+                        }
+                        .encode(),
+                    ),
+                    (
+                        0x04,
+                        tlv::encode(vec![
+                            (0x01, vec![99]),
+                            (0x02, 7u32.to_le_bytes().to_vec()),
+                            (0x03, 299u16.to_le_bytes().to_vec()),
+                            (0x04, 0.5f32.to_le_bytes().to_vec()),
+                            (0x05, 1378u16.to_le_bytes().to_vec()),
+                        ]),
+                    ),
+                ]),
+            ),
+        ]);
+
+        let request = StreamRequest::decode(&tlv::decode(&request)).unwrap();
+
+        assert_eq!(request.session_id, session_id);
+        assert_eq!(request.command, SessionCommand::Start);
+        assert_eq!(request.audio, None);
+        assert_eq!(
+            request.video,
+            Some(SelectedVideoParameters {
+                profile: H264Profile::High,
+                level: H264Level::Level4,
+                attributes: VideoAttributes {
+                    width: 1280,
+                    height: 720,
+                    frame_rate: 30,
+                },
+                rtp: RtpParameters {
+                    payload_type: 99,
+                    ssrc: 7,
+                    max_bit_rate: 299,
+                    min_rtcp_interval: 0.5,
+                    max_mtu: Some(1378),
+                    comfort_noise_payload_type: None,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_encode_supported_rtp_configuration() {
+        let configuration = StreamConfiguration {
+            srtp_crypto_suites: vec![SrtpCryptoSuite::AesCm128HmacSha1_80, SrtpCryptoSuite::None],
+            ..Default::default()
+        };
+
+        assert_eq!(configuration.encode_supported_rtp_configuration(), vec![
+            0x02, 0x01, 0x00, 0x00, 0x00, 0x02, 0x01, 0x02
+        ]);
+    }
+}
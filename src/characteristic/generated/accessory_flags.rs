@@ -148,6 +148,10 @@ impl HapCharacteristic for AccessoryFlagsCharacteristic {
     fn get_pid(&self) -> Option<u64> { HapCharacteristic::get_pid(&self.0) }
 
     fn set_pid(&mut self, pid: Option<u64>) { HapCharacteristic::set_pid(&mut self.0, pid) }
+
+    fn get_persistent(&self) -> bool { HapCharacteristic::get_persistent(&self.0) }
+
+    fn set_persistent(&mut self, persistent: bool) { HapCharacteristic::set_persistent(&mut self.0, persistent) }
 }
 
 impl HapCharacteristicSetup for AccessoryFlagsCharacteristic {
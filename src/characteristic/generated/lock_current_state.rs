@@ -30,8 +30,10 @@ use crate::{
 pub struct LockCurrentStateCharacteristic(Characteristic<u8>);
 
 pub enum Value {
-	Num0 = 0,
-	Num1 = 1,
+	Unsecured = 0,
+	Secured = 1,
+	Jammed = 2,
+	Unknown = 3,
 }
 
 impl LockCurrentStateCharacteristic {
@@ -51,8 +53,10 @@ impl LockCurrentStateCharacteristic {
 			min_value: Some(0),
 			step_value: Some(1),
 			valid_values: Some(vec![
-				0, // 0
-				1, // 1
+				0, // UNSECURED
+				1, // SECURED
+				2, // JAMMED
+				3, // UNKNOWN
 			]),
             ..Default::default()
         });
@@ -160,6 +164,10 @@ impl HapCharacteristic for LockCurrentStateCharacteristic {
     fn get_pid(&self) -> Option<u64> { HapCharacteristic::get_pid(&self.0) }
 
     fn set_pid(&mut self, pid: Option<u64>) { HapCharacteristic::set_pid(&mut self.0, pid) }
+
+    fn get_persistent(&self) -> bool { HapCharacteristic::get_persistent(&self.0) }
+
+    fn set_persistent(&mut self, persistent: bool) { HapCharacteristic::set_persistent(&mut self.0, persistent) }
 }
 
 impl HapCharacteristicSetup for LockCurrentStateCharacteristic {
@@ -43,6 +43,7 @@ impl RotationSpeedCharacteristic {
 				Perm::PairedRead,
 				Perm::PairedWrite,
             ],
+			unit: Some(Unit::Percentage),
 			max_value: Some(100 as f32),
 			min_value: Some(0 as f32),
 			step_value: Some(1 as f32),
@@ -152,6 +153,10 @@ impl HapCharacteristic for RotationSpeedCharacteristic {
     fn get_pid(&self) -> Option<u64> { HapCharacteristic::get_pid(&self.0) }
 
     fn set_pid(&mut self, pid: Option<u64>) { HapCharacteristic::set_pid(&mut self.0, pid) }
+
+    fn get_persistent(&self) -> bool { HapCharacteristic::get_persistent(&self.0) }
+
+    fn set_persistent(&mut self, persistent: bool) { HapCharacteristic::set_persistent(&mut self.0, persistent) }
 }
 
 impl HapCharacteristicSetup for RotationSpeedCharacteristic {
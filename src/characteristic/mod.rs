@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use erased_serde::serialize_trait_object;
 use futures::future::BoxFuture;
 use serde::{
@@ -7,9 +8,16 @@ use serde::{
     Serialize,
 };
 use serde_json::json;
-use std::fmt;
-
-use crate::{event::Event, pointer, Error, HapType, Result};
+use std::{fmt, sync::Arc};
+
+use crate::{
+    event::{self, Event},
+    pointer,
+    ConnectionInfo,
+    Error,
+    HapType,
+    Result,
+};
 
 mod generated;
 
@@ -61,9 +69,9 @@ pub struct Characteristic<T: fmt::Debug + Default + Clone + Serialize + Send + S
     /// default value is 2097152.
     max_data_len: Option<u32>, // TODO - use this value in `set_value`
     /// An array of numbers where each element represents a valid value.
-    valid_values: Option<Vec<T>>, // TODO - use this value in `set_value`
+    valid_values: Option<Vec<T>>,
     /// A 2 element array representing the starting value and ending value of the range of valid values.
-    valid_values_range: Option<[T; 2]>, // TODO - use this value in `set_value`
+    valid_values_range: Option<[T; 2]>,
 
     /// Specified TTL in milliseconds the controller requests the accessory to securely execute a write command.
     /// Maximum value of this is 9007199254740991.
@@ -71,6 +79,10 @@ pub struct Characteristic<T: fmt::Debug + Default + Clone + Serialize + Send + S
     /// 64-bit unsigned integer assigned by the controller to uniquely identify the timed write transaction.
     pid: Option<u64>, // TODO - use this value in `set_value`
 
+    /// Whether the value of the characteristic is persisted to the [`Storage`](crate::storage::Storage) and restored
+    /// when the accessory is added to the server again, e.g. after a restart.
+    persistent: bool,
+
     /// Sets a callback function on a characteristic that is called every time a controller attempts to read its value.
     /// Returning a `Some(T)` from this function changes the value of the characteristic before the controller reads
     /// it so the Controller reads the new value.
@@ -113,6 +125,7 @@ impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync> fmt::Debug for C
             .field("valid_values_range", &self.valid_values_range)
             .field("ttl", &self.ttl)
             .field("pid", &self.pid)
+            .field("persistent", &self.persistent)
             .finish()
     }
 }
@@ -161,6 +174,7 @@ where
             valid_values_range,
             ttl,
             pid,
+            persistent: false,
             on_read: None,
             on_update: None,
             on_read_async: None,
@@ -175,6 +189,9 @@ where
     /// Sets the ID of the characteristic.
     pub fn set_id(&mut self, id: u64) { self.id = id; }
 
+    /// Sets the ID of the accessory the characteristic belongs to.
+    pub(crate) fn set_accessory_id(&mut self, accessory_id: u64) { self.accessory_id = accessory_id; }
+
     /// Returns the [`HapType`](HapType) of the characteristic.
     pub fn get_type(&self) -> HapType { self.hap_type }
 
@@ -249,15 +266,12 @@ where
 
         if self.event_notifications == Some(true) {
             if let Some(ref event_emitter) = self.event_emitter {
-                event_emitter
-                    .lock()
-                    .await
-                    .emit(&Event::CharacteristicValueChanged {
-                        aid: self.accessory_id,
-                        iid: self.id,
-                        value: json!(&val),
-                    })
-                    .await;
+                event::emit(event_emitter, &Event::CharacteristicValueChanged {
+                    aid: self.accessory_id,
+                    iid: self.id,
+                    value: value_to_json(self.format, &val),
+                })
+                .await;
             }
         }
 
@@ -305,15 +319,40 @@ where
     /// Returns the valid values of the characteristic.
     pub fn get_valid_values(&self) -> Option<Vec<T>> { self.valid_values.clone() }
 
-    /// Sets the valid values of the characteristic.
+    /// Sets the valid values of the characteristic, e.g. to restrict the modes an enum characteristic offers. The
+    /// valid values are announced to controllers, and controller writes of other values are rejected with the
+    /// `InvalidValueInRequest` status.
     pub fn set_valid_values(&mut self, val: Option<Vec<T>>) { self.valid_values = val; }
 
     /// Returns the valid values range of the characteristic.
     pub fn get_valid_values_range(&self) -> Option<[T; 2]> { self.valid_values_range.clone() }
 
-    /// Sets the valid values range of the characteristic.
+    /// Sets the valid values range of the characteristic. Like the valid values, the range is enforced on controller
+    /// writes.
     pub fn set_valid_values_range(&mut self, val: Option<[T; 2]>) { self.valid_values_range = val; }
 
+    /// Checks a written value against the valid values and the valid values range of the characteristic.
+    fn check_valid_value(&self, val: &T) -> Result<()> {
+        let value = to_json(val);
+
+        if let Some(ref valid_values) = self.valid_values {
+            if !valid_values.iter().any(|v| to_json(v) == value) {
+                return Err(Error::DisallowedValue(value));
+            }
+        }
+
+        if let Some([ref start, ref end]) = self.valid_values_range {
+            let (start, end) = (to_json(start).as_f64(), to_json(end).as_f64());
+            if let (Some(v), Some(start), Some(end)) = (value.as_f64(), start, end) {
+                if v < start || v > end {
+                    return Err(Error::DisallowedValue(value));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Returns the TTL of the characteristic.
     pub fn get_ttl(&self) -> Option<u64> { self.ttl }
 
@@ -326,6 +365,15 @@ where
     /// Sets the PID of the characteristic.
     pub fn set_pid(&mut self, val: Option<u64>) { self.pid = val; }
 
+    /// Returns whether the value of the characteristic is persisted.
+    pub fn get_persistent(&self) -> bool { self.persistent }
+
+    /// Sets whether the value of the characteristic is persisted. Values of persistent characteristics are saved to
+    /// the [`Storage`](crate::storage::Storage) whenever a controller writes them or they're updated through
+    /// [`Server::update_characteristic`](crate::server::Server::update_characteristic), and restored when the
+    /// accessory is added to the server. Characteristics aren't persistent by default.
+    pub fn set_persistent(&mut self, persistent: bool) { self.persistent = persistent; }
+
     /// Sets a callback function on a characteristic that is called every time a controller attempts to read its value.
     /// Returning a `Some(T)` from this function changes the value of the characteristic before the controller reads
     /// it so the controller reads the new value.
@@ -376,31 +424,44 @@ impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync> Serialize for Ch
         }
 
         if self.perms.contains(&Perm::PairedRead) {
-            state.serialize_field("value", &self.value)?;
+            if is_stateless(self.hap_type) {
+                state.serialize_field("value", &serde_json::Value::Null)?;
+            } else {
+                state.serialize_field("value", &value_to_json(self.format, &self.value))?;
+            }
         }
         if let Some(ref unit) = self.unit {
             state.serialize_field("unit", unit)?;
         }
-        if let Some(ref max_value) = self.max_value {
-            state.serialize_field("maxValue", max_value)?;
-        }
-        if let Some(ref min_value) = self.min_value {
-            state.serialize_field("minValue", min_value)?;
-        }
-        if let Some(ref step_value) = self.step_value {
-            state.serialize_field("minStep", step_value)?;
-        }
-        if let Some(ref max_len) = self.max_len {
-            state.serialize_field("maxLen", max_len)?;
+        // the range and step fields only apply to numeric formats, the length fields to strings and data
+        if is_numeric(self.format) {
+            if let Some(ref max_value) = self.max_value {
+                state.serialize_field("maxValue", max_value)?;
+            }
+            if let Some(ref min_value) = self.min_value {
+                state.serialize_field("minValue", min_value)?;
+            }
+            if let Some(ref step_value) = self.step_value {
+                state.serialize_field("minStep", step_value)?;
+            }
         }
-        if let Some(ref max_data_len) = self.max_data_len {
-            state.serialize_field("maxDataLen", max_data_len)?;
+        if self.format == Format::String {
+            if let Some(ref max_len) = self.max_len {
+                state.serialize_field("maxLen", max_len)?;
+            }
         }
-        if let Some(ref valid_values) = self.valid_values {
-            state.serialize_field("valid-values", valid_values)?;
+        if self.format == Format::Data {
+            if let Some(ref max_data_len) = self.max_data_len {
+                state.serialize_field("maxDataLen", max_data_len)?;
+            }
         }
-        if let Some(ref valid_values_range) = self.valid_values_range {
-            state.serialize_field("valid-values-range", valid_values_range)?;
+        if is_numeric(self.format) {
+            if let Some(ref valid_values) = self.valid_values {
+                state.serialize_field("valid-values", valid_values)?;
+            }
+            if let Some(ref valid_values_range) = self.valid_values_range {
+                state.serialize_field("valid-values-range", valid_values_range)?;
+            }
         }
         if let Some(ref ttl) = self.ttl {
             state.serialize_field("TTL", ttl)?;
@@ -432,6 +493,10 @@ pub enum Perm {
 }
 
 /// [`Unit`](Unit) of a characteristic.
+///
+/// The generated characteristics are created with the unit the HAP specification defines for them and can be changed
+/// with `set_unit`. Temperatures are always transmitted in Celsius though; iOS converts them for display according to
+/// the Temperature Display Units characteristic.
 #[derive(Debug, Copy, Clone, Serialize)]
 pub enum Unit {
     #[serde(rename = "celsius")]
@@ -452,6 +517,133 @@ pub enum Unit {
     MicrogramsPerCubicMeter,
 }
 
+/// Error a read or update callback can return to answer the controller's request with a specific HAP status code.
+/// Any other error returned by a callback is reported as a service communication failure.
+///
+/// # Examples
+///
+/// ```
+/// use hap::characteristic::{
+///     power_state::PowerStateCharacteristic,
+///     CharacteristicCallbacks,
+///     CharacteristicError,
+/// };
+///
+/// let mut power_state = PowerStateCharacteristic::new(1, 1);
+/// power_state.on_update(Some(|_: &bool, _: &bool| {
+///     Err(CharacteristicError::ResourceBusy.into())
+/// }));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CharacteristicError {
+    #[error("The request was denied due to insufficient privileges.")]
+    InsufficientPrivileges,
+    #[error("Unable to communicate with the requested service.")]
+    ServiceCommunicationFailure,
+    #[error("The resource is busy, try again.")]
+    ResourceBusy,
+    #[error("The accessory is out of resources to complete the request.")]
+    OutOfResource,
+    #[error("The operation timed out.")]
+    OperationTimedOut,
+    #[error("The resource doesn't exist.")]
+    ResourceDoesNotExist,
+    #[error("The value in the request is invalid.")]
+    InvalidValueInRequest,
+}
+
+/// An [`AuthorizationRequest`](AuthorizationRequest) is a controller's write of a characteristic with the
+/// `AdditionalAuthorization` permission, passed to the [`AuthorizationValidator`](AuthorizationValidator).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorizationRequest {
+    /// ID of the accessory of the written characteristic.
+    pub aid: u64,
+    /// Instance ID of the written characteristic.
+    pub iid: u64,
+    /// The value the controller attempts to write.
+    pub value: serde_json::Value,
+    /// The decoded `authData` of the write, if the controller sent any.
+    pub auth_data: Option<Vec<u8>>,
+    /// The connection the write was received on.
+    pub connection: ConnectionInfo,
+}
+
+/// An [`AuthorizationValidator`](AuthorizationValidator) validates the additional authorization data controllers send
+/// with writes of characteristics that have the [`Perm::AdditionalAuthorization`](Perm::AdditionalAuthorization)
+/// permission, e.g. the target state of a high-security lock.
+///
+/// It's called before the value is set and resolves to whether the write is authorized. Unauthorized writes are
+/// answered with the `Insufficient Authorization` status and leave the characteristic unchanged. Writes of
+/// characteristics without the permission aren't passed to it. Without a validator, writes of characteristics with the
+/// permission are always rejected.
+///
+/// # Examples
+/// ```
+/// use hap::{characteristic::AuthorizationValidator, futures::FutureExt, Config};
+///
+/// let token = b"secret token".to_vec();
+///
+/// let config = Config {
+///     authorization_validator: Some(AuthorizationValidator::new(move |request| {
+///         let authorized = request.auth_data.as_ref() == Some(&token);
+///         async move { authorized }.boxed()
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct AuthorizationValidator(Arc<dyn Fn(&AuthorizationRequest) -> BoxFuture<'static, bool> + Send + Sync>);
+
+impl AuthorizationValidator {
+    /// Creates a new [`AuthorizationValidator`](AuthorizationValidator) from a callback.
+    pub fn new<F>(validate: F) -> AuthorizationValidator
+    where
+        F: Fn(&AuthorizationRequest) -> BoxFuture<'static, bool> + Send + Sync + 'static,
+    {
+        AuthorizationValidator(Arc::new(validate))
+    }
+
+    /// Calls the callback for a write that's about to be applied.
+    pub(crate) async fn validate(&self, request: &AuthorizationRequest) -> bool { (self.0)(request).await }
+}
+
+impl fmt::Debug for AuthorizationValidator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("AuthorizationValidator") }
+}
+
+/// Value of a `Programmable Switch Event` characteristic, i.e. the kind of a button press.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProgrammableSwitchEvent {
+    SinglePress = 0,
+    DoublePress = 1,
+    LongPress = 2,
+}
+
+/// Value of a `Valve Type` characteristic, i.e. the kind of water outlet a valve controls.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ValveType {
+    GenericValve = 0,
+    Irrigation = 1,
+    ShowerHead = 2,
+    WaterFaucet = 3,
+}
+
+/// Value of an `Input Source Type` characteristic, i.e. the kind of an input of a television.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputSourceType {
+    Other = 0,
+    HomeScreen = 1,
+    Tuner = 2,
+    Hdmi = 3,
+    CompositeVideo = 4,
+    SVideo = 5,
+    ComponentVideo = 6,
+    Dvi = 7,
+    AirPlay = 8,
+    Usb = 9,
+    Application = 10,
+}
+
 /// [`Format`](Format) (data type) of a characteristic.
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Format {
@@ -510,7 +702,8 @@ pub trait HapCharacteristic: HapCharacteristicSetup + erased_serde::Serialize +
     fn set_event_notifications(&mut self, event_notifications: Option<bool>);
     /// Returns the value of the characteristic.
     async fn get_value(&mut self) -> Result<serde_json::Value>;
-    /// Sets the value of the characteristic.
+    /// Sets the value of the characteristic. Values outside of its valid values or valid values range are rejected
+    /// with [`Error::DisallowedValue`](Error::DisallowedValue).
     async fn set_value(&mut self, value: serde_json::Value) -> Result<()>;
     /// Returns the [`Unit`](Unit) of the characteristic.
     fn get_unit(&self) -> Option<Unit>;
@@ -552,6 +745,11 @@ pub trait HapCharacteristic: HapCharacteristicSetup + erased_serde::Serialize +
     fn get_pid(&self) -> Option<u64>;
     /// Sets the PID of the characteristic.
     fn set_pid(&mut self, pid: Option<u64>);
+    /// Returns whether the value of the characteristic is persisted.
+    fn get_persistent(&self) -> bool;
+    /// Sets whether the value of the characteristic is persisted to the [`Storage`](crate::storage::Storage) and
+    /// restored when the accessory is added to the server.
+    fn set_persistent(&mut self, persistent: bool);
 }
 
 serialize_trait_object!(HapCharacteristic);
@@ -684,10 +882,14 @@ where
 
     async fn get_value(&mut self) -> Result<serde_json::Value> {
         let value = Characteristic::get_value(self).await?;
-        Ok(json!(value))
+        if is_stateless(self.hap_type) {
+            return Ok(serde_json::Value::Null);
+        }
+        Ok(value_to_json(self.format, &value))
     }
 
     async fn set_value(&mut self, value: serde_json::Value) -> Result<()> {
+        let value = value_from_json(self.format, value)?;
         let v;
         // for whatever reason, the controller is setting boolean values either as a boolean or as an integer
         if self.format == Format::Bool && value.is_number() {
@@ -702,6 +904,7 @@ where
         } else {
             v = serde_json::from_value(value).map_err(|_| Error::InvalidValue(Characteristic::get_format(self)))?;
         }
+        self.check_valid_value(&v)?;
         Characteristic::set_value(self, v).await
     }
 
@@ -709,7 +912,7 @@ where
 
     fn set_unit(&mut self, unit: Option<Unit>) { Characteristic::set_unit(self, unit) }
 
-    fn get_max_value(&self) -> Option<serde_json::Value> { Characteristic::get_max_value(self).map(|v| json!(v)) }
+    fn get_max_value(&self) -> Option<serde_json::Value> { Characteristic::get_max_value(self).map(|v| to_json(&v)) }
 
     fn set_max_value(&mut self, max_value: Option<serde_json::Value>) -> Result<()> {
         Characteristic::set_max_value(self, match max_value {
@@ -721,7 +924,7 @@ where
         Ok(())
     }
 
-    fn get_min_value(&self) -> Option<serde_json::Value> { Characteristic::get_min_value(self).map(|v| json!(v)) }
+    fn get_min_value(&self) -> Option<serde_json::Value> { Characteristic::get_min_value(self).map(|v| to_json(&v)) }
 
     fn set_min_value(&mut self, min_value: Option<serde_json::Value>) -> Result<()> {
         Characteristic::set_min_value(self, match min_value {
@@ -733,7 +936,7 @@ where
         Ok(())
     }
 
-    fn get_step_value(&self) -> Option<serde_json::Value> { Characteristic::get_step_value(self).map(|v| json!(v)) }
+    fn get_step_value(&self) -> Option<serde_json::Value> { Characteristic::get_step_value(self).map(|v| to_json(&v)) }
 
     fn set_step_value(&mut self, step_value: Option<serde_json::Value>) -> Result<()> {
         Characteristic::set_step_value(self, match step_value {
@@ -754,7 +957,7 @@ where
     fn set_max_data_len(&mut self, max_data_len: Option<u32>) { Characteristic::set_max_data_len(self, max_data_len) }
 
     fn get_valid_values(&self) -> Option<Vec<serde_json::Value>> {
-        Characteristic::get_valid_values(self).map(|v| v.into_iter().map(|v| json!(v)).collect())
+        Characteristic::get_valid_values(self).map(|v| v.iter().map(to_json).collect())
     }
 
     fn set_valid_values(&mut self, valid_values: Option<Vec<serde_json::Value>>) -> Result<()> {
@@ -773,7 +976,7 @@ where
     }
 
     fn get_valid_values_range(&self) -> Option<[serde_json::Value; 2]> {
-        Characteristic::get_valid_values_range(self).map(|v| [json!(v[0]), json!(v[1])])
+        Characteristic::get_valid_values_range(self).map(|v| [to_json(&v[0]), to_json(&v[1])])
     }
 
     fn set_valid_values_range(&mut self, valid_values_range: Option<[serde_json::Value; 2]>) -> Result<()> {
@@ -795,6 +998,55 @@ where
     fn get_pid(&self) -> Option<u64> { Characteristic::get_pid(self) }
 
     fn set_pid(&mut self, pid: Option<u64>) { Characteristic::set_pid(self, pid) }
+
+    fn get_persistent(&self) -> bool { Characteristic::get_persistent(self) }
+
+    fn set_persistent(&mut self, persistent: bool) { Characteristic::set_persistent(self, persistent) }
+}
+
+/// Returns whether characteristics of a type only signal events and read as `null`, like the `Programmable Switch
+/// Event` of a button press.
+fn is_stateless(hap_type: HapType) -> bool { hap_type == HapType::ProgrammableSwitchEvent }
+
+/// Returns whether values of a [`Format`](Format) are numbers.
+fn is_numeric(format: Format) -> bool {
+    matches!(
+        format,
+        Format::UInt8 | Format::UInt16 | Format::UInt32 | Format::UInt64 | Format::Int32 | Format::Float
+    )
+}
+
+/// Converts a value to a [`serde_json::Value`](serde_json::Value). Unlike `json!`, this goes through the serialized
+/// string, since a `serde_json::Value` stores an `f32` widened to `f64`, which would turn `0.1` into
+/// `0.10000000149011612`.
+fn to_json<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_string(value)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_else(|| json!(value))
+}
+
+/// Converts a characteristic value to its JSON representation. The bytes of `tlv8` and `data` values are sent base64
+/// encoded.
+fn value_to_json<T: Serialize>(format: Format, value: &T) -> serde_json::Value {
+    let value = to_json(value);
+    match format {
+        Format::Tlv8 | Format::Data if value.is_array() => serde_json::from_value::<Vec<u8>>(value.clone())
+            .map(|bytes| json!(BASE64.encode(bytes)))
+            .unwrap_or(value),
+        _ => value,
+    }
+}
+
+/// Counterpart of [`value_to_json`](value_to_json), decoding base64 encoded `tlv8` and `data` values to bytes.
+fn value_from_json(format: Format, value: serde_json::Value) -> Result<serde_json::Value> {
+    match (format, &value) {
+        (Format::Tlv8 | Format::Data, serde_json::Value::String(encoded)) => BASE64
+            .decode(encoded)
+            .map(|bytes| json!(bytes))
+            .map_err(|_| Error::InvalidValue(format)),
+        _ => Ok(value),
+    }
 }
 
 impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync> HapCharacteristicSetup for Characteristic<T>
@@ -824,6 +1076,39 @@ where
     fn on_update_async(&mut self, f: Option<impl OnUpdateFuture<T>>) { Characteristic::on_update_async(self, f) }
 }
 
+/// Creates a custom characteristic with the given UUID and none of the optional fields set.
+#[cfg(test)]
+pub(crate) fn custom_characteristic<T: fmt::Debug + Default + Clone + Serialize + Send + Sync>(
+    uuid: &str,
+    format: Format,
+    perms: Vec<Perm>,
+    value: T,
+) -> Characteristic<T>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    Characteristic::new(
+        0,
+        0,
+        HapType::Custom(uuid::Uuid::parse_str(uuid).unwrap()),
+        format,
+        perms,
+        None,
+        None,
+        value,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -863,4 +1148,156 @@ mod tests {
         let json = serde_json::to_string(&characteristic).unwrap();
         assert_eq!(json, "{\"iid\":1,\"type\":\"C1\",\"format\":\"uint16\",\"perms\":[\"pr\",\"ev\"],\"description\":\"Acme Tilt Angle\",\"ev\":true,\"value\":123,\"unit\":\"arcdegrees\",\"maxValue\":360,\"minValue\":0,\"minStep\":1,\"valid-values-range\":[0,360]}".to_string());
     }
+
+    #[test]
+    fn test_float_json_serialization() {
+        let characteristic = Characteristic::<f32> {
+            id: 8,
+            accessory_id: 1,
+            hap_type: HapType::CurrentTemperature,
+            format: Format::Float,
+            perms: vec![Perm::Events, Perm::PairedRead],
+            value: 21.7,
+            unit: Some(Unit::Celsius),
+            max_value: Some(100.0),
+            min_value: Some(0.0),
+            step_value: Some(0.1),
+            // not applicable to floats
+            max_len: Some(64),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&characteristic).unwrap();
+        assert_eq!(json, "{\"iid\":8,\"type\":\"11\",\"format\":\"float\",\"perms\":[\"ev\",\"pr\"],\"value\":21.7,\"unit\":\"celsius\",\"maxValue\":100.0,\"minValue\":0.0,\"minStep\":0.1}".to_string());
+
+        // the JSON values of the meta fields have to keep the representation of the serialized floats
+        assert_eq!(HapCharacteristic::get_step_value(&characteristic), Some(json!(0.1)));
+        assert_eq!(
+            serde_json::to_string(&value_to_json(Format::Float, &0.1f32)).unwrap(),
+            "0.1"
+        );
+        assert_eq!(
+            serde_json::to_string(&value_to_json(Format::Float, &1f32)).unwrap(),
+            "1.0"
+        );
+    }
+
+    #[test]
+    fn test_string_json_serialization() {
+        let characteristic = Characteristic::<String> {
+            id: 2,
+            accessory_id: 1,
+            hap_type: HapType::Name,
+            format: Format::String,
+            perms: vec![Perm::PairedRead],
+            value: "Acme".into(),
+            max_len: Some(64),
+            // not applicable to strings
+            max_value: Some("z".into()),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&characteristic).unwrap();
+        assert_eq!(
+            json,
+            "{\"iid\":2,\"type\":\"23\",\"format\":\"string\",\"perms\":[\"pr\"],\"value\":\"Acme\",\"maxLen\":64}"
+                .to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_valid_values_are_enforced() {
+        let mut characteristic = Characteristic::<u8> {
+            hap_type: HapType::TargetFanState,
+            format: Format::UInt8,
+            perms: vec![Perm::PairedRead, Perm::PairedWrite],
+            ..Default::default()
+        };
+        characteristic.set_valid_values(Some(vec![0, 2]));
+
+        HapCharacteristic::set_value(&mut characteristic, json!(2))
+            .await
+            .unwrap();
+        assert!(matches!(
+            HapCharacteristic::set_value(&mut characteristic, json!(1)).await,
+            Err(Error::DisallowedValue(_))
+        ));
+        assert_eq!(characteristic.value, 2);
+
+        characteristic.set_valid_values(None);
+        characteristic.set_valid_values_range(Some([1, 3]));
+
+        HapCharacteristic::set_value(&mut characteristic, json!(3))
+            .await
+            .unwrap();
+        assert!(matches!(
+            HapCharacteristic::set_value(&mut characteristic, json!(0)).await,
+            Err(Error::DisallowedValue(_))
+        ));
+        assert_eq!(characteristic.value, 3);
+    }
+
+    #[tokio::test]
+    async fn test_tlv8_values_are_base64_encoded() {
+        let mut characteristic = Characteristic::<Vec<u8>> {
+            hap_type: HapType::SetupEndpoint,
+            format: Format::Tlv8,
+            perms: vec![Perm::PairedRead, Perm::PairedWrite],
+            ..Default::default()
+        };
+
+        HapCharacteristic::set_value(&mut characteristic, json!("AQEA"))
+            .await
+            .unwrap();
+        assert_eq!(characteristic.value, vec![1, 1, 0]);
+        assert_eq!(
+            HapCharacteristic::get_value(&mut characteristic).await.unwrap(),
+            json!("AQEA")
+        );
+
+        HapCharacteristic::set_value(&mut characteristic, json!([2, 1, 1]))
+            .await
+            .unwrap();
+        assert_eq!(characteristic.value, vec![2, 1, 1]);
+
+        assert!(matches!(
+            HapCharacteristic::set_value(&mut characteristic, json!("not base64!")).await,
+            Err(Error::InvalidValue(Format::Tlv8))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stateless_characteristic_reads_null() {
+        let mut characteristic = Characteristic::<u8> {
+            hap_type: HapType::ProgrammableSwitchEvent,
+            format: Format::UInt8,
+            perms: vec![Perm::Events, Perm::PairedRead],
+            ..Default::default()
+        };
+
+        HapCharacteristic::set_value(&mut characteristic, json!(ProgrammableSwitchEvent::DoublePress as u8))
+            .await
+            .unwrap();
+
+        assert_eq!(characteristic.value, 1);
+        assert_eq!(
+            HapCharacteristic::get_value(&mut characteristic).await.unwrap(),
+            serde_json::Value::Null
+        );
+        assert!(serde_json::to_string(&characteristic)
+            .unwrap()
+            .contains("\"value\":null"));
+    }
+
+    #[test]
+    fn test_generated_characteristics_have_units() {
+        let current_temperature = CurrentTemperatureCharacteristic::new(1, 1);
+        assert!(matches!(current_temperature.get_unit(), Some(Unit::Celsius)));
+
+        let mut rotation_speed = RotationSpeedCharacteristic::new(1, 1);
+        assert!(serde_json::to_string(&rotation_speed)
+            .unwrap()
+            .contains("\"unit\":\"percentage\""));
+
+        rotation_speed.set_unit(None);
+        assert!(!serde_json::to_string(&rotation_speed).unwrap().contains("\"unit\""));
+    }
 }
@@ -1,14 +1,32 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use ed25519_dalek::SigningKey as Ed25519Keypair;
 //use eui48::MacAddress;
 use macaddr::MacAddr6 as MacAddress;
 use rand::{rngs::OsRng, Rng};
 use serde::{Deserialize, Serialize};
-use std::net::IpAddr;
+use sha2::{Digest, Sha512};
+use std::{
+    net::{IpAddr, Ipv6Addr},
+    time::Duration,
+};
 
-use crate::{accessory::AccessoryCategory, BonjourFeatureFlag, BonjourStatusFlag, Pin};
+use crate::{
+    accessory::AccessoryCategory,
+    camera::SnapshotHandler,
+    characteristic::AuthorizationValidator,
+    event::EventBackpressure,
+    pairing::PairingApprover,
+    tlv,
+    BonjourFeatureFlag,
+    BonjourStatusFlag,
+    Pin,
+};
 
 /// The `Config` struct is used to store configuration options for the HomeKit Accessory Server.
 ///
+/// The hooks, i.e. `pairing_approver`, `snapshot_handler`, `authorization_validator` and `tlv_observer`, aren't
+/// persisted, so they have to be set again when the config is loaded from storage.
+///
 /// # Examples
 ///
 /// ```
@@ -24,10 +42,29 @@ use crate::{accessory::AccessoryCategory, BonjourFeatureFlag, BonjourStatusFlag,
 /// ```
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
-    /// Socket IP address to serve on. Defaults to the IP of the system's first non-loopback network interface.
+    /// Socket IP address to serve on. Defaults to the IP of the system's first non-loopback IPv4 network interface,
+    /// falling back to the first routable IPv6 address.
+    ///
+    /// Only this address is announced via mDNS. Setting it to an unspecified address (`0.0.0.0` or `::`) serves on
+    /// all interfaces and announces all of their IPv4 and IPv6 addresses via A and AAAA records. Whether `::` also
+    /// accepts IPv4 connections depends on the system's dual-stack settings.
     pub host: IpAddr,
-    /// Port to serve on. Defaults to `32000`.
+    /// Port to serve on. Defaults to `32000`. With `0`, the server binds to an ephemeral port chosen by the OS, which
+    /// is returned by [`IpServer::local_addr`](crate::server::IpServer::local_addr).
     pub port: u16,
+    /// Names of the network interfaces (e.g. `"eth0"`) whose addresses are announced via mDNS when serving on an
+    /// unspecified `host`. Lets a multi-homed host keep the accessory off networks HomeKit shouldn't see. Ignored if
+    /// `mdns_ips` isn't empty. Defaults to an empty list, i.e. the addresses of all interfaces are announced.
+    #[serde(default)]
+    pub mdns_interfaces: Vec<String>,
+    /// IP addresses to announce via mDNS, overriding both `host` and `mdns_interfaces`. Useful when serving on an
+    /// unspecified `host` but only some of the addresses should be reachable for controllers. Defaults to an empty
+    /// list, i.e. the announced addresses are derived from `host` and `mdns_interfaces`.
+    #[serde(default)]
+    pub mdns_ips: Vec<IpAddr>,
+    /// Time to live in seconds of the announced mDNS records, i.e. how long controllers cache them. Defaults to `60`.
+    #[serde(default = "default_mdns_ttl")]
+    pub mdns_ttl: u32,
     /// 8 digit pin used for pairing. Defaults to `11122333`.
     ///
     /// The following pins are considered too easy and are therefore not allowed:
@@ -46,6 +83,13 @@ pub struct Config {
     pub pin: Pin,
     /// Model name of the accessory. E.g. "Acme Lightbulb".
     pub name: String,
+    /// Optional mDNS service instance name the accessory is announced with. Defaults to `None`, i.e. the accessory is
+    /// announced with its `name`. Set to a renamed instance name like `"Acme Lightbulb (2)"` when a name conflict is
+    /// resolved with
+    /// [`IpServer::resolve_bonjour_name_conflict`](crate::server::IpServer::resolve_bonjour_name_conflict), so the
+    /// new name persists across restarts. Name conflicts aren't detected automatically.
+    #[serde(default)]
+    pub bonjour_name: Option<String>,
     /// Device ID of the accessory. Generated randomly if not specified. This value is also used as the accessory's
     /// Pairing Identifier. Must be a unique random number generated at every factory reset and must persist across
     /// reboots.
@@ -64,18 +108,79 @@ pub struct Config {
     /// Bonjour Status Flag. Defaults to `StatusFlag::NotPaired` and is changed to `StatusFlag::Zero` after a
     /// successful pairing.
     pub status_flag: BonjourStatusFlag, // Bonjour: sf
-    /// Bonjour Feature Flag. Currently only used to indicate MFi compliance.
+    /// Bonjour Feature Flag, indicating how the accessory authenticates during pair setup:
+    /// `SupportsHardwareAuthentication` for MFi accessories with an authentication coprocessor,
+    /// `SupportsSoftwareAuthentication` for software token authentication. Uncertified accessories use `Zero`. Setup
+    /// mechanisms like QR codes and NFC tags aren't advertised here but in the setup payload, see `setup_nfc`.
     pub feature_flag: BonjourFeatureFlag, // Bonjour: ff
-    /// Optional maximum number of paired controllers.
+    /// Optional maximum number of paired controllers. Enforced on the initial pair setup as well as when an admin
+    /// controller adds a pairing; a new pairing is rejected once the number of pairings has reached this limit.
     pub max_peers: Option<usize>,
+    /// 4 character alphanumeric setup ID (`0-9`, `A-Z`). Used in the setup payload and to derive the setup hash, which
+    /// lets iOS find the accessory after scanning its setup QR code. Generated randomly if not specified.
+    #[serde(default = "generate_setup_id")]
+    pub setup_id: String, // Bonjour: sh (hashed)
+    /// Whether the accessory carries an NFC tag with its setup payload. Sets the NFC flag of the setup payload, so iOS
+    /// offers to set the accessory up by tapping the tag. Defaults to `false`.
+    #[serde(default)]
+    pub setup_nfc: bool,
+    /// Maximum size in bytes of the TLV encoded request bodies of the pairing endpoints. Larger requests are rejected
+    /// while they are still being received. Defaults to 16 KiB.
+    #[serde(default = "default_max_tlv_body_size")]
+    pub max_tlv_body_size: usize,
+    /// Optional time window in which value changes of subscribed characteristics are coalesced. Changes within the
+    /// window are sent to the controller as a single event notification, carrying only the latest value of each
+    /// characteristic. Defaults to `None`, i.e. every change is notified immediately.
+    #[serde(default)]
+    pub event_coalescing_window: Option<Duration>,
+    /// Optional maximum number of simultaneous controller connections. When a new connection would exceed the limit,
+    /// a connection without an encrypted session is closed, or the least recently used connection if there's none.
+    /// Defaults to `None`, i.e. the number of connections is unbounded.
+    #[serde(default)]
+    pub max_connections: Option<usize>,
+    /// Optional time after which a connection without any requests or event notifications is closed. Defaults to
+    /// `None`, i.e. connections are kept open until the controller closes them.
+    #[serde(default)]
+    pub connection_idle_timeout: Option<Duration>,
+    /// Number of events that can be queued for each listener added with
+    /// [`IpServer::add_event_listener`](crate::server::IpServer::add_event_listener) or
+    /// [`IpServer::subscribe_characteristic`](crate::server::IpServer::subscribe_characteristic). Listeners are called
+    /// from a separate task, so a slow listener doesn't hold up the request that caused an event until its queue is
+    /// full. Defaults to `64`.
+    #[serde(default = "default_event_queue_capacity")]
+    pub event_queue_capacity: usize,
+    /// What happens when an event is emitted while the queue of a listener is full. Defaults to
+    /// `EventBackpressure::Block`, i.e. emitting waits for the listener, so no events are lost.
+    #[serde(default)]
+    pub event_backpressure: EventBackpressure,
+    /// Optional hook approving or rejecting new controller pairings before they are saved. Defaults to `None`, i.e.
+    /// every pairing is approved.
+    #[serde(skip)]
+    pub pairing_approver: Option<PairingApprover>,
+    /// Optional hook taking camera snapshots for the `/resource` endpoint. Defaults to `None`, i.e. snapshot requests
+    /// are answered with `404 Not Found`.
+    #[serde(skip)]
+    pub snapshot_handler: Option<SnapshotHandler>,
+    /// Optional hook validating the additional authorization data of writes to characteristics with the
+    /// `AdditionalAuthorization` permission. Defaults to `None`, i.e. such writes are rejected with the
+    /// `Insufficient Authorization` status.
+    #[serde(skip)]
+    pub authorization_validator: Option<AuthorizationValidator>,
+    /// Optional hook observing the decoded TLV requests and responses of the pairing endpoints, e.g. for debugging
+    /// interoperability issues. Defaults to `None`.
+    #[serde(skip)]
+    pub tlv_observer: Option<tlv::Observer>,
 }
 
 impl Config {
     /// Redetermines the `host` field to the IP of the system's first non-loopback network interface.
     pub fn redetermine_local_ip(&mut self) { self.host = get_local_ip(); }
 
+    /// Returns the mDNS service instance name, i.e. the `bonjour_name` falling back to the `name`.
+    pub(crate) fn bonjour_name(&self) -> &str { self.bonjour_name.as_deref().unwrap_or(&self.name) }
+
     /// Derives mDNS TXT records from the `Config`.
-    pub(crate) fn txt_records(&self) -> [String; 8] {
+    pub(crate) fn txt_records(&self) -> [String; 9] {
         [
             format!("c#={}", self.configuration_number),
             format!("ff={}", self.feature_flag as u8),
@@ -85,9 +190,57 @@ impl Config {
             format!("s#={}", self.state_number),
             format!("sf={}", self.status_flag as u8),
             format!("ci={}", self.category as u8),
-            // format!("sh={}", self.setup_hash as u8), setup hash seems to be still undocumented
+            format!("sh={}", self.setup_hash()),
         ]
     }
+
+    /// Returns the `X-HM://` setup payload URI that HomeKit uses for setup QR codes and NFC tags.
+    ///
+    /// The payload encodes the setup code, the accessory category and the IP transport flag and has the setup ID
+    /// appended to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::{accessory::AccessoryCategory, Config, Pin};
+    ///
+    /// let config = Config {
+    ///     pin: Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap(),
+    ///     category: AccessoryCategory::Lightbulb,
+    ///     setup_id: "ABCD".into(),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(config.setup_payload(), "X-HM://00527813XABCD");
+    /// ```
+    pub fn setup_payload(&self) -> String {
+        // bits 0-26: setup code, 27-30: flags, 31-38: category, 39-42: reserved, 43-45: version
+        let mut flags = SETUP_FLAG_IP;
+        if self.setup_nfc {
+            flags |= SETUP_FLAG_NFC;
+        }
+        let payload = (self.category as u64) << 31 | flags << 27 | self.pin.to_u32() as u64;
+
+        format!("X-HM://{:0>9}{}", encode_base36(payload), self.setup_id)
+    }
+
+    /// Renders the setup payload as an SVG QR code, ready to be printed on a label.
+    #[cfg(feature = "qr")]
+    pub fn setup_qr_svg(&self) -> crate::Result<String> {
+        let code = qrcode::QrCode::new(self.setup_payload())?;
+
+        Ok(code.render::<qrcode::render::svg::Color>().build())
+    }
+
+    /// Derives the setup hash, i.e. the Base64 encoded first 4 bytes of the SHA-512 hash of setup ID and device ID.
+    fn setup_hash(&self) -> String {
+        let mut hasher = Sha512::new();
+        hasher.update(self.setup_id.as_bytes());
+        hasher.update(self.device_id.to_string().as_bytes());
+        let hash = hasher.finalize();
+
+        BASE64.encode(&hash[..4])
+    }
 }
 
 impl Default for Config {
@@ -95,8 +248,12 @@ impl Default for Config {
         Config {
             host: get_local_ip(),
             port: 32000,
+            mdns_interfaces: Vec::new(),
+            mdns_ips: Vec::new(),
+            mdns_ttl: default_mdns_ttl(),
             pin: Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap(),
             name: "Accessory".into(),
+            bonjour_name: None,
             device_id: generate_random_mac_address(),
             device_ed25519_keypair: generate_ed25519_keypair(),
             configuration_number: 1,
@@ -106,8 +263,60 @@ impl Default for Config {
             status_flag: BonjourStatusFlag::NotPaired,
             feature_flag: BonjourFeatureFlag::Zero,
             max_peers: None,
+            setup_id: generate_setup_id(),
+            setup_nfc: false,
+            max_tlv_body_size: default_max_tlv_body_size(),
+            event_coalescing_window: None,
+            max_connections: None,
+            connection_idle_timeout: None,
+            event_queue_capacity: default_event_queue_capacity(),
+            event_backpressure: EventBackpressure::Block,
+            pairing_approver: None,
+            snapshot_handler: None,
+            authorization_validator: None,
+            tlv_observer: None,
+        }
+    }
+}
+
+/// Setup payload flag indicating an NFC tag carrying the setup payload.
+const SETUP_FLAG_NFC: u64 = 1;
+/// Setup payload flag indicating support for the IP transport.
+const SETUP_FLAG_IP: u64 = 2;
+
+/// Characters allowed in setup IDs and used for Base36 encoding.
+const BASE36_ALPHABET: &[u8; 36] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Returns the default maximum size of TLV request bodies.
+fn default_max_tlv_body_size() -> usize { 16 * 1024 }
+
+/// Returns the default time to live of mDNS records.
+fn default_mdns_ttl() -> u32 { 60 }
+
+/// Returns the default capacity of the event queues of listeners.
+fn default_event_queue_capacity() -> usize { 64 }
+
+/// Generates a random setup ID.
+fn generate_setup_id() -> String {
+    let mut csprng = OsRng {};
+    (0..4)
+        .map(|_| BASE36_ALPHABET[csprng.gen_range(0..BASE36_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Encodes a number as uppercase Base36.
+fn encode_base36(mut n: u64) -> String {
+    let mut digits = Vec::new();
+    loop {
+        digits.push(BASE36_ALPHABET[(n % 36) as usize]);
+        n /= 36;
+        if n == 0 {
+            break;
         }
     }
+    digits.reverse();
+
+    String::from_utf8(digits).expect("Base36 digits are valid UTF-8")
 }
 
 /// Generates a random MAC address.
@@ -123,12 +332,80 @@ fn generate_ed25519_keypair() -> Ed25519Keypair {
     Ed25519Keypair::generate(&mut csprng)
 }
 
-/// Returns the IP of the system's first non-loopback network interface or defaults to `127.0.0.1`.
+/// Returns the IP of the system's first non-loopback IPv4 network interface, falling back to the first non-loopback,
+/// non-link-local IPv6 address or defaulting to `127.0.0.1`.
+///
+/// Link-local IPv6 addresses are skipped because they can't be bound to without a scope ID.
 fn get_local_ip() -> IpAddr {
-    for iface in if_addrs::get_if_addrs().unwrap() {
-        if !iface.is_loopback() {
-            return iface.ip();
-        }
+    let ips: Vec<IpAddr> = if_addrs::get_if_addrs()
+        .unwrap()
+        .into_iter()
+        .filter(|iface| !iface.is_loopback())
+        .map(|iface| iface.ip())
+        .collect();
+
+    ips.iter()
+        .find(|ip| ip.is_ipv4())
+        .or_else(|| {
+            ips.iter().find(|ip| match ip {
+                IpAddr::V6(ip) => !is_unicast_link_local(ip),
+                IpAddr::V4(_) => false,
+            })
+        })
+        .copied()
+        .unwrap_or_else(|| "127.0.0.1".parse().unwrap())
+}
+
+/// Returns whether an IPv6 address is in the unicast link-local range `fe80::/10`.
+fn is_unicast_link_local(ip: &Ipv6Addr) -> bool { (ip.segments()[0] & 0xffc0) == 0xfe80 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_unicast_link_local() {
+        assert!(is_unicast_link_local(&"fe80::1".parse().unwrap()));
+        assert!(is_unicast_link_local(&"febf::1".parse().unwrap()));
+        assert!(!is_unicast_link_local(&"fec0::1".parse().unwrap()));
+        assert!(!is_unicast_link_local(&"2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_setup_payload() {
+        let config = Config {
+            pin: Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap(),
+            category: AccessoryCategory::Lightbulb,
+            setup_id: "ABCD".into(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.setup_payload(), "X-HM://00527813XABCD");
+
+        let config = Config {
+            setup_nfc: true,
+            ..config
+        };
+
+        assert_eq!(config.setup_payload(), "X-HM://0054F4S65ABCD");
+    }
+
+    #[test]
+    fn test_setup_hash() {
+        let config = Config {
+            device_id: MacAddress::from([10, 20, 30, 40, 50, 60]),
+            setup_id: "ABCD".into(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.setup_hash(), "5d6Z4w==");
+    }
+
+    #[test]
+    fn test_generate_setup_id() {
+        let setup_id = generate_setup_id();
+
+        assert_eq!(setup_id.len(), 4);
+        assert!(setup_id.bytes().all(|b| BASE36_ALPHABET.contains(&b)));
     }
-    "127.0.0.1".parse().unwrap()
 }
@@ -1,4 +1,4 @@
-use std::{io, num, str, sync::mpsc};
+use std::{io, net::SocketAddr, num, str, sync::mpsc};
 use thiserror::Error;
 
 use crate::characteristic::Format;
@@ -11,9 +11,7 @@ pub enum Error {
     PinTooEasy,
     #[error("The PIN contains invalid digits. You may only use numbers from 0 to 9.")]
     InvalidPin,
-    #[error(
-        "Invalid pairing permission Byte: {0}. Only `Permissions::User = 0x00` and `Permissions::Admin = 0x01` are allowed."
-    )]
+    #[error("Invalid pairing permission Byte: {0}. Pairing permissions have to fit into a single Byte.")]
     InvalidPairingPermission(u8),
     #[error("The value is below the `min_value` of the characteristic.")]
     ValueBelowMinValue,
@@ -21,12 +19,32 @@ pub enum Error {
     ValueAboveMaxValue,
     #[error("The selected accessory is not present on the server.")]
     AccessoryNotFound,
+    #[error("The selected characteristic is not present on the accessory.")]
+    CharacteristicNotFound,
     #[error("The provided accessory was already added to the server.")]
     DuplicateAccessory,
+    #[error("A bridge accessory with the accessory ID 1 has to be added before adding bridged accessories.")]
+    BridgeNotFound,
+    #[error(
+        "The accessories with the IDs {0} and {1} share the serial number `{2}`. Serial numbers have to be unique."
+    )]
+    DuplicateSerialNumber(u64, u64, String),
+    #[error("A pair setup is in progress.")]
+    PairSetupInProgress,
+    #[error("The pairing was rejected.")]
+    PairingRejected,
     #[error(
         "The provided value has an invalid data type for the characteristic. The characteristic's format is {0:?}."
     )]
     InvalidValue(Format),
+    #[error("The value {0} isn't among the valid values of the characteristic.")]
+    DisallowedValue(serde_json::Value),
+    #[error("Invalid revision `{0}`. Revisions must have the format `x[.y[.z]]`, e.g. `1.0.2`.")]
+    InvalidRevision(String),
+    #[error("The accessory needs at least one {0} service.")]
+    NoServices(&'static str),
+    #[error("The accessory already has a {0} service.")]
+    DuplicateService(&'static str),
     #[error("Invalid HapType string value: `{0}`.")]
     InvalidHapTypeString(String),
     #[error("Error on value read: {0}")]
@@ -35,6 +53,14 @@ pub enum Error {
     ValueOnUpdate(Box<dyn std::error::Error + Send + Sync>),
     #[error("Error interacting with the storage.")]
     Storage,
+    #[error("Error initializing the server from the storage: {0}")]
+    StorageInit(#[source] Box<Error>),
+    #[error("Couldn't bind the TCP listener to {0}: {1}")]
+    Bind(SocketAddr, #[source] io::Error),
+    #[error("The request body exceeds the maximum size of {0} bytes.")]
+    RequestBodyTooLarge(usize),
+    #[error("The nonce counter of the encrypted session is exhausted. The controller has to pair-verify again.")]
+    NonceCounterExhausted,
 
     // converted errors
     #[error("IO Error: {0}")]
@@ -61,6 +87,15 @@ pub enum Error {
     ParseInt(#[from] num::ParseIntError),
     #[error("MPSC Send Error: {0}")]
     MpscSend(#[from] mpsc::SendError<()>),
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite Error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(feature = "qr")]
+    #[error("QR Code Error: {0}")]
+    QrCode(#[from] qrcode::types::QrError),
+    #[cfg(any(test, feature = "test-util"))]
+    #[error("Test Controller Error: {0}")]
+    TestController(String),
 }
 
 impl From<aead::Error> for Error {
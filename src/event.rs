@@ -1,31 +1,300 @@
-use futures::future::{join_all, BoxFuture};
-use log::debug;
+use futures::future::{join_all, BoxFuture, Future, FutureExt};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::fmt::Debug;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex as StdMutex},
+};
+use tokio::sync::{broadcast, mpsc};
 use uuid::Uuid;
 
-#[derive(Debug)]
+use crate::pointer;
+
+/// An event emitted by the HAP server.
+#[derive(Debug, Clone)]
 pub enum Event {
+    /// A controller was paired or its pairing was updated.
     ControllerPaired { id: Uuid },
+    /// A controller was unpaired.
     ControllerUnpaired { id: Uuid },
+    /// A paired controller set up an encrypted session via pair verify.
+    ControllerConnected { controller_id: Uuid },
+    /// The connection of a controller with an encrypted session was closed.
+    ControllerDisconnected { controller_id: Uuid },
+    /// The accessory went from having no pairings to having at least one (`paired: true`) or vice versa (`paired:
+    /// false`).
+    PairedStateChanged { paired: bool },
+    /// The value of a characteristic changed.
     CharacteristicValueChanged { aid: u64, iid: u64, value: Value },
+    /// A controller asked the accessory to identify itself, e.g. by blinking a light, either by writing the `Identify`
+    /// characteristic or, for unpaired accessories, via the `/identify` endpoint.
+    Identify { aid: u64 },
+    /// A controller connection enabled event notifications for a characteristic.
+    CharacteristicSubscribed { aid: u64, iid: u64 },
+    /// A controller connection disabled event notifications for a characteristic, either explicitly or by closing the
+    /// connection.
+    CharacteristicUnsubscribed { aid: u64, iid: u64 },
+    /// An accessory was removed from the server. All event subscriptions to its characteristics are dropped.
+    AccessoryRemoved { aid: u64 },
+}
+
+/// Behavior of the event queue of a listener added with
+/// [`IpServer::add_event_listener`](crate::server::IpServer::add_event_listener) or
+/// [`IpServer::subscribe_characteristic`](crate::server::IpServer::subscribe_characteristic) once the queue is full,
+/// i.e. when the listener doesn't keep up with the emitted events.
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EventBackpressure {
+    /// Emitting an event waits until there is room in the queue again. No events are lost, but a slow listener holds
+    /// up the request that caused the event.
+    #[default]
+    Block,
+    /// The oldest event in the queue is dropped to make room for the new one. Emitting never waits for the listener.
+    DropOldest,
 }
 
 #[derive(Default)]
 pub struct EventEmitter {
-    listeners: Vec<Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>>,
+    listeners: Vec<Arc<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>>,
+    characteristic_listeners: HashMap<(u64, u64), Vec<Arc<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>>>,
 }
 
 impl EventEmitter {
-    pub fn new() -> EventEmitter { EventEmitter { listeners: vec![] } }
+    pub fn new() -> EventEmitter { EventEmitter::default() }
 
     pub fn add_listener(&mut self, listener: Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>) {
-        self.listeners.push(listener);
+        self.listeners.push(Arc::from(listener));
+    }
+
+    /// Adds a listener that is only called with `Event::CharacteristicValueChanged` events of the characteristic with
+    /// the given accessory and instance IDs.
+    pub fn subscribe_characteristic(
+        &mut self,
+        aid: u64,
+        iid: u64,
+        listener: Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>,
+    ) {
+        self.characteristic_listeners
+            .entry((aid, iid))
+            .or_default()
+            .push(Arc::from(listener));
+    }
+
+    /// Returns the listeners that are called with the given event.
+    fn listeners_of(&self, event: &Event) -> Vec<Arc<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>> {
+        let characteristic_listeners = match *event {
+            Event::CharacteristicValueChanged { aid, iid, .. } => self.characteristic_listeners.get(&(aid, iid)),
+            _ => None,
+        };
+
+        self.listeners
+            .iter()
+            .chain(characteristic_listeners.into_iter().flatten())
+            .cloned()
+            .collect()
     }
+}
+
+tokio::task_local! {
+    /// Events emitted within [`with_deferred_events`](with_deferred_events), emitted once it finishes.
+    static DEFERRED_EVENTS: Arc<StdMutex<Vec<Event>>>;
+}
+
+/// Emits an event on a shared [`EventEmitter`](EventEmitter).
+///
+/// The lock of the emitter is only held to look up the listeners and is released before they are called, so listeners
+/// that are running or waiting for room in a full event queue never keep other tasks from emitting events or adding
+/// listeners. Within [`with_deferred_events`](with_deferred_events), the event is only emitted once it finishes.
+pub(crate) async fn emit(event_emitter: &pointer::EventEmitter, event: &Event) {
+    if let Ok(deferred_events) = DEFERRED_EVENTS.try_with(|deferred_events| deferred_events.clone()) {
+        debug!("deferring event: {:?}", event);
+        deferred_events
+            .lock()
+            .expect("accessing deferred events")
+            .push(event.clone());
+        return;
+    }
+
+    debug!("emitting event: {:?}", event);
+
+    let listeners = event_emitter.lock().await.listeners_of(event);
+    join_all(listeners.iter().map(|listener| listener(event))).await;
+}
+
+/// Runs `f` and emits the events emitted within it in order once it has finished.
+///
+/// Characteristics emit their value changes while the caller holds the `AccessoryDatabase` lock. A listener with a full
+/// `EventBackpressure::Block` queue would hold up the emitting task until the listener catches up, while the listener
+/// may itself be waiting for that lock, e.g. to update another characteristic. `f` therefore has to release every lock
+/// it takes before it finishes.
+pub(crate) async fn with_deferred_events<T>(event_emitter: &pointer::EventEmitter, f: impl Future<Output = T>) -> T {
+    let deferred_events = Arc::new(StdMutex::new(Vec::new()));
+    let output = DEFERRED_EVENTS.scope(deferred_events.clone(), f).await;
+
+    let events = std::mem::take(&mut *deferred_events.lock().expect("accessing deferred events"));
+    for event in events {
+        emit(event_emitter, &event).await;
+    }
+
+    output
+}
+
+/// Wraps a listener into one that hands the events to a bounded queue of `capacity` events, from which a separate task
+/// calls the wrapped listener in the order the events were emitted. A slow listener thereby only holds up the emitter
+/// while its queue is full and `backpressure` is `EventBackpressure::Block`.
+pub(crate) fn queued_listener(
+    listener: Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>,
+    capacity: usize,
+    backpressure: EventBackpressure,
+) -> Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync> {
+    // both channels panic on a capacity of 0
+    let capacity = capacity.max(1);
+
+    match backpressure {
+        EventBackpressure::Block => {
+            let (sender, mut receiver) = mpsc::channel::<Event>(capacity);
+            tokio::spawn(async move {
+                while let Some(event) = receiver.recv().await {
+                    listener(&event).await;
+                }
+            });
+
+            Box::new(move |event| {
+                let sender = sender.clone();
+                let event = event.clone();
+                async move {
+                    sender.send(event).await.ok();
+                }
+                .boxed()
+            })
+        },
+        EventBackpressure::DropOldest => {
+            let (sender, mut receiver) = broadcast::channel::<Event>(capacity);
+            tokio::spawn(async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) => listener(&event).await,
+                        Err(broadcast::error::RecvError::Lagged(count)) => {
+                            warn!("event listener fell behind; dropped the {} oldest events", count);
+                        },
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            Box::new(move |event| {
+                sender.send(event.clone()).ok();
+                async {}.boxed()
+            })
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribe_characteristic() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_ = calls.clone();
+
+        let mut event_emitter = EventEmitter::new();
+        event_emitter.subscribe_characteristic(
+            1,
+            9,
+            Box::new(move |_| {
+                calls_.fetch_add(1, Ordering::SeqCst);
+                async {}.boxed()
+            }),
+        );
+        let event_emitter: pointer::EventEmitter = Arc::new(futures::lock::Mutex::new(event_emitter));
+
+        emit(&event_emitter, &Event::CharacteristicValueChanged {
+            aid: 1,
+            iid: 9,
+            value: json!(true),
+        })
+        .await;
+        emit(&event_emitter, &Event::CharacteristicValueChanged {
+            aid: 2,
+            iid: 9,
+            value: json!(true),
+        })
+        .await;
+        emit(&event_emitter, &Event::ControllerUnpaired { id: Uuid::nil() }).await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    /// Returns a listener recording the accessory IDs of `Event::Identify` events.
+    fn recording_listener(
+        aids: Arc<std::sync::Mutex<Vec<u64>>>,
+    ) -> Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync> {
+        Box::new(move |event| {
+            if let Event::Identify { aid } = *event {
+                aids.lock().unwrap().push(aid);
+            }
+            async {}.boxed()
+        })
+    }
+
+    /// Yields to the spawned queue tasks until `aids` holds `count` accessory IDs.
+    async fn wait_for(aids: &Arc<std::sync::Mutex<Vec<u64>>>, count: usize) {
+        for _ in 0..100 {
+            if aids.lock().unwrap().len() >= count {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocking_queue_delivers_all_events() {
+        let aids = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let mut event_emitter = EventEmitter::new();
+        event_emitter.add_listener(queued_listener(
+            recording_listener(aids.clone()),
+            1,
+            EventBackpressure::Block,
+        ));
+        let event_emitter: pointer::EventEmitter = Arc::new(futures::lock::Mutex::new(event_emitter));
+
+        for aid in 1..=3 {
+            emit(&event_emitter, &Event::Identify { aid }).await;
+        }
+        wait_for(&aids, 3).await;
+
+        assert_eq!(*aids.lock().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_queue_drops_oldest_events() {
+        let aids = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let mut event_emitter = EventEmitter::new();
+        event_emitter.add_listener(queued_listener(
+            recording_listener(aids.clone()),
+            2,
+            EventBackpressure::DropOldest,
+        ));
+        let event_emitter: pointer::EventEmitter = Arc::new(futures::lock::Mutex::new(event_emitter));
 
-    pub async fn emit(&self, event: &Event) {
-        debug!("emitting event: {:?}", event);
+        // the queue task can't run before the test yields, so the queue overflows
+        for aid in 1..=5 {
+            emit(&event_emitter, &Event::Identify { aid }).await;
+        }
+        wait_for(&aids, 2).await;
 
-        join_all(self.listeners.iter().map(|listener| listener(&event))).await;
+        assert_eq!(*aids.lock().unwrap(), vec![4, 5]);
     }
 }
@@ -6,22 +6,29 @@ pub use serde_json;
 pub use crate::{
     config::Config,
     error::Error,
+    event::{Event, EventBackpressure},
     hap_type::HapType,
+    metrics::MetricsSnapshot,
     pin::Pin,
-    transport::bonjour::{BonjourFeatureFlag, BonjourStatusFlag},
+    transport::{
+        bonjour::{BonjourFeatureFlag, BonjourStatusFlag},
+        ConnectionInfo,
+    },
 };
 
 mod config;
 mod error;
 mod event;
 mod hap_type;
+mod metrics;
 mod pin;
 mod pointer;
-mod tlv;
 mod transport;
 
 /// Definitions of HomeKit accessories.
 pub mod accessory;
+/// Camera snapshots and RTP streaming.
+pub mod camera;
 /// Definitions of HomeKit characteristics.
 pub mod characteristic;
 /// Representation of paired controllers.
@@ -32,6 +39,11 @@ pub mod server;
 pub mod service;
 /// Representations of persistent storage.
 pub mod storage;
+/// A controller for testing accessories without an iOS device.
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+/// The TLV8 format of the pairing endpoints.
+pub mod tlv;
 
 /// `Result` type redefinition.
 pub type Result<T> = std::result::Result<T, Error>;
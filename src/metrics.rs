@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of the server, updated by the HTTP handlers and the connection handling.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    pairings_added: AtomicU64,
+    pairings_removed: AtomicU64,
+    pair_setup_attempts: AtomicU64,
+    pair_setup_failures: AtomicU64,
+    active_connections: AtomicU64,
+    event_notifications_sent: AtomicU64,
+}
+
+impl Metrics {
+    /// Records a pairing added by pair setup or an add pairing request.
+    pub(crate) fn record_pairing_added(&self) { self.pairings_added.fetch_add(1, Ordering::Relaxed); }
+
+    /// Records removed pairings.
+    pub(crate) fn record_pairings_removed(&self, count: u64) {
+        self.pairings_removed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Records a received pair setup M1 request.
+    pub(crate) fn record_pair_setup_attempt(&self) { self.pair_setup_attempts.fetch_add(1, Ordering::Relaxed); }
+
+    /// Records a pair setup that ended with an error.
+    pub(crate) fn record_pair_setup_failure(&self) { self.pair_setup_failures.fetch_add(1, Ordering::Relaxed); }
+
+    /// Records an accepted connection.
+    pub(crate) fn record_connection_opened(&self) { self.active_connections.fetch_add(1, Ordering::Relaxed); }
+
+    /// Records a closed connection.
+    pub(crate) fn record_connection_closed(&self) {
+        self.active_connections
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .ok();
+    }
+
+    /// Records an event notification sent to a controller.
+    pub(crate) fn record_event_notification_sent(&self) {
+        self.event_notifications_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current values of all counters.
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            pairings_added: self.pairings_added.load(Ordering::Relaxed),
+            pairings_removed: self.pairings_removed.load(Ordering::Relaxed),
+            pair_setup_attempts: self.pair_setup_attempts.load(Ordering::Relaxed),
+            pair_setup_failures: self.pair_setup_failures.load(Ordering::Relaxed),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            event_notifications_sent: self.event_notifications_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A snapshot of the counters of a server since it was created. Obtained by calling
+/// [`IpServer::metrics`](crate::server::IpServer::metrics).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// Number of pairings added by pair setup or by add pairing requests of admin controllers.
+    pub pairings_added: u64,
+    /// Number of pairings removed by remove pairing requests or by
+    /// [`IpServer::unpair_all`](crate::server::IpServer::unpair_all).
+    pub pairings_removed: u64,
+    /// Number of pair setup attempts, i.e. received M1 requests.
+    pub pair_setup_attempts: u64,
+    /// Number of pair setup steps answered with an error, e.g. because of a wrong setup code.
+    pub pair_setup_failures: u64,
+    /// Number of currently open connections.
+    pub active_connections: u64,
+    /// Number of event notifications sent to controllers.
+    pub event_notifications_sent: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let metrics = Metrics::default();
+
+        metrics.record_pair_setup_attempt();
+        metrics.record_pair_setup_attempt();
+        metrics.record_pair_setup_failure();
+        metrics.record_pairing_added();
+        metrics.record_pairings_removed(1);
+        metrics.record_connection_opened();
+        metrics.record_connection_opened();
+        metrics.record_connection_closed();
+        metrics.record_event_notification_sent();
+
+        assert_eq!(metrics.snapshot(), MetricsSnapshot {
+            pairings_added: 1,
+            pairings_removed: 1,
+            pair_setup_attempts: 2,
+            pair_setup_failures: 1,
+            active_connections: 1,
+            event_notifications_sent: 1,
+        });
+    }
+
+    #[test]
+    fn test_active_connections_dont_underflow() {
+        let metrics = Metrics::default();
+
+        metrics.record_connection_closed();
+
+        assert_eq!(metrics.snapshot().active_connections, 0);
+    }
+}
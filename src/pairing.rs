@@ -1,4 +1,6 @@
-use serde::{Deserialize, Serialize};
+use futures::future::BoxFuture;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, ops::BitOr, sync::Arc};
 use uuid::Uuid;
 
 use crate::{Error, Result};
@@ -34,6 +36,54 @@ impl Pairing {
     }
 }
 
+/// A [`PairingApprover`](PairingApprover) decides whether a new controller pairing may be saved, e.g. after a
+/// physical confirmation on the device or by checking the controller ID against an allowlist.
+///
+/// It's called before a new pairing is saved, both on the initial pair setup and when an admin controller adds a
+/// pairing. Returning an error rejects the pairing with an authentication error.
+///
+/// # Examples
+/// ```
+/// use hap::{futures::FutureExt, pairing::PairingApprover, Config, Error};
+/// use uuid::Uuid;
+///
+/// let allowed_id = Uuid::parse_str("bc158b86-cabf-432d-aee4-422ef0e3f1d5").unwrap();
+///
+/// let config = Config {
+///     pairing_approver: Some(PairingApprover::new(move |pairing| {
+///         let approved = pairing.id == allowed_id;
+///         async move {
+///             if approved {
+///                 Ok(())
+///             } else {
+///                 Err(Error::PairingRejected)
+///             }
+///         }
+///         .boxed()
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct PairingApprover(Arc<dyn Fn(&Pairing) -> BoxFuture<'static, Result<()>> + Send + Sync>);
+
+impl PairingApprover {
+    /// Creates a new [`PairingApprover`](PairingApprover) from a callback.
+    pub fn new<F>(approve: F) -> PairingApprover
+    where
+        F: Fn(&Pairing) -> BoxFuture<'static, Result<()>> + Send + Sync + 'static,
+    {
+        PairingApprover(Arc::new(approve))
+    }
+
+    /// Calls the callback for a pairing that's about to be saved.
+    pub(crate) async fn approve(&self, pairing: &Pairing) -> Result<()> { (self.0)(pairing).await }
+}
+
+impl fmt::Debug for PairingApprover {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("PairingApprover") }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -42,7 +92,7 @@ mod tests {
     fn test_pairing_from_bytes() {
         let pairing = Pairing {
             id: Uuid::parse_str("bc158b86-cabf-432d-aee4-422ef0e3f1d5").unwrap(),
-            permissions: Permissions::Admin,
+            permissions: Permissions::ADMIN,
             public_key: [
                 215, 90, 152, 1, 130, 177, 10, 183, 213, 75, 254, 211, 201, 100, 7, 58, 14, 225, 114, 243, 218, 166,
                 35, 37, 175, 2, 26, 104, 247, 7, 81, 26,
@@ -58,7 +108,7 @@ mod tests {
     fn test_pairing_to_bytes() {
         let pairing = Pairing {
             id: Uuid::parse_str("bc158b86-cabf-432d-aee4-422ef0e3f1d5").unwrap(),
-            permissions: Permissions::User,
+            permissions: Permissions::USER,
             public_key: [
                 215, 90, 152, 1, 130, 177, 10, 183, 213, 75, 254, 211, 201, 100, 7, 58, 14, 225, 114, 243, 218, 166,
                 35, 37, 175, 2, 26, 104, 247, 7, 81, 26,
@@ -69,34 +119,105 @@ mod tests {
             b"{\"id\":\"bc158b86-cabf-432d-aee4-422ef0e3f1d5\",\"permissions\":\"0x00\",\"public_key\":[215,90,152,1,130,177,10,183,213,75,254,211,201,100,7,58,14,225,114,243,218,166,35,37,175,2,26,104,247,7,81,26]}".to_vec()
         );
     }
+
+    #[test]
+    fn test_permissions_from_bytes() {
+        assert_eq!(Permissions::from_bytes(&[0x01]).unwrap(), Permissions::ADMIN);
+        assert_eq!(Permissions::from_bytes(&[0x80, 0x00]).unwrap().as_byte(), 0x80);
+        assert!(matches!(
+            Permissions::from_bytes(&[0x01, 0x02]),
+            Err(Error::InvalidPairingPermission(0x02))
+        ));
+        assert!(Permissions::from_bytes(&[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pairing_approver() {
+        use futures::FutureExt;
+
+        let allowed_id = Uuid::parse_str("bc158b86-cabf-432d-aee4-422ef0e3f1d5").unwrap();
+        let approver = PairingApprover::new(move |pairing| {
+            let approved = pairing.id == allowed_id;
+            async move {
+                if approved {
+                    Ok(())
+                } else {
+                    Err(crate::Error::PairingRejected)
+                }
+            }
+            .boxed()
+        });
+
+        let allowed = Pairing::new(allowed_id, Permissions::ADMIN, [0; 32]);
+        let other = Pairing::new(Uuid::new_v4(), Permissions::ADMIN, [0; 32]);
+        assert!(approver.approve(&allowed).await.is_ok());
+        assert!(approver.approve(&other).await.is_err());
+    }
 }
 
 /// The permissions of a paired controller.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub enum Permissions {
-    #[serde(rename = "0x00")]
-    User,
+///
+/// Permissions are a bit field. Bit 0 is the admin bit; the remaining bits are reserved and are preserved as they are
+/// when a pairing is saved again.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Permissions(u8);
+
+impl Permissions {
     /// Admins are pairings that have the admin bit set. Admins are exclusively authorized to add, remove, and list
     /// pairings.
-    #[serde(rename = "0x01")]
-    Admin,
-}
+    pub const ADMIN: Permissions = Permissions(0x01);
+    /// Regular users have no permission bits set.
+    pub const USER: Permissions = Permissions(0x00);
 
-impl Permissions {
-    /// Converts a Byte value to the corresponding `Permissions` variant.
-    pub fn from_byte(byte: u8) -> Result<Permissions> {
-        match byte {
-            0x00 => Ok(Permissions::User),
-            0x01 => Ok(Permissions::Admin),
-            _ => Err(Error::InvalidPairingPermission(byte)),
+    /// Converts a Byte value to `Permissions`, keeping all of its bits.
+    pub const fn from_byte(byte: u8) -> Permissions { Permissions(byte) }
+
+    /// Converts the little-endian value of a `Permissions` TLV item to `Permissions`. Trailing zero Bytes are
+    /// allowed; any other Byte after the first one is out of range and returned as
+    /// [`Error::InvalidPairingPermission`](Error::InvalidPairingPermission).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Permissions> {
+        match bytes.split_first() {
+            Some((byte, rest)) => match rest.iter().find(|b| **b != 0) {
+                Some(b) => Err(Error::InvalidPairingPermission(*b)),
+                None => Ok(Permissions(*byte)),
+            },
+            None => Err(Error::InvalidPairingPermission(0)),
         }
     }
 
-    /// Converts a `Permissions` variant to the corresponding Byte value.
-    pub fn as_byte(&self) -> u8 {
-        match *self {
-            Permissions::User => 0x00,
-            Permissions::Admin => 0x01,
-        }
+    /// Converts `Permissions` to the corresponding Byte value.
+    pub const fn as_byte(&self) -> u8 { self.0 }
+
+    /// Returns `true` if all bits set in `other` are also set in `self`.
+    pub const fn contains(&self, other: Permissions) -> bool { self.0 & other.0 == other.0 }
+
+    /// Sets the bits of `other` on `self`.
+    pub fn insert(&mut self, other: Permissions) { self.0 |= other.0; }
+
+    /// Clears the bits of `other` on `self`.
+    pub fn remove(&mut self, other: Permissions) { self.0 &= !other.0; }
+}
+
+impl BitOr for Permissions {
+    type Output = Permissions;
+
+    fn bitor(self, other: Permissions) -> Permissions { Permissions(self.0 | other.0) }
+}
+
+impl Serialize for Permissions {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{:02x}", self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Permissions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let byte = s
+            .strip_prefix("0x")
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| de::Error::custom(format!("invalid pairing permissions: `{}`", s)))?;
+
+        Ok(Permissions(byte))
     }
 }
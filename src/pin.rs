@@ -54,6 +54,9 @@ impl Pin {
         Ok(Pin { pin })
     }
 
+    /// Returns the pin as a number, e.g. `11122333`.
+    pub(crate) fn to_u32(&self) -> u32 { self.pin.iter().fold(0, |n, digit| n * 10 + *digit as u32) }
+
     // TODO: fix UTF-8 encoding here
     // pub fn as_bytes(&self) -> [u8; 10] {
     //     [
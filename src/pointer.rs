@@ -1,8 +1,30 @@
-use futures::lock::Mutex;
-use std::sync::{Arc, RwLock};
+//! Shared pointers to the state of the server.
+//!
+//! To rule out deadlocks between concurrent requests, the locks behind these pointers are always acquired in the
+//! following order. While holding one of them, only locks further down the list may be acquired:
+//!
+//! 1. `MdnsResponder`, which locks the `Config` when updating its records
+//! 2. `AccessoryDatabase`, then the `Accessory`s in it
+//! 3. `Config`
+//! 4. `Storage`
+//! 5. `EventSubscriptions`
+//!
+//! The `EventEmitter` is never held while acquiring another lock or calling listeners. Events are emitted with
+//! [`event::emit`](crate::event::emit), which only locks the emitter to look up the listeners. Listeners of pairing
+//! events lock the `Config`, the `Storage` and the `MdnsResponder`, so pairing events are emitted without holding any
+//! lock.
+//!
+//! Characteristics emit their value changes while their `Accessory` is locked, so the server defers these events with
+//! `event::with_deferred_events` and emits them once it has released the `AccessoryDatabase`. Listeners may therefore
+//! call back into the server, which would otherwise deadlock a full event queue with
+//! [`EventBackpressure::Block`](crate::event::EventBackpressure::Block). Code that sets characteristic values while
+//! holding an accessory lock of its own doesn't get this protection and must not wait on listeners that lock it.
+
+use futures::{channel::oneshot, lock::Mutex};
+use std::sync::{atomic::AtomicBool, Arc, RwLock};
 use uuid::Uuid;
 
-use crate::{accessory, event, storage};
+use crate::{accessory, event, metrics, storage};
 
 pub type ControllerId = Arc<RwLock<Option<Uuid>>>;
 
@@ -19,3 +41,11 @@ pub type Storage = Arc<Mutex<Box<dyn storage::Storage>>>;
 pub type Config = Arc<Mutex<crate::Config>>;
 
 pub type MdnsResponder = Arc<Mutex<crate::transport::mdns::MdnsResponder>>;
+
+pub type PairSetupInProgress = Arc<AtomicBool>;
+
+pub type PreparedWrite = Arc<Mutex<Option<crate::transport::http::handler::prepare::PreparedWrite>>>;
+
+pub type SessionSender = Arc<Mutex<Option<oneshot::Sender<crate::transport::tcp::Session>>>>;
+
+pub type Metrics = Arc<metrics::Metrics>;
@@ -1,20 +1,38 @@
 use async_trait::async_trait;
 use futures::{
-    future::{BoxFuture, FutureExt},
+    future::{self, BoxFuture, Either, FutureExt},
     lock::Mutex,
 };
-use log::{error, info};
-use std::sync::Arc;
+use log::{error, info, warn};
+use serde_json::json;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{signal, sync::watch};
 
 use crate::{
-    accessory::HapAccessory,
+    accessory::{is_valid_revision, AccessoryCategory, HapAccessory},
+    characteristic::ProgrammableSwitchEvent,
     config::Config,
-    event::{Event, EventEmitter},
+    event::{self, queued_listener, with_deferred_events, Event, EventEmitter},
+    pairing::Pairing,
     pointer,
     server::Server,
     storage::{accessory_database::AccessoryDatabase, Storage},
-    transport::{http::server::Server as HttpServer, mdns::MdnsResponder},
+    transport::{
+        http::{handler::pair_setup, server::Server as HttpServer},
+        mdns::{conflict_free_name, MdnsResponder},
+    },
     BonjourStatusFlag,
+    Error,
+    HapType,
+    MetricsSnapshot,
+    Pin,
     Result,
 };
 
@@ -26,11 +44,36 @@ pub struct IpServer {
     accessory_database: pointer::AccessoryDatabase,
     http_server: HttpServer,
     mdns_responder: pointer::MdnsResponder,
+    event_emitter: pointer::EventEmitter,
+    pair_setup_in_progress: pointer::PairSetupInProgress,
+    metrics: pointer::Metrics,
     aid_cache: Arc<Mutex<Vec<u64>>>,
+    shutdown_sender: Arc<watch::Sender<bool>>,
+    shutdown_receiver: watch::Receiver<bool>,
+}
+
+/// A handle to gracefully shut down a running [`IpServer`](IpServer). Obtained by calling
+/// [`IpServer::shutdown_handle`](IpServer::shutdown_handle).
+#[derive(Clone)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl ShutdownHandle {
+    /// Makes the run handle of the server stop accepting connections, close the open ones, remove the mDNS records,
+    /// save the config and resolve. Triggering the shutdown before the run handle is polled makes it resolve right
+    /// away.
+    pub fn shutdown(&self) {
+        info!("shutting down");
+
+        self.0.send(true).ok();
+    }
 }
 
 impl IpServer {
-    /// Creates a new [`IpServer`](IpServer).
+    /// Creates a new [`IpServer`](IpServer) and binds its TCP listener to the host and port of the config.
+    ///
+    /// Fails with [`Error::Bind`](Error::Bind) if the listener can't be bound, e.g. because the port is in use, and
+    /// with [`Error::StorageInit`](Error::StorageInit) wrapping the storage's error if the pairings, the config or
+    /// the accessory ID cache can't be read from or written to the storage.
     ///
     /// # Examples
     /// ```no_run
@@ -86,30 +129,60 @@ impl IpServer {
     /// }
     /// ```
     pub async fn new<S: Storage + Send + Sync + 'static>(config: Config, storage: S) -> Result<Self> {
+        Self::with_boxed_storage(config, Box::new(storage)).await
+    }
+
+    /// Creates a new [`IpServer`](IpServer) from a boxed [`Storage`](Storage) trait object. This is useful if the
+    /// storage implementation is only known at runtime, e.g. when it's selected by a config file read on startup.
+    /// Fails like [`IpServer::new`](IpServer::new).
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use hap::{
+    ///     server::IpServer,
+    ///     storage::{FileStorage, Storage},
+    ///     Config,
+    ///     Result,
+    /// };
+    ///
+    /// async fn build_server(config: Config) -> Result<IpServer> {
+    ///     let storage: Box<dyn Storage> = Box::new(FileStorage::current_dir().await?);
+    ///
+    ///     IpServer::with_boxed_storage(config, storage).await
+    /// }
+    /// ```
+    pub async fn with_boxed_storage(config: Config, storage: Box<dyn Storage>) -> Result<Self> {
         let config = Arc::new(Mutex::new(config));
-        let storage: pointer::Storage = Arc::new(Mutex::new(Box::new(storage)));
+        let storage: pointer::Storage = Arc::new(Mutex::new(storage));
 
         let config_ = config.clone();
         let storage_ = storage.clone();
         let mut event_emitter = EventEmitter::new();
 
-        let mut s = storage_.lock().await;
-        if s.count_pairings().await? > 0 {
+        let paired = storage_
+            .lock()
+            .await
+            .count_pairings()
+            .await
+            .map_err(storage_init_error)?
+            > 0;
+        let mut c = config_.lock().await;
+        if paired {
             info!("1 or more controllers paired; setting Bonjour status flag to `Zero`");
 
-            let mut c = config_.lock().await;
             c.status_flag = BonjourStatusFlag::Zero;
-
-            s.save_config(&c).await?;
         } else {
             info!("0 controllers paired; setting Bonjour status flag to `Not Paired`");
 
-            let mut c = config_.lock().await;
             c.status_flag = BonjourStatusFlag::NotPaired;
-
-            s.save_config(&c).await?;
         }
-        drop(s);
+        storage_
+            .lock()
+            .await
+            .save_config(&c)
+            .await
+            .map_err(storage_init_error)?;
+        drop(c);
 
         let mdns_responder = Arc::new(Mutex::new(MdnsResponder::new(config.clone()).await));
         let mdns_responder_ = mdns_responder.clone();
@@ -180,12 +253,17 @@ impl IpServer {
         let event_emitter = Arc::new(Mutex::new(event_emitter));
         let accessory_database = Arc::new(Mutex::new(AccessoryDatabase::new(event_emitter.clone())));
 
+        let pair_setup_in_progress = Arc::new(AtomicBool::new(false));
+        let metrics = pointer::Metrics::default();
+
         let http_server = HttpServer::new(
             config.clone(),
             storage.clone(),
             accessory_database.clone(),
-            event_emitter,
+            event_emitter.clone(),
             mdns_responder.clone(),
+            pair_setup_in_progress.clone(),
+            metrics.clone(),
         );
 
         let mut storage_lock = storage.lock().await;
@@ -194,43 +272,351 @@ impl IpServer {
             Err(_) => {
                 storage_lock.delete_aid_cache().await.ok();
                 let aid_cache = Vec::new();
-                storage_lock.save_aid_cache(&aid_cache).await?;
+                storage_lock
+                    .save_aid_cache(&aid_cache)
+                    .await
+                    .map_err(storage_init_error)?;
                 aid_cache
             },
         }));
         drop(storage_lock);
 
+        http_server.bind().await?;
+
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+
         let server = IpServer {
             config,
             storage,
             accessory_database,
             http_server,
             mdns_responder,
+            event_emitter,
+            pair_setup_in_progress,
+            metrics,
             aid_cache,
+            shutdown_sender: Arc::new(shutdown_sender),
+            shutdown_receiver,
         };
 
         Ok(server)
     }
+
+    /// Returns a [`ShutdownHandle`](ShutdownHandle) to gracefully shut down the server, e.g. from a signal handler.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use hap::{
+    ///     server::{IpServer, Server},
+    ///     Result,
+    /// };
+    ///
+    /// async fn run_for_an_hour(server: IpServer) -> Result<()> {
+    ///     let shutdown_handle = server.shutdown_handle();
+    ///     tokio::spawn(async move {
+    ///         tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    ///         shutdown_handle.shutdown();
+    ///     });
+    ///
+    ///     server.run_handle().await
+    /// }
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle { ShutdownHandle(self.shutdown_sender.clone()) }
+
+    /// Returns the address the TCP listener of the server is bound to.
+    ///
+    /// With the port of the config set to `0`, the listener is bound to an ephemeral port chosen by the OS. That port
+    /// is reported here and announced via mDNS, but not saved to the config.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.http_server
+            .local_addr()
+            .expect("the TCP listener is bound on creation")
+    }
+
+    /// Removes all paired controllers from the server, e.g. on a factory reset. An `Event::ControllerUnpaired` is
+    /// emitted for every removed controller, which puts the accessory back into the unpaired, discoverable state once
+    /// the last pairing is gone.
+    ///
+    /// The persisted record of failed pair setup attempts is cleared as well. Once more than 100 attempts failed, pair
+    /// setup is refused until then.
+    pub async fn unpair_all(&self) -> Result<()> {
+        let ids = self.storage.lock().await.delete_all_pairings().await?;
+        pair_setup::reset_failed_attempts(&self.storage).await;
+
+        info!("removed {} pairings", ids.len());
+        self.metrics.record_pairings_removed(ids.len() as u64);
+
+        let paired = !ids.is_empty();
+
+        for id in ids {
+            event::emit(&self.event_emitter, &Event::ControllerUnpaired { id }).await;
+        }
+        if paired {
+            event::emit(&self.event_emitter, &Event::PairedStateChanged { paired: false }).await;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the pairings of all currently paired controllers, e.g. to show them in a management UI.
+    pub async fn list_pairings(&self) -> Result<Vec<Pairing>> { self.storage.lock().await.list_pairings().await }
+
+    /// Returns a snapshot of the server's counters, e.g. pairing activity, open connections and sent event
+    /// notifications, to export to a monitoring system.
+    pub fn metrics(&self) -> MetricsSnapshot { self.metrics.snapshot() }
+
+    /// Returns whether a controller is currently in the middle of a pair setup, which makes pair setups of other
+    /// controllers fail as busy.
+    pub fn pair_setup_in_progress(&self) -> bool { self.pair_setup_in_progress.load(Ordering::SeqCst) }
+
+    /// Closes all connections that haven't established an encrypted session yet, dropping any half-open pair setup or
+    /// pair verify, e.g. of a controller that abandoned pairing mid-flow and now gets its retries rejected as busy.
+    /// Connections with an encrypted session are kept. Returns the number of closed connections.
+    pub fn reset_sessions(&self) -> usize { self.http_server.reset_sessions(None) }
+
+    /// Like [`reset_sessions`](IpServer::reset_sessions), but only closes the connections of the controller at the
+    /// given IP address.
+    pub fn reset_session(&self, peer_ip: IpAddr) -> usize { self.http_server.reset_sessions(Some(peer_ip)) }
+
+    /// Increments the configuration number (`c#`), saves it and re-announces the mDNS records, making controllers
+    /// refetch the accessory database.
+    ///
+    /// Adding and removing accessories does this automatically. Call it after changing the services or characteristics
+    /// of an accessory, e.g. by adding services to it between restarts.
+    pub async fn increment_config_number(&self) -> Result<()> {
+        let mut c = self.config.lock().await;
+        // valid configuration numbers are 1 to 65535 and wrap around to 1
+        c.configuration_number = if c.configuration_number >= 65535 {
+            1
+        } else {
+            c.configuration_number + 1
+        };
+        self.storage.lock().await.save_config(&c).await?;
+
+        info!("configuration number incremented to {}", c.configuration_number);
+
+        drop(c);
+
+        self.mdns_responder.lock().await.update_records().await;
+
+        Ok(())
+    }
+
+    /// Sets a new setup code, e.g. to rotate it after a failed pairing attempt. The new pin is used by every pair setup
+    /// started after the change and re-advertised if the accessory isn't paired yet.
+    ///
+    /// Fails with [`Error::PairSetupInProgress`](Error::PairSetupInProgress) while a controller is in the middle of a
+    /// pair setup.
+    pub async fn set_pin(&self, pin: Pin) -> Result<()> {
+        // pair setup M1 marks the pair setup as in progress before it locks the config to read the pin, so checking the
+        // flag while holding the config lock ensures no pair setup runs with the pin that's replaced
+        let mut c = self.config.lock().await;
+        if self.pair_setup_in_progress.load(Ordering::SeqCst) {
+            return Err(Error::PairSetupInProgress);
+        }
+
+        c.pin = pin;
+        self.storage.lock().await.save_config(&c).await?;
+        drop(c);
+
+        info!("setup code changed");
+
+        if self.storage.lock().await.count_pairings().await? == 0 {
+            self.mdns_responder.lock().await.update_records().await;
+        }
+
+        Ok(())
+    }
+
+    /// Sets whether the accessory advertises itself as discoverable via the Bonjour status flag (`sf`) and
+    /// re-announces the mDNS records.
+    ///
+    /// An accessory can only be discoverable while it has no paired controllers, so passing `true` restores the
+    /// `Not Paired` flag only if that's the case. The flag is derived from the pairing state again on the next pairing
+    /// or unpairing.
+    pub async fn set_discoverable(&self, discoverable: bool) -> Result<()> {
+        let paired = self.storage.lock().await.count_pairings().await? > 0;
+        let status_flag = if discoverable && !paired {
+            BonjourStatusFlag::NotPaired
+        } else {
+            BonjourStatusFlag::Zero
+        };
+
+        info!("setting Bonjour status flag to `{:?}`", status_flag);
+
+        let mut c = self.config.lock().await;
+        c.status_flag = status_flag;
+        self.storage.lock().await.save_config(&c).await?;
+        drop(c);
+
+        self.mdns_responder.lock().await.update_records().await;
+
+        Ok(())
+    }
+
+    /// Renames the mDNS service instance after a name conflict and re-announces the accessory under the new name,
+    /// e.g. `"Acme Lightbulb"` becomes `"Acme Lightbulb (2)"`. The new name is saved as the `bonjour_name` of the
+    /// config and returned.
+    ///
+    /// Name conflicts aren't detected by the server: the mDNS responder neither probes for conflicting instance names
+    /// before announcing nor reports conflicting announcements of other hosts. This has to be called by whatever
+    /// detects the conflict, e.g. an mDNS browser seeing another `_hap._tcp` instance with the same name.
+    pub async fn resolve_bonjour_name_conflict(&self) -> Result<String> {
+        let mut c = self.config.lock().await;
+        let name = conflict_free_name(c.bonjour_name());
+
+        warn!("mDNS name conflict, renaming the accessory to `{}`", &name);
+
+        c.bonjour_name = Some(name.clone());
+        self.storage.lock().await.save_config(&c).await?;
+        drop(c);
+
+        self.mdns_responder.lock().await.update_records().await;
+
+        Ok(name)
+    }
+
+    /// Sets the accessory category advertised via the Bonjour category identifier (`ci`) and re-announces the mDNS
+    /// records.
+    pub async fn set_category(&self, category: AccessoryCategory) -> Result<()> {
+        info!("setting accessory category to `{:?}`", category);
+
+        let mut c = self.config.lock().await;
+        c.category = category;
+        self.storage.lock().await.save_config(&c).await?;
+        drop(c);
+
+        self.mdns_responder.lock().await.update_records().await;
+
+        Ok(())
+    }
+
+    /// Adds a listener that is called with every [`Event`](Event) emitted by the server.
+    ///
+    /// The listener is called from a separate task with the events in the order they were emitted. Events emitted
+    /// while it's still busy are queued; see `Config::event_queue_capacity` and `Config::event_backpressure` for what
+    /// happens when the queue is full. Must be called from within a Tokio runtime.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use hap::{futures::FutureExt, server::IpServer, Event};
+    ///
+    /// async fn watch_pairing_state(server: &IpServer) {
+    ///     server
+    ///         .add_event_listener(Box::new(|event| {
+    ///             async move {
+    ///                 if let Event::PairedStateChanged { paired } = event {
+    ///                     println!("paired: {}", paired);
+    ///                 }
+    ///             }
+    ///             .boxed()
+    ///         }))
+    ///         .await;
+    /// }
+    /// ```
+    pub async fn add_event_listener(&self, listener: Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>) {
+        let listener = self.queue_listener(listener).await;
+        self.event_emitter.lock().await.add_listener(listener);
+    }
+
+    /// Adds a listener that is only called with the `Event::CharacteristicValueChanged` events of a single
+    /// characteristic, identified by its accessory ID `aid` and instance ID `iid`.
+    ///
+    /// Like the listeners of [`add_event_listener`](IpServer::add_event_listener), the listener is called from a
+    /// separate task with its own event queue.
+    pub async fn subscribe_characteristic(
+        &self,
+        aid: u64,
+        iid: u64,
+        listener: Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>,
+    ) {
+        let listener = self.queue_listener(listener).await;
+        self.event_emitter
+            .lock()
+            .await
+            .subscribe_characteristic(aid, iid, listener);
+    }
+
+    /// Wraps a listener into one with an event queue as configured by `event_queue_capacity` and
+    /// `event_backpressure`.
+    async fn queue_listener(
+        &self,
+        listener: Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>,
+    ) -> Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync> {
+        let c = self.config.lock().await;
+        queued_listener(listener, c.event_queue_capacity, c.event_backpressure)
+    }
 }
 
 #[async_trait]
 impl Server for IpServer {
     fn run_handle(&self) -> BoxFuture<Result<()>> {
         let http_handle = self.http_server.run_handle();
+        let http_server = self.http_server.clone();
         let mdns_responder = self.mdns_responder.clone();
+        let config = self.config.clone();
+        let storage = self.storage.clone();
+        let mut shutdown_receiver = self.shutdown_receiver.clone();
 
         let handle = async move {
-            let mdns_handle = mdns_responder.lock().await.run_handle();
+            let mut mdns_handle = mdns_responder.lock().await.run_handle();
+
+            let run = future::try_join(http_handle, (&mut mdns_handle).map(|_| Ok(())));
+            let shutdown = async move {
+                while !*shutdown_receiver.borrow() {
+                    // if the sender is gone, the shutdown can't be triggered anymore
+                    if shutdown_receiver.changed().await.is_err() {
+                        future::pending::<()>().await;
+                    }
+                }
+            }
+            .boxed();
+
+            match future::select(run, shutdown).await {
+                Either::Left((res, _)) => res.map(|_| ()),
+                Either::Right((_, run)) => {
+                    // stop accepting connections and drop the TCP listener, then close the open connections
+                    drop(run);
+                    let closed = http_server.close_connections();
+                    info!("closed {} connections", closed);
+
+                    mdns_responder.lock().await.unregister();
+
+                    // keep only the responder running for a moment to send the goodbye packets
+                    tokio::time::timeout(Duration::from_millis(500), mdns_handle).await.ok();
+
+                    let c = config.lock().await;
+                    storage.lock().await.save_config(&c).await?;
 
-            futures::try_join!(http_handle, mdns_handle.map(|_| Ok(())))?;
+                    info!("server shut down");
 
-            Ok(())
+                    Ok(())
+                },
+            }
         }
         .boxed();
 
         Box::pin(handle)
     }
 
+    async fn run(&self) -> Result<()> {
+        let run = self.run_handle();
+
+        match future::select(run, shutdown_signal().boxed()).await {
+            Either::Left((res, _)) => res,
+            Either::Right((res, run)) => {
+                res?;
+
+                info!("received a shutdown signal");
+
+                self.shutdown_handle().shutdown();
+                run.await
+            },
+        }
+    }
+
     fn config_pointer(&self) -> pointer::Config { self.config.clone() }
 
     fn storage_pointer(&self) -> pointer::Storage { self.storage.clone() }
@@ -243,39 +629,193 @@ impl Server for IpServer {
             .lock()
             .await
             .add_accessory(Box::new(accessory))?;
+        // restored values are emitted as changes once the accessory database is unlocked again
+        with_deferred_events(&self.event_emitter, async {
+            self.accessory_database
+                .lock()
+                .await
+                .restore_characteristic_values(aid, &self.storage)
+                .await
+        })
+        .await?;
 
         let mut aid_cache = self.aid_cache.lock().await;
         if !aid_cache.contains(&aid) {
             aid_cache.push(aid);
             self.storage.lock().await.save_aid_cache(&aid_cache).await?;
+            drop(aid_cache);
 
-            let mut config = self.config.lock().await;
-            config.configuration_number += 1;
-            self.storage.lock().await.save_config(&config).await?;
+            self.increment_config_number().await?;
         }
 
         Ok(accessory)
     }
 
-    async fn remove_accessory(&self, accessory: &pointer::Accessory) -> Result<()> {
-        let aid = accessory.lock().await.get_id();
+    async fn add_bridged_accessory<A, F>(&self, build: F) -> Result<pointer::Accessory>
+    where
+        A: HapAccessory + 'static,
+        F: FnOnce(u64) -> Result<A> + Send,
+    {
+        let mut aids = Vec::new();
+        for accessory in &self.accessory_database.lock().await.accessories {
+            aids.push(accessory.lock().await.get_id());
+        }
+        if !aids.contains(&1) {
+            return Err(Error::BridgeNotFound);
+        }
+        let aid = aids.into_iter().max().unwrap_or(1) + 1;
 
-        self.accessory_database
+        let accessory = build(aid)?;
+        // the accessory database has no per-accessory category, iOS picks the icon of a bridged accessory by its
+        // primary service
+        if !accessory.get_services().iter().any(|service| service.get_primary()) {
+            warn!(
+                "bridged accessory {} has no primary service; iOS can't pick a matching icon for it",
+                aid
+            );
+        }
+
+        let accessory = self.add_accessory(accessory).await?;
+
+        if let Some((first_aid, second_aid, serial_number)) = self
+            .accessory_database
             .lock()
             .await
-            .remove_accessory(&accessory)
-            .await?;
+            .find_duplicate_serial_number()
+            .await
+        {
+            warn!(
+                "accessories {} and {} share the serial number `{}`; iOS will merge them",
+                first_aid, second_aid, serial_number
+            );
+        }
+
+        let mut c = self.config.lock().await;
+        if c.category != AccessoryCategory::Bridge {
+            info!("bridged accessory added; setting accessory category to `Bridge`");
+
+            c.category = AccessoryCategory::Bridge;
+            self.storage.lock().await.save_config(&c).await?;
+            drop(c);
+
+            self.mdns_responder.lock().await.update_records().await;
+        }
+
+        Ok(accessory)
+    }
+
+    async fn remove_accessory(&self, accessory: &pointer::Accessory) -> Result<()> {
+        let aid = accessory.lock().await.get_id();
+
+        self.remove_accessory_by_id(aid).await.map(|_| ())
+    }
+
+    async fn remove_accessory_by_id(&self, aid: u64) -> Result<pointer::Accessory> {
+        let accessory = self.accessory_database.lock().await.remove_accessory_by_id(aid).await?;
+
+        event::emit(&self.event_emitter, &Event::AccessoryRemoved { aid }).await;
 
         let mut aid_cache = self.aid_cache.lock().await;
         if aid_cache.contains(&aid) {
             aid_cache.retain(|id| *id != aid);
             self.storage.lock().await.save_aid_cache(&aid_cache).await?;
+            drop(aid_cache);
 
-            let mut config = self.config.lock().await;
-            config.configuration_number += 1;
+            self.increment_config_number().await?;
         }
 
-        Ok(())
+        Ok(accessory)
+    }
+
+    async fn read_characteristic(&self, aid: u64, iid: u64) -> Result<serde_json::Value> {
+        self.accessory_database
+            .lock()
+            .await
+            .get_characteristic_value(aid, iid)
+            .await
+    }
+
+    async fn update_characteristic(&self, aid: u64, iid: u64, value: serde_json::Value) -> Result<()> {
+        // the value change is emitted once the accessory database is unlocked again
+        with_deferred_events(&self.event_emitter, async {
+            let accessory_database = self.accessory_database.lock().await;
+            accessory_database.set_characteristic_value(aid, iid, value).await?;
+            accessory_database
+                .persist_characteristic_value(aid, iid, &self.storage)
+                .await
+        })
+        .await
+    }
+
+    async fn ring_doorbell(&self, aid: u64) -> Result<()> {
+        with_deferred_events(&self.event_emitter, async {
+            let accessory_database = self.accessory_database.lock().await;
+            let iid = accessory_database
+                .get_characteristic_id(aid, HapType::Doorbell, HapType::ProgrammableSwitchEvent)
+                .await?;
+
+            accessory_database
+                .set_characteristic_value(aid, iid, json!(ProgrammableSwitchEvent::SinglePress as u8))
+                .await
+        })
+        .await
+    }
+
+    async fn trigger_programmable_switch_event(
+        &self,
+        aid: u64,
+        iid: u64,
+        event: ProgrammableSwitchEvent,
+    ) -> Result<()> {
+        with_deferred_events(&self.event_emitter, async {
+            let accessory_database = self.accessory_database.lock().await;
+            if accessory_database.get_characteristic_type(aid, iid).await? != HapType::ProgrammableSwitchEvent {
+                return Err(Error::CharacteristicNotFound);
+            }
+
+            accessory_database
+                .set_characteristic_value(aid, iid, json!(event as u8))
+                .await
+        })
+        .await
+    }
+
+    async fn report_firmware_update(&self, aid: u64, firmware_revision: &str) -> Result<()> {
+        if !is_valid_revision(firmware_revision) {
+            return Err(Error::InvalidRevision(firmware_revision.to_string()));
+        }
+
+        with_deferred_events(&self.event_emitter, async {
+            let accessory_database = self.accessory_database.lock().await;
+            let iid = accessory_database
+                .get_characteristic_id(aid, HapType::AccessoryInformation, HapType::FirmwareRevision)
+                .await?;
+            accessory_database
+                .set_characteristic_value(aid, iid, json!(firmware_revision))
+                .await?;
+            accessory_database
+                .persist_characteristic_value(aid, iid, &self.storage)
+                .await
+        })
+        .await?;
+
+        info!("accessory {} updated to firmware revision {}", aid, firmware_revision);
+
+        self.increment_config_number().await
+    }
+
+    async fn validate(&self) -> Result<()> {
+        match self
+            .accessory_database
+            .lock()
+            .await
+            .find_duplicate_serial_number()
+            .await
+        {
+            Some((first_aid, second_aid, serial_number)) =>
+                Err(Error::DuplicateSerialNumber(first_aid, second_aid, serial_number)),
+            None => Ok(()),
+        }
     }
 
     // async fn factory_reset(&mut self) -> Result<()> {
@@ -284,3 +824,340 @@ impl Server for IpServer {
     //     Ok(())
     // }
 }
+
+/// Resolves once the process receives Ctrl-C or, on Unix, `SIGTERM`.
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+
+        future::select(signal::ctrl_c().boxed(), terminate.recv().map(|_| Ok(())).boxed())
+            .await
+            .factor_first()
+            .0
+    }
+    #[cfg(not(unix))]
+    {
+        signal::ctrl_c().await
+    }
+}
+
+fn storage_init_error(e: Error) -> Error { Error::StorageInit(Box::new(e)) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::AtomicUsize;
+
+    use crate::{
+        accessory::{lightbulb::LightbulbAccessory, outlet::OutletAccessory, AccessoryInformation},
+        characteristic::HapCharacteristic,
+        event::EventBackpressure,
+        pairing::Permissions,
+        storage::MemoryStorage,
+        test_util::{test_config, wait_until},
+    };
+
+    #[tokio::test]
+    async fn test_new_fails_if_the_port_is_in_use() {
+        let config = |port| Config { port, ..test_config() };
+
+        let server = IpServer::new(config(0), MemoryStorage::new()).await.unwrap();
+        let port = server.local_addr().port();
+
+        assert!(matches!(
+            IpServer::new(config(port), MemoryStorage::new()).await,
+            Err(Error::Bind(address, _)) if address.port() == port
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_local_addr_reports_the_assigned_port() {
+        let config = test_config();
+
+        let server = IpServer::new(config, MemoryStorage::new()).await.unwrap();
+        let local_addr = server.local_addr();
+
+        assert_eq!(local_addr.ip(), IpAddr::from([127, 0, 0, 1]));
+        assert_ne!(local_addr.port(), 0);
+        assert_eq!(server.config_pointer().lock().await.port, 0);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_bonjour_name_conflict() {
+        let storage = MemoryStorage::new();
+        let config = Config {
+            name: "Acme Lightbulb".into(),
+            ..test_config()
+        };
+        let server = IpServer::new(config, storage.clone()).await.unwrap();
+        server.mdns_responder.lock().await.update_records().await;
+        assert_eq!(
+            server.mdns_responder.lock().await.announced_name(),
+            Some("Acme Lightbulb")
+        );
+
+        assert_eq!(
+            server.resolve_bonjour_name_conflict().await.unwrap(),
+            "Acme Lightbulb (2)"
+        );
+        assert_eq!(
+            server.mdns_responder.lock().await.announced_name(),
+            Some("Acme Lightbulb (2)")
+        );
+
+        assert_eq!(
+            server.resolve_bonjour_name_conflict().await.unwrap(),
+            "Acme Lightbulb (3)"
+        );
+        assert_eq!(
+            server.mdns_responder.lock().await.announced_name(),
+            Some("Acme Lightbulb (3)")
+        );
+        // the model name is kept, and the instance name survives a restart
+        let saved = storage.load_config().await.unwrap();
+        assert_eq!(saved.name, "Acme Lightbulb");
+        assert_eq!(saved.bonjour_name.as_deref(), Some("Acme Lightbulb (3)"));
+    }
+
+    #[tokio::test]
+    async fn test_report_firmware_update() {
+        let storage = MemoryStorage::new();
+        let server_with_revision = |firmware_revision: &'static str| {
+            let storage = storage.clone();
+            async move {
+                let server = IpServer::new(test_config(), storage).await.unwrap();
+                server
+                    .add_accessory(
+                        OutletAccessory::new(1, AccessoryInformation {
+                            firmware_revision: Some(firmware_revision.into()),
+                            ..Default::default()
+                        })
+                        .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                server
+            }
+        };
+        let server = server_with_revision("1.0").await;
+        let iid = server
+            .accessory_database
+            .lock()
+            .await
+            .get_characteristic_id(1, HapType::AccessoryInformation, HapType::FirmwareRevision)
+            .await
+            .unwrap();
+        let configuration_number = server.config_pointer().lock().await.configuration_number;
+
+        assert!(matches!(
+            server.report_firmware_update(1, "1.1-beta").await,
+            Err(Error::InvalidRevision(_))
+        ));
+
+        server.report_firmware_update(1, "1.1").await.unwrap();
+
+        assert_eq!(server.read_characteristic(1, iid).await.unwrap(), json!("1.1"));
+        assert_eq!(
+            server.config_pointer().lock().await.configuration_number,
+            configuration_number + 1
+        );
+        assert_eq!(storage.load_bytes("firmware_revision_1").await.unwrap(), b"1.1");
+
+        // the reported revision outlives a restart with the old one, but not an update to a newer one
+        let server = server_with_revision("1.0").await;
+        assert_eq!(server.read_characteristic(1, iid).await.unwrap(), json!("1.1"));
+        let server = server_with_revision("1.2").await;
+        assert_eq!(server.read_characteristic(1, iid).await.unwrap(), json!("1.2"));
+    }
+
+    #[tokio::test]
+    async fn test_listeners_can_call_back_into_the_server() {
+        let config = Config {
+            event_queue_capacity: 1,
+            event_backpressure: EventBackpressure::Block,
+            ..test_config()
+        };
+        let server = IpServer::new(config, MemoryStorage::new()).await.unwrap();
+        let mut outlet = OutletAccessory::new(1, AccessoryInformation::default()).unwrap();
+        let power_state_iid = outlet.outlet.power_state.get_id();
+        outlet.outlet.power_state.set_event_notifications(Some(true));
+        server.add_accessory(outlet).await.unwrap();
+
+        let reads = Arc::new(AtomicUsize::new(0));
+        let reads_ = reads.clone();
+        let server_ = server.clone();
+        server
+            .add_event_listener(Box::new(move |event| {
+                let server = server_.clone();
+                let reads = reads_.clone();
+                let changed = matches!(*event, Event::CharacteristicValueChanged { .. });
+                async move {
+                    if changed {
+                        server.read_characteristic(1, power_state_iid).await.unwrap();
+                        reads.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+                .boxed()
+            }))
+            .await;
+
+        let updates = async {
+            for i in 0..16 {
+                server
+                    .update_characteristic(1, power_state_iid, json!(i % 2 == 0))
+                    .await
+                    .unwrap();
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), updates)
+            .await
+            .expect("updating characteristics deadlocked");
+
+        wait_until(|| reads.load(Ordering::SeqCst) == 16).await;
+        assert_eq!(reads.load(Ordering::SeqCst), 16);
+    }
+
+    #[tokio::test]
+    async fn test_listeners_can_call_back_into_the_server_while_values_are_restored() {
+        let config = Config {
+            event_queue_capacity: 1,
+            event_backpressure: EventBackpressure::Block,
+            ..test_config()
+        };
+        let server = IpServer::new(config, MemoryStorage::new()).await.unwrap();
+        let mut lightbulb = LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap();
+        let service = &mut lightbulb.lightbulb;
+        let characteristics: [(&mut dyn HapCharacteristic, serde_json::Value); 4] = [
+            (&mut service.power_state, json!(true)),
+            (service.brightness.as_mut().unwrap(), json!(50)),
+            (service.hue.as_mut().unwrap(), json!(120.0)),
+            (service.saturation.as_mut().unwrap(), json!(50.0)),
+        ];
+        for (characteristic, value) in characteristics {
+            characteristic.set_persistent(true);
+            characteristic.set_event_notifications(Some(true));
+            server
+                .storage
+                .lock()
+                .await
+                .save_characteristic_value(1, characteristic.get_id(), &value)
+                .await
+                .unwrap();
+        }
+
+        let reads = Arc::new(AtomicUsize::new(0));
+        let reads_ = reads.clone();
+        let server_ = server.clone();
+        server
+            .add_event_listener(Box::new(move |event| {
+                let server = server_.clone();
+                let reads = reads_.clone();
+                let changed = match *event {
+                    Event::CharacteristicValueChanged { aid, iid, .. } => Some((aid, iid)),
+                    _ => None,
+                };
+                async move {
+                    if let Some((aid, iid)) = changed {
+                        server.read_characteristic(aid, iid).await.unwrap();
+                        reads.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+                .boxed()
+            }))
+            .await;
+
+        tokio::time::timeout(Duration::from_secs(5), server.add_accessory(lightbulb))
+            .await
+            .expect("restoring characteristic values deadlocked")
+            .unwrap();
+
+        wait_until(|| reads.load(Ordering::SeqCst) == 4).await;
+        assert_eq!(reads.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_set_pin_is_refused_during_pair_setup() {
+        let server = IpServer::new(test_config(), MemoryStorage::new()).await.unwrap();
+        let pin = server.config.lock().await.pin.clone();
+        let new_pin = Pin::new([2, 4, 6, 8, 1, 3, 5, 7]).unwrap();
+
+        server.pair_setup_in_progress.store(true, Ordering::SeqCst);
+        assert!(matches!(
+            server.set_pin(new_pin.clone()).await,
+            Err(Error::PairSetupInProgress)
+        ));
+        assert_eq!(server.config.lock().await.pin, pin);
+
+        server.pair_setup_in_progress.store(false, Ordering::SeqCst);
+        server.set_pin(new_pin.clone()).await.unwrap();
+        assert_eq!(server.config.lock().await.pin, new_pin);
+        assert_eq!(server.storage.lock().await.load_config().await.unwrap().pin, new_pin);
+    }
+
+    #[tokio::test]
+    async fn test_unpair_all_clears_failed_pair_setup_attempts() {
+        let config = test_config();
+        let server = IpServer::new(config, MemoryStorage::new()).await.unwrap();
+
+        // 101 failed attempts, the last one at unix time 0
+        let mut failed_attempts = [0; 12];
+        failed_attempts[0] = 101;
+        server
+            .storage
+            .lock()
+            .await
+            .save_bytes("pair_setup_failed_attempts", &failed_attempts)
+            .await
+            .unwrap();
+
+        server.unpair_all().await.unwrap();
+
+        assert!(server
+            .storage
+            .lock()
+            .await
+            .load_bytes("pair_setup_failed_attempts")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unpair_all_removes_every_pairing() {
+        let config = test_config();
+        let mut storage = MemoryStorage::new();
+        let pairings = [
+            Pairing::new(uuid::Uuid::new_v4(), Permissions::ADMIN, [1; 32]),
+            Pairing::new(uuid::Uuid::new_v4(), Permissions::USER, [2; 32]),
+        ];
+        for pairing in &pairings {
+            storage.save_pairing(pairing).await.unwrap();
+        }
+        let server = IpServer::new(config, storage).await.unwrap();
+        assert_eq!(server.config.lock().await.status_flag, BonjourStatusFlag::Zero);
+
+        // the listener is queued, so it sees the events on a task of its own
+        let unpaired = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let unpaired_ = unpaired.clone();
+        server
+            .add_event_listener(Box::new(move |event| {
+                if let Event::ControllerUnpaired { id } = *event {
+                    unpaired_.lock().unwrap().push(id);
+                }
+                async {}.boxed()
+            }))
+            .await;
+
+        server.unpair_all().await.unwrap();
+
+        wait_until(|| unpaired.lock().unwrap().len() >= pairings.len()).await;
+        let mut unpaired = unpaired.lock().unwrap().clone();
+        unpaired.sort();
+        let mut ids: Vec<_> = pairings.iter().map(|pairing| pairing.id).collect();
+        ids.sort();
+        assert_eq!(unpaired, ids);
+        assert_eq!(server.config.lock().await.status_flag, BonjourStatusFlag::NotPaired);
+        assert!(server.list_pairings().await.unwrap().is_empty());
+    }
+}
@@ -1,8 +1,8 @@
 use async_trait::async_trait;
 use futures::future::BoxFuture;
 
-use crate::{accessory::HapAccessory, pointer, Result};
-pub use ip::IpServer;
+use crate::{accessory::HapAccessory, characteristic::ProgrammableSwitchEvent, pointer, Result};
+pub use ip::{IpServer, ShutdownHandle};
 
 mod ip;
 
@@ -12,14 +12,120 @@ mod ip;
 pub trait Server {
     /// Returns a [`BoxFuture`](BoxFuture) run handle to the server that can be passed to an executor.
     fn run_handle(&self) -> BoxFuture<Result<()>>;
+    /// Runs the server until the process receives Ctrl-C or, on Unix, `SIGTERM`, then shuts it down gracefully.
+    ///
+    /// This is the batteries-included alternative to awaiting [`run_handle`](Server::run_handle) manually. Must be
+    /// called from within a Tokio runtime.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use hap::{
+    ///     server::{IpServer, Server},
+    ///     Result,
+    /// };
+    ///
+    /// async fn serve(server: IpServer) -> Result<()> {
+    ///     env_logger::init();
+    ///
+    ///     server.run().await
+    /// }
+    /// ```
+    async fn run(&self) -> Result<()>;
     /// Returns a pointer to the [`Config`](crate::Config) of the server.
     fn config_pointer(&self) -> pointer::Config;
     /// Returns a pointer to the [`Storage`](crate::storage::Storage) of the server.
     fn storage_pointer(&self) -> pointer::Storage;
     /// Adds an accessory to the server and returns a pointer to it.
+    ///
+    /// Names controllers gave to the accessory or its services through ConfiguredName characteristics and the values
+    /// of characteristics marked as persistent with
+    /// [`set_persistent`](crate::characteristic::HapCharacteristic::set_persistent) are restored from the
+    /// [`Storage`](crate::storage::Storage).
     async fn add_accessory<A: HapAccessory + 'static>(&self, accessory: A) -> Result<pointer::Accessory>;
+    /// Adds an accessory bridged by the bridge accessory to the server and returns a pointer to it.
+    ///
+    /// A bridge accessory with the accessory ID `1` has to be added before. The server assigns the next free accessory
+    /// ID to the bridged accessory and passes it to `build`, which creates the accessory with it. As long as bridged
+    /// accessories are added in the same order, they keep their IDs across restarts. The accessory category of the
+    /// server is set to `Bridge`.
+    ///
+    /// The category only exists for the whole server, as advertised via mDNS. iOS shows every bridged accessory with
+    /// the icon of its primary service, so the accessory should mark one of its services as primary, like the
+    /// predefined accessories do.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use hap::{
+    ///     accessory::{lightbulb::LightbulbAccessory, AccessoryInformation},
+    ///     server::Server,
+    ///     Result,
+    /// };
+    ///
+    /// async fn add_lightbulb<S: Server + Sync>(server: &S) -> Result<()> {
+    ///     server
+    ///         .add_bridged_accessory(|aid| {
+    ///             LightbulbAccessory::new(aid, AccessoryInformation {
+    ///                 name: "Lightbulb".into(),
+    ///                 ..Default::default()
+    ///             })
+    ///         })
+    ///         .await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    async fn add_bridged_accessory<A, F>(&self, build: F) -> Result<pointer::Accessory>
+    where
+        A: HapAccessory + 'static,
+        F: FnOnce(u64) -> Result<A> + Send;
     /// Takes a pointer to an accessory and removes it from the server.
     async fn remove_accessory(&self, accessory: &pointer::Accessory) -> Result<()>;
+    /// Removes the accessory with the given accessory ID from the server and returns a pointer to it.
+    ///
+    /// Event subscriptions of connected controllers to characteristics of the accessory are dropped and the
+    /// configuration number is incremented, so controllers fetch the updated accessory database.
+    async fn remove_accessory_by_id(&self, aid: u64) -> Result<pointer::Accessory>;
+    /// Returns the current value of the characteristic with the given accessory and instance IDs.
+    ///
+    /// That's the value last written by a controller or set locally. If the characteristic has a read callback, it is
+    /// called to fetch the value.
+    async fn read_characteristic(&self, aid: u64, iid: u64) -> Result<serde_json::Value>;
+    /// Sets the value of the characteristic with the given accessory and instance IDs and sends event notifications to
+    /// subscribed controllers.
+    ///
+    /// This is meant for values changing on the accessory's side, e.g. a sensor reading. The value has to match the
+    /// format of the characteristic, otherwise [`Error::InvalidValue`](crate::Error::InvalidValue) is returned. Values
+    /// of persistent characteristics are saved to the [`Storage`](crate::storage::Storage).
+    async fn update_characteristic(&self, aid: u64, iid: u64, value: serde_json::Value) -> Result<()>;
+    /// Notifies subscribed controllers of a press of the doorbell with the given accessory ID, which triggers the
+    /// doorbell notification on iOS devices.
+    ///
+    /// The accessory has to have a Doorbell service, like the
+    /// [`DoorbellAccessory`](crate::accessory::doorbell::DoorbellAccessory).
+    async fn ring_doorbell(&self, aid: u64) -> Result<()>;
+    /// Notifies subscribed controllers of a button press, e.g. to trigger a scene. `iid` is the instance ID of the
+    /// Programmable Switch Event characteristic of the pressed button, like the one of a button of the
+    /// [`ProgrammableSwitchAccessory`](crate::accessory::programmable_switch::ProgrammableSwitchAccessory).
+    ///
+    /// Fails with [`Error::CharacteristicNotFound`](crate::Error::CharacteristicNotFound) if the characteristic isn't a
+    /// Programmable Switch Event characteristic.
+    async fn trigger_programmable_switch_event(&self, aid: u64, iid: u64, event: ProgrammableSwitchEvent)
+        -> Result<()>;
+    /// Reports an installed firmware update of the accessory with the given accessory ID: sets its Firmware Revision
+    /// characteristic to `firmware_revision` and increments the configuration number, so controllers refetch the
+    /// accessory database.
+    ///
+    /// Fails with [`Error::InvalidRevision`](crate::Error::InvalidRevision) if `firmware_revision` isn't an `x[.y[.z]]`
+    /// version string and with [`Error::CharacteristicNotFound`](crate::Error::CharacteristicNotFound) if the accessory
+    /// was created without a firmware revision. The revision is saved to the [`Storage`](crate::storage::Storage) and
+    /// restored on the next start as long as it's newer than the one passed with the
+    /// [`AccessoryInformation`](crate::accessory::AccessoryInformation) of the accessory.
+    async fn report_firmware_update(&self, aid: u64, firmware_revision: &str) -> Result<()>;
+    /// Checks the added accessories for mistakes iOS doesn't report, meant to be called before running the server.
+    ///
+    /// Fails with [`Error::DuplicateSerialNumber`](crate::Error::DuplicateSerialNumber) if two accessories share a
+    /// serial number, which makes iOS merge them.
+    async fn validate(&self) -> Result<()>;
     // /// Every accessory must support a manufacturer-defined mechanism to restore itself to a “factory reset” state
     // where /// all pairing information is erased and restored to factory default settings. This method is doing
     // just that. async fn factory_reset(&mut self) -> Result<()>;
@@ -0,0 +1,166 @@
+use serde::{
+    ser::{Serialize, SerializeStruct, Serializer},
+    Deserialize,
+};
+use std::fmt;
+
+use crate::{
+    characteristic::{Characteristic, HapCharacteristic},
+    service::HapService,
+    HapType,
+};
+
+/// A characteristic of a [`CustomService`](CustomService), which gets its IDs from the accessory it's built into.
+trait CustomCharacteristic: HapCharacteristic + fmt::Debug {
+    fn set_accessory_id(&mut self, accessory_id: u64);
+    fn as_hap_characteristic(&self) -> &dyn HapCharacteristic;
+    fn as_mut_hap_characteristic(&mut self) -> &mut dyn HapCharacteristic;
+}
+
+impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync + 'static> CustomCharacteristic for Characteristic<T>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    fn set_accessory_id(&mut self, accessory_id: u64) { Characteristic::set_accessory_id(self, accessory_id) }
+
+    fn as_hap_characteristic(&self) -> &dyn HapCharacteristic { self }
+
+    fn as_mut_hap_characteristic(&mut self) -> &mut dyn HapCharacteristic { self }
+}
+
+/// Custom service with an arbitrary [`HapType`](HapType) and arbitrary characteristics, e.g. a service of a
+/// manufacturer-specific UUID, to be built into an accessory with an
+/// [`AccessoryBuilder`](crate::accessory::AccessoryBuilder).
+///
+/// The Home app shows custom services as unsupported, but other apps can use them.
+#[derive(Debug, Default)]
+pub struct CustomService {
+    /// Instance ID of the service.
+    id: u64,
+    /// [`HapType`](HapType) of the service.
+    hap_type: HapType,
+    /// When set to true, this service is not visible to user.
+    hidden: bool,
+    /// When set to true, this is the primary service on the accessory.
+    primary: bool,
+    /// An array of numbers containing the instance IDs of the services that this service links to.
+    linked_services: Vec<u64>,
+
+    characteristics: Vec<Box<dyn CustomCharacteristic>>,
+}
+
+impl CustomService {
+    /// Creates a new custom service of the given [`HapType`](HapType) without characteristics.
+    pub fn new(hap_type: HapType) -> Self {
+        Self {
+            hap_type,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a characteristic to the service. Its instance and accessory IDs are assigned when the accessory is built,
+    /// so the ones it was created with are ignored.
+    pub fn add_characteristic<T: fmt::Debug + Default + Clone + Serialize + Send + Sync + 'static>(
+        mut self,
+        characteristic: Characteristic<T>,
+    ) -> Self
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.characteristics.push(Box::new(characteristic));
+        self
+    }
+
+    /// Makes the service the primary service of the accessory.
+    pub fn primary(mut self) -> Self {
+        self.primary = true;
+        self
+    }
+
+    /// Hides the service from the user.
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    /// Sets the instance ID of the service, numbers its characteristics consecutively after it and returns the next
+    /// free instance ID.
+    pub(crate) fn assign_ids(&mut self, id: u64, accessory_id: u64) -> u64 {
+        self.id = id;
+
+        let mut next_id = id + 1;
+        for characteristic in &mut self.characteristics {
+            characteristic.set_id(next_id);
+            characteristic.set_accessory_id(accessory_id);
+            next_id += 1;
+        }
+
+        next_id
+    }
+}
+
+impl HapService for CustomService {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_type(&self) -> HapType { self.hap_type }
+
+    fn set_type(&mut self, hap_type: HapType) { self.hap_type = hap_type; }
+
+    fn get_hidden(&self) -> bool { self.hidden }
+
+    fn set_hidden(&mut self, hidden: bool) { self.hidden = hidden; }
+
+    fn get_primary(&self) -> bool { self.primary }
+
+    fn set_primary(&mut self, primary: bool) { self.primary = primary; }
+
+    fn get_linked_services(&self) -> Vec<u64> { self.linked_services.clone() }
+
+    fn set_linked_services(&mut self, linked_services: Vec<u64>) { self.linked_services = linked_services; }
+
+    fn get_characteristic(&self, hap_type: HapType) -> Option<&dyn HapCharacteristic> {
+        for characteristic in self.get_characteristics() {
+            if characteristic.get_type() == hap_type {
+                return Some(characteristic);
+            }
+        }
+        None
+    }
+
+    fn get_mut_characteristic(&mut self, hap_type: HapType) -> Option<&mut dyn HapCharacteristic> {
+        for characteristic in self.get_mut_characteristics() {
+            if characteristic.get_type() == hap_type {
+                return Some(characteristic);
+            }
+        }
+        None
+    }
+
+    fn get_characteristics(&self) -> Vec<&dyn HapCharacteristic> {
+        self.characteristics.iter().map(|c| c.as_hap_characteristic()).collect()
+    }
+
+    fn get_mut_characteristics(&mut self) -> Vec<&mut dyn HapCharacteristic> {
+        self.characteristics
+            .iter_mut()
+            .map(|c| c.as_mut_hap_characteristic())
+            .collect()
+    }
+}
+
+impl Serialize for CustomService {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapService", 6)?;
+        state.serialize_field("iid", &self.get_id())?;
+        state.serialize_field("type", &self.get_type())?;
+        state.serialize_field("hidden", &self.get_hidden())?;
+        state.serialize_field("primary", &self.get_primary())?;
+        state.serialize_field("characteristics", &self.get_characteristics())?;
+        if !self.linked_services.is_empty() {
+            state.serialize_field("linked", &self.linked_services)?;
+        }
+        state.end()
+    }
+}
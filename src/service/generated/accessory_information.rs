@@ -224,13 +224,15 @@ impl HapService for AccessoryInformationService {
 
 impl Serialize for AccessoryInformationService {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut state = serializer.serialize_struct("HapService", 5)?;
+        let mut state = serializer.serialize_struct("HapService", 6)?;
         state.serialize_field("iid", &self.get_id())?;
         state.serialize_field("type", &self.get_type())?;
         state.serialize_field("hidden", &self.get_hidden())?;
         state.serialize_field("primary", &self.get_primary())?;
         state.serialize_field("characteristics", &self.get_characteristics())?;
-        // linked services left out for now
+        if !self.linked_services.is_empty() {
+            state.serialize_field("linked", &self.linked_services)?;
+        }
         state.end()
     }
 }
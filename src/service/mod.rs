@@ -4,6 +4,9 @@ use crate::{characteristic::HapCharacteristic, HapType};
 
 mod generated;
 
+/// Custom service definition.
+pub mod custom;
+
 pub use crate::service::generated::*;
 
 /// [`HapService`](HapService) is implemented by every HAP service.
@@ -26,7 +29,9 @@ pub trait HapService: erased_serde::Serialize + Send + Sync {
     fn set_primary(&mut self, primary: bool);
     /// Returns the `linked_services` value of the service.
     fn get_linked_services(&self) -> Vec<u64>;
-    /// Sets the `linked_services` value of the service.
+    /// Sets the `linked_services` value of the service, i.e. the instance IDs of the services this service links to.
+    /// They are announced in the `linked` array of the service in the accessory database, e.g. to link the input
+    /// sources of a television to its Television Service.
     fn set_linked_services(&mut self, linked_services: Vec<u64>);
     /// Returns a reference to a specific characteristic of the service if it's present on it.
     fn get_characteristic(&self, hap_type: HapType) -> Option<&dyn HapCharacteristic>;
@@ -1,15 +1,16 @@
 use std::sync::Arc;
 
 use futures::lock::Mutex;
-use log::debug;
-use serde_json::json;
+use log::{debug, warn};
 
 use crate::{
-    accessory::HapAccessory,
-    characteristic::Perm,
+    accessory::{is_newer_revision, HapAccessory},
+    characteristic::{Format, HapCharacteristic, Perm},
+    event::{self, Event},
     pointer,
     transport::http::{ReadResponseObject, Status, WriteObject, WriteResponseObject},
     Error,
+    HapType,
     Result,
 };
 
@@ -42,22 +43,284 @@ impl AccessoryDatabase {
 
     /// Takes a pointer to an accessory and removes the accessory from the `AccessoryDatabase`.
     pub async fn remove_accessory(&mut self, accessory: &pointer::Accessory) -> Result<()> {
-        let accessory = accessory.lock().await;
+        // the lock has to be released before iterating, as the accessory itself is among the ones we lock there
+        let aid = accessory.lock().await.get_id();
+
+        self.remove_accessory_by_id(aid).await.map(|_| ())
+    }
+
+    /// Removes the accessory with the given accessory ID from the `AccessoryDatabase` and returns a pointer to it.
+    pub async fn remove_accessory_by_id(&mut self, aid: u64) -> Result<pointer::Accessory> {
         let mut remove = None;
 
         for (i, a) in self.accessories.iter_mut().enumerate() {
-            if a.lock().await.get_id() == accessory.get_id() {
-                a.lock().await.set_event_emitter_on_characteristics(None);
+            let mut a = a.lock().await;
+            if a.get_id() == aid {
+                a.set_event_emitter_on_characteristics(None);
 
                 remove = Some(i);
                 break;
             }
         }
 
-        if let Some(i) = remove {
-            self.accessories.remove(i);
+        match remove {
+            Some(i) => Ok(self.accessories.remove(i)),
+            None => Err(Error::AccessoryNotFound),
+        }
+    }
+
+    /// Returns the current value of the characteristic with the given accessory and instance IDs.
+    pub async fn get_characteristic_value(&self, aid: u64, iid: u64) -> Result<serde_json::Value> {
+        for accessory in self.accessories.iter() {
+            let mut a = accessory.lock().await;
+            if a.get_id() == aid {
+                for service in a.get_mut_services() {
+                    for characteristic in service.get_mut_characteristics() {
+                        if characteristic.get_id() == iid {
+                            return characteristic.get_value().await;
+                        }
+                    }
+                }
+
+                return Err(Error::CharacteristicNotFound);
+            }
+        }
+
+        Err(Error::AccessoryNotFound)
+    }
+
+    /// Returns the accessory IDs and the serial number of the first two accessories sharing a serial number, if any.
+    /// The stored serial numbers are compared, so no `on_read` callbacks are called.
+    pub(crate) async fn find_duplicate_serial_number(&self) -> Option<(u64, u64, String)> {
+        let mut serial_numbers: Vec<(u64, serde_json::Value)> = Vec::new();
+        for accessory in self.accessories.iter() {
+            let a = accessory.lock().await;
+            let aid = a.get_id();
+            let serial_number = match a
+                .get_service(HapType::AccessoryInformation)
+                .and_then(|service| service.get_characteristic(HapType::SerialNumber))
+                .and_then(stored_value)
+            {
+                Some(serial_number) => serial_number,
+                None => continue,
+            };
+
+            if let Some((other_aid, _)) = serial_numbers.iter().find(|(_, s)| *s == serial_number) {
+                let serial_number = serial_number.as_str().unwrap_or_default().to_string();
+                return Some((*other_aid, aid, serial_number));
+            }
+            serial_numbers.push((aid, serial_number));
+        }
+
+        None
+    }
+
+    /// Returns the instance ID of the characteristic of the given type in the service of the given type of an
+    /// accessory.
+    pub async fn get_characteristic_id(
+        &self,
+        aid: u64,
+        service_type: HapType,
+        characteristic_type: HapType,
+    ) -> Result<u64> {
+        for accessory in self.accessories.iter() {
+            let a = accessory.lock().await;
+            if a.get_id() == aid {
+                return a
+                    .get_service(service_type)
+                    .and_then(|service| service.get_characteristic(characteristic_type))
+                    .map(|characteristic| characteristic.get_id())
+                    .ok_or(Error::CharacteristicNotFound);
+            }
+        }
+
+        Err(Error::AccessoryNotFound)
+    }
+
+    /// Returns the [`HapType`](HapType) of the characteristic with the given accessory and instance IDs.
+    pub async fn get_characteristic_type(&self, aid: u64, iid: u64) -> Result<HapType> {
+        for accessory in self.accessories.iter() {
+            let a = accessory.lock().await;
+            if a.get_id() == aid {
+                for service in a.get_services() {
+                    for characteristic in service.get_characteristics() {
+                        if characteristic.get_id() == iid {
+                            return Ok(characteristic.get_type());
+                        }
+                    }
+                }
+
+                return Err(Error::CharacteristicNotFound);
+            }
+        }
+
+        Err(Error::AccessoryNotFound)
+    }
+
+    /// Returns the [`Perm`](Perm)s of the characteristic with the given accessory and instance IDs.
+    pub(crate) async fn get_characteristic_perms(&self, aid: u64, iid: u64) -> Result<Vec<Perm>> {
+        for accessory in self.accessories.iter() {
+            let a = accessory.lock().await;
+            if a.get_id() == aid {
+                for service in a.get_services() {
+                    for characteristic in service.get_characteristics() {
+                        if characteristic.get_id() == iid {
+                            return Ok(characteristic.get_perms());
+                        }
+                    }
+                }
+
+                return Err(Error::CharacteristicNotFound);
+            }
+        }
+
+        Err(Error::AccessoryNotFound)
+    }
+
+    /// Sets the value of the characteristic with the given accessory and instance IDs, notifying subscribed
+    /// controllers of the change.
+    ///
+    /// The value is checked against the format of the characteristic and rejected with
+    /// [`Error::InvalidValue`](Error::InvalidValue) if it doesn't match.
+    pub async fn set_characteristic_value(&self, aid: u64, iid: u64, value: serde_json::Value) -> Result<()> {
+        for accessory in self.accessories.iter() {
+            let mut a = accessory.lock().await;
+            if a.get_id() == aid {
+                for service in a.get_mut_services() {
+                    for characteristic in service.get_mut_characteristics() {
+                        if characteristic.get_id() == iid {
+                            return characteristic.set_value(value).await;
+                        }
+                    }
+                }
+
+                return Err(Error::CharacteristicNotFound);
+            }
+        }
+
+        Err(Error::AccessoryNotFound)
+    }
+
+    /// Persists the value of the characteristic with the given accessory and instance IDs to the
+    /// [`Storage`](crate::storage::Storage) if it's a ConfiguredName characteristic, so names given by controllers
+    /// survive restarts, a FirmwareRevision characteristic, so reported firmware updates do, or if it's marked as
+    /// persistent with [`set_persistent`](crate::characteristic::HapCharacteristic::set_persistent). Other
+    /// characteristics are ignored.
+    pub(crate) async fn persist_characteristic_value(
+        &self,
+        aid: u64,
+        iid: u64,
+        storage: &pointer::Storage,
+    ) -> Result<()> {
+        let (hap_type, persistent) = self.get_characteristic_persistence(aid, iid).await?;
+
+        if hap_type == HapType::ConfiguredName {
+            if let serde_json::Value::String(name) = self.get_characteristic_value(aid, iid).await? {
+                storage
+                    .lock()
+                    .await
+                    .save_bytes(&configured_name_key(aid, iid), name.as_bytes())
+                    .await?;
+            }
+        } else if hap_type == HapType::FirmwareRevision {
+            if let serde_json::Value::String(revision) = self.get_characteristic_value(aid, iid).await? {
+                storage
+                    .lock()
+                    .await
+                    .save_bytes(&firmware_revision_key(aid), revision.as_bytes())
+                    .await?;
+            }
+        } else if persistent {
+            let value = self.get_characteristic_value(aid, iid).await?;
+            storage.lock().await.save_characteristic_value(aid, iid, &value).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Restores the values of the ConfiguredName and persistent characteristics of the accessory with the given
+    /// accessory ID that were persisted by
+    /// [`persist_characteristic_value`](AccessoryDatabase::persist_characteristic_value).
+    ///
+    /// Restored values are set like values written by a controller, so update callbacks are called with them. Values
+    /// that don't fit the characteristic anymore, e.g. after its format changed, or that are corrupted are skipped with
+    /// a warning, so the only error is an unknown accessory ID. A persisted firmware
+    /// revision is only restored while it's newer than the one the accessory was created with, so a later firmware
+    /// passed with its [`AccessoryInformation`](crate::accessory::AccessoryInformation) takes precedence.
+    pub(crate) async fn restore_characteristic_values(&self, aid: u64, storage: &pointer::Storage) -> Result<()> {
+        for accessory in self.accessories.iter() {
+            let mut a = accessory.lock().await;
+            if a.get_id() == aid {
+                for service in a.get_mut_services() {
+                    for characteristic in service.get_mut_characteristics() {
+                        let iid = characteristic.get_id();
+
+                        if characteristic.get_type() == HapType::ConfiguredName {
+                            if let Ok(name) = storage.lock().await.load_bytes(&configured_name_key(aid, iid)).await {
+                                let restored = match String::from_utf8(name) {
+                                    Ok(name) => characteristic.set_value(serde_json::Value::String(name)).await,
+                                    Err(_) => Err(Error::InvalidValue(Format::String)),
+                                };
+                                if let Err(e) = restored {
+                                    warn!("error restoring configured name {}.{}: {:?}", aid, iid, e);
+                                }
+                            }
+                        } else if characteristic.get_type() == HapType::FirmwareRevision {
+                            if let Ok(revision) = storage.lock().await.load_bytes(&firmware_revision_key(aid)).await {
+                                let revision = match String::from_utf8(revision) {
+                                    Ok(revision) => revision,
+                                    Err(_) => {
+                                        warn!(
+                                            "skipping persisted firmware revision of accessory {}, it isn't UTF-8",
+                                            aid
+                                        );
+                                        continue;
+                                    },
+                                };
+                                let newer = match stored_value(&*characteristic) {
+                                    Some(serde_json::Value::String(current)) => is_newer_revision(&revision, &current),
+                                    _ => false,
+                                };
+                                if newer {
+                                    if let Err(e) = characteristic.set_value(serde_json::Value::String(revision)).await
+                                    {
+                                        warn!("error restoring firmware revision {}.{}: {:?}", aid, iid, e);
+                                    }
+                                }
+                            }
+                        } else if characteristic.get_persistent() {
+                            if let Ok(value) = storage.lock().await.load_characteristic_value(aid, iid).await {
+                                if let Err(e) = characteristic.set_value(value).await {
+                                    warn!("error restoring value of characteristic {}.{}: {:?}", aid, iid, e);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                return Ok(());
+            }
+        }
+
+        Err(Error::AccessoryNotFound)
+    }
+
+    /// Returns the [`HapType`](HapType) of the characteristic with the given accessory and instance IDs and whether
+    /// it's persistent.
+    async fn get_characteristic_persistence(&self, aid: u64, iid: u64) -> Result<(HapType, bool)> {
+        for accessory in self.accessories.iter() {
+            let a = accessory.lock().await;
+            if a.get_id() == aid {
+                for service in a.get_services() {
+                    for characteristic in service.get_characteristics() {
+                        if characteristic.get_id() == iid {
+                            return Ok((characteristic.get_type(), characteristic.get_persistent()));
+                        }
+                    }
+                }
 
-            return Ok(());
+                return Err(Error::CharacteristicNotFound);
+            }
         }
 
         Err(Error::AccessoryNotFound)
@@ -71,7 +334,6 @@ impl AccessoryDatabase {
         meta: bool,
         perms: bool,
         hap_type: bool,
-        ev: bool,
     ) -> Result<ReadResponseObject> {
         let mut result_object = ReadResponseObject {
             iid,
@@ -86,7 +348,10 @@ impl AccessoryDatabase {
             min_value: None,
             step_value: None,
             max_len: None,
-            status: Some(0),
+            max_data_len: None,
+            valid_values: None,
+            valid_values_range: None,
+            status: Some(Status::ResourceDoesNotExist as i32),
         };
 
         'l: for accessory in self.accessories.iter() {
@@ -95,6 +360,7 @@ impl AccessoryDatabase {
                 for service in a.get_mut_services() {
                     for characteristic in service.get_mut_characteristics() {
                         if characteristic.get_id() == iid {
+                            result_object.status = Some(0);
                             let characteristic_perms = characteristic.get_perms();
                             if characteristic_perms.contains(&Perm::PairedRead) {
                                 result_object.value = Some(characteristic.get_value().await?);
@@ -105,6 +371,9 @@ impl AccessoryDatabase {
                                     result_object.min_value = characteristic.get_min_value();
                                     result_object.step_value = characteristic.get_step_value();
                                     result_object.max_len = characteristic.get_max_len();
+                                    result_object.max_data_len = characteristic.get_max_data_len();
+                                    result_object.valid_values = characteristic.get_valid_values();
+                                    result_object.valid_values_range = characteristic.get_valid_values_range();
                                 }
                                 if perms {
                                     result_object.perms = Some(characteristic_perms);
@@ -112,9 +381,6 @@ impl AccessoryDatabase {
                                 if hap_type {
                                     result_object.hap_type = Some(characteristic.get_type());
                                 }
-                                if ev {
-                                    result_object.ev = characteristic.get_event_notifications();
-                                }
                             } else {
                                 result_object.status = Some(Status::WriteOnlyCharacteristic as i32);
                             }
@@ -128,17 +394,21 @@ impl AccessoryDatabase {
         Ok(result_object)
     }
 
-    /// Writes the value of a characteristic.
+    /// Writes the value of a characteristic. Characteristics with the `TimedWrite` permission only accept values of
+    /// `timed_write`s, i.e. writes admitted by a preceding prepare request.
     pub(crate) async fn write_characteristic(
         &mut self,
         write_object: WriteObject,
         event_subscriptions: &pointer::EventSubscriptions,
+        timed_write: bool,
     ) -> Result<WriteResponseObject> {
         let mut result_object = WriteResponseObject {
             aid: write_object.aid,
             iid: write_object.iid,
-            status: 0,
+            status: Status::ResourceDoesNotExist as i32,
+            value: None,
         };
+        let mut identified = false;
 
         'l: for accessory in self.accessories.iter_mut() {
             let mut a = accessory.lock().await;
@@ -146,6 +416,7 @@ impl AccessoryDatabase {
                 for service in a.get_mut_services() {
                     for characteristic in service.get_mut_characteristics() {
                         if characteristic.get_id() == write_object.iid {
+                            result_object.status = 0;
                             let characteristic_perms = characteristic.get_perms();
                             if let Some(ev) = write_object.ev {
                                 if characteristic_perms.contains(&Perm::Events) {
@@ -167,8 +438,19 @@ impl AccessoryDatabase {
                                 }
                             }
                             if let Some(value) = write_object.value {
-                                if characteristic_perms.contains(&Perm::PairedWrite) {
+                                if characteristic_perms.contains(&Perm::TimedWrite) && !timed_write {
+                                    result_object.status = Status::InvalidValueInRequest as i32;
+                                } else if characteristic_perms.contains(&Perm::PairedWrite) {
+                                    identified = characteristic.get_type() == HapType::Identify
+                                        && value == serde_json::Value::Bool(true);
                                     characteristic.set_value(value).await?;
+                                    // the response value is read after the write, so `on_read` callbacks can answer
+                                    // the written request
+                                    if write_object.response == Some(true)
+                                        && characteristic_perms.contains(&Perm::WriteResponse)
+                                    {
+                                        result_object.value = Some(characteristic.get_value().await?);
+                                    }
                                 } else {
                                     result_object.status = Status::ReadOnlyCharacteristic as i32;
                                 }
@@ -180,28 +462,539 @@ impl AccessoryDatabase {
             }
         }
 
+        if identified {
+            event::emit(&self.event_emitter, &Event::Identify { aid: write_object.aid }).await;
+        }
+
         Ok(result_object)
     }
 
     pub(crate) async fn as_serialized_json(&self) -> Result<Vec<u8>> {
-        let mut accessory_values = Vec::new();
-        for accessory in &self.accessories {
+        // the accessories are serialized straight to bytes rather than collected as `serde_json::Value`s, which would
+        // widen the `float` values to `f64` and change their representation
+        let mut json = b"{\"accessories\":[".to_vec();
+        for (i, accessory) in self.accessories.iter().enumerate() {
+            if i > 0 {
+                json.push(b',');
+            }
             let a = accessory.lock().await;
-            accessory_values.push(serde_json::to_value(&*a)?);
+            serde_json::to_writer(&mut json, &*a)?;
         }
+        json.extend_from_slice(b"]}");
 
-        let json = json!({ "accessories": accessory_values });
+        debug!("accessory list JSON: {}", String::from_utf8_lossy(&json));
 
-        debug!("accessory list JSON: {}", &json);
-
-        Ok(serde_json::to_vec(&json)?)
+        Ok(json)
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
+fn configured_name_key(aid: u64, iid: u64) -> String { format!("configured_name_{}_{}", aid, iid) }
+
+fn firmware_revision_key(aid: u64) -> String { format!("firmware_revision_{}", aid) }
+
+/// Returns the value a readable characteristic holds without calling its `on_read` callbacks.
+fn stored_value(characteristic: &dyn HapCharacteristic) -> Option<serde_json::Value> {
+    serde_json::to_value(characteristic)
+        .ok()?
+        .get_mut("value")
+        .map(serde_json::Value::take)
+}
+
+/// Creates an accessory database without any accessories and with an event emitter of its own.
+#[cfg(test)]
+pub(crate) fn empty_database() -> AccessoryDatabase {
+    AccessoryDatabase::new(Arc::new(Mutex::new(event::EventEmitter::new())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        accessory::{
+            doorbell::DoorbellAccessory,
+            lightbulb::LightbulbAccessory,
+            switch::SwitchAccessory,
+            temperature_sensor::TemperatureSensorAccessory,
+            AccessoryBuilder,
+            AccessoryInformation,
+        },
+        characteristic::{custom_characteristic, CharacteristicCallbacks, HapCharacteristic},
+        service::{custom::CustomService, HapService},
+        storage::MemoryStorage,
+    };
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_configured_name_persistence() {
+        let storage: pointer::Storage = Arc::new(Mutex::new(Box::new(MemoryStorage::new())));
+        let information = || AccessoryInformation {
+            name: "Lightbulb".into(),
+            configured_name: Some("Lightbulb".into()),
+            ..Default::default()
+        };
+
+        let mut accessory_database = empty_database();
+        let lightbulb = LightbulbAccessory::new(1, information()).unwrap();
+        let iid = lightbulb
+            .accessory_information
+            .configured_name
+            .as_ref()
+            .unwrap()
+            .get_id();
+        let power_state_iid = lightbulb.lightbulb.power_state.get_id();
+        accessory_database.add_accessory(Box::new(lightbulb)).unwrap();
+
+        accessory_database
+            .set_characteristic_value(1, iid, json!("Desk Lamp"))
+            .await
+            .unwrap();
+        accessory_database
+            .persist_characteristic_value(1, iid, &storage)
+            .await
+            .unwrap();
+        accessory_database
+            .persist_characteristic_value(1, power_state_iid, &storage)
+            .await
+            .unwrap();
+
+        let mut accessory_database = empty_database();
+        let lightbulb = LightbulbAccessory::new(1, information()).unwrap();
+        accessory_database.add_accessory(Box::new(lightbulb)).unwrap();
+        accessory_database
+            .restore_characteristic_values(1, &storage)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            accessory_database.get_characteristic_value(1, iid).await.unwrap(),
+            json!("Desk Lamp")
+        );
+        assert!(storage
+            .lock()
+            .await
+            .load_bytes(&configured_name_key(1, power_state_iid))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_values_are_skipped() {
+        let storage: pointer::Storage = Arc::new(Mutex::new(Box::new(MemoryStorage::new())));
+        let mut lightbulb = LightbulbAccessory::new(1, AccessoryInformation {
+            name: "Lightbulb".into(),
+            configured_name: Some("Lightbulb".into()),
+            firmware_revision: Some("1.0".into()),
+            ..Default::default()
+        })
+        .unwrap();
+        lightbulb.lightbulb.power_state.set_persistent(true);
+        let name_iid = lightbulb
+            .accessory_information
+            .configured_name
+            .as_ref()
+            .unwrap()
+            .get_id();
+        let firmware_revision_iid = lightbulb
+            .accessory_information
+            .firmware_revision
+            .as_ref()
+            .unwrap()
+            .get_id();
+        let power_state_iid = lightbulb.lightbulb.power_state.get_id();
+
+        let mut s = storage.lock().await;
+        s.save_bytes(&configured_name_key(1, name_iid), &[0xff, 0xfe])
+            .await
+            .unwrap();
+        s.save_bytes(&firmware_revision_key(1), &[0xff]).await.unwrap();
+        s.save_characteristic_value(1, power_state_iid, &json!(true))
+            .await
+            .unwrap();
+        drop(s);
+
+        let mut accessory_database = empty_database();
+        accessory_database.add_accessory(Box::new(lightbulb)).unwrap();
+        accessory_database
+            .restore_characteristic_values(1, &storage)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            accessory_database.get_characteristic_value(1, name_iid).await.unwrap(),
+            json!("Lightbulb")
+        );
+        assert_eq!(
+            accessory_database
+                .get_characteristic_value(1, firmware_revision_iid)
+                .await
+                .unwrap(),
+            json!("1.0")
+        );
+        assert_eq!(
+            accessory_database
+                .get_characteristic_value(1, power_state_iid)
+                .await
+                .unwrap(),
+            json!(true)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_persistent_characteristic_values() {
+        let storage: pointer::Storage = Arc::new(Mutex::new(Box::new(MemoryStorage::new())));
+        let lightbulb = || {
+            let mut lightbulb = LightbulbAccessory::new(1, AccessoryInformation {
+                name: "Lightbulb".into(),
+                ..Default::default()
+            })
+            .unwrap();
+            lightbulb.lightbulb.power_state.set_persistent(true);
+            lightbulb
+        };
 
-//     #[test]
-//     fn test_json_serialization() {} // TODO: test it
-// }
+        let mut accessory_database = empty_database();
+        let accessory = lightbulb();
+        let power_state_iid = accessory.lightbulb.power_state.get_id();
+        let brightness_iid = accessory.lightbulb.brightness.as_ref().unwrap().get_id();
+        accessory_database.add_accessory(Box::new(accessory)).unwrap();
+
+        for (iid, value) in [(power_state_iid, json!(true)), (brightness_iid, json!(50))] {
+            accessory_database
+                .set_characteristic_value(1, iid, value)
+                .await
+                .unwrap();
+            accessory_database
+                .persist_characteristic_value(1, iid, &storage)
+                .await
+                .unwrap();
+        }
+
+        let mut accessory_database = empty_database();
+        accessory_database.add_accessory(Box::new(lightbulb())).unwrap();
+        accessory_database
+            .restore_characteristic_values(1, &storage)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            accessory_database
+                .get_characteristic_value(1, power_state_iid)
+                .await
+                .unwrap(),
+            json!(true)
+        );
+        assert_eq!(
+            accessory_database
+                .get_characteristic_value(1, brightness_iid)
+                .await
+                .unwrap(),
+            json!(0)
+        );
+        assert!(storage
+            .lock()
+            .await
+            .load_characteristic_value(1, brightness_iid)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_json_serialization() {
+        let mut accessory_database = empty_database();
+        let mut sensor = TemperatureSensorAccessory::new(1, AccessoryInformation {
+            name: "Sensor".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        sensor
+            .temperature_sensor
+            .current_temperature
+            .set_value(json!(21.7))
+            .await
+            .unwrap();
+        accessory_database.add_accessory(Box::new(sensor)).unwrap();
+        let switch = SwitchAccessory::new(2, AccessoryInformation {
+            name: "Switch".into(),
+            firmware_revision: Some("1.0".into()),
+            ..Default::default()
+        })
+        .unwrap();
+        accessory_database.add_accessory(Box::new(switch)).unwrap();
+
+        let json = String::from_utf8(accessory_database.as_serialized_json().await.unwrap()).unwrap();
+
+        assert!(
+            json.contains("\"value\":21.7,\"unit\":\"celsius\",\"maxValue\":100.0,\"minValue\":0.0,\"minStep\":0.1}")
+        );
+        // `serde_json::Value` numbers only compare equal with the same representation, e.g. `100.0` isn't `100`
+        let golden: serde_json::Value = serde_json::from_str(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/accessory_database.json"
+        )))
+        .unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), golden);
+    }
+
+    #[tokio::test]
+    async fn test_write_response() {
+        let mut control_point = custom_characteristic(
+            "2db3ac3f-8b9c-4431-8d87-670351dc872a",
+            Format::UInt8,
+            vec![Perm::PairedRead, Perm::PairedWrite, Perm::WriteResponse],
+            0u8,
+        );
+        control_point.on_read(Some(|| Ok(Some(42))));
+        let accessory = AccessoryBuilder::new(1, AccessoryInformation::default())
+            .add_service(CustomService::new(HapType::Custom(uuid::Uuid::new_v4())).add_characteristic(control_point))
+            .build()
+            .unwrap();
+        let iid = accessory.services[0].get_characteristics()[0].get_id();
+
+        let mut accessory_database = empty_database();
+        accessory_database.add_accessory(Box::new(accessory)).unwrap();
+        let event_subscriptions = Arc::new(Mutex::new(Vec::new()));
+
+        let write_object = serde_json::from_value(json!({"aid": 1, "iid": iid, "value": 1, "r": true})).unwrap();
+        let result = accessory_database
+            .write_characteristic(write_object, &event_subscriptions, false)
+            .await
+            .unwrap();
+        assert_eq!(result.value, Some(json!(42)));
+
+        let write_object = serde_json::from_value(json!({"aid": 1, "iid": iid, "value": 1})).unwrap();
+        let result = accessory_database
+            .write_characteristic(write_object, &event_subscriptions, false)
+            .await
+            .unwrap();
+        assert_eq!(result.value, None);
+    }
+
+    #[tokio::test]
+    async fn test_timed_write() {
+        let night_vision = custom_characteristic(
+            "2db3ac3f-8b9c-4431-8d87-670351dc872a",
+            Format::Bool,
+            vec![Perm::PairedRead, Perm::PairedWrite, Perm::TimedWrite],
+            false,
+        );
+        let accessory = AccessoryBuilder::new(1, AccessoryInformation::default())
+            .add_service(CustomService::new(HapType::Custom(uuid::Uuid::new_v4())).add_characteristic(night_vision))
+            .build()
+            .unwrap();
+        let iid = accessory.services[0].get_characteristics()[0].get_id();
+
+        let mut accessory_database = empty_database();
+        accessory_database.add_accessory(Box::new(accessory)).unwrap();
+        let event_subscriptions = Arc::new(Mutex::new(Vec::new()));
+
+        let write_object = serde_json::from_value(json!({"aid": 1, "iid": iid, "value": true})).unwrap();
+        let result = accessory_database
+            .write_characteristic(write_object, &event_subscriptions, false)
+            .await
+            .unwrap();
+        assert_eq!(result.status, Status::InvalidValueInRequest as i32);
+
+        let write_object = serde_json::from_value(json!({"aid": 1, "iid": iid, "value": true})).unwrap();
+        let result = accessory_database
+            .write_characteristic(write_object, &event_subscriptions, true)
+            .await
+            .unwrap();
+        assert_eq!(result.status, 0);
+
+        let read = accessory_database
+            .read_characteristic(1, iid, false, false, false)
+            .await
+            .unwrap();
+        assert_eq!(read.value, Some(json!(true)));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_characteristics() {
+        let mut accessory_database = empty_database();
+        let lightbulb = LightbulbAccessory::new(1, AccessoryInformation {
+            name: "Lightbulb".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let iid = lightbulb.lightbulb.power_state.get_id();
+        accessory_database.add_accessory(Box::new(lightbulb)).unwrap();
+        let event_subscriptions = Arc::new(Mutex::new(Vec::new()));
+
+        for (aid, iid, status) in [
+            (1, iid, 0),
+            (1, 1000, Status::ResourceDoesNotExist as i32),
+            (2, iid, Status::ResourceDoesNotExist as i32),
+        ] {
+            let read = accessory_database
+                .read_characteristic(aid, iid, false, false, false)
+                .await
+                .unwrap();
+            assert_eq!(read.status, Some(status));
+
+            let write_object = serde_json::from_value(json!({"aid": aid, "iid": iid, "value": true})).unwrap();
+            let write = accessory_database
+                .write_characteristic(write_object, &event_subscriptions, false)
+                .await
+                .unwrap();
+            assert_eq!(write.status, status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remove_accessory() {
+        let mut accessory_database = empty_database();
+        for aid in 1..=2 {
+            let lightbulb = LightbulbAccessory::new(aid, AccessoryInformation {
+                name: "Lightbulb".into(),
+                ..Default::default()
+            })
+            .unwrap();
+            accessory_database.add_accessory(Box::new(lightbulb)).unwrap();
+        }
+
+        let accessory = accessory_database.accessories[0].clone();
+        accessory_database.remove_accessory(&accessory).await.unwrap();
+        assert!(matches!(
+            accessory_database.remove_accessory(&accessory).await,
+            Err(Error::AccessoryNotFound)
+        ));
+
+        let removed = accessory_database.remove_accessory_by_id(2).await.unwrap();
+        assert_eq!(removed.lock().await.get_id(), 2);
+        assert!(accessory_database.accessories.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_characteristic_value() {
+        let mut accessory_database = empty_database();
+        let mut lightbulb = LightbulbAccessory::new(1, AccessoryInformation {
+            name: "Lightbulb".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        lightbulb.lightbulb.power_state.set_value(json!(true)).await.unwrap();
+        let iid = lightbulb.lightbulb.power_state.get_id();
+        accessory_database.add_accessory(Box::new(lightbulb)).unwrap();
+
+        assert_eq!(
+            accessory_database.get_characteristic_value(1, iid).await.unwrap(),
+            json!(true)
+        );
+        assert!(matches!(
+            accessory_database.get_characteristic_value(1, 1000).await,
+            Err(Error::CharacteristicNotFound)
+        ));
+        assert!(matches!(
+            accessory_database.get_characteristic_value(2, iid).await,
+            Err(Error::AccessoryNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_characteristic_value() {
+        let mut accessory_database = empty_database();
+        let lightbulb = LightbulbAccessory::new(1, AccessoryInformation {
+            name: "Lightbulb".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let iid = lightbulb.lightbulb.power_state.get_id();
+        accessory_database.add_accessory(Box::new(lightbulb)).unwrap();
+
+        accessory_database
+            .set_characteristic_value(1, iid, json!(true))
+            .await
+            .unwrap();
+        assert_eq!(
+            accessory_database.get_characteristic_value(1, iid).await.unwrap(),
+            json!(true)
+        );
+        assert!(matches!(
+            accessory_database.set_characteristic_value(1, iid, json!("on")).await,
+            Err(Error::InvalidValue(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_characteristic_id() {
+        let mut accessory_database = empty_database();
+        let doorbell = DoorbellAccessory::new(1, AccessoryInformation {
+            name: "Doorbell".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let iid = doorbell.doorbell.programmable_switch_event.get_id();
+        accessory_database.add_accessory(Box::new(doorbell)).unwrap();
+
+        assert_eq!(
+            accessory_database
+                .get_characteristic_id(1, HapType::Doorbell, HapType::ProgrammableSwitchEvent)
+                .await
+                .unwrap(),
+            iid
+        );
+        assert!(matches!(
+            accessory_database
+                .get_characteristic_id(1, HapType::Lightbulb, HapType::PowerState)
+                .await,
+            Err(Error::CharacteristicNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_characteristic_type() {
+        let mut accessory_database = empty_database();
+        let doorbell = DoorbellAccessory::new(1, AccessoryInformation {
+            name: "Doorbell".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let iid = doorbell.doorbell.programmable_switch_event.get_id();
+        accessory_database.add_accessory(Box::new(doorbell)).unwrap();
+
+        assert_eq!(
+            accessory_database.get_characteristic_type(1, iid).await.unwrap(),
+            HapType::ProgrammableSwitchEvent
+        );
+        assert!(matches!(
+            accessory_database.get_characteristic_type(1, 1000).await,
+            Err(Error::CharacteristicNotFound)
+        ));
+        assert!(matches!(
+            accessory_database.get_characteristic_type(2, iid).await,
+            Err(Error::AccessoryNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_serial_numbers_are_found() {
+        let information = |serial_number: &str| AccessoryInformation {
+            serial_number: serial_number.into(),
+            ..Default::default()
+        };
+
+        let mut accessory_database = empty_database();
+        accessory_database
+            .add_accessory(Box::new(LightbulbAccessory::new(1, information("A1")).unwrap()))
+            .unwrap();
+        accessory_database
+            .add_accessory(Box::new(LightbulbAccessory::new(2, information("B2")).unwrap()))
+            .unwrap();
+        assert_eq!(accessory_database.find_duplicate_serial_number().await, None);
+
+        let mut lightbulb = LightbulbAccessory::new(3, information("A1")).unwrap();
+        let reads = Arc::new(AtomicUsize::new(0));
+        let reads_ = reads.clone();
+        lightbulb.accessory_information.serial_number.on_read(Some(move || {
+            reads_.fetch_add(1, Ordering::SeqCst);
+            Ok(Some("C3".into()))
+        }));
+        accessory_database.add_accessory(Box::new(lightbulb)).unwrap();
+        assert_eq!(
+            accessory_database.find_duplicate_serial_number().await,
+            Some((1, 3, "A1".into()))
+        );
+        assert_eq!(reads.load(Ordering::SeqCst), 0);
+    }
+}
@@ -1,43 +1,103 @@
+use aead::{generic_array::GenericArray, AeadInPlace, KeyInit};
 use async_trait::async_trait;
-use log::debug;
+use chacha20poly1305::ChaCha20Poly1305;
+use log::{debug, warn};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::{
+    collections::HashSet,
     env,
     ffi::OsStr,
+    fmt,
     fs,
-    io::{BufReader, BufWriter, Read, Write},
+    io::{self, BufReader, BufWriter, Read, Write},
     path::{Path, PathBuf},
     str,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 use tokio::task::spawn_blocking;
 use uuid::Uuid;
+use zeroize::Zeroizing;
 
 use crate::{pairing::Pairing, storage::Storage, Config, Error, Result};
 
+const NONCE_LENGTH: usize = 12;
+const AUTH_TAG_LENGTH: usize = 16;
+
+/// When a [`FileStorage`](FileStorage) syncs written files to disk with `fsync`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Leaves writing the files back to disk to the operating system. This is the default.
+    #[default]
+    Never,
+    /// Syncs every file right after writing it, so a write survives a power loss once it returns.
+    Always,
+    /// Syncs all files written within the interval together at its end, coalescing bursts of writes, e.g. the
+    /// pairing updates of a busy bridge.
+    Interval(Duration),
+}
+
 /// [`FileStorage`](FileStorage) is an implementor of the [`Storage`](Storage) trait that stores data to the file
 /// system.
-#[derive(Debug)]
+///
+/// The stored [`Config`](Config) holds the Ed25519 private key of the accessory, so anyone able to read it can
+/// impersonate the accessory to its paired controllers, and anyone able to write the pairings can pair a controller of
+/// their own. On Unix, files are therefore created readable and writable by the owner only (`0600`). Where the file
+/// system itself can't be trusted, [`FileStorage::with_encryption`](FileStorage::with_encryption) additionally
+/// encrypts every file with ChaCha20-Poly1305. The key then has to be kept somewhere else, e.g. in a hardware keystore.
 pub struct FileStorage {
     dir_path: PathBuf,
+    encryption_key: Option<Zeroizing<[u8; 32]>>,
+    sync_policy: SyncPolicy,
+    /// Files written since the last sync with [`SyncPolicy::Interval`](SyncPolicy::Interval).
+    pending_syncs: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl fmt::Debug for FileStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileStorage")
+            .field("dir_path", &self.dir_path)
+            .field("encrypted", &self.encryption_key.is_some())
+            .field("sync_policy", &self.sync_policy)
+            .finish()
+    }
 }
 
 impl FileStorage {
-    /// Creates a new [`FileStorage`](FileStorage).
+    /// Creates a new [`FileStorage`](FileStorage) storing its data in the given directory, which is created if it
+    /// doesn't exist yet.
     pub async fn new<D: AsRef<OsStr> + ?Sized>(dir: &D) -> Result<Self> {
         let dir_path = Path::new(dir).to_path_buf();
         let dir_path = spawn_blocking(move || -> Result<PathBuf> {
             fs::create_dir_all(&dir_path)?;
-
-            let dir_path_str = dir_path.to_str().expect("couldn't stringify current_dir");
             // create subdirectory for pairings
-            fs::create_dir_all(&format!("{}/pairings", dir_path_str))?;
+            fs::create_dir_all(dir_path.join("pairings"))?;
             // create subdirectory for custom byte storage
-            fs::create_dir_all(&format!("{}/misc", dir_path_str))?;
+            fs::create_dir_all(dir_path.join("misc"))?;
 
             Ok(dir_path)
         })
         .await??;
 
-        Ok(FileStorage { dir_path })
+        Ok(FileStorage {
+            dir_path,
+            encryption_key: None,
+            sync_policy: SyncPolicy::default(),
+            pending_syncs: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+
+    /// Creates a new [`FileStorage`](FileStorage) like [`FileStorage::new`](FileStorage::new) that encrypts the
+    /// stored data with ChaCha20-Poly1305 under the given key.
+    ///
+    /// Every file is encrypted under a random nonce and bound to its path, so files can't be swapped either. Loading
+    /// data fails with [`Error::Aead`](Error::Aead) if it was stored under a different key or without encryption.
+    pub async fn with_encryption<D: AsRef<OsStr> + ?Sized>(dir: &D, key: [u8; 32]) -> Result<Self> {
+        let mut storage = Self::new(dir).await?;
+        storage.encryption_key = Some(Zeroizing::new(key));
+
+        Ok(storage)
     }
 
     /// Creates a new [`FileStorage`](FileStorage) with the current directory as storage path.
@@ -50,6 +110,19 @@ impl FileStorage {
         Self::new(&data_path).await
     }
 
+    /// Sets when written files are synced to disk. Defaults to [`SyncPolicy::Never`](SyncPolicy::Never).
+    pub fn set_sync_policy(&mut self, sync_policy: SyncPolicy) { self.sync_policy = sync_policy; }
+
+    /// Returns the directory the [`FileStorage`](FileStorage) stores its data in.
+    pub fn dir_path(&self) -> &Path { &self.dir_path }
+
+    /// Returns the path of the file the [`Config`](Config) is stored in. As the config holds the Ed25519 private key
+    /// of the accessory, its permissions should be restricted, e.g. to `0600`.
+    pub fn config_path(&self) -> PathBuf { self.storage_path("config.json") }
+
+    /// Returns the path of the directory the [`Pairing`](Pairing)s are stored in, one file per pairing.
+    pub fn pairings_path(&self) -> PathBuf { self.storage_path("pairings") }
+
     fn storage_path(&self, fd: &str) -> PathBuf {
         let mut fd_path = self.dir_path.clone();
         fd_path.push(fd);
@@ -72,11 +145,14 @@ impl FileStorage {
     async fn get_writer(&self, file: &str) -> Result<BufWriter<fs::File>> {
         let file_path = self.storage_path(file);
         let writer = spawn_blocking(move || -> Result<BufWriter<fs::File>> {
-            let file = fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(file_path)?;
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let file = options.open(file_path)?;
+            // files created by earlier versions may still be world-readable
+            #[cfg(unix)]
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
             let writer = BufWriter::new(file);
 
             Ok(writer)
@@ -96,21 +172,114 @@ impl FileStorage {
         })
         .await??;
 
-        Ok(value)
+        self.decrypt(key, value)
     }
 
     async fn write_bytes(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let value = self.encrypt(key, value)?;
         let mut writer = self.get_writer(key).await?;
+        let sync = self.sync_policy == SyncPolicy::Always;
         spawn_blocking(move || -> Result<()> {
             writer.write_all(&value)?;
+            let file = writer.into_inner().map_err(|e| e.into_error())?;
+            if sync {
+                file.sync_all()?;
+            }
 
             Ok(())
         })
         .await??;
 
+        if let SyncPolicy::Interval(interval) = self.sync_policy {
+            self.schedule_sync(self.storage_path(key), interval);
+        }
+
         Ok(())
     }
 
+    fn schedule_sync(&self, file_path: PathBuf, interval: Duration) {
+        let mut pending_syncs = self.pending_syncs.lock().expect("accessing pending syncs");
+        let sync_scheduled = !pending_syncs.is_empty();
+        pending_syncs.insert(file_path);
+        drop(pending_syncs);
+
+        // the first write within an interval schedules the sync of all files written in that interval
+        if !sync_scheduled {
+            let pending_syncs = self.pending_syncs.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(interval).await;
+
+                let file_paths: Vec<PathBuf> = pending_syncs.lock().expect("accessing pending syncs").drain().collect();
+                spawn_blocking(move || {
+                    for file_path in file_paths {
+                        match fs::File::open(&file_path).and_then(|file| file.sync_all()) {
+                            // the file was deleted in the meantime
+                            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+                            Err(e) => warn!("couldn't sync {}: {}", file_path.display(), e),
+                            Ok(()) => {},
+                        }
+                    }
+                })
+                .await
+                .ok();
+            });
+        }
+    }
+
+    fn encrypt(&self, key: &str, mut value: Vec<u8>) -> Result<Vec<u8>> {
+        let encryption_key = match self.encryption_key {
+            Some(ref encryption_key) => encryption_key,
+            None => return Ok(value),
+        };
+
+        let aead = ChaCha20Poly1305::new(GenericArray::from_slice(&encryption_key[..]));
+        let nonce: [u8; NONCE_LENGTH] = rand::random();
+        let auth_tag = aead.encrypt_in_place_detached(
+            GenericArray::from_slice(&nonce),
+            self.associated_data(key).as_bytes(),
+            &mut value,
+        )?;
+
+        let mut encrypted_value = nonce.to_vec();
+        encrypted_value.extend(value);
+        encrypted_value.extend(&auth_tag);
+
+        Ok(encrypted_value)
+    }
+
+    fn decrypt(&self, key: &str, value: Vec<u8>) -> Result<Vec<u8>> {
+        let encryption_key = match self.encryption_key {
+            Some(ref encryption_key) => encryption_key,
+            None => return Ok(value),
+        };
+
+        if value.len() < NONCE_LENGTH + AUTH_TAG_LENGTH {
+            return Err(Error::Aead);
+        }
+        let (nonce, encrypted_value) = value.split_at(NONCE_LENGTH);
+        let (encrypted_value, auth_tag) = encrypted_value.split_at(encrypted_value.len() - AUTH_TAG_LENGTH);
+
+        let aead = ChaCha20Poly1305::new(GenericArray::from_slice(&encryption_key[..]));
+        let mut decrypted_value = encrypted_value.to_vec();
+        aead.decrypt_in_place_detached(
+            GenericArray::from_slice(nonce),
+            self.associated_data(key).as_bytes(),
+            &mut decrypted_value,
+            GenericArray::from_slice(auth_tag),
+        )?;
+
+        Ok(decrypted_value)
+    }
+
+    /// Returns the path of a file relative to the storage directory, whether it's addressed by a relative key or by
+    /// the absolute path [`list_files`](FileStorage::list_files) returns.
+    fn associated_data(&self, key: &str) -> String {
+        let file_path = self.storage_path(key);
+        let relative_path = file_path.strip_prefix(&self.dir_path).unwrap_or(&file_path);
+
+        relative_path.to_string_lossy().into_owned()
+    }
+
     async fn remove_file(&self, key: &str) -> Result<()> {
         let file_path = self.storage_path(key);
         spawn_blocking(move || -> Result<()> {
@@ -283,6 +452,67 @@ mod tests {
         assert!(saved_config.is_err());
     }
 
+    #[tokio::test]
+    async fn test_storage_paths() {
+        let mut temp_dir = std::env::temp_dir();
+        temp_dir.push("hap_paths");
+        temp_dir.push("nested");
+
+        let mut storage = FileStorage::new(&temp_dir).await.unwrap();
+
+        assert_eq!(storage.dir_path(), temp_dir.as_path());
+        assert!(storage.pairings_path().is_dir());
+
+        storage.save_config(&Config::default()).await.unwrap();
+
+        assert_eq!(storage.config_path(), temp_dir.join("config.json"));
+        assert!(storage.config_path().is_file());
+
+        storage.delete_config().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_storage() {
+        let mut temp_dir = std::env::temp_dir();
+        temp_dir.push("hap_encrypted");
+
+        let mut storage = FileStorage::with_encryption(&temp_dir, [7; 32]).await.unwrap();
+
+        let config = Config {
+            name: "Encrypted".into(),
+            ..Default::default()
+        };
+        storage.save_config(&config).await.unwrap();
+        assert_eq!(storage.load_config().await.unwrap().name, "Encrypted");
+
+        // the file on disk must not contain the plaintext
+        let raw = fs::read(storage.config_path()).unwrap();
+        assert!(!raw.windows(9).any(|w| w == b"Encrypted"));
+
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(storage.config_path()).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        let pairing = Pairing {
+            id: Uuid::parse_str("bc158b86-cabf-432d-aee4-422ef0e3f1d5").unwrap(),
+            permissions: Permissions::ADMIN,
+            public_key: [1; 32],
+        };
+        storage.save_pairing(&pairing).await.unwrap();
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![pairing.clone()]);
+
+        // neither a different key nor no key at all can read the data
+        let other_storage = FileStorage::with_encryption(&temp_dir, [8; 32]).await.unwrap();
+        assert!(matches!(other_storage.load_config().await, Err(Error::Aead)));
+        let plain_storage = FileStorage::new(&temp_dir).await.unwrap();
+        assert!(plain_storage.load_config().await.is_err());
+
+        storage.delete_pairing(&pairing.id).await.unwrap();
+        storage.delete_config().await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_aid_cache_storage() {
         let mut aid_cache = vec![1, 2, 3, 4];
@@ -317,7 +547,7 @@ mod tests {
     async fn test_pairing_storage() {
         let pairing = Pairing {
             id: Uuid::parse_str("bc158b86-cabf-432d-aee4-422ef0e3f1d5").unwrap(),
-            permissions: Permissions::Admin,
+            permissions: Permissions::ADMIN,
             public_key: [
                 215, 90, 152, 1, 130, 177, 10, 183, 213, 75, 254, 211, 201, 100, 7, 58, 14, 225, 114, 243, 218, 166,
                 35, 37, 175, 2, 26, 104, 247, 7, 81, 26,
@@ -368,6 +598,32 @@ mod tests {
         assert!(saved_pairing.is_err());
     }
 
+    #[tokio::test]
+    async fn test_interval_sync_coalesces_writes() {
+        let mut temp_dir = std::env::temp_dir();
+        temp_dir.push("hap_sync");
+
+        let mut storage = FileStorage::new(&temp_dir).await.unwrap();
+        storage.set_sync_policy(SyncPolicy::Interval(Duration::from_millis(20)));
+
+        storage.save_bytes("first", &[1]).await.unwrap();
+        storage.save_bytes("second", &[2]).await.unwrap();
+        storage.save_bytes("first", &[3]).await.unwrap();
+        assert_eq!(storage.pending_syncs.lock().unwrap().len(), 2);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(storage.pending_syncs.lock().unwrap().is_empty());
+        assert_eq!(storage.load_bytes("first").await.unwrap(), vec![3]);
+
+        storage.set_sync_policy(SyncPolicy::Always);
+        storage.save_bytes("second", &[4]).await.unwrap();
+        assert!(storage.pending_syncs.lock().unwrap().is_empty());
+        assert_eq!(storage.load_bytes("second").await.unwrap(), vec![4]);
+
+        storage.delete_bytes("first").await.unwrap();
+        storage.delete_bytes("second").await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_byte_storage() {
         let mut bytes = vec![1, 2, 3, 4];
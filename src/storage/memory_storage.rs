@@ -0,0 +1,188 @@
+use async_trait::async_trait;
+use log::debug;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use uuid::Uuid;
+
+use crate::{pairing::Pairing, storage::Storage, Config, Error, Result};
+
+#[derive(Debug, Default)]
+struct Data {
+    // `Config` isn't `Clone`, so it's held serialized
+    config: Option<Vec<u8>>,
+    aid_cache: Option<Vec<u64>>,
+    pairings: HashMap<Uuid, Pairing>,
+    bytes: HashMap<String, Vec<u8>>,
+}
+
+/// [`MemoryStorage`](MemoryStorage) is an implementor of the [`Storage`](Storage) trait that keeps all data in memory.
+/// It's useful for tests and ephemeral accessories that don't need to persist anything across restarts.
+///
+/// Cloning a [`MemoryStorage`](MemoryStorage) is cheap and yields a handle to the same underlying data, so a clone can
+/// be used to inspect the state after the server mutated it.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    data: Arc<Mutex<Data>>,
+}
+
+impl MemoryStorage {
+    /// Creates a new, empty [`MemoryStorage`](MemoryStorage).
+    pub fn new() -> Self { Self::default() }
+
+    fn data(&self) -> Result<MutexGuard<Data>> { self.data.lock().map_err(|_| Error::Storage) }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn load_config(&self) -> Result<Config> {
+        let data = self.data()?;
+        let config_bytes = data.config.as_ref().ok_or(Error::Storage)?;
+        let config = serde_json::from_slice(config_bytes)?;
+
+        debug!("loaded Config: {:?}", &config);
+
+        Ok(config)
+    }
+
+    async fn save_config(&mut self, config: &Config) -> Result<()> {
+        let config_bytes = serde_json::to_vec(config)?;
+        self.data()?.config = Some(config_bytes);
+
+        Ok(())
+    }
+
+    async fn delete_config(&mut self) -> Result<()> { self.data()?.config.take().map(|_| ()).ok_or(Error::Storage) }
+
+    async fn load_aid_cache(&self) -> Result<Vec<u64>> {
+        let aid_cache = self.data()?.aid_cache.clone().ok_or(Error::Storage)?;
+
+        debug!("loaded AID cache: {:?}", &aid_cache);
+
+        Ok(aid_cache)
+    }
+
+    async fn save_aid_cache(&mut self, aid_cache: &[u64]) -> Result<()> {
+        self.data()?.aid_cache = Some(aid_cache.to_vec());
+
+        Ok(())
+    }
+
+    async fn delete_aid_cache(&mut self) -> Result<()> {
+        self.data()?.aid_cache.take().map(|_| ()).ok_or(Error::Storage)
+    }
+
+    async fn load_pairing(&self, id: &Uuid) -> Result<Pairing> {
+        let pairing = self.data()?.pairings.get(id).cloned().ok_or(Error::Storage)?;
+
+        debug!("loaded Pairing: {:?}", &pairing);
+
+        Ok(pairing)
+    }
+
+    async fn save_pairing(&mut self, pairing: &Pairing) -> Result<()> {
+        self.data()?.pairings.insert(pairing.id, pairing.clone());
+
+        Ok(())
+    }
+
+    async fn delete_pairing(&mut self, id: &Uuid) -> Result<()> {
+        self.data()?.pairings.remove(id).map(|_| ()).ok_or(Error::Storage)
+    }
+
+    async fn list_pairings(&self) -> Result<Vec<Pairing>> { Ok(self.data()?.pairings.values().cloned().collect()) }
+
+    async fn count_pairings(&self) -> Result<usize> { Ok(self.data()?.pairings.len()) }
+
+    async fn delete_all_pairings(&mut self) -> Result<Vec<Uuid>> {
+        Ok(self.data()?.pairings.drain().map(|(id, _)| id).collect())
+    }
+
+    async fn load_bytes(&self, key: &str) -> Result<Vec<u8>> {
+        self.data()?.bytes.get(key).cloned().ok_or(Error::Storage)
+    }
+
+    async fn save_bytes(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.data()?.bytes.insert(key.to_string(), value.to_vec());
+
+        Ok(())
+    }
+
+    async fn delete_bytes(&mut self, key: &str) -> Result<()> {
+        self.data()?.bytes.remove(key).map(|_| ()).ok_or(Error::Storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{pairing::Permissions, BonjourStatusFlag};
+
+    #[tokio::test]
+    async fn test_config_storage() {
+        let mut config = Config::default();
+        let mut storage = MemoryStorage::new();
+
+        storage.save_config(&config).await.unwrap();
+
+        let saved_config = storage.load_config().await.unwrap();
+        assert_eq!(saved_config.device_id, config.device_id);
+        assert_eq!(saved_config.status_flag, config.status_flag);
+
+        config.status_flag = BonjourStatusFlag::Zero;
+        storage.save_config(&config).await.unwrap();
+
+        let saved_config = storage.load_config().await.unwrap();
+        assert_eq!(saved_config.status_flag, BonjourStatusFlag::Zero);
+
+        storage.delete_config().await.unwrap();
+        assert!(storage.load_config().await.is_err());
+    }
+
+    /// Ensure clones share the same data, so tests can inspect what a server did to its storage.
+    #[tokio::test]
+    async fn test_pairing_storage() {
+        let pairing = Pairing {
+            id: Uuid::parse_str("bc158b86-cabf-432d-aee4-422ef0e3f1d5").unwrap(),
+            permissions: Permissions::ADMIN,
+            public_key: [
+                215, 90, 152, 1, 130, 177, 10, 183, 213, 75, 254, 211, 201, 100, 7, 58, 14, 225, 114, 243, 218, 166,
+                35, 37, 175, 2, 26, 104, 247, 7, 81, 26,
+            ],
+        };
+
+        let inspector = MemoryStorage::new();
+        let mut storage = inspector.clone();
+
+        assert_eq!(inspector.count_pairings().await.unwrap(), 0);
+        assert!(inspector.load_pairing(&pairing.id).await.is_err());
+
+        storage.save_pairing(&pairing).await.unwrap();
+
+        assert_eq!(inspector.count_pairings().await.unwrap(), 1);
+        assert_eq!(inspector.list_pairings().await.unwrap(), vec![pairing.clone()]);
+        assert_eq!(inspector.load_pairing(&pairing.id).await.unwrap(), pairing);
+
+        storage.delete_pairing(&pairing.id).await.unwrap();
+
+        assert_eq!(inspector.count_pairings().await.unwrap(), 0);
+        assert!(storage.delete_pairing(&pairing.id).await.is_err());
+
+        storage.save_pairing(&pairing).await.unwrap();
+        assert_eq!(storage.delete_all_pairings().await.unwrap(), vec![pairing.id]);
+        assert_eq!(inspector.count_pairings().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_byte_storage() {
+        let mut storage = MemoryStorage::new();
+
+        storage.save_bytes("my_custom_bytes", &[1, 2, 3, 4]).await.unwrap();
+        assert_eq!(storage.load_bytes("my_custom_bytes").await.unwrap(), vec![1, 2, 3, 4]);
+
+        storage.delete_bytes("my_custom_bytes").await.unwrap();
+        assert!(storage.load_bytes("my_custom_bytes").await.is_err());
+    }
+}
@@ -1,6 +1,15 @@
 mod file_storage;
+mod memory_storage;
+#[cfg(feature = "sqlite")]
+mod sqlite_storage;
 mod storage;
 
 pub(crate) mod accessory_database;
 
-pub use self::{file_storage::FileStorage, storage::Storage};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite_storage::SqliteStorage;
+pub use self::{
+    file_storage::{FileStorage, SyncPolicy},
+    memory_storage::MemoryStorage,
+    storage::Storage,
+};
@@ -0,0 +1,371 @@
+use async_trait::async_trait;
+use log::debug;
+use rusqlite::{params, Connection};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use tokio::task::spawn_blocking;
+use uuid::Uuid;
+
+use crate::{pairing::Pairing, storage::Storage, Config, Error, Result};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS objects (key TEXT PRIMARY KEY NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE IF NOT EXISTS pairings (id TEXT PRIMARY KEY NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE IF NOT EXISTS bytes (key TEXT PRIMARY KEY NOT NULL, value BLOB NOT NULL);
+";
+
+/// [`SqliteStorage`](SqliteStorage) is an implementor of the [`Storage`](Storage) trait that stores data to a SQLite
+/// database. It's only available with the `sqlite` feature enabled.
+///
+/// Cloning a [`SqliteStorage`](SqliteStorage) is cheap and yields a handle to the same underlying database connection.
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    /// Creates a new [`SqliteStorage`](SqliteStorage) backed by the database file at `path`. The file and the schema
+    /// are created if they don't exist yet.
+    pub async fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let connection = spawn_blocking(move || -> Result<Connection> { Ok(Connection::open(path)?) }).await??;
+
+        Self::from_connection(connection).await
+    }
+
+    /// Creates a new [`SqliteStorage`](SqliteStorage) backed by a transient in-memory database.
+    pub async fn in_memory() -> Result<Self> {
+        let connection = spawn_blocking(move || -> Result<Connection> { Ok(Connection::open_in_memory()?) }).await??;
+
+        Self::from_connection(connection).await
+    }
+
+    async fn from_connection(connection: Connection) -> Result<Self> {
+        let storage = SqliteStorage {
+            connection: Arc::new(Mutex::new(connection)),
+        };
+
+        storage
+            .with_connection(|connection| {
+                connection.execute_batch(SCHEMA)?;
+
+                Ok(())
+            })
+            .await?;
+
+        Ok(storage)
+    }
+
+    async fn with_connection<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        spawn_blocking(move || -> Result<T> {
+            let mut connection = connection.lock().map_err(|_| Error::Storage)?;
+            f(&mut *connection)
+        })
+        .await?
+    }
+
+    async fn read_object(&self, key: &'static str) -> Result<Vec<u8>> {
+        self.with_connection(move |connection| {
+            let value = connection.query_row("SELECT value FROM objects WHERE key = ?1", params![key], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })?;
+
+            Ok(value)
+        })
+        .await
+    }
+
+    async fn write_object(&self, key: &'static str, value: Vec<u8>) -> Result<()> {
+        self.with_connection(move |connection| {
+            connection.execute("INSERT OR REPLACE INTO objects (key, value) VALUES (?1, ?2)", params![
+                key, value
+            ])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove_object(&self, key: &'static str) -> Result<()> {
+        self.with_connection(move |connection| {
+            let deleted = connection.execute("DELETE FROM objects WHERE key = ?1", params![key])?;
+            if deleted == 0 {
+                return Err(Error::Storage);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn load_config(&self) -> Result<Config> {
+        let config_bytes = self.read_object("config").await?;
+        let config = serde_json::from_slice(&config_bytes)?;
+
+        debug!("loaded Config: {:?}", &config);
+
+        Ok(config)
+    }
+
+    async fn save_config(&mut self, config: &Config) -> Result<()> {
+        let config_bytes = serde_json::to_vec(config)?;
+        self.write_object("config", config_bytes).await
+    }
+
+    async fn delete_config(&mut self) -> Result<()> { self.remove_object("config").await }
+
+    async fn load_aid_cache(&self) -> Result<Vec<u64>> {
+        let aid_cache_bytes = self.read_object("aid_cache").await?;
+        let aid_cache = serde_json::from_slice(&aid_cache_bytes)?;
+
+        debug!("loaded AID cache: {:?}", &aid_cache);
+
+        Ok(aid_cache)
+    }
+
+    async fn save_aid_cache(&mut self, aid_cache: &[u64]) -> Result<()> {
+        let aid_cache_bytes = serde_json::to_vec(aid_cache)?;
+        self.write_object("aid_cache", aid_cache_bytes).await
+    }
+
+    async fn delete_aid_cache(&mut self) -> Result<()> { self.remove_object("aid_cache").await }
+
+    async fn load_pairing(&self, id: &Uuid) -> Result<Pairing> {
+        let id = id.to_string();
+        let pairing_bytes = self
+            .with_connection(move |connection| {
+                let value = connection.query_row("SELECT value FROM pairings WHERE id = ?1", params![id], |row| {
+                    row.get::<_, Vec<u8>>(0)
+                })?;
+
+                Ok(value)
+            })
+            .await?;
+
+        let pairing = Pairing::from_bytes(&pairing_bytes)?;
+
+        debug!("loaded Pairing: {:?}", &pairing);
+
+        Ok(pairing)
+    }
+
+    async fn save_pairing(&mut self, pairing: &Pairing) -> Result<()> {
+        let id = pairing.id.to_string();
+        let pairing_bytes = pairing.as_bytes()?;
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute("INSERT OR REPLACE INTO pairings (id, value) VALUES (?1, ?2)", params![
+                id,
+                pairing_bytes
+            ])?;
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_pairing(&mut self, id: &Uuid) -> Result<()> {
+        let id = id.to_string();
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+            let deleted = transaction.execute("DELETE FROM pairings WHERE id = ?1", params![id])?;
+            if deleted == 0 {
+                return Err(Error::Storage);
+            }
+            transaction.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_all_pairings(&mut self) -> Result<Vec<Uuid>> {
+        self.with_connection(|connection| {
+            let transaction = connection.transaction()?;
+
+            let mut ids = Vec::new();
+            {
+                let mut statement = transaction.prepare("SELECT id FROM pairings ORDER BY id")?;
+                let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+                for id in rows {
+                    ids.push(Uuid::parse_str(&id?).map_err(|_| Error::Storage)?);
+                }
+            }
+
+            transaction.execute("DELETE FROM pairings", [])?;
+            transaction.commit()?;
+
+            Ok(ids)
+        })
+        .await
+    }
+
+    async fn list_pairings(&self) -> Result<Vec<Pairing>> {
+        self.with_connection(|connection| {
+            let mut statement = connection.prepare("SELECT value FROM pairings ORDER BY id")?;
+            let rows = statement.query_map([], |row| row.get::<_, Vec<u8>>(0))?;
+
+            let mut pairings = Vec::new();
+            for pairing_bytes in rows {
+                let pairing = Pairing::from_bytes(&pairing_bytes?)?;
+                pairings.push(pairing);
+            }
+
+            Ok(pairings)
+        })
+        .await
+    }
+
+    async fn count_pairings(&self) -> Result<usize> {
+        self.with_connection(|connection| {
+            let count = connection.query_row("SELECT COUNT(*) FROM pairings", [], |row| row.get::<_, i64>(0))?;
+
+            Ok(count as usize)
+        })
+        .await
+    }
+
+    async fn load_bytes(&self, key: &str) -> Result<Vec<u8>> {
+        let key = key.to_string();
+        self.with_connection(move |connection| {
+            let value = connection.query_row("SELECT value FROM bytes WHERE key = ?1", params![key], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })?;
+
+            Ok(value)
+        })
+        .await
+    }
+
+    async fn save_bytes(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let key = key.to_string();
+        let value = value.to_vec();
+        self.with_connection(move |connection| {
+            connection.execute("INSERT OR REPLACE INTO bytes (key, value) VALUES (?1, ?2)", params![
+                key, value
+            ])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_bytes(&mut self, key: &str) -> Result<()> {
+        let key = key.to_string();
+        self.with_connection(move |connection| {
+            let deleted = connection.execute("DELETE FROM bytes WHERE key = ?1", params![key])?;
+            if deleted == 0 {
+                return Err(Error::Storage);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pairing::Permissions;
+
+    #[tokio::test]
+    async fn test_config_storage() {
+        let mut config: Config = Default::default();
+
+        let mut storage = SqliteStorage::in_memory().await.unwrap();
+
+        storage.save_config(&config).await.unwrap();
+
+        // config should be correctly saved
+        let saved_config = storage.load_config().await.unwrap();
+        assert_eq!(saved_config.device_id, config.device_id);
+        assert_eq!(saved_config.configuration_number, config.configuration_number);
+
+        config.configuration_number += 1;
+        storage.save_config(&config).await.unwrap();
+
+        // config should be correctly updated
+        let saved_config = storage.load_config().await.unwrap();
+        assert_eq!(saved_config.configuration_number, config.configuration_number);
+
+        storage.delete_config().await.unwrap();
+
+        // config should be deleted
+        let saved_config = storage.load_config().await;
+        assert!(saved_config.is_err());
+    }
+
+    /// Ensure we can correctly create, read, count, list and delete [`Pairing`](Pairing)s.
+    #[tokio::test]
+    async fn test_pairing_storage() {
+        let pairing = Pairing {
+            id: Uuid::parse_str("bc158b86-cabf-432d-aee4-422ef0e3f1d5").unwrap(),
+            permissions: Permissions::ADMIN,
+            public_key: [
+                215, 90, 152, 1, 130, 177, 10, 183, 213, 75, 254, 211, 201, 100, 7, 58, 14, 225, 114, 243, 218, 166,
+                35, 37, 175, 2, 26, 104, 247, 7, 81, 26,
+            ],
+        };
+
+        let mut storage = SqliteStorage::in_memory().await.unwrap();
+
+        // a fresh storage should count 0 pairings, list an empty Vec, and error on a non-existent ID
+        assert_eq!(storage.count_pairings().await.unwrap(), 0);
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![]);
+        assert!(storage.load_pairing(&pairing.id).await.is_err());
+
+        storage.save_pairing(&pairing).await.unwrap();
+
+        // saving the same pairing twice should update it instead of adding a second row
+        storage.save_pairing(&pairing).await.unwrap();
+
+        assert_eq!(storage.count_pairings().await.unwrap(), 1);
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![pairing.clone()]);
+        assert_eq!(storage.load_pairing(&pairing.id).await.unwrap(), pairing);
+
+        storage.delete_pairing(&pairing.id).await.unwrap();
+
+        assert_eq!(storage.count_pairings().await.unwrap(), 0);
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![]);
+        assert!(storage.load_pairing(&pairing.id).await.is_err());
+
+        // deleting all pairings should return the IDs of the removed ones
+        let mut other_pairing = pairing.clone();
+        other_pairing.id = Uuid::parse_str("ec5f0f1a-6a64-4d43-8d5c-2e0a0f1b4c6e").unwrap();
+        storage.save_pairing(&pairing).await.unwrap();
+        storage.save_pairing(&other_pairing).await.unwrap();
+
+        let deleted_ids = storage.delete_all_pairings().await.unwrap();
+        assert_eq!(deleted_ids, vec![pairing.id, other_pairing.id]);
+        assert_eq!(storage.count_pairings().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_byte_storage() {
+        let mut storage = SqliteStorage::in_memory().await.unwrap();
+
+        storage.save_bytes("my_custom_bytes", &[1, 2, 3, 4]).await.unwrap();
+        assert_eq!(storage.load_bytes("my_custom_bytes").await.unwrap(), vec![1, 2, 3, 4]);
+
+        storage.save_bytes("my_custom_bytes", &[1, 2, 3, 4, 5]).await.unwrap();
+        assert_eq!(storage.load_bytes("my_custom_bytes").await.unwrap(), vec![
+            1, 2, 3, 4, 5
+        ]);
+
+        storage.delete_bytes("my_custom_bytes").await.unwrap();
+        assert!(storage.load_bytes("my_custom_bytes").await.is_err());
+    }
+}
@@ -3,8 +3,9 @@ use uuid::Uuid;
 
 use crate::{pairing::Pairing, Config, Result};
 
-/// [`Storage`](Storage) is implemented by the persistent data storage methods HAP supports. Currently, that's just
-/// [`FileStorage`](crate::storage::FileStorage).
+/// [`Storage`](Storage) is implemented by the persistent data storage methods HAP supports. Currently, that's
+/// [`FileStorage`](crate::storage::FileStorage), [`MemoryStorage`](crate::storage::MemoryStorage) and, with the
+/// `sqlite` feature enabled, `SqliteStorage`.
 #[async_trait]
 pub trait Storage: Send + Sync {
     /// Loads the [`Config`](Config) from the [`Storage`](Storage).
@@ -25,6 +26,16 @@ pub trait Storage: Send + Sync {
     async fn save_pairing(&mut self, pairing: &Pairing) -> Result<()>;
     /// Deletes the [`Pairing`](Pairing) from the [`Storage`](Storage).
     async fn delete_pairing(&mut self, id: &Uuid) -> Result<()>;
+    /// Deletes all [`Pairing`](Pairing)s from the [`Storage`](Storage) and returns the IDs of the deleted ones.
+    async fn delete_all_pairings(&mut self) -> Result<Vec<Uuid>> {
+        let mut ids = Vec::new();
+        for pairing in self.list_pairings().await? {
+            self.delete_pairing(&pairing.id).await?;
+            ids.push(pairing.id);
+        }
+
+        Ok(ids)
+    }
     /// Loads all [`Pairing`](Pairing)s from the [`Storage`](Storage).
     async fn list_pairings(&self) -> Result<Vec<Pairing>>;
     /// Returns the count of [`Pairing`](Pairing)s stored on the [`Storage`](Storage).
@@ -35,4 +46,19 @@ pub trait Storage: Send + Sync {
     async fn save_bytes(&mut self, key: &str, value: &[u8]) -> Result<()>;
     /// Deletes a set of arbitrary bytes from the [`Storage`](Storage).
     async fn delete_bytes(&mut self, key: &str) -> Result<()>;
+    /// Loads the persisted value of the characteristic with the given accessory and instance IDs from the
+    /// [`Storage`](Storage).
+    async fn load_characteristic_value(&self, aid: u64, iid: u64) -> Result<serde_json::Value> {
+        let value = self.load_bytes(&characteristic_value_key(aid, iid)).await?;
+
+        Ok(serde_json::from_slice(&value)?)
+    }
+    /// Saves the value of the characteristic with the given accessory and instance IDs to the [`Storage`](Storage).
+    async fn save_characteristic_value(&mut self, aid: u64, iid: u64, value: &serde_json::Value) -> Result<()> {
+        let value = serde_json::to_vec(value)?;
+
+        self.save_bytes(&characteristic_value_key(aid, iid), &value).await
+    }
 }
+
+fn characteristic_value_key(aid: u64, iid: u64) -> String { format!("characteristic_value_{}_{}", aid, iid) }
@@ -0,0 +1,937 @@
+//! A controller for testing accessories without an iOS device, available with the `test-util` feature.
+//!
+//! [`TestController`](TestController) connects to a running [`IpServer`](crate::server::IpServer) and talks to it like
+//! an iOS device would: it pairs with pair setup, establishes an encrypted session with pair verify and sends requests
+//! to the HTTP endpoints, e.g. to read and write characteristics or to manage pairings.
+//!
+//! # Examples
+//! ```no_run
+//! use hap::{
+//!     accessory::{lightbulb::LightbulbAccessory, AccessoryInformation},
+//!     futures::{future, pin_mut},
+//!     server::{IpServer, Server},
+//!     storage::MemoryStorage,
+//!     test_util::TestController,
+//!     Config,
+//!     Result,
+//! };
+//!
+//! # async fn test() -> Result<()> {
+//! let config = Config {
+//!     host: [127, 0, 0, 1].into(),
+//!     port: 0,
+//!     ..Default::default()
+//! };
+//! let pin = config.pin.clone();
+//!
+//! let server = IpServer::new(config, MemoryStorage::new()).await?;
+//! server
+//!     .add_accessory(LightbulbAccessory::new(1, AccessoryInformation::default())?)
+//!     .await?;
+//!
+//! let test = async {
+//!     let mut controller = TestController::connect(server.local_addr()).await?;
+//!     controller.pair_setup(&pin).await?;
+//!     controller.pair_verify().await?;
+//!
+//!     let response = controller.get("/accessories").await?;
+//!     assert_eq!(response.status, 200);
+//!
+//!     Ok::<_, hap::Error>(())
+//! };
+//! pin_mut!(test);
+//!
+//! future::select(server.run_handle(), test)
+//!     .await
+//!     .factor_first()
+//!     .0
+//! # }
+//! ```
+
+use aead::{generic_array::GenericArray, AeadInPlace, KeyInit};
+use byteorder::{ByteOrder, LittleEndian};
+use chacha20poly1305::ChaCha20Poly1305;
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha512;
+use srp::{client::SrpClient, groups::G_3072};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    str,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use uuid::Uuid;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::{
+    pairing::Permissions,
+    tlv::{self, Encodable, Method, Type, Value},
+    transport::{
+        hkdf_extract_and_expand,
+        http::handler::pair_setup::{accessory_proof, client_proof, PAIRING_FLAG_TRANSIENT},
+        tcp,
+    },
+    Error,
+    Pin,
+    Result,
+};
+
+/// A controller connected to an accessory server.
+///
+/// Every controller has its own random pairing ID and key pair. Requests are sent unencrypted until
+/// [`pair_verify`](TestController::pair_verify) succeeds and encrypted afterwards, like on a connection of an iOS
+/// device.
+pub struct TestController {
+    address: SocketAddr,
+    id: Uuid,
+    keypair: SigningKey,
+    accessory_public_key: Option<VerifyingKey>,
+    stream: TcpStream,
+    session: Option<Session>,
+    buf: Vec<u8>,
+    events: VecDeque<serde_json::Value>,
+}
+
+/// Returns a config for a server listening on an ephemeral port of the loopback interface.
+#[cfg(test)]
+pub(crate) fn test_config() -> crate::Config {
+    crate::Config {
+        host: [127, 0, 0, 1].into(),
+        port: 0,
+        ..Default::default()
+    }
+}
+
+/// Runs `test` against the running `server` and returns its output. Panics if the server stops first.
+#[cfg(test)]
+pub(crate) async fn run_with_server<T>(
+    server: &crate::server::IpServer,
+    test: impl std::future::Future<Output = T>,
+) -> T {
+    futures::pin_mut!(test);
+    match futures::future::select(crate::server::Server::run_handle(server), test).await {
+        futures::future::Either::Left((result, _)) => panic!("the server stopped: {:?}", result),
+        futures::future::Either::Right((output, _)) => output,
+    }
+}
+
+/// Polls `condition` until it holds, e.g. until a queued event listener has seen its events. Gives up after a second.
+#[cfg(test)]
+pub(crate) async fn wait_until(condition: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+}
+
+/// Keys and frame counters of an encrypted session.
+struct Session {
+    write_key: [u8; 32],
+    read_key: [u8; 32],
+    write_count: u64,
+    read_count: u64,
+}
+
+/// A response of the accessory server to a request of a [`TestController`](TestController).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResponse {
+    /// HTTP status code of the response.
+    pub status: u16,
+    /// Body of the response.
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    /// Deserializes the JSON body of the response.
+    pub fn json(&self) -> Result<serde_json::Value> { Ok(serde_json::from_slice(&self.body)?) }
+}
+
+impl TestController {
+    /// Connects a new controller to the accessory server listening on the given address.
+    pub async fn connect(address: SocketAddr) -> Result<Self> {
+        let stream = TcpStream::connect(address).await?;
+        let mut csprng = OsRng {};
+
+        Ok(Self {
+            address,
+            id: Uuid::new_v4(),
+            keypair: SigningKey::generate(&mut csprng),
+            accessory_public_key: None,
+            stream,
+            session: None,
+            buf: Vec::new(),
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Closes the connection and opens a new, unencrypted one. The pairing ID and the keys are kept, so the controller
+    /// can pair verify again.
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.stream = TcpStream::connect(self.address).await?;
+        self.session = None;
+        self.buf.clear();
+        self.events.clear();
+
+        Ok(())
+    }
+
+    /// Returns the pairing ID of the controller.
+    pub fn id(&self) -> Uuid { self.id }
+
+    /// Returns the long-term public key of the controller.
+    pub fn public_key(&self) -> [u8; 32] { self.keypair.verifying_key().to_bytes() }
+
+    /// Returns the long-term public key of the accessory, which is known after a pair setup.
+    pub fn accessory_public_key(&self) -> Option<[u8; 32]> { self.accessory_public_key.map(|key| key.to_bytes()) }
+
+    /// Sets the long-term public key of the accessory, e.g. for a controller that was added by an admin controller
+    /// with [`add_pairing`](TestController::add_pairing) instead of running a pair setup itself.
+    pub fn set_accessory_public_key(&mut self, public_key: [u8; 32]) -> Result<()> {
+        self.accessory_public_key = Some(verifying_key(&public_key)?);
+
+        Ok(())
+    }
+
+    /// Pairs the controller with the accessory using its setup code, like adding the accessory in the Home app does.
+    pub async fn pair_setup(&mut self, pin: &Pin) -> Result<()> {
+        let shared_secret = self.srp_exchange(pin, vec![]).await?;
+
+        let encryption_key =
+            hkdf_extract_and_expand(b"Pair-Setup-Encrypt-Salt", &shared_secret, b"Pair-Setup-Encrypt-Info")?;
+        let controller_x = hkdf_extract_and_expand(
+            b"Pair-Setup-Controller-Sign-Salt",
+            &shared_secret,
+            b"Pair-Setup-Controller-Sign-Info",
+        )?;
+
+        let id = self.id.to_string();
+        let public_key = self.keypair.verifying_key();
+        let mut controller_info: Vec<u8> = Vec::new();
+        controller_info.extend(&controller_x);
+        controller_info.extend(id.as_bytes());
+        controller_info.extend(public_key.as_bytes());
+        let signature = self.keypair.sign(&controller_info);
+
+        let sub_tlv = vec![
+            Value::Identifier(id),
+            Value::PublicKey(public_key.as_bytes().to_vec()),
+            Value::Signature(signature.to_bytes().to_vec()),
+        ]
+        .encode();
+        let encrypted_data = encrypt(&encryption_key, b"PS-Msg05", &sub_tlv)?;
+
+        let m6 = self
+            .pair_step(
+                "/pair-setup",
+                vec![Value::State(5), Value::EncryptedData(encrypted_data)],
+                6,
+            )
+            .await?;
+
+        let sub_tlv = tlv::decode(&decrypt(&encryption_key, b"PS-Msg06", item(&m6, Type::EncryptedData)?)?);
+        let accessory_public_key = verifying_key(item(&sub_tlv, Type::PublicKey)?)?;
+        let accessory_x = hkdf_extract_and_expand(
+            b"Pair-Setup-Accessory-Sign-Salt",
+            &shared_secret,
+            b"Pair-Setup-Accessory-Sign-Info",
+        )?;
+
+        let mut accessory_info: Vec<u8> = Vec::new();
+        accessory_info.extend(&accessory_x);
+        accessory_info.extend(item(&sub_tlv, Type::Identifier)?);
+        accessory_info.extend(accessory_public_key.as_bytes());
+        if !verify_signature(&accessory_public_key, &accessory_info, item(&sub_tlv, Type::Signature)?) {
+            return Err(failure("pair setup M6: invalid signature of the accessory"));
+        }
+
+        self.accessory_public_key = Some(accessory_public_key);
+
+        Ok(())
+    }
+
+    /// Establishes an encrypted session with a transient pair setup, which authenticates the controller with the setup
+    /// code without storing a pairing, like a one-off setup of an accessory does. The session has no controller ID.
+    pub async fn pair_setup_transient(&mut self, pin: &Pin) -> Result<()> {
+        let shared_secret = self
+            .srp_exchange(pin, vec![Value::Flags(PAIRING_FLAG_TRANSIENT)])
+            .await?;
+
+        self.session = Some(Session {
+            write_key: hkdf_extract_and_expand(b"Control-Salt", &shared_secret, b"Control-Write-Encryption-Key")?,
+            read_key: hkdf_extract_and_expand(b"Control-Salt", &shared_secret, b"Control-Read-Encryption-Key")?,
+            write_count: 0,
+            read_count: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Runs M1 to M4 of a pair setup and returns the SRP shared secret. `flags` are added to the M1 request.
+    async fn srp_exchange(&mut self, pin: &Pin, flags: tlv::Container) -> Result<Vec<u8>> {
+        // the method item 0 requests a pair setup without MFi authentication
+        let mut m1 = vec![Value::State(1), Value::Bytes(Type::Method as u8, vec![0])];
+        m1.extend(flags);
+        let m2 = self.pair_step("/pair-setup", m1, 2).await?;
+        let salt = item(&m2, Type::Salt)?;
+        let b_pub = item(&m2, Type::PublicKey)?;
+
+        let mut a = [0; 64];
+        OsRng {}.fill_bytes(&mut a);
+        let srp_client = SrpClient::<Sha512>::new(&G_3072);
+        let a_pub = srp_client.compute_public_ephemeral(&a);
+        let verifier = srp_client
+            .process_reply(&a, b"Pair-Setup", pin.to_string().as_bytes(), salt, b_pub)
+            .map_err(|_| failure("pair setup M2: invalid SRP public key of the accessory"))?;
+        let shared_secret = verifier.key().to_vec();
+        let a_proof = client_proof::<Sha512>(b_pub, &a_pub, salt, &shared_secret, &G_3072);
+
+        let m4 = self
+            .pair_step(
+                "/pair-setup",
+                vec![
+                    Value::State(3),
+                    Value::PublicKey(a_pub.clone()),
+                    Value::Proof(a_proof.clone()),
+                ],
+                4,
+            )
+            .await?;
+        if item(&m4, Type::Proof)? != accessory_proof::<Sha512>(&a_pub, &a_proof, &shared_secret).as_slice() {
+            return Err(failure("pair setup M4: invalid SRP proof of the accessory"));
+        }
+
+        Ok(shared_secret)
+    }
+
+    /// Establishes an encrypted session on the connection. The controller has to be paired and the long-term public
+    /// key of the accessory has to be known, either from a [`pair_setup`](TestController::pair_setup) or from
+    /// [`set_accessory_public_key`](TestController::set_accessory_public_key).
+    pub async fn pair_verify(&mut self) -> Result<()> {
+        let accessory_public_key = self
+            .accessory_public_key
+            .ok_or_else(|| failure("pair verify: the public key of the accessory is unknown"))?;
+
+        let a = EphemeralSecret::random();
+        let a_pub = PublicKey::from(&a);
+
+        let m2 = self
+            .pair_step(
+                "/pair-verify",
+                vec![Value::State(1), Value::PublicKey(a_pub.as_bytes().to_vec())],
+                2,
+            )
+            .await?;
+        let b_pub = <[u8; 32]>::try_from(item(&m2, Type::PublicKey)?)
+            .map_err(|_| failure("pair verify M2: invalid public key of the accessory"))?;
+        let b_pub = PublicKey::from(b_pub);
+        let shared_secret = a.diffie_hellman(&b_pub);
+        let session_key = hkdf_extract_and_expand(
+            b"Pair-Verify-Encrypt-Salt",
+            shared_secret.as_bytes(),
+            b"Pair-Verify-Encrypt-Info",
+        )?;
+
+        let sub_tlv = tlv::decode(&decrypt(&session_key, b"PV-Msg02", item(&m2, Type::EncryptedData)?)?);
+        let mut accessory_info: Vec<u8> = Vec::new();
+        accessory_info.extend(b_pub.as_bytes());
+        accessory_info.extend(item(&sub_tlv, Type::Identifier)?);
+        accessory_info.extend(a_pub.as_bytes());
+        if !verify_signature(&accessory_public_key, &accessory_info, item(&sub_tlv, Type::Signature)?) {
+            return Err(failure("pair verify M2: invalid signature of the accessory"));
+        }
+
+        let id = self.id.to_string();
+        let mut controller_info: Vec<u8> = Vec::new();
+        controller_info.extend(a_pub.as_bytes());
+        controller_info.extend(id.as_bytes());
+        controller_info.extend(b_pub.as_bytes());
+        let signature = self.keypair.sign(&controller_info);
+
+        let sub_tlv = vec![Value::Identifier(id), Value::Signature(signature.to_bytes().to_vec())].encode();
+        let encrypted_data = encrypt(&session_key, b"PV-Msg03", &sub_tlv)?;
+
+        self.pair_step(
+            "/pair-verify",
+            vec![Value::State(3), Value::EncryptedData(encrypted_data)],
+            4,
+        )
+        .await?;
+
+        self.session = Some(Session {
+            write_key: hkdf_extract_and_expand(
+                b"Control-Salt",
+                shared_secret.as_bytes(),
+                b"Control-Write-Encryption-Key",
+            )?,
+            read_key: hkdf_extract_and_expand(
+                b"Control-Salt",
+                shared_secret.as_bytes(),
+                b"Control-Read-Encryption-Key",
+            )?,
+            write_count: 0,
+            read_count: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Adds a pairing of another controller. This controller has to be an admin with an encrypted session.
+    pub async fn add_pairing(&mut self, id: Uuid, public_key: [u8; 32], permissions: Permissions) -> Result<()> {
+        self.pair_step(
+            "/pairings",
+            vec![
+                Value::State(1),
+                Value::Method(Method::AddPairing),
+                Value::Identifier(id.to_string()),
+                Value::PublicKey(public_key.to_vec()),
+                Value::Permissions(permissions),
+            ],
+            2,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes the pairing of a controller. This controller has to be an admin with an encrypted session.
+    pub async fn remove_pairing(&mut self, id: Uuid) -> Result<()> {
+        self.pair_step(
+            "/pairings",
+            vec![
+                Value::State(1),
+                Value::Method(Method::RemovePairing),
+                Value::Identifier(id.to_string()),
+            ],
+            2,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Sends a `GET` request, e.g. to `/accessories` or `/characteristics?id=1.9`.
+    pub async fn get(&mut self, path: &str) -> Result<TestResponse> { self.request("GET", path, None).await }
+
+    /// Sends a `PUT` request with a JSON body, e.g. to `/characteristics`.
+    pub async fn put(&mut self, path: &str, body: &serde_json::Value) -> Result<TestResponse> {
+        let body = serde_json::to_vec(body)?;
+
+        self.request("PUT", path, Some(("application/hap+json", body))).await
+    }
+
+    /// Sends a `POST` request with a TLV body to one of the pairing endpoints and returns the decoded TLV items of the
+    /// response.
+    pub async fn post_tlv(&mut self, path: &str, body: tlv::Container) -> Result<HashMap<u8, Vec<u8>>> {
+        let response = self
+            .request("POST", path, Some(("application/pairing+tlv8", body.encode())))
+            .await?;
+
+        Ok(tlv::decode(&response.body))
+    }
+
+    /// Returns the body of the next event notification sent by the accessory server, waiting for it if none was
+    /// received yet. Event notifications are only sent for characteristics the controller subscribed to.
+    pub async fn next_event(&mut self) -> Result<serde_json::Value> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            if let Some(response) = self.read_message().await? {
+                return Err(failure(format!(
+                    "received a response with status {} while waiting for an event",
+                    response.status
+                )));
+            }
+        }
+    }
+
+    /// Posts a pairing request and returns the TLV items of the response, which has to be the given step and mustn't
+    /// contain an error.
+    async fn pair_step(&mut self, path: &str, request: tlv::Container, state: u8) -> Result<HashMap<u8, Vec<u8>>> {
+        let response = self.post_tlv(path, request).await?;
+
+        if let Some(error) = response.get(&(Type::Error as u8)) {
+            return Err(failure(format!(
+                "{} M{}: the accessory answered with the error code {:?}",
+                path, state, error
+            )));
+        }
+        if response.get(&(Type::State as u8)) != Some(&vec![state]) {
+            return Err(failure(format!("{} M{}: unexpected response", path, state)));
+        }
+
+        Ok(response)
+    }
+
+    async fn request(&mut self, method: &str, path: &str, body: Option<(&str, Vec<u8>)>) -> Result<TestResponse> {
+        let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", method, path, self.address).into_bytes();
+        match body {
+            Some((content_type, body)) => {
+                request.extend(
+                    format!(
+                        "Content-Type: {}\r\nContent-Length: {}\r\n\r\n",
+                        content_type,
+                        body.len()
+                    )
+                    .bytes(),
+                );
+                request.extend(body);
+            },
+            None => request.extend(b"\r\n"),
+        }
+
+        self.write(&request).await?;
+
+        loop {
+            if let Some(response) = self.read_message().await? {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Reads the next HTTP message. Event notifications are queued and `None` is returned for them.
+    async fn read_message(&mut self) -> Result<Option<TestResponse>> {
+        let (head_len, body_start) = loop {
+            if let Some(head_end) = find_head_end(&self.buf) {
+                break head_end;
+            }
+            self.fill().await?;
+        };
+
+        let head = str::from_utf8(&self.buf[..head_len])?.to_string();
+        let mut lines = head.lines();
+        let status_line = lines.next().unwrap_or_default();
+        let mut status_parts = status_line.split_whitespace();
+        let protocol = status_parts.next().unwrap_or_default();
+        let status = status_parts
+            .next()
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| failure(format!("invalid status line `{}`", status_line)))?;
+
+        let mut content_length = 0;
+        for line in lines {
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse()?;
+                }
+            }
+        }
+
+        while self.buf.len() < body_start + content_length {
+            self.fill().await?;
+        }
+        let body = self.buf[body_start..body_start + content_length].to_vec();
+        self.buf.drain(..body_start + content_length);
+
+        if protocol == "EVENT/1.0" {
+            self.events.push_back(serde_json::from_slice(&body)?);
+            return Ok(None);
+        }
+
+        Ok(Some(TestResponse { status, body }))
+    }
+
+    /// Reads data from the connection into the buffer, decrypting a frame if the session is encrypted.
+    async fn fill(&mut self) -> Result<()> {
+        match self.session {
+            Some(ref mut session) => {
+                let mut aad = [0; 2];
+                self.stream.read_exact(&mut aad).await?;
+                let mut frame = vec![0; LittleEndian::read_u16(&aad) as usize + 16];
+                self.stream.read_exact(&mut frame).await?;
+                let auth_tag = frame.split_off(frame.len() - 16);
+
+                let nonce = tcp::next_nonce(&mut session.read_count)?;
+                ChaCha20Poly1305::new(GenericArray::from_slice(&session.read_key)).decrypt_in_place_detached(
+                    GenericArray::from_slice(&nonce),
+                    &aad,
+                    &mut frame,
+                    GenericArray::from_slice(&auth_tag),
+                )?;
+
+                self.buf.extend(frame);
+            },
+            None => {
+                let mut data = [0; 1024];
+                let len = self.stream.read(&mut data).await?;
+                if len == 0 {
+                    return Err(failure("the accessory server closed the connection"));
+                }
+
+                self.buf.extend_from_slice(&data[..len]);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Writes data to the connection, encrypting it in frames of up to 1024 bytes if the session is encrypted.
+    async fn write(&mut self, data: &[u8]) -> Result<()> {
+        match self.session {
+            Some(ref mut session) =>
+                for chunk in data.chunks(1024) {
+                    let mut aad = [0; 2];
+                    LittleEndian::write_u16(&mut aad, chunk.len() as u16);
+
+                    let nonce = tcp::next_nonce(&mut session.write_count)?;
+                    let mut frame = chunk.to_vec();
+                    let auth_tag = ChaCha20Poly1305::new(GenericArray::from_slice(&session.write_key))
+                        .encrypt_in_place_detached(GenericArray::from_slice(&nonce), &aad, &mut frame)?;
+
+                    self.stream
+                        .write_all(&[&aad[..], &frame[..], &auth_tag[..]].concat())
+                        .await?;
+                },
+            None => self.stream.write_all(data).await?,
+        }
+
+        Ok(())
+    }
+}
+
+fn failure(message: impl Into<String>) -> Error { Error::TestController(message.into()) }
+
+/// Returns the TLV item of the given type.
+fn item(items: &HashMap<u8, Vec<u8>>, t: Type) -> Result<&[u8]> {
+    items
+        .get(&(t as u8))
+        .map(|item| item.as_slice())
+        .ok_or_else(|| failure(format!("missing TLV item of type {:?}", t)))
+}
+
+fn verifying_key(bytes: &[u8]) -> Result<VerifyingKey> {
+    let bytes = <[u8; 32]>::try_from(bytes).map_err(|_| failure("invalid Ed25519 public key"))?;
+
+    VerifyingKey::from_bytes(&bytes).map_err(|_| failure("invalid Ed25519 public key"))
+}
+
+fn verify_signature(public_key: &VerifyingKey, message: &[u8], signature: &[u8]) -> bool {
+    match <[u8; 64]>::try_from(signature) {
+        Ok(signature) => public_key
+            .verify_strict(message, &ed25519_dalek::Signature::from_bytes(&signature))
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Encrypts the sub-TLV of a pairing message, appending the authentication tag. The nonce is the given message label
+/// prefixed by 4 zero bytes.
+fn encrypt(key: &[u8; 32], label: &[u8; 8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = vec![0; 4];
+    nonce.extend(label);
+
+    let mut encrypted_data = data.to_vec();
+    let auth_tag = ChaCha20Poly1305::new(GenericArray::from_slice(key)).encrypt_in_place_detached(
+        GenericArray::from_slice(&nonce),
+        &[],
+        &mut encrypted_data,
+    )?;
+    encrypted_data.extend(&auth_tag);
+
+    Ok(encrypted_data)
+}
+
+/// Decrypts the sub-TLV of a pairing message encrypted like by [`encrypt`](encrypt).
+fn decrypt(key: &[u8; 32], label: &[u8; 8], data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 16 {
+        return Err(failure("the encrypted data is too short"));
+    }
+    let (encrypted_data, auth_tag) = data.split_at(data.len() - 16);
+
+    let mut nonce = vec![0; 4];
+    nonce.extend(label);
+
+    let mut decrypted_data = encrypted_data.to_vec();
+    ChaCha20Poly1305::new(GenericArray::from_slice(key)).decrypt_in_place_detached(
+        GenericArray::from_slice(&nonce),
+        &[],
+        &mut decrypted_data,
+        GenericArray::from_slice(auth_tag),
+    )?;
+
+    Ok(decrypted_data)
+}
+
+/// Returns the length of the head of the HTTP message at the start of the buffer and the offset of its body, if the
+/// head is complete. Event notifications separate their lines with `\n` instead of `\r\n`.
+fn find_head_end(buf: &[u8]) -> Option<(usize, usize)> {
+    for (i, &byte) in buf.iter().enumerate() {
+        if byte == b'\n' {
+            if buf[i + 1..].starts_with(b"\n") {
+                return Some((i, i + 2));
+            }
+            if buf[i + 1..].starts_with(b"\r\n") {
+                return Some((i, i + 3));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        accessory::{lightbulb::LightbulbAccessory, AccessoryInformation},
+        characteristic::{HapCharacteristic, Perm},
+        server::{IpServer, Server},
+        storage::MemoryStorage,
+        transport::http::Status,
+        Config,
+        Event,
+    };
+    use futures::FutureExt;
+    use serde_json::json;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    #[test]
+    fn test_find_head_end() {
+        assert_eq!(find_head_end(b"HTTP/1.1 204 No Content\r\n\r\n"), Some((24, 27)));
+        assert_eq!(
+            find_head_end(b"EVENT/1.0 200 OK\nContent-Length: 2\n\n{}"),
+            Some((34, 36))
+        );
+        assert_eq!(find_head_end(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_pairing_and_requests() {
+        let config = test_config();
+        let pin = config.pin.clone();
+
+        let server = IpServer::new(config, MemoryStorage::new()).await.unwrap();
+        let address = server.local_addr();
+        let lightbulb = LightbulbAccessory::new(1, AccessoryInformation {
+            name: "Lightbulb".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let power_state_iid = lightbulb.lightbulb.power_state.get_id();
+        server.add_accessory(lightbulb).await.unwrap();
+
+        let test = async {
+            let mut admin = TestController::connect(address).await?;
+            assert_eq!(admin.get("/accessories").await?.status, 470);
+
+            admin.pair_setup(&pin).await?;
+            admin.pair_verify().await?;
+            assert_eq!(admin.get("/accessories").await?.status, 200);
+
+            let response = admin
+                .put(
+                    "/characteristics",
+                    &json!({ "characteristics": [{ "aid": 1, "iid": power_state_iid, "value": true }] }),
+                )
+                .await?;
+            assert_eq!(response.status, 204);
+
+            let mut user = TestController::connect(address).await?;
+            user.set_accessory_public_key(admin.accessory_public_key().unwrap())?;
+            assert!(user.pair_verify().await.is_err());
+
+            admin
+                .add_pairing(user.id(), user.public_key(), Permissions::USER)
+                .await?;
+            user.reconnect().await?;
+            user.pair_verify().await?;
+
+            let response = user.get(&format!("/characteristics?id=1.{}", power_state_iid)).await?;
+            assert_eq!(response.json()?["characteristics"][0]["value"], json!(true));
+
+            // only admins may manage pairings
+            assert!(user.remove_pairing(admin.id()).await.is_err());
+            admin.remove_pairing(user.id()).await?;
+
+            Ok::<(), Error>(())
+        };
+        run_with_server(&server, test).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transient_pair_setup() {
+        let config = test_config();
+        let pin = config.pin.clone();
+
+        let server = IpServer::new(config, MemoryStorage::new()).await.unwrap();
+        let address = server.local_addr();
+        server
+            .add_accessory(LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap())
+            .await
+            .unwrap();
+
+        let test = async {
+            let mut controller = TestController::connect(address).await?;
+            controller.pair_setup_transient(&pin).await?;
+            assert_eq!(controller.get("/accessories").await?.status, 200);
+
+            // no pairing is stored, so the session ends with its connection and the accessory stays unpaired
+            assert!(server.list_pairings().await?.is_empty());
+            controller.reconnect().await?;
+            assert_eq!(controller.get("/accessories").await?.status, 470);
+            controller.pair_setup(&pin).await?;
+            assert_eq!(server.list_pairings().await?.len(), 1);
+
+            Ok::<(), Error>(())
+        };
+        run_with_server(&server, test).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connection_events() {
+        let config = test_config();
+        let pin = config.pin.clone();
+
+        let server = IpServer::new(config, MemoryStorage::new()).await.unwrap();
+        let address = server.local_addr();
+        let events = Arc::new(Mutex::new(vec![]));
+        let events_ = events.clone();
+        server
+            .add_event_listener(Box::new(move |event| {
+                match *event {
+                    Event::ControllerConnected { controller_id } => events_.lock().unwrap().push((true, controller_id)),
+                    Event::ControllerDisconnected { controller_id } =>
+                        events_.lock().unwrap().push((false, controller_id)),
+                    _ => {},
+                }
+                async {}.boxed()
+            }))
+            .await;
+
+        let test = async {
+            let mut controller = TestController::connect(address).await?;
+            controller.pair_setup(&pin).await?;
+            assert!(events.lock().unwrap().is_empty());
+
+            controller.pair_verify().await?;
+            let id = controller.id();
+            wait_for(&events, 1).await;
+            assert_eq!(*events.lock().unwrap(), vec![(true, id)]);
+
+            controller.reconnect().await?;
+            wait_for(&events, 2).await;
+            assert_eq!(*events.lock().unwrap(), vec![(true, id), (false, id)]);
+
+            // a connection closed right after the pair verify is reported as connected before it's disconnected
+            controller.pair_verify().await?;
+            controller.reconnect().await?;
+            wait_for(&events, 4).await;
+            assert_eq!(*events.lock().unwrap(), vec![
+                (true, id),
+                (false, id),
+                (true, id),
+                (false, id)
+            ]);
+
+            Ok::<(), Error>(())
+        };
+        run_with_server(&server, test).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tlv_observer() {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let messages_ = messages.clone();
+        let config = Config {
+            tlv_observer: Some(tlv::Observer::new(move |message| {
+                messages_.lock().unwrap().push(message.clone());
+            })),
+            ..test_config()
+        };
+        let pin = config.pin.clone();
+
+        let server = IpServer::new(config, MemoryStorage::new()).await.unwrap();
+        let address = server.local_addr();
+
+        let test = async {
+            let mut controller = TestController::connect(address).await?;
+            controller.pair_setup(&pin).await?;
+
+            Ok::<(), Error>(())
+        };
+        run_with_server(&server, test).await.unwrap();
+
+        let messages = messages.lock().unwrap();
+        // M1 to M6, each answered by the accessory
+        assert_eq!(messages.len(), 6);
+        for (i, message) in messages.iter().enumerate() {
+            let direction = if i % 2 == 0 {
+                tlv::Direction::Request
+            } else {
+                tlv::Direction::Response
+            };
+            assert_eq!(message.direction, direction);
+            assert_eq!(message.path, "/pair-setup");
+            assert!(message.items.contains(&(Type::State as u8, vec![i as u8 + 1])));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timed_write() {
+        let config = test_config();
+        let pin = config.pin.clone();
+
+        let server = IpServer::new(config, MemoryStorage::new()).await.unwrap();
+        let address = server.local_addr();
+        let mut lightbulb = LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap();
+        lightbulb.lightbulb.power_state.set_perms(vec![
+            Perm::Events,
+            Perm::PairedRead,
+            Perm::PairedWrite,
+            Perm::TimedWrite,
+        ]);
+        let power_state_iid = lightbulb.lightbulb.power_state.get_id();
+        server.add_accessory(lightbulb).await.unwrap();
+
+        let test = async {
+            let mut controller = TestController::connect(address).await?;
+            controller.pair_setup(&pin).await?;
+            controller.pair_verify().await?;
+
+            let write = |pid: Option<u64>| json!({ "characteristics": [{ "aid": 1, "iid": power_state_iid, "value": true }], "pid": pid });
+            let rejected = json!({ "characteristics": [{
+                "aid": 1,
+                "iid": power_state_iid,
+                "status": Status::InvalidValueInRequest as i32,
+            }] });
+
+            // writes without a prepared write are rejected
+            let response = controller.put("/characteristics", &write(None)).await?;
+            assert_eq!(response.status, 207);
+            assert_eq!(response.json()?, rejected);
+
+            let response = controller.put("/prepare", &json!({ "ttl": 2500, "pid": 7 })).await?;
+            assert_eq!(response.status, 200);
+            assert_eq!(response.json()?, json!({ "status": 0 }));
+            let response = controller.put("/characteristics", &write(Some(7))).await?;
+            assert_eq!(response.status, 204);
+
+            // the prepared write was consumed by the write
+            let response = controller.put("/characteristics", &write(Some(7))).await?;
+            assert_eq!(response.status, 207);
+
+            controller.put("/prepare", &json!({ "ttl": 0, "pid": 8 })).await?;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let response = controller.put("/characteristics", &write(Some(8))).await?;
+            assert_eq!(response.status, 207);
+            assert_eq!(response.json()?, rejected);
+
+            Ok::<(), Error>(())
+        };
+        run_with_server(&server, test).await.unwrap();
+    }
+
+    /// Waits until `events` holds `count` events, giving up after a second.
+    async fn wait_for(events: &Arc<Mutex<Vec<(bool, Uuid)>>>, count: usize) {
+        wait_until(|| events.lock().unwrap().len() >= count).await;
+    }
+}
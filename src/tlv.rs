@@ -1,6 +1,19 @@
-use std::{cell, collections::HashMap, io, str};
+//! Encoding and decoding of the TLV8 format used by the pairing endpoints.
+//!
+//! # Examples
+//! ```
+//! use hap::tlv::{self, Encodable, Type, Value};
+//!
+//! let encoded = vec![Value::State(1), Value::PublicKey(vec![0; 384])].encode();
+//! let decoded = tlv::decode(&encoded);
+//!
+//! assert_eq!(decoded.get(&(Type::State as u8)), Some(&vec![1]));
+//! assert_eq!(decoded.get(&(Type::PublicKey as u8)), Some(&vec![0; 384]));
+//! ```
 
-use byteorder::{LittleEndian, WriteBytesExt};
+use std::{cell, collections::HashMap, fmt, io, str, sync::Arc};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 use log::error;
 use srp::types::SrpAuthError;
 use thiserror::Error;
@@ -43,33 +56,103 @@ pub fn encode(tlvs: Vec<(u8, Vec<u8>)>) -> Vec<u8> {
 }
 
 /// Decodes a slice of concatenated TLVs to a `HashMap<u8, Vec<u8>>` in the format `<Type, Value>`.
-pub fn decode(tlv: &[u8]) -> HashMap<u8, Vec<u8>> {
-    let mut hm = HashMap::new();
-    let mut buf: Vec<u8> = Vec::new();
+///
+/// Consecutive items of the same type are reassembled to a single value if all but the last one have the maximum length
+/// of 255 bytes, which is how values longer than 255 bytes are fragmented by [`encode`](encode). A truncated item at
+/// the end of the input is ignored. Of multiple items of the same type, the last one is kept; use
+/// [`decode_items`](decode_items) or [`decode_list`](decode_list) to decode all of them.
+pub fn decode(tlv: &[u8]) -> HashMap<u8, Vec<u8>> { decode_items(tlv).into_iter().collect() }
+
+/// Decodes a slice of concatenated TLVs to a `Vec<(u8, Vec<u8>)>` in the format `(<Type>, <Value>)`, keeping the
+/// order of the items and items of the same type. Fragmented values are reassembled like by [`decode`](decode).
+pub fn decode_items(tlv: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut items: Vec<(u8, Vec<u8>)> = Vec::new();
+    // whether the last item has the maximum length, so an item of the same type following it is a continuation
+    let mut fragmented = false;
     let mut p = 0;
-    let mut pt = 0;
-    while p < tlv.len() {
+    while p + 2 <= tlv.len() {
         let t = tlv[p];
-        let l = tlv[p + 1];
-        if l < 255 {
-            if t != pt && !buf.is_empty() {
-                hm.insert(t, buf.clone());
-                buf.clear();
-            }
-            buf.extend_from_slice(&tlv[p + 2..p + 2 + l as usize]);
-            hm.insert(t, buf.clone());
-            buf.clear();
-        } else {
-            buf.extend_from_slice(&tlv[p + 2..p + 2 + l as usize]);
+        let l = tlv[p + 1] as usize;
+        if p + 2 + l > tlv.len() {
+            break;
+        }
+        let value = &tlv[p + 2..p + 2 + l];
+        match items.last_mut() {
+            Some((last_t, last_value)) if fragmented && *last_t == t => last_value.extend_from_slice(value),
+            _ => items.push((t, value.to_vec())),
         }
-        pt = t;
-        p = p + 2 + l as usize;
+        fragmented = l == 255;
+        p = p + 2 + l;
+    }
+    items
+}
+
+/// Decodes a slice of concatenated TLVs holding a list of entries delimited by `Separator` items, e.g. a List
+/// Pairings response, to one `HashMap<u8, Vec<u8>>` per entry, as returned by [`decode`](decode).
+pub fn decode_list(tlv: &[u8]) -> Vec<HashMap<u8, Vec<u8>>> {
+    let mut list = vec![HashMap::new()];
+    for (t, value) in decode_items(tlv) {
+        if t == Type::Separator as u8 {
+            list.push(HashMap::new());
+        } else if let Some(entry) = list.last_mut() {
+            entry.insert(t, value);
+        }
+    }
+    if list.last().map_or(false, |entry| entry.is_empty()) {
+        list.pop();
+    }
+    list
+}
+
+/// Encodes an unsigned integer little-endian in the smallest of 1, 2, 4 or 8 bytes that holds it.
+pub fn encode_integer(value: u64) -> Vec<u8> {
+    let len = match value {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFFFF_FFFF => 4,
+        _ => 8,
+    };
+    value.to_le_bytes()[..len].to_vec()
+}
+
+/// Decodes an unsigned little-endian integer of 1 to 8 bytes, the counterpart of [`encode_integer`].
+pub fn decode_integer(value: &[u8]) -> Option<u64> {
+    if value.is_empty() || value.len() > 8 {
+        return None;
     }
-    if !buf.is_empty() {
-        hm.insert(pt, buf.clone());
-        buf.clear();
+    Some(value.iter().rev().fold(0, |n, b| (n << 8) | *b as u64))
+}
+
+/// Reads the value of type `t` of decoded TLVs as a `u8`.
+pub fn read_u8(decoded: &HashMap<u8, Vec<u8>>, t: u8) -> Option<u8> {
+    match decoded.get(&t).map(|v| v.as_slice()) {
+        Some([value]) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Reads the value of type `t` of decoded TLVs as a little-endian `u16`.
+pub fn read_u16(decoded: &HashMap<u8, Vec<u8>>, t: u8) -> Option<u16> {
+    match decoded.get(&t) {
+        Some(value) if value.len() == 2 => Some(LittleEndian::read_u16(value)),
+        _ => None,
+    }
+}
+
+/// Reads the value of type `t` of decoded TLVs as a little-endian `u32`.
+pub fn read_u32(decoded: &HashMap<u8, Vec<u8>>, t: u8) -> Option<u32> {
+    match decoded.get(&t) {
+        Some(value) if value.len() == 4 => Some(LittleEndian::read_u32(value)),
+        _ => None,
+    }
+}
+
+/// Reads the value of type `t` of decoded TLVs as a little-endian `u64`.
+pub fn read_u64(decoded: &HashMap<u8, Vec<u8>>, t: u8) -> Option<u64> {
+    match decoded.get(&t) {
+        Some(value) if value.len() == 8 => Some(LittleEndian::read_u64(value)),
+        _ => None,
     }
-    hm
 }
 
 /// `Encodable` is implemented by types that can be encoded to a to a `Vec<u8>` of concatenated
@@ -120,6 +203,10 @@ pub enum Value {
     FragmentLast(Vec<u8>),
     Flags(u32),
     Separator,
+    /// An unsigned integer under an arbitrary type, encoded by [`encode_integer`].
+    Integer(u8, u64),
+    /// Arbitrary bytes under an arbitrary type.
+    Bytes(u8, Vec<u8>),
 }
 
 impl Value {
@@ -133,7 +220,7 @@ impl Value {
             Value::Proof(proof) => (Type::Proof as u8, proof),
             Value::EncryptedData(data) => (Type::EncryptedData as u8, data),
             Value::State(state) => (Type::State as u8, vec![state]),
-            Value::Error(error) => (Type::Error as u8, vec![error as u8]),
+            Value::Error(error) => (Type::Error as u8, vec![error.code()]),
             Value::RetryDelay(delay) => {
                 let val = delay as u16;
                 let mut vec: Vec<u8> = Vec::new();
@@ -150,7 +237,10 @@ impl Value {
                 vec.write_u32::<LittleEndian>(flags).unwrap();
                 (Type::Flags as u8, vec)
             },
-            Value::Separator => (Type::Separator as u8, vec![0x00]),
+            // separators have no value
+            Value::Separator => (Type::Separator as u8, Vec::new()),
+            Value::Integer(t, value) => (t, encode_integer(value)),
+            Value::Bytes(t, bytes) => (t, bytes),
         }
     }
 }
@@ -165,23 +255,52 @@ pub enum Method {
     ListPairings = 5,
 }
 
+/// Errors of the pairing endpoints. Every variant maps to one of the error codes defined by the protocol, see
+/// [`Error::code`](Error::code), while the more specific variants keep the cause around for logging.
 #[allow(dead_code)]
-#[derive(Debug, Copy, Clone, Error)]
+#[derive(Debug, Error)]
 pub enum Error {
     #[error("Generic error to handle unexpected errors.")]
-    Unknown = 0x01,
+    Unknown,
     #[error("Setup code or signature verification failed.")]
-    Authentication = 0x02,
+    Authentication,
     #[error("Client must look at the retry delay TLV item and wait that many seconds before retrying.")]
-    Backoff = 0x03,
+    Backoff,
     #[error("Server cannot accept any more pairings.")]
-    MaxPeers = 0x04,
+    MaxPeers,
     #[error("Server reached its maximum number of authentication attempts.")]
-    MaxTries = 0x05,
+    MaxTries,
     #[error("Server pairing method is unavailable.")]
-    Unavailable = 0x06,
+    Unavailable,
     #[error("Server is busy and cannot accept a pairing request at this time.")]
-    Busy = 0x07,
+    Busy,
+    #[error("Invalid UTF-8: {0}")]
+    InvalidUtf8(#[from] str::Utf8Error),
+    #[error("Invalid UUID: {0}")]
+    InvalidUuid(#[from] uuid::Error),
+    #[error("Invalid Ed25519 public key.")]
+    InvalidPublicKey,
+    #[error("Missing TLV item of type {0:?}.")]
+    MissingTlvItem(Type),
+    #[error("The public key doesn't match the one of the existing pairing.")]
+    KeyMismatch,
+}
+
+impl Error {
+    /// Returns the error code sent to the controller.
+    pub fn code(&self) -> u8 {
+        match self {
+            Error::Unknown => 0x01,
+            Error::Authentication => 0x02,
+            Error::Backoff => 0x03,
+            Error::MaxPeers => 0x04,
+            Error::MaxTries => 0x05,
+            Error::Unavailable => 0x06,
+            Error::Busy => 0x07,
+            Error::InvalidUtf8(_) | Error::InvalidUuid(_) | Error::MissingTlvItem(_) | Error::KeyMismatch => 0x01,
+            Error::InvalidPublicKey => 0x02,
+        }
+    }
 }
 
 impl From<error::Error> for Error {
@@ -219,20 +338,6 @@ impl From<tokio::task::JoinError> for Error {
     }
 }
 
-impl From<str::Utf8Error> for Error {
-    fn from(err: str::Utf8Error) -> Self {
-        error!("{:?}", err);
-        Error::Unknown
-    }
-}
-
-impl From<uuid::Error> for Error {
-    fn from(err: uuid::Error) -> Self {
-        error!("{:?}", err);
-        Error::Unknown
-    }
-}
-
 impl From<SrpAuthError> for Error {
     fn from(err: SrpAuthError) -> Self {
         error!("{:?}", err);
@@ -250,7 +355,7 @@ impl From<aead::Error> for Error {
 impl From<ed25519_dalek::SignatureError> for Error {
     fn from(err: ed25519_dalek::SignatureError) -> Self {
         error!("{:?}", err);
-        Error::Authentication
+        Error::InvalidPublicKey
     }
 }
 
@@ -263,12 +368,213 @@ impl Encodable for Container {
 pub struct ErrorContainer {
     step: u8,
     error: Error,
+    retry_delay: Option<usize>,
 }
 
 impl ErrorContainer {
-    pub fn new(step: u8, error: Error) -> ErrorContainer { ErrorContainer { step, error } }
+    pub fn new(step: u8, error: Error) -> ErrorContainer {
+        ErrorContainer {
+            step,
+            error,
+            retry_delay: None,
+        }
+    }
+
+    /// Creates a `kTLVError_Backoff` error telling the controller to wait `retry_delay` seconds before retrying.
+    pub fn backoff(step: u8, retry_delay: usize) -> ErrorContainer {
+        ErrorContainer {
+            step,
+            error: Error::Backoff,
+            retry_delay: Some(retry_delay),
+        }
+    }
+
+    pub fn error(&self) -> &Error { &self.error }
+
+    pub fn retry_delay(&self) -> Option<usize> { self.retry_delay }
 }
 
 impl Encodable for ErrorContainer {
-    fn encode(self) -> Vec<u8> { vec![Value::State(self.step), Value::Error(self.error)].encode() }
+    fn encode(self) -> Vec<u8> {
+        let mut container = vec![Value::State(self.step), Value::Error(self.error)];
+        if let Some(retry_delay) = self.retry_delay {
+            container.push(Value::RetryDelay(retry_delay));
+        }
+        container.encode()
+    }
+}
+
+/// Direction of a [`Message`](Message) observed by an [`Observer`](Observer).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// A request of a controller.
+    Request,
+    /// A response of the accessory.
+    Response,
+}
+
+/// A TLV message exchanged with a pairing endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// Whether the message is a request or a response.
+    pub direction: Direction,
+    /// Path of the endpoint, e.g. `/pair-setup`.
+    pub path: String,
+    /// Decoded items of the message in their original order, as returned by [`decode_items`](decode_items).
+    /// Encrypted sub-TLVs are passed on as they were sent.
+    pub items: Vec<(u8, Vec<u8>)>,
+}
+
+/// Hook observing the TLV messages of the pairing endpoints, e.g. to capture them for a protocol analyzer.
+///
+/// The callback is called for every request to and every response of `/pair-setup`, `/pair-verify` and `/pairings`
+/// and must not block.
+///
+/// # Examples
+/// ```
+/// use hap::{
+///     tlv::{Direction, Observer, Type},
+///     Config,
+/// };
+///
+/// let config = Config {
+///     tlv_observer: Some(Observer::new(|message| {
+///         if message.direction == Direction::Request {
+///             let state = message.items.iter().find(|(t, _)| *t == Type::State as u8);
+///             println!("{} request in state {:?}", message.path, state);
+///         }
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct Observer(Arc<dyn Fn(&Message) + Send + Sync>);
+
+impl Observer {
+    /// Creates a new [`Observer`](Observer) from a callback.
+    pub fn new<F>(observe: F) -> Observer
+    where
+        F: Fn(&Message) + Send + Sync + 'static,
+    {
+        Observer(Arc::new(observe))
+    }
+
+    /// Calls the callback for an exchanged message.
+    pub(crate) fn observe(&self, message: &Message) { (self.0)(message) }
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("Observer") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_values() {
+        let encoded = vec![
+            Value::Integer(0x01, 7),
+            Value::Integer(0x02, 0x1234),
+            Value::Integer(0x03, 0x0001_0000),
+            Value::Integer(0x04, u64::MAX),
+            Value::Bytes(0x05, vec![1, 2, 3]),
+        ]
+        .encode();
+        let decoded = decode(&encoded);
+
+        assert_eq!(decoded.get(&0x01), Some(&vec![7]));
+        assert_eq!(decoded.get(&0x02), Some(&vec![0x34, 0x12]));
+        assert_eq!(decoded.get(&0x03), Some(&vec![0, 0, 1, 0]));
+        assert_eq!(decoded.get(&0x05), Some(&vec![1, 2, 3]));
+
+        assert_eq!(read_u8(&decoded, 0x01), Some(7));
+        assert_eq!(read_u16(&decoded, 0x02), Some(0x1234));
+        assert_eq!(read_u32(&decoded, 0x03), Some(0x0001_0000));
+        assert_eq!(read_u64(&decoded, 0x04), Some(u64::MAX));
+        assert_eq!(read_u16(&decoded, 0x01), None);
+        assert_eq!(read_u8(&decoded, 0x06), None);
+
+        for t in 0x01..=0x04 {
+            assert!(decode_integer(decoded.get(&t).unwrap()).is_some());
+        }
+        assert_eq!(decode_integer(&[0x34, 0x12]), Some(0x1234));
+        assert_eq!(decode_integer(&[]), None);
+        assert_eq!(decode_integer(&[0; 9]), None);
+    }
+
+    #[test]
+    fn test_encode_fragments_long_values() {
+        let value: Vec<u8> = (0..600).map(|i| i as u8).collect();
+        let encoded = encode(vec![(Type::PublicKey as u8, value.clone())]);
+
+        assert_eq!(encoded.len(), 600 + 3 * 2);
+        assert_eq!(&encoded[..2], &[Type::PublicKey as u8, 255]);
+        assert_eq!(&encoded[257..259], &[Type::PublicKey as u8, 255]);
+        assert_eq!(&encoded[514..516], &[Type::PublicKey as u8, 90]);
+    }
+
+    #[test]
+    fn test_round_trip_fragmented_values() {
+        let public_key: Vec<u8> = (0..384).map(|i| i as u8).collect();
+        let certificate: Vec<u8> = (0..510).map(|i| (i * 7) as u8).collect();
+        let encoded = vec![
+            Value::State(2),
+            Value::PublicKey(public_key.clone()),
+            Value::Certificate(certificate.clone()),
+            Value::Salt([1; 16]),
+        ]
+        .encode();
+
+        let decoded = decode(&encoded);
+        assert_eq!(decoded.get(&(Type::State as u8)), Some(&vec![2]));
+        assert_eq!(decoded.get(&(Type::PublicKey as u8)), Some(&public_key));
+        assert_eq!(decoded.get(&(Type::Certificate as u8)), Some(&certificate));
+        assert_eq!(decoded.get(&(Type::Salt as u8)), Some(&vec![1; 16]));
+    }
+
+    #[test]
+    fn test_decode_ignores_truncated_item() {
+        let decoded = decode(&[Type::State as u8, 1, 3, Type::PublicKey as u8, 32, 0, 0]);
+
+        assert_eq!(decoded.get(&(Type::State as u8)), Some(&vec![3]));
+        assert_eq!(decoded.get(&(Type::PublicKey as u8)), None);
+    }
+
+    #[test]
+    fn test_decode_items_keeps_duplicate_types() {
+        let encoded = vec![
+            Value::Identifier("a".into()),
+            Value::Identifier("b".into()),
+            Value::PublicKey(vec![7; 300]),
+        ]
+        .encode();
+
+        assert_eq!(decode_items(&encoded), vec![
+            (Type::Identifier as u8, b"a".to_vec()),
+            (Type::Identifier as u8, b"b".to_vec()),
+            (Type::PublicKey as u8, vec![7; 300]),
+        ]);
+        assert_eq!(decode(&encoded).get(&(Type::Identifier as u8)), Some(&b"b".to_vec()));
+    }
+
+    #[test]
+    fn test_decode_list() {
+        let encoded = vec![
+            Value::State(2),
+            Value::Identifier("a".into()),
+            Value::Separator,
+            Value::Identifier("b".into()),
+        ]
+        .encode();
+        assert_eq!(&encoded[6..8], &[Type::Separator as u8, 0]);
+
+        let list = decode_list(&encoded);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].get(&(Type::State as u8)), Some(&vec![2]));
+        assert_eq!(list[0].get(&(Type::Identifier as u8)), Some(&b"a".to_vec()));
+        assert_eq!(list[1].get(&(Type::Identifier as u8)), Some(&b"b".to_vec()));
+
+        assert!(decode_list(&[]).is_empty());
+    }
 }
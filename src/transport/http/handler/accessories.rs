@@ -1,10 +1,11 @@
 use futures::future::{BoxFuture, FutureExt};
 use hyper::{Body, Response, StatusCode, Uri};
-use log::info;
+use tracing::info;
 
 use crate::{
     pointer,
     transport::http::{handler::JsonHandlerExt, json_response},
+    ConnectionInfo,
     Result,
 };
 
@@ -20,6 +21,7 @@ impl JsonHandlerExt for Accessories {
         _: Uri,
         _: Body,
         _: pointer::ControllerId,
+        _: ConnectionInfo,
         _: pointer::EventSubscriptions,
         _: pointer::Config,
         _: pointer::Storage,
@@ -1,10 +1,13 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use futures::future::{BoxFuture, FutureExt};
 use hyper::{body::Buf, Body, Response, StatusCode, Uri};
-use log::error;
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Instant};
+use tracing::{debug, error};
 use url::form_urlencoded;
 
 use crate::{
+    characteristic::{AuthorizationRequest, AuthorizationValidator, Perm},
+    event::{self, Event},
     pointer,
     transport::http::{
         handler::JsonHandlerExt,
@@ -14,8 +17,10 @@ use crate::{
         ReadResponseObject,
         Status,
         WriteObject,
+        WriteRequestBody,
         WriteResponseObject,
     },
+    ConnectionInfo,
     Error,
     Result,
 };
@@ -32,7 +37,8 @@ impl JsonHandlerExt for GetCharacteristics {
         uri: Uri,
         _: Body,
         _: pointer::ControllerId,
-        _: pointer::EventSubscriptions,
+        _: ConnectionInfo,
+        event_subscriptions: pointer::EventSubscriptions,
         _: pointer::Config,
         _: pointer::Storage,
         accessory_database: pointer::AccessoryDatabase,
@@ -63,13 +69,16 @@ impl JsonHandlerExt for GetCharacteristics {
                     let res_object = match accessory_database
                         .lock()
                         .await
-                        .read_characteristic(aid, iid, f_meta, f_perms, f_type, f_ev)
+                        .read_characteristic(aid, iid, f_meta, f_perms, f_type)
                         .await
                     {
                         Ok(mut res_object) => {
                             if res_object.status != Some(0) {
                                 some_err = true;
                                 res_object.value = None;
+                            } else if f_ev {
+                                // event notifications are enabled per connection
+                                res_object.ev = Some(event_subscriptions.lock().await.contains(&(aid, iid)));
                             }
                             res_object
                         },
@@ -79,7 +88,7 @@ impl JsonHandlerExt for GetCharacteristics {
                             ReadResponseObject {
                                 iid,
                                 aid,
-                                status: Some(Status::ServiceCommunicationFailure as i32),
+                                status: Some(Status::from_error(&e) as i32),
                                 ..Default::default()
                             }
                         },
@@ -106,20 +115,18 @@ impl JsonHandlerExt for GetCharacteristics {
     }
 }
 
+/// Returns the `meta`, `perms`, `type` and `ev` flags of a read request. Flags are set with `1` or `true`.
 fn check_flags(flags: &HashMap<String, String>) -> (bool, bool, bool, bool) {
-    let true_val = "1".to_string();
-    (
-        flags.get("meta") == Some(&true_val),
-        flags.get("perms") == Some(&true_val),
-        flags.get("type") == Some(&true_val),
-        flags.get("ev") == Some(&true_val),
-    )
+    let is_set = |flag: &str| matches!(flags.get(flag).map(String::as_str), Some("1") | Some("true"));
+    (is_set("meta"), is_set("perms"), is_set("type"), is_set("ev"))
 }
 
-pub struct UpdateCharacteristics;
+pub struct UpdateCharacteristics {
+    prepared_write: pointer::PreparedWrite,
+}
 
 impl UpdateCharacteristics {
-    pub fn new() -> Self { UpdateCharacteristics {} }
+    pub fn new(prepared_write: pointer::PreparedWrite) -> Self { UpdateCharacteristics { prepared_write } }
 }
 
 impl JsonHandlerExt for UpdateCharacteristics {
@@ -128,57 +135,113 @@ impl JsonHandlerExt for UpdateCharacteristics {
         _: Uri,
         body: Body,
         _: pointer::ControllerId,
+        connection: ConnectionInfo,
         event_subscriptions: pointer::EventSubscriptions,
-        _: pointer::Config,
-        _: pointer::Storage,
+        config: pointer::Config,
+        storage: pointer::Storage,
         accessories: pointer::AccessoryDatabase,
-        _: pointer::EventEmitter,
+        event_emitter: pointer::EventEmitter,
     ) -> BoxFuture<Result<Response<Body>>> {
+        let prepared_write = self.prepared_write.clone();
+
         async move {
+            let authorization_validator = config.lock().await.authorization_validator.clone();
+
             let aggregated_body = hyper::body::aggregate(body).await?;
 
-            let write_body: CharacteristicResponseBody<WriteObject> = serde_json::from_slice(aggregated_body.chunk())?;
+            let write_body: WriteRequestBody = serde_json::from_slice(aggregated_body.chunk())?;
+
+            // a timed write consumes the prepared write of the connection, whether it's admitted or not
+            let timed_write = match write_body.pid {
+                Some(pid) => prepared_write
+                    .lock()
+                    .await
+                    .take()
+                    .map_or(false, |prepared_write| prepared_write.admits(pid, Instant::now())),
+                None => false,
+            };
+
             let mut resp_body = CharacteristicResponseBody::<WriteResponseObject> {
                 characteristics: Vec::new(),
             };
             let mut some_err = false;
-            let mut all_err = true;
+            let mut some_value = false;
 
             for c in write_body.characteristics {
                 let iid = c.iid;
                 let aid = c.aid;
-                let res_object = match accessories
-                    .lock()
-                    .await
-                    .write_characteristic(c, &event_subscriptions)
-                    .await
-                {
-                    Ok(res_object) => {
-                        if res_object.status != 0 {
+                let ev = c.ev;
+                let has_value = c.value.is_some();
+                let was_subscribed = event_subscriptions.lock().await.contains(&(aid, iid));
+                let rejection = match c.value {
+                    Some(_) => authorize_write(authorization_validator.as_ref(), &c, connection, &accessories).await,
+                    None => None,
+                };
+                let res_object = if let Some(status) = rejection {
+                    some_err = true;
+                    WriteResponseObject {
+                        iid,
+                        aid,
+                        status: status as i32,
+                        value: None,
+                    }
+                } else {
+                    // the value changes are emitted once the accessory database is unlocked again
+                    let written = event::with_deferred_events(&event_emitter, async {
+                        accessories
+                            .lock()
+                            .await
+                            .write_characteristic(c, &event_subscriptions, timed_write)
+                            .await
+                    })
+                    .await;
+                    match written {
+                        Ok(res_object) => {
+                            if res_object.status != 0 {
+                                some_err = true;
+                            } else if has_value {
+                                if let Err(e) = accessories
+                                    .lock()
+                                    .await
+                                    .persist_characteristic_value(aid, iid, &storage)
+                                    .await
+                                {
+                                    error!("error persisting characteristic value: {:?}", e);
+                                }
+                            }
+                            res_object
+                        },
+                        Err(e) => {
+                            error!("error updating characteristic: {:?}", e);
                             some_err = true;
-                        } else {
-                            all_err = false;
-                        }
-                        res_object
-                    },
-                    Err(e) => {
-                        error!("error updating characteristic: {:?}", e);
-                        some_err = true;
-                        WriteResponseObject {
-                            iid,
-                            aid,
-                            status: Status::ServiceCommunicationFailure as i32,
-                        }
-                    },
+                            WriteResponseObject {
+                                iid,
+                                aid,
+                                status: Status::from_error(&e) as i32,
+                                value: None,
+                            }
+                        },
+                    }
                 };
 
+                if ev.is_some() {
+                    let is_subscribed = event_subscriptions.lock().await.contains(&(aid, iid));
+                    if is_subscribed != was_subscribed {
+                        let event = if is_subscribed {
+                            Event::CharacteristicSubscribed { aid, iid }
+                        } else {
+                            Event::CharacteristicUnsubscribed { aid, iid }
+                        };
+                        event::emit(&event_emitter, &event).await;
+                    }
+                }
+
+                some_value |= res_object.value.is_some();
                 resp_body.characteristics.push(res_object);
             }
 
-            if all_err {
-                let res = serde_json::to_vec(&resp_body)?;
-                json_response(res, StatusCode::BAD_REQUEST)
-            } else if some_err {
+            // failed writes are reported per characteristic, so a batch isn't failed as a whole by one of them
+            if some_err || some_value {
                 let res = serde_json::to_vec(&resp_body)?;
                 json_response(res, StatusCode::MULTI_STATUS)
             } else {
@@ -188,3 +251,168 @@ impl JsonHandlerExt for UpdateCharacteristics {
         .boxed()
     }
 }
+
+/// Passes a write of a characteristic with the `AdditionalAuthorization` permission to the
+/// [`AuthorizationValidator`](AuthorizationValidator) and returns the status to reject it with, if it isn't authorized.
+/// Without a validator, such writes are never authorized.
+async fn authorize_write(
+    validator: Option<&AuthorizationValidator>,
+    write_object: &WriteObject,
+    connection: ConnectionInfo,
+    accessories: &pointer::AccessoryDatabase,
+) -> Option<Status> {
+    let perms = accessories
+        .lock()
+        .await
+        .get_characteristic_perms(write_object.aid, write_object.iid)
+        .await;
+    // writes of unknown characteristics are answered by the write itself
+    if !perms.map_or(false, |perms| perms.contains(&Perm::AdditionalAuthorization)) {
+        return None;
+    }
+    let validator = match validator {
+        Some(validator) => validator,
+        None => {
+            debug!(
+                "rejecting write of {}.{}, no authorization validator is configured",
+                write_object.aid, write_object.iid
+            );
+
+            return Some(Status::InsufficientAuthorization);
+        },
+    };
+
+    let auth_data = match write_object.auth_data {
+        Some(ref auth_data) => match BASE64.decode(auth_data) {
+            Ok(auth_data) => Some(auth_data),
+            Err(_) => return Some(Status::InvalidValueInRequest),
+        },
+        None => None,
+    };
+    let request = AuthorizationRequest {
+        aid: write_object.aid,
+        iid: write_object.iid,
+        value: write_object.value.clone().unwrap_or_default(),
+        auth_data,
+        connection,
+    };
+
+    if validator.validate(&request).await {
+        None
+    } else {
+        debug!("rejecting unauthorized write of {}.{}", request.aid, request.iid);
+
+        Some(Status::InsufficientAuthorization)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::lock::Mutex;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    use crate::{
+        accessory::{lightbulb::LightbulbAccessory, AccessoryInformation},
+        characteristic::HapCharacteristic,
+        storage::accessory_database::empty_database,
+    };
+
+    #[test]
+    fn test_check_flags() {
+        let queries: HashMap<String, String> = form_urlencoded::parse(b"id=1.9&meta=1&perms=true&type=0")
+            .map(|(key, val)| (key.into(), val.into()))
+            .collect();
+
+        assert_eq!(check_flags(&queries), (true, true, false, false));
+    }
+
+    #[tokio::test]
+    async fn test_authorize_write() {
+        let mut lightbulb = LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap();
+        lightbulb.lightbulb.power_state.set_perms(vec![
+            Perm::Events,
+            Perm::PairedRead,
+            Perm::PairedWrite,
+            Perm::AdditionalAuthorization,
+        ]);
+        let power_state_iid = lightbulb.lightbulb.power_state.get_id();
+        let brightness_iid = lightbulb.lightbulb.brightness.as_ref().unwrap().get_id();
+
+        let mut accessory_database = empty_database();
+        accessory_database.add_accessory(Box::new(lightbulb)).unwrap();
+        let accessories = Arc::new(Mutex::new(accessory_database));
+
+        let connection = ConnectionInfo {
+            peer_address: "192.168.0.2:52000".parse().unwrap(),
+            session_id: 1,
+        };
+        let validator = AuthorizationValidator::new(move |request| {
+            let authorized = request.auth_data.as_deref() == Some(&b"token"[..]) && request.connection == connection;
+            async move { authorized }.boxed()
+        });
+        let write = |iid: u64, auth_data: Option<&str>| -> WriteObject {
+            serde_json::from_value(json!({"aid": 1, "iid": iid, "value": true, "authData": auth_data})).unwrap()
+        };
+
+        let validator = Some(&validator);
+
+        // "token" and "wrong", base64 encoded
+        assert!(authorize_write(
+            validator,
+            &write(power_state_iid, Some("dG9rZW4=")),
+            connection,
+            &accessories
+        )
+        .await
+        .is_none());
+        assert!(matches!(
+            authorize_write(
+                validator,
+                &write(power_state_iid, Some("d3Jvbmc=")),
+                connection,
+                &accessories
+            )
+            .await,
+            Some(Status::InsufficientAuthorization)
+        ));
+        assert!(matches!(
+            authorize_write(validator, &write(power_state_iid, None), connection, &accessories).await,
+            Some(Status::InsufficientAuthorization)
+        ));
+        assert!(matches!(
+            authorize_write(
+                validator,
+                &write(power_state_iid, Some("not base64!")),
+                connection,
+                &accessories
+            )
+            .await,
+            Some(Status::InvalidValueInRequest)
+        ));
+        assert!(
+            authorize_write(validator, &write(brightness_iid, None), connection, &accessories)
+                .await
+                .is_none()
+        );
+
+        // without a validator, writes requiring additional authorization are rejected
+        assert!(matches!(
+            authorize_write(
+                None,
+                &write(power_state_iid, Some("dG9rZW4=")),
+                connection,
+                &accessories
+            )
+            .await,
+            Some(Status::InsufficientAuthorization)
+        ));
+        assert!(
+            authorize_write(None, &write(brightness_iid, None), connection, &accessories)
+                .await
+                .is_none()
+        );
+    }
+}
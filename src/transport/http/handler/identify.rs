@@ -3,8 +3,10 @@ use hyper::{Body, Response, StatusCode, Uri};
 use serde_json::json;
 
 use crate::{
+    event::{self, Event},
     pointer,
     transport::http::{handler::JsonHandlerExt, json_response, status_response, Status},
+    ConnectionInfo,
     HapType,
     Result,
 };
@@ -21,11 +23,12 @@ impl JsonHandlerExt for Identify {
         _: Uri,
         _: Body,
         _: pointer::ControllerId,
+        _: ConnectionInfo,
         _: pointer::EventSubscriptions,
         _: pointer::Config,
         storage: pointer::Storage,
         accessory_database: pointer::AccessoryDatabase,
-        _: pointer::EventEmitter,
+        event_emitter: pointer::EventEmitter,
     ) -> BoxFuture<Result<Response<Body>>> {
         let storage = storage.clone();
         let accessory_database = accessory_database.clone();
@@ -36,10 +39,11 @@ impl JsonHandlerExt for Identify {
                 return json_response(body, StatusCode::BAD_REQUEST);
             }
 
+            let mut aids = Vec::new();
             for accessory in accessory_database.lock().await.accessories.iter_mut() {
+                let mut accessory = accessory.lock().await;
+                aids.push(accessory.get_id());
                 accessory
-                    .lock()
-                    .await
                     .get_mut_service(HapType::AccessoryInformation)
                     .expect("missing Accessory Information Service") // every accessory needs to have it, so this should never panic
                     .get_mut_characteristic(HapType::Identify)
@@ -50,6 +54,10 @@ impl JsonHandlerExt for Identify {
 
             // TODO: defer setting them all back to false after a few secs
 
+            for aid in aids {
+                event::emit(&event_emitter, &Event::Identify { aid }).await;
+            }
+
             status_response(StatusCode::NO_CONTENT)
         }
         .boxed()
@@ -1,10 +1,19 @@
+use ed25519_dalek::{Signature, VerifyingKey};
 use futures::future::{BoxFuture, FutureExt};
-use hyper::{body::Body, Response, StatusCode, Uri};
+use hyper::{
+    body::{Body, HttpBody},
+    Response,
+    StatusCode,
+    Uri,
+};
+use serde_json::json;
+use tracing::{error, field, info_span, Instrument, Span};
 
 use crate::{
     pointer,
-    tlv::{self, Encodable},
-    transport::http::{status_response, tlv_response},
+    tlv::{self, Encodable, Type},
+    transport::http::{json_response, status_response, tlv_response, Status},
+    ConnectionInfo,
     Error,
     Result,
 };
@@ -15,6 +24,8 @@ pub mod identify;
 pub mod pair_setup;
 pub mod pair_verify;
 pub mod pairings;
+pub mod prepare;
+pub mod resource;
 
 pub trait HandlerExt {
     fn handle(
@@ -22,6 +33,7 @@ pub trait HandlerExt {
         uri: Uri,
         body: Body,
         controller_id: pointer::ControllerId,
+        connection: ConnectionInfo,
         event_subscriptions: pointer::EventSubscriptions,
         config: pointer::Config,
         storage: pointer::Storage,
@@ -34,11 +46,12 @@ pub trait TlvHandlerExt {
     type ParseResult: Send;
     type Result: Encodable;
 
-    fn parse(&self, body: Body) -> BoxFuture<std::result::Result<Self::ParseResult, tlv::ErrorContainer>>;
+    fn parse(&self, body: Vec<u8>) -> BoxFuture<std::result::Result<Self::ParseResult, tlv::ErrorContainer>>;
     fn handle(
         &mut self,
         step: Self::ParseResult,
         controller_id: pointer::ControllerId,
+        connection: ConnectionInfo,
         config: pointer::Config,
         storage: pointer::Storage,
         event_emitter: pointer::EventEmitter,
@@ -55,35 +68,128 @@ impl<T: TlvHandlerExt + Send + Sync> From<T> for TlvHandler<T> {
 impl<T: TlvHandlerExt + Send + Sync> HandlerExt for TlvHandler<T> {
     fn handle(
         &mut self,
-        _: Uri,
+        uri: Uri,
         body: Body,
         controller_id: pointer::ControllerId,
+        connection: ConnectionInfo,
         _: pointer::EventSubscriptions,
         config: pointer::Config,
         storage: pointer::Storage,
         _: pointer::AccessoryDatabase,
         event_emitter: pointer::EventEmitter,
     ) -> BoxFuture<Result<Response<Body>>> {
+        // the state and method are recorded once the body is read
+        let span = info_span!(
+            "tlv_request",
+            path = uri.path(),
+            peer_address = %connection.peer_address,
+            session_id = connection.session_id,
+            controller_id = ?*controller_id.read().unwrap(),
+            state = field::Empty,
+            method = field::Empty,
+        );
+
         async move {
+            let c = config.lock().await;
+            let max_tlv_body_size = c.max_tlv_body_size;
+            let tlv_observer = c.tlv_observer.clone();
+            drop(c);
+
+            let body = match read_body(body, max_tlv_body_size).await {
+                Ok(body) => body,
+                Err(e) => {
+                    error!("error reading TLV request body: {}", e);
+                    return tlv_response(
+                        tlv::ErrorContainer::new(0, tlv::Error::Unknown).encode(),
+                        StatusCode::OK,
+                    );
+                },
+            };
+
+            if let Some(tlv_observer) = &tlv_observer {
+                tlv_observer.observe(&tlv::Message {
+                    direction: tlv::Direction::Request,
+                    path: uri.path().into(),
+                    items: tlv::decode_items(&body),
+                });
+            }
+
+            let decoded = tlv::decode(&body);
+            let span = Span::current();
+            if let Some(state) = decoded.get(&(Type::State as u8)).and_then(|v| v.first()) {
+                span.record("state", state);
+            }
+            if let Some(method) = decoded.get(&(Type::Method as u8)).and_then(|v| v.first()) {
+                span.record("method", method);
+            }
+
             let response = match self.0.parse(body).await {
-                Err(e) => e.encode(),
-                Ok(step) => match self.0.handle(step, controller_id, config, storage, event_emitter).await {
-                    Err(e) => e.encode(),
+                Err(e) => {
+                    error!("error parsing TLV request: {}", e.error());
+                    e.encode()
+                },
+                Ok(step) => match self
+                    .0
+                    .handle(step, controller_id, connection, config, storage, event_emitter)
+                    .await
+                {
+                    Err(e) => {
+                        error!("error handling TLV request: {}", e.error());
+                        e.encode()
+                    },
                     Ok(res) => res.encode(),
                 },
             };
+
+            if let Some(tlv_observer) = &tlv_observer {
+                tlv_observer.observe(&tlv::Message {
+                    direction: tlv::Direction::Response,
+                    path: uri.path().into(),
+                    items: tlv::decode_items(&response),
+                });
+            }
+
             tlv_response(response, StatusCode::OK)
         }
+        .instrument(span)
         .boxed()
     }
 }
 
+/// Reads a request body to the end, failing as soon as it grows beyond `limit` bytes.
+async fn read_body(mut body: Body, limit: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if buf.len() + chunk.len() > limit {
+            return Err(Error::RequestBodyTooLarge(limit));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+
+    Ok(buf)
+}
+
+/// Verifies the Ed25519 signature of a controller strictly, rejecting malleable signatures with a non-canonical `S`
+/// and signatures made with weak, small-order keys, which plain verification accepts.
+fn verify_controller_signature(
+    public_key: &VerifyingKey,
+    message: &[u8],
+    signature: &[u8],
+) -> std::result::Result<(), tlv::Error> {
+    let signature = Signature::from_slice(signature).map_err(|_| tlv::Error::Authentication)?;
+    public_key
+        .verify_strict(message, &signature)
+        .map_err(|_| tlv::Error::Authentication)
+}
+
 pub trait JsonHandlerExt {
     fn handle(
         &mut self,
         uri: Uri,
         body: Body,
         controller_id: pointer::ControllerId,
+        connection: ConnectionInfo,
         event_subscriptions: pointer::EventSubscriptions,
         config: pointer::Config,
         storage: pointer::Storage,
@@ -105,12 +211,21 @@ impl<T: JsonHandlerExt + Send + Sync> HandlerExt for JsonHandler<T> {
         uri: Uri,
         body: Body,
         controller_id: pointer::ControllerId,
+        connection: ConnectionInfo,
         event_subscriptions: pointer::EventSubscriptions,
         config: pointer::Config,
         storage: pointer::Storage,
         accessory_database: pointer::AccessoryDatabase,
         event_emitter: pointer::EventEmitter,
     ) -> BoxFuture<Result<Response<Body>>> {
+        let span = info_span!(
+            "json_request",
+            path = uri.path(),
+            peer_address = %connection.peer_address,
+            session_id = connection.session_id,
+            controller_id = ?*controller_id.read().unwrap(),
+        );
+
         async move {
             match self
                 .0
@@ -118,6 +233,7 @@ impl<T: JsonHandlerExt + Send + Sync> HandlerExt for JsonHandler<T> {
                     uri,
                     body,
                     controller_id,
+                    connection,
                     event_subscriptions,
                     config,
                     storage,
@@ -129,10 +245,86 @@ impl<T: JsonHandlerExt + Send + Sync> HandlerExt for JsonHandler<T> {
                 Ok(res) => Ok(res),
                 Err(e) => match e {
                     Error::HttpStatus(status) => status_response(status),
+                    Error::Json(e) => {
+                        error!("error parsing JSON request: {}", e);
+                        let body = serde_json::to_vec(&json!({ "status": Status::InvalidValueInRequest as i32 }))?;
+                        json_response(body, StatusCode::BAD_REQUEST)
+                    },
                     _ => status_response(StatusCode::INTERNAL_SERVER_ERROR),
                 },
             }
         }
+        .instrument(span)
         .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_body() {
+        let body = read_body(Body::from(vec![1, 2, 3]), 3).await.unwrap();
+        assert_eq!(body, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_read_body_too_large() {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            sender.send_data(vec![0; 8].into()).await.ok();
+            sender.send_data(vec![0; 8].into()).await.ok();
+        });
+
+        assert!(matches!(read_body(body, 12).await, Err(Error::RequestBodyTooLarge(12))));
+    }
+
+    #[test]
+    fn test_verify_controller_signature() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let public_key = signing_key.verifying_key();
+        let message = b"controller info";
+        let signature = signing_key.sign(message).to_bytes();
+
+        assert!(verify_controller_signature(&public_key, message, &signature).is_ok());
+        assert!(matches!(
+            verify_controller_signature(&public_key, b"other info", &signature),
+            Err(tlv::Error::Authentication)
+        ));
+        assert!(matches!(
+            verify_controller_signature(&public_key, message, &signature[..63]),
+            Err(tlv::Error::Authentication)
+        ));
+
+        // adding the group order L to S yields a different signature that verifies against the same equation
+        const L: [u8; 32] = [
+            0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+        ];
+        let mut malleated = signature;
+        let mut carry = 0u16;
+        for i in 0..32 {
+            let sum = malleated[32 + i] as u16 + L[i] as u16 + carry;
+            malleated[32 + i] = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(matches!(
+            verify_controller_signature(&public_key, message, &malleated),
+            Err(tlv::Error::Authentication)
+        ));
+
+        // with the identity point as key and R, and S = 0, the signature equation holds for every message
+        let mut identity = [0; 32];
+        identity[0] = 1;
+        let weak_key = VerifyingKey::from_bytes(&identity).unwrap();
+        let mut forged = [0; 64];
+        forged[..32].copy_from_slice(&identity);
+        assert!(matches!(
+            verify_controller_signature(&weak_key, message, &forged),
+            Err(tlv::Error::Authentication)
+        ));
+    }
+}
@@ -1,9 +1,8 @@
 use aead::{generic_array::GenericArray, AeadInPlace, KeyInit};
+use byteorder::{ByteOrder, LittleEndian};
 use chacha20poly1305::ChaCha20Poly1305;
 use ed25519_dalek::ed25519::signature::SignerMut;
 use futures::future::{BoxFuture, FutureExt};
-use hyper::{body::Buf, Body};
-use log::{debug, info};
 use num::BigUint;
 use rand::{rngs::OsRng, RngCore};
 use sha2::{digest::Digest, Sha512};
@@ -13,40 +12,95 @@ use srp::{
     server::SrpServer,
     types::SrpGroup,
 };
-use std::{ops::BitXor, str};
+use std::{
+    ops::BitXor,
+    str,
+    sync::atomic::Ordering,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::{
-    event::Event,
+    event::{self, Event},
     pairing::{Pairing, Permissions},
     pointer,
     tlv::{self, Encodable, Type, Value},
-    transport::{hkdf_extract_and_expand, http::handler::TlvHandlerExt},
+    transport::{
+        hkdf_extract_and_expand,
+        http::handler::{
+            pairings::{approve_pairing, exceeds_max_peers},
+            verify_controller_signature,
+            TlvHandlerExt,
+        },
+        tcp,
+    },
+    ConnectionInfo,
 };
 
+/// Pairing type flag requesting a transient pair setup, which establishes a secure session without storing a pairing.
+pub(crate) const PAIRING_FLAG_TRANSIENT: u32 = 0x10;
+
 struct Session {
     salt: [u8; 16],
     verifier: Vec<u8>,
     b: [u8; 64],
     b_pub: Vec<u8>,
     shared_secret: Option<Vec<u8>>,
+    transient: bool,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.verifier.zeroize();
+        self.b.zeroize();
+        self.shared_secret.zeroize();
+    }
 }
 
 pub struct PairSetup {
     session: Option<Session>,
-    unsuccessful_tries: u8,
+    session_sender: pointer::SessionSender,
+    pair_setup_in_progress: pointer::PairSetupInProgress,
+    metrics: pointer::Metrics,
+    owns_pair_setup: bool,
+    /// The request step the session expects next.
+    next_step: StepNumber,
 }
 
 impl PairSetup {
-    pub fn new() -> PairSetup {
+    pub fn new(
+        session_sender: pointer::SessionSender,
+        pair_setup_in_progress: pointer::PairSetupInProgress,
+        metrics: pointer::Metrics,
+    ) -> PairSetup {
         PairSetup {
             session: None,
-            unsuccessful_tries: 0,
+            session_sender,
+            pair_setup_in_progress,
+            metrics,
+            owns_pair_setup: false,
+            next_step: StepNumber::SrpStartRequest,
+        }
+    }
+
+    /// Ends the current pair setup session, allowing other controllers to start one.
+    fn end_session(&mut self) {
+        self.session = None;
+        self.next_step = StepNumber::SrpStartRequest;
+        if self.owns_pair_setup {
+            self.pair_setup_in_progress.store(false, Ordering::SeqCst);
+            self.owns_pair_setup = false;
         }
     }
 }
 
-#[derive(Debug, Clone)]
+impl Drop for PairSetup {
+    fn drop(&mut self) { self.end_session(); }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum StepNumber {
     Unknown = 0,
     SrpStartRequest = 1,
@@ -59,27 +113,45 @@ enum StepNumber {
 
 #[derive(Debug, Clone)]
 pub enum Step {
-    Start,
+    Start { flags: u32 },
     Verify { a_pub: Vec<u8>, a_proof: Vec<u8> },
     Exchange { data: Vec<u8> },
 }
 
+impl Step {
+    /// Returns the number of the request step.
+    fn number(&self) -> StepNumber {
+        match self {
+            Step::Start { .. } => StepNumber::SrpStartRequest,
+            Step::Verify { .. } => StepNumber::SrpVerifyRequest,
+            Step::Exchange { .. } => StepNumber::ExchangeRequest,
+        }
+    }
+}
+
 impl TlvHandlerExt for PairSetup {
     type ParseResult = Step;
     type Result = tlv::Container;
 
-    fn parse(&self, body: Body) -> BoxFuture<Result<Step, tlv::ErrorContainer>> {
-        async {
-            let aggregated_body = hyper::body::aggregate(body)
-                .await
-                .map_err(|_| tlv::ErrorContainer::new(StepNumber::Unknown as u8, tlv::Error::Unknown))?;
-
-            debug!("received body: {:?}", aggregated_body.chunk());
-
-            let mut decoded = tlv::decode(aggregated_body.chunk());
-            match decoded.get(&(Type::State as u8)) {
-                Some(method) => match method[0] {
-                    x if x == StepNumber::SrpStartRequest as u8 => Ok(Step::Start),
+    fn parse(&self, body: Vec<u8>) -> BoxFuture<Result<Step, tlv::ErrorContainer>> {
+        async move {
+            trace!("received body: {:?}", &body);
+
+            let mut decoded = tlv::decode(&body);
+            match decoded.get(&(Type::State as u8)).and_then(|state| state.first()) {
+                Some(&state) => match state {
+                    x if x == StepNumber::SrpStartRequest as u8 => {
+                        let flags = match decoded.get(&(Type::Flags as u8)) {
+                            Some(flags) => tlv::decode_integer(flags)
+                                .and_then(|flags| u32::try_from(flags).ok())
+                                .ok_or(tlv::ErrorContainer::new(
+                                    StepNumber::SrpStartResponse as u8,
+                                    tlv::Error::Unknown,
+                                ))?,
+                            None => 0,
+                        };
+                        Ok(Step::Start { flags })
+                    },
                     x if x == StepNumber::SrpVerifyRequest as u8 => {
                         let a_pub = decoded
                             .remove(&(Type::PublicKey as u8))
@@ -114,39 +186,90 @@ impl TlvHandlerExt for PairSetup {
         &mut self,
         step: Step,
         _: pointer::ControllerId,
+        _: ConnectionInfo,
         config: pointer::Config,
         storage: pointer::Storage,
         event_emitter: pointer::EventEmitter,
     ) -> BoxFuture<Result<tlv::Container, tlv::ErrorContainer>> {
         async move {
+            // steps arriving out of order, e.g. a replayed M1 or an M3 before M1, end the session
+            let step_number = step.number();
+            if step_number != self.next_step {
+                info!(
+                    "pair setup: received step M{} while expecting M{}",
+                    step_number as u8, self.next_step as u8
+                );
+                self.end_session();
+                self.metrics.record_pair_setup_failure();
+                return Err(tlv::ErrorContainer::new(step_number as u8 + 1, tlv::Error::Unknown));
+            }
+
             match step {
-                Step::Start => match handle_start(self, config).await {
-                    Ok(res) => {
-                        self.unsuccessful_tries = 0;
-                        Ok(res)
-                    },
-                    Err(err) => {
-                        self.unsuccessful_tries += 1;
-                        Err(tlv::ErrorContainer::new(StepNumber::SrpStartResponse as u8, err))
-                    },
+                Step::Start { flags } => {
+                    self.metrics.record_pair_setup_attempt();
+
+                    let failed_attempts = FailedAttempts::load(&storage).await;
+                    if failed_attempts.count > MAX_FAILED_ATTEMPTS {
+                        info!(
+                            "pair setup M1: refusing pair setup after {} failed attempts",
+                            failed_attempts.count
+                        );
+                        self.metrics.record_pair_setup_failure();
+                        return Err(tlv::ErrorContainer::new(
+                            StepNumber::SrpStartResponse as u8,
+                            tlv::Error::MaxTries,
+                        ));
+                    }
+                    if let Some(retry_delay) = failed_attempts.retry_delay(unix_time()) {
+                        info!("pair setup M1: backing off for {} seconds", retry_delay);
+                        self.metrics.record_pair_setup_failure();
+                        return Err(tlv::ErrorContainer::backoff(
+                            StepNumber::SrpStartResponse as u8,
+                            retry_delay as usize,
+                        ));
+                    }
+
+                    match handle_start(self, config, storage, flags).await {
+                        Ok(res) => {
+                            self.next_step = StepNumber::SrpVerifyRequest;
+                            Ok(res)
+                        },
+                        Err(err) => {
+                            self.metrics.record_pair_setup_failure();
+                            Err(tlv::ErrorContainer::new(StepNumber::SrpStartResponse as u8, err))
+                        },
+                    }
                 },
                 Step::Verify { a_pub, a_proof } => match handle_verify(self, &a_pub, &a_proof).await {
                     Ok(res) => {
-                        self.unsuccessful_tries = 0;
+                        FailedAttempts::reset(&storage).await;
+                        // a transient pair setup is complete after M4
+                        if self.session.as_ref().map_or(false, |session| session.transient) {
+                            self.end_session();
+                        } else {
+                            self.next_step = StepNumber::ExchangeRequest;
+                        }
                         Ok(res)
                     },
                     Err(err) => {
-                        self.unsuccessful_tries += 1;
+                        // only a wrong setup code counts as a failed attempt
+                        if let tlv::Error::Authentication = err {
+                            FailedAttempts::record(&storage, unix_time()).await;
+                        }
+                        self.end_session();
+                        self.metrics.record_pair_setup_failure();
                         Err(tlv::ErrorContainer::new(StepNumber::SrpVerifyResponse as u8, err))
                     },
                 },
                 Step::Exchange { data } => match handle_exchange(self, config, storage, event_emitter, &data).await {
                     Ok(res) => {
-                        self.unsuccessful_tries = 0;
+                        self.end_session();
+                        self.metrics.record_pairing_added();
                         Ok(res)
                     },
                     Err(err) => {
-                        self.unsuccessful_tries += 1;
+                        self.end_session();
+                        self.metrics.record_pair_setup_failure();
                         Err(tlv::ErrorContainer::new(StepNumber::ExchangeResponse as u8, err))
                     },
                 },
@@ -156,23 +279,42 @@ impl TlvHandlerExt for PairSetup {
     }
 }
 
-async fn handle_start(handler: &mut PairSetup, config: pointer::Config) -> Result<tlv::Container, tlv::Error> {
+async fn handle_start(
+    handler: &mut PairSetup,
+    config: pointer::Config,
+    storage: pointer::Storage,
+    flags: u32,
+) -> Result<tlv::Container, tlv::Error> {
     info!("pair setup M1: received SRP start request");
 
-    // TODO
+    let transient = flags & PAIRING_FLAG_TRANSIENT != 0;
+    if transient {
+        info!("pair setup M1: transient pair setup requested");
+    }
+
     // If the accessory is already paired, it must respond with the following TLV items:
     // kTLVType_State <M2>
     // kTLVType_Error <kTLVError_Unavailable>
-
-    if handler.unsuccessful_tries > 100 {
-        return Err(tlv::Error::MaxTries);
+    // further controllers are added by an admin controller through the pairings endpoint instead
+    if storage.lock().await.count_pairings().await? > 0 {
+        info!("pair setup M1: refusing pair setup, the accessory is already paired");
+        return Err(tlv::Error::Unavailable);
     }
 
-    // TODO
     // If the accessory is currently performing a PairSetup procedure with a different controller, it must respond with
     // the following TLV items:
     // kTLVType_State <M2>
     // kTLVType_Error <kTLVError_Busy>
+    if !handler.owns_pair_setup {
+        if handler
+            .pair_setup_in_progress
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(tlv::Error::Busy);
+        }
+        handler.owns_pair_setup = true;
+    }
 
     let mut csprng = OsRng {};
     let mut salt = [0; 16]; // s
@@ -184,12 +326,10 @@ async fn handle_start(handler: &mut PairSetup, config: pointer::Config) -> Resul
     let srp_client = SrpClient::<Sha512>::new(&G_3072);
     let verifier = srp_client.compute_verifier(b"Pair-Setup", &config.lock().await.pin.to_string().as_bytes(), &salt);
 
-    info!("pair setup M2: verifier: {:?}", verifier);
-
     let srp_server = SrpServer::<Sha512>::new(&G_3072);
     let b_pub = srp_server.compute_public_ephemeral(&b, verifier.as_slice());
 
-    info!("pair setup M2: b_pub: {:?}", b_pub);
+    trace!("pair setup M2: b_pub: {:?}", b_pub);
 
     handler.session = Some(Session {
         salt,
@@ -197,6 +337,7 @@ async fn handle_start(handler: &mut PairSetup, config: pointer::Config) -> Resul
         b,
         b_pub: b_pub.clone(),
         shared_secret: None,
+        transient,
     });
 
     info!("pair setup M2: sending SRP start response");
@@ -218,16 +359,31 @@ async fn handle_verify(handler: &mut PairSetup, a_pub: &[u8], a_proof: &[u8]) ->
             let verifier = srp_server.process_reply(&session.b, &session.verifier, a_pub)?;
 
             let shared_secret = verifier.key();
-            info!("pair setup M3: shared_secret: {:?}", shared_secret);
 
             session.shared_secret = Some(shared_secret.to_vec());
 
             let b_proof =
                 verify_client_proof::<Sha512>(&session.b_pub, a_pub, a_proof, &session.salt, &shared_secret, &G_3072)?;
 
+            // a transient pair setup ends after M4; the SRP shared secret is used to derive the session keys directly
+            // and no pairing is stored
+            if session.transient {
+                info!("pair setup M4: establishing transient session");
+
+                if let Some(sender) = handler.session_sender.lock().await.take() {
+                    let encrypted_session = tcp::Session {
+                        controller_id: None,
+                        shared_secret: Zeroizing::new(shared_secret.to_vec()),
+                    };
+                    let _session = sender.send(encrypted_session);
+                } else {
+                    return Err(tlv::Error::Unknown);
+                }
+            }
+
             info!("pair setup M4: sending SRP verify response");
 
-            info!("pair setup M4: b_proof: {:?}", b_proof);
+            trace!("pair setup M4: b_proof: {:?}", b_proof);
 
             Ok(vec![
                 Value::State(StepNumber::SrpVerifyResponse as u8),
@@ -272,15 +428,20 @@ async fn handle_exchange(
                 )?;
 
                 let sub_tlv = tlv::decode(&decrypted_data);
-                let device_pairing_id = sub_tlv.get(&(Type::Identifier as u8)).ok_or(tlv::Error::Unknown)?;
-                let device_ltpk_bytes = sub_tlv.get(&(Type::PublicKey as u8)).ok_or(tlv::Error::Unknown)?;
-                let mut device_ltpk_bytes_array = [0u8; 32];
-                device_ltpk_bytes_array.copy_from_slice(device_ltpk_bytes.as_slice());
+                let device_pairing_id = sub_tlv
+                    .get(&(Type::Identifier as u8))
+                    .ok_or(tlv::Error::MissingTlvItem(Type::Identifier))?;
+                let device_ltpk_bytes = sub_tlv
+                    .get(&(Type::PublicKey as u8))
+                    .ok_or(tlv::Error::MissingTlvItem(Type::PublicKey))?;
+                let device_ltpk_bytes_array: [u8; 32] = device_ltpk_bytes
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| tlv::Error::InvalidPublicKey)?;
                 let device_ltpk = ed25519_dalek::VerifyingKey::from_bytes(&device_ltpk_bytes_array)?;
-                let device_signature_bytes = sub_tlv.get(&(Type::Signature as u8)).ok_or(tlv::Error::Unknown)?;
-                let mut device_signature_bytes_array = [0u8; 64];
-                device_signature_bytes_array.copy_from_slice(device_signature_bytes.as_slice());
-                let device_signature = ed25519_dalek::Signature::from_bytes(&device_signature_bytes_array);
+                let device_signature = sub_tlv
+                    .get(&(Type::Signature as u8))
+                    .ok_or(tlv::Error::MissingTlvItem(Type::Signature))?;
 
                 let device_x = hkdf_extract_and_expand(
                     b"Pair-Setup-Controller-Sign-Salt",
@@ -293,22 +454,23 @@ async fn handle_exchange(
                 device_info.extend(device_pairing_id);
                 device_info.extend(device_ltpk.as_bytes());
 
-                if device_ltpk.verify_strict(&device_info, &device_signature).is_err() {
-                    return Err(tlv::Error::Authentication);
-                }
+                verify_controller_signature(&device_ltpk, &device_info, device_signature)?;
 
                 let uuid_str = str::from_utf8(device_pairing_id)?;
                 let pairing_uuid = Uuid::parse_str(uuid_str)?;
                 let mut pairing_ltpk = [0; 32];
                 pairing_ltpk[..32].copy_from_slice(&device_ltpk.as_bytes()[..32]);
 
-                if let Some(max_peers) = config.lock().await.max_peers {
-                    if storage.lock().await.count_pairings().await? + 1 > max_peers {
-                        return Err(tlv::Error::MaxPeers);
-                    }
+                let pairing_count = storage.lock().await.count_pairings().await?;
+                let c = config.lock().await;
+                if exceeds_max_peers(pairing_count, c.max_peers) {
+                    return Err(tlv::Error::MaxPeers);
                 }
+                let pairing_approver = c.pairing_approver.clone();
+                drop(c);
 
-                let pairing = Pairing::new(pairing_uuid, Permissions::Admin, device_ltpk.to_bytes());
+                let pairing = Pairing::new(pairing_uuid, Permissions::ADMIN, device_ltpk.to_bytes());
+                approve_pairing(pairing_approver, &pairing).await?;
                 storage.lock().await.save_pairing(&pairing).await?;
 
                 debug!("pairing: {:?}", &pairing);
@@ -349,11 +511,10 @@ async fn handle_exchange(
                     aead.encrypt_in_place_detached(GenericArray::from_slice(&nonce), &[], &mut encrypted_data)?;
                 encrypted_data.extend(&auth_tag);
 
-                event_emitter
-                    .lock()
-                    .await
-                    .emit(&Event::ControllerPaired { id: pairing.id })
-                    .await;
+                event::emit(&event_emitter, &Event::ControllerPaired { id: pairing.id }).await;
+                if pairing_count == 0 {
+                    event::emit(&event_emitter, &Event::PairedStateChanged { paired: true }).await;
+                }
 
                 info!("pair setup M6: sending exchange response");
 
@@ -366,6 +527,87 @@ async fn handle_exchange(
     }
 }
 
+/// Clears the record of failed pair setup attempts, which otherwise refuses every pair setup once it exceeds the
+/// maximum number of attempts.
+pub(crate) async fn reset_failed_attempts(storage: &pointer::Storage) { FailedAttempts::reset(storage).await }
+
+/// Storage key of the record of failed pair setup attempts.
+const FAILED_ATTEMPTS_KEY: &str = "pair_setup_failed_attempts";
+/// Number of failed attempts exceeding which every further pair setup is refused with `kTLVError_MaxTries`.
+const MAX_FAILED_ATTEMPTS: u32 = 100;
+/// Number of failed attempts that may be retried immediately. Later attempts have to wait an escalating retry delay.
+const FREE_ATTEMPTS: u32 = 3;
+/// Maximum retry delay in seconds.
+const MAX_RETRY_DELAY: u64 = 3600;
+
+/// Consecutive failed pair setup attempts, i.e. SRP verifications with a wrong setup code. The record is persisted, so
+/// neither reconnecting nor restarting the accessory resets it. Only a successful SRP verification and
+/// [`IpServer::unpair_all`](crate::server::IpServer::unpair_all) do.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct FailedAttempts {
+    count: u32,
+    /// Unix time in seconds of the last failed attempt.
+    last: u64,
+}
+
+impl FailedAttempts {
+    async fn load(storage: &pointer::Storage) -> FailedAttempts {
+        match storage.lock().await.load_bytes(FAILED_ATTEMPTS_KEY).await {
+            Ok(bytes) if bytes.len() == 12 => FailedAttempts {
+                count: LittleEndian::read_u32(&bytes[..4]),
+                last: LittleEndian::read_u64(&bytes[4..]),
+            },
+            _ => FailedAttempts::default(),
+        }
+    }
+
+    /// Records a failed attempt at unix time `now`.
+    async fn record(storage: &pointer::Storage, now: u64) {
+        let mut failed_attempts = FailedAttempts::load(storage).await;
+        failed_attempts.count = failed_attempts.count.saturating_add(1);
+        failed_attempts.last = now;
+
+        warn!("pair setup: failed attempt number {}", failed_attempts.count);
+
+        let mut bytes = [0; 12];
+        LittleEndian::write_u32(&mut bytes[..4], failed_attempts.count);
+        LittleEndian::write_u64(&mut bytes[4..], failed_attempts.last);
+        if let Err(e) = storage.lock().await.save_bytes(FAILED_ATTEMPTS_KEY, &bytes).await {
+            error!("error saving failed pair setup attempts: {:?}", e);
+        }
+    }
+
+    async fn reset(storage: &pointer::Storage) {
+        if FailedAttempts::load(storage).await != FailedAttempts::default() {
+            let _ = storage.lock().await.delete_bytes(FAILED_ATTEMPTS_KEY).await;
+        }
+    }
+
+    /// Returns the seconds left until the next attempt is allowed at unix time `now`, if any. The delay doubles with
+    /// every failed attempt exceeding the free ones, starting at 1 second.
+    fn retry_delay(&self, now: u64) -> Option<u64> {
+        if self.count < FREE_ATTEMPTS {
+            return None;
+        }
+
+        let delay = 2u64.saturating_pow(self.count - FREE_ATTEMPTS).min(MAX_RETRY_DELAY);
+        let remaining = (self.last + delay).saturating_sub(now);
+
+        if remaining > 0 {
+            Some(remaining)
+        } else {
+            None
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 fn verify_client_proof<D: Digest>(
     b_pub: &[u8],
     a_pub: &[u8],
@@ -374,6 +616,21 @@ fn verify_client_proof<D: Digest>(
     key: &[u8],
     group: &SrpGroup,
 ) -> Result<Vec<u8>, tlv::Error> {
+    if a_proof == client_proof::<D>(b_pub, a_pub, salt, key, group).as_slice() {
+        Ok(accessory_proof::<D>(a_pub, a_proof, key))
+    } else {
+        Err(tlv::Error::Authentication)
+    }
+}
+
+/// Computes the proof of the controller, `M = H(H(N) xor H(g), H(I), s, A, B, K)`.
+pub(crate) fn client_proof<D: Digest>(
+    b_pub: &[u8],
+    a_pub: &[u8],
+    salt: &[u8],
+    key: &[u8],
+    group: &SrpGroup,
+) -> Vec<u8> {
     let mut dhn = D::new();
     dhn.update(&group.n.to_bytes_be());
     let hn = BigUint::from_bytes_be(&dhn.finalize());
@@ -389,30 +646,60 @@ fn verify_client_proof<D: Digest>(
     let hi = dhi.finalize();
 
     let mut d = D::new();
-    // M = H(H(N) xor H(g), H(I), s, A, B, K)
     d.update(&hng.to_bytes_be());
     d.update(&hi);
     d.update(salt);
     d.update(a_pub);
     d.update(b_pub);
     d.update(key);
+    d.finalize().as_slice().to_vec()
+}
 
-    if a_proof == d.finalize().as_slice() {
-        // H(A, M, K)
-        let mut d = D::new();
-        d.update(a_pub);
-        d.update(a_proof);
-        d.update(key);
-        Ok(d.finalize().as_slice().to_vec())
-    } else {
-        Err(tlv::Error::Authentication)
-    }
+/// Computes the proof of the accessory, `H(A, M, K)`.
+pub(crate) fn accessory_proof<D: Digest>(a_pub: &[u8], a_proof: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut d = D::new();
+    d.update(a_pub);
+    d.update(a_proof);
+    d.update(key);
+    d.finalize().as_slice().to_vec()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn connection() -> ConnectionInfo {
+        ConnectionInfo {
+            peer_address: "192.168.0.2:52000".parse().unwrap(),
+            session_id: 1,
+        }
+    }
+
+    fn pair_setup() -> (PairSetup, pointer::PairSetupInProgress, pointer::Metrics) {
+        let pair_setup_in_progress = pointer::PairSetupInProgress::default();
+        let metrics = pointer::Metrics::default();
+        let pair_setup = PairSetup::new(
+            std::sync::Arc::new(futures::lock::Mutex::new(None)),
+            pair_setup_in_progress.clone(),
+            metrics.clone(),
+        );
+
+        (pair_setup, pair_setup_in_progress, metrics)
+    }
+
+    fn storage() -> pointer::Storage {
+        std::sync::Arc::new(futures::lock::Mutex::new(
+            Box::new(crate::storage::MemoryStorage::new()),
+        ))
+    }
+
+    fn state() -> (pointer::Config, pointer::Storage, pointer::EventEmitter) {
+        let config = std::sync::Arc::new(futures::lock::Mutex::new(crate::Config::default()));
+        let event_emitter = std::sync::Arc::new(futures::lock::Mutex::new(crate::event::EventEmitter::new()));
+
+        (config, storage(), event_emitter)
+    }
+
     #[test]
     fn test_verify_client_proof() {
         let b_pub = [
@@ -473,4 +760,141 @@ mod tests {
             26, 121, 180, 13, 192, 173, 246, 172, 223, 161, 192, 52, 251, 187, 66, 52, 170, 18, 85
         ]);
     }
+
+    #[tokio::test]
+    async fn test_flags_are_parsed() {
+        let (pair_setup, ..) = pair_setup();
+        let start = |flags: Vec<u8>| vec![Value::State(1), Value::Bytes(Type::Flags as u8, flags)].encode();
+
+        assert!(matches!(
+            pair_setup.parse(start(vec![0x10])).await,
+            Ok(Step::Start { flags: 0x10 })
+        ));
+        assert!(matches!(
+            pair_setup.parse(start(vec![0x10, 0, 0, 0])).await,
+            Ok(Step::Start { flags: 0x10 })
+        ));
+        assert!(pair_setup.parse(start(vec![0, 0, 0, 0, 1])).await.is_err());
+        assert!(pair_setup.parse(start(vec![])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order_step_is_rejected() {
+        let (mut pair_setup, pair_setup_in_progress, _) = pair_setup();
+        let controller_id = pointer::ControllerId::default();
+        let (config, storage, event_emitter) = state();
+
+        let err = pair_setup
+            .handle(
+                Step::Verify {
+                    a_pub: vec![0; 384],
+                    a_proof: vec![0; 64],
+                },
+                controller_id,
+                connection(),
+                config,
+                storage,
+                event_emitter,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err.error(), tlv::Error::Unknown));
+        assert_eq!(pair_setup.next_step, StepNumber::SrpStartRequest);
+        assert!(!pair_setup_in_progress.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_pair_setup_is_refused_when_paired() {
+        let (mut pair_setup, pair_setup_in_progress, metrics) = pair_setup();
+        let controller_id = pointer::ControllerId::default();
+        let (config, storage, event_emitter) = state();
+
+        let pairing = Pairing::new(Uuid::new_v4(), Permissions::ADMIN, [0; 32]);
+        storage.lock().await.save_pairing(&pairing).await.unwrap();
+
+        let err = pair_setup
+            .handle(
+                Step::Start { flags: 0 },
+                controller_id,
+                connection(),
+                config,
+                storage,
+                event_emitter,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err.error(), tlv::Error::Unavailable));
+        assert!(pair_setup.session.is_none());
+        assert!(!pair_setup_in_progress.load(Ordering::SeqCst));
+        assert_eq!(metrics.snapshot().pair_setup_attempts, 1);
+        assert_eq!(metrics.snapshot().pair_setup_failures, 1);
+    }
+
+    #[test]
+    fn test_retry_delay_escalates() {
+        let failed_attempts = |count| FailedAttempts { count, last: 1000 };
+
+        assert_eq!(failed_attempts(2).retry_delay(1000), None);
+        assert_eq!(failed_attempts(3).retry_delay(1000), Some(1));
+        assert_eq!(failed_attempts(5).retry_delay(1000), Some(4));
+        assert_eq!(failed_attempts(5).retry_delay(1003), Some(1));
+        assert_eq!(failed_attempts(5).retry_delay(1004), None);
+        assert_eq!(failed_attempts(99).retry_delay(1000), Some(MAX_RETRY_DELAY));
+    }
+
+    #[tokio::test]
+    async fn test_failed_attempts_are_persisted() {
+        let storage = storage();
+
+        FailedAttempts::record(&storage, 1000).await;
+        FailedAttempts::record(&storage, 1010).await;
+        assert_eq!(FailedAttempts::load(&storage).await, FailedAttempts {
+            count: 2,
+            last: 1010
+        });
+
+        FailedAttempts::reset(&storage).await;
+        assert_eq!(FailedAttempts::load(&storage).await, FailedAttempts::default());
+    }
+
+    #[tokio::test]
+    async fn test_pair_setup_is_refused_after_failed_attempts() {
+        let (mut pair_setup, ..) = pair_setup();
+        let (config, storage, event_emitter) = state();
+
+        for _ in 0..FREE_ATTEMPTS {
+            FailedAttempts::record(&storage, unix_time()).await;
+        }
+        let err = pair_setup
+            .handle(
+                Step::Start { flags: 0 },
+                pointer::ControllerId::default(),
+                connection(),
+                config.clone(),
+                storage.clone(),
+                event_emitter.clone(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error(), tlv::Error::Backoff));
+        assert!(err.retry_delay().is_some());
+
+        for _ in FREE_ATTEMPTS..=MAX_FAILED_ATTEMPTS {
+            FailedAttempts::record(&storage, 0).await;
+        }
+        let err = pair_setup
+            .handle(
+                Step::Start { flags: 0 },
+                pointer::ControllerId::default(),
+                connection(),
+                config,
+                storage,
+                event_emitter,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error(), tlv::Error::MaxTries));
+    }
 }
@@ -1,20 +1,22 @@
 use aead::{generic_array::GenericArray, AeadInPlace, KeyInit};
 use chacha20poly1305::ChaCha20Poly1305;
 use ed25519_dalek::ed25519::signature::SignerMut;
-use futures::{
-    channel::oneshot,
-    future::{BoxFuture, FutureExt},
-};
-use hyper::{body::Buf, Body};
-use log::{debug, info};
+use futures::future::{BoxFuture, FutureExt};
 use std::str;
+use tracing::{debug, info, trace};
 use uuid::Uuid;
 use x25519_dalek::{EphemeralSecret, PublicKey};
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::{
     pointer,
     tlv::{self, Encodable, Type, Value},
-    transport::{hkdf_extract_and_expand, http::handler::TlvHandlerExt, tcp},
+    transport::{
+        hkdf_extract_and_expand,
+        http::handler::{verify_controller_signature, TlvHandlerExt},
+        tcp,
+    },
+    ConnectionInfo,
 };
 
 struct Session {
@@ -24,21 +26,31 @@ struct Session {
     session_key: [u8; 32],
 }
 
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.shared_secret.zeroize();
+        self.session_key.zeroize();
+    }
+}
+
 pub struct PairVerify {
     session: Option<Session>,
-    session_sender: Option<oneshot::Sender<tcp::Session>>,
+    session_sender: pointer::SessionSender,
+    /// The request step the session expects next.
+    next_step: StepNumber,
 }
 
 impl PairVerify {
-    pub fn new(session_sender: oneshot::Sender<tcp::Session>) -> PairVerify {
+    pub fn new(session_sender: pointer::SessionSender) -> PairVerify {
         PairVerify {
             session: None,
-            session_sender: Some(session_sender),
+            session_sender,
+            next_step: StepNumber::StartReq,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum StepNumber {
     Unknown = 0,
     StartReq = 1,
@@ -53,21 +65,27 @@ pub enum Step {
     Finish { data: Vec<u8> },
 }
 
+impl Step {
+    /// Returns the number of the request step.
+    fn number(&self) -> StepNumber {
+        match self {
+            Step::Start { .. } => StepNumber::StartReq,
+            Step::Finish { .. } => StepNumber::FinishReq,
+        }
+    }
+}
+
 impl TlvHandlerExt for PairVerify {
     type ParseResult = Step;
     type Result = tlv::Container;
 
-    fn parse(&self, body: Body) -> BoxFuture<Result<Step, tlv::ErrorContainer>> {
-        async {
-            let aggregated_body = hyper::body::aggregate(body)
-                .await
-                .map_err(|_| tlv::ErrorContainer::new(StepNumber::Unknown as u8, tlv::Error::Unknown))?;
-
-            debug!("received body: {:?}", aggregated_body.chunk());
+    fn parse(&self, body: Vec<u8>) -> BoxFuture<Result<Step, tlv::ErrorContainer>> {
+        async move {
+            trace!("received body: {:?}", &body);
 
-            let mut decoded = tlv::decode(aggregated_body.chunk());
-            match decoded.get(&(Type::State as u8)) {
-                Some(method) => match method[0] {
+            let mut decoded = tlv::decode(&body);
+            match decoded.get(&(Type::State as u8)).and_then(|state| state.first()) {
+                Some(&state) => match state {
                     x if x == StepNumber::StartReq as u8 => {
                         let a_pub = decoded
                             .remove(&(Type::PublicKey as u8))
@@ -98,19 +116,41 @@ impl TlvHandlerExt for PairVerify {
         &mut self,
         step: Step,
         _: pointer::ControllerId,
+        _: ConnectionInfo,
         config: pointer::Config,
         storage: pointer::Storage,
         _: pointer::EventEmitter,
     ) -> BoxFuture<Result<tlv::Container, tlv::ErrorContainer>> {
         async move {
+            // steps arriving out of order, e.g. an M3 before M1 or a replayed M3, end the session
+            let step_number = step.number();
+            if step_number != self.next_step {
+                info!(
+                    "pair verify: received step M{} while expecting M{}",
+                    step_number as u8, self.next_step as u8
+                );
+                self.session = None;
+                self.next_step = StepNumber::StartReq;
+                return Err(tlv::ErrorContainer::new(step_number as u8 + 1, tlv::Error::Unknown));
+            }
+
             match step {
                 Step::Start { a_pub } => match handle_start(self, config, a_pub).await {
-                    Ok(res) => Ok(res),
+                    Ok(res) => {
+                        self.next_step = StepNumber::FinishReq;
+                        Ok(res)
+                    },
                     Err(err) => Err(tlv::ErrorContainer::new(StepNumber::StartRes as u8, err)),
                 },
-                Step::Finish { data } => match handle_finish(self, storage, &data).await {
-                    Ok(res) => Ok(res),
-                    Err(err) => Err(tlv::ErrorContainer::new(StepNumber::FinishRes as u8, err)),
+                Step::Finish { data } => {
+                    // a pair verify ends after M4, whether it succeeded or not
+                    self.next_step = StepNumber::StartReq;
+                    let res = handle_finish(self, storage, &data).await;
+                    self.session = None;
+                    match res {
+                        Ok(res) => Ok(res),
+                        Err(err) => Err(tlv::ErrorContainer::new(StepNumber::FinishRes as u8, err)),
+                    }
                 },
             }
         }
@@ -216,13 +256,14 @@ async fn handle_finish(
             )?;
 
             let sub_tlv = tlv::decode(&decrypted_data);
-            debug!("received sub-TLV: {:?}", &sub_tlv);
-            let device_pairing_id = sub_tlv.get(&(Type::Identifier as u8)).ok_or(tlv::Error::Unknown)?;
+            trace!("received sub-TLV: {:?}", &sub_tlv);
+            let device_pairing_id = sub_tlv
+                .get(&(Type::Identifier as u8))
+                .ok_or(tlv::Error::MissingTlvItem(Type::Identifier))?;
             debug!("raw device pairing ID: {:?}", &device_pairing_id);
-            let device_signature_bytes = sub_tlv.get(&(Type::Signature as u8)).ok_or(tlv::Error::Unknown)?;
-            let mut device_signature_bytes_array = [0u8; ed25519_dalek::SIGNATURE_LENGTH];
-            device_signature_bytes_array.copy_from_slice(device_signature_bytes);
-            let device_signature = ed25519_dalek::Signature::from_bytes(&device_signature_bytes_array);
+            let device_signature = sub_tlv
+                .get(&(Type::Signature as u8))
+                .ok_or(tlv::Error::MissingTlvItem(Type::Signature))?;
             debug!("device signature: {:?}", &device_signature);
 
             let uuid_str = str::from_utf8(device_pairing_id)?;
@@ -236,17 +277,13 @@ async fn handle_finish(
             device_info.extend(device_pairing_id);
             device_info.extend(session.b_pub.as_bytes());
 
-            if ed25519_dalek::VerifyingKey::from_bytes(&pairing.public_key)?
-                .verify_strict(&device_info, &device_signature)
-                .is_err()
-            {
-                return Err(tlv::Error::Authentication);
-            }
+            let device_ltpk = ed25519_dalek::VerifyingKey::from_bytes(&pairing.public_key)?;
+            verify_controller_signature(&device_ltpk, &device_info, device_signature)?;
 
-            if let Some(sender) = handler.session_sender.take() {
+            if let Some(sender) = handler.session_sender.lock().await.take() {
                 let encrypted_session = tcp::Session {
-                    controller_id: pairing_uuid,
-                    shared_secret: session.shared_secret,
+                    controller_id: Some(pairing_uuid),
+                    shared_secret: Zeroizing::new(session.shared_secret.to_vec()),
                 };
                 let _session = sender.send(encrypted_session);
             } else {
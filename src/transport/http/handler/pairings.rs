@@ -1,22 +1,24 @@
 use futures::future::{BoxFuture, FutureExt};
-use hyper::{body::Buf, Body};
-use log::{debug, info};
 use std::{ops::Deref, str};
+use tracing::{info, trace};
 use uuid::Uuid;
 use ed25519_dalek::PUBLIC_KEY_LENGTH;
 
 use crate::{
-    event::Event,
-    pairing::{Pairing, Permissions},
+    event::{self, Event},
+    pairing::{Pairing, PairingApprover, Permissions},
     pointer,
     tlv::{self, Type, Value},
     transport::http::handler::TlvHandlerExt,
+    ConnectionInfo,
 };
 
-pub struct Pairings;
+pub struct Pairings {
+    metrics: pointer::Metrics,
+}
 
 impl Pairings {
-    pub fn new() -> Pairings { Pairings }
+    pub fn new(metrics: pointer::Metrics) -> Pairings { Pairings { metrics } }
 }
 
 #[derive(Debug, Clone)]
@@ -48,15 +50,11 @@ impl TlvHandlerExt for Pairings {
     type ParseResult = HandlerType;
     type Result = tlv::Container;
 
-    fn parse(&self, body: Body) -> BoxFuture<Result<HandlerType, tlv::ErrorContainer>> {
-        async {
-            let aggregated_body = hyper::body::aggregate(body)
-                .await
-                .map_err(|_| tlv::ErrorContainer::new(StepNumber::Unknown as u8, tlv::Error::Unknown))?;
-
-            debug!("received body: {:?}", aggregated_body.chunk());
+    fn parse(&self, body: Vec<u8>) -> BoxFuture<Result<HandlerType, tlv::ErrorContainer>> {
+        async move {
+            trace!("received body: {:?}", &body);
 
-            let mut decoded = tlv::decode(aggregated_body.chunk());
+            let mut decoded = tlv::decode(&body);
             if decoded.get(&(Type::State as u8)) != Some(&vec![1]) {
                 return Err(tlv::ErrorContainer::new(0, tlv::Error::Unknown));
             }
@@ -65,14 +63,29 @@ impl TlvHandlerExt for Pairings {
                     x if x == HandlerNumber::Add as u8 => {
                         let pairing_id = decoded
                             .remove(&(Type::Identifier as u8))
-                            .ok_or(tlv::ErrorContainer::new(StepNumber::Res as u8, tlv::Error::Unknown))?;
+                            .ok_or(tlv::ErrorContainer::new(
+                                StepNumber::Res as u8,
+                                tlv::Error::MissingTlvItem(Type::Identifier),
+                            ))?;
                         let ltpk = decoded
                             .remove(&(Type::PublicKey as u8))
-                            .ok_or(tlv::ErrorContainer::new(StepNumber::Res as u8, tlv::Error::Unknown))?;
+                            .ok_or(tlv::ErrorContainer::new(
+                                StepNumber::Res as u8,
+                                tlv::Error::MissingTlvItem(Type::PublicKey),
+                            ))?;
                         let perms = decoded
                             .remove(&(Type::Permissions as u8))
-                            .ok_or(tlv::ErrorContainer::new(StepNumber::Res as u8, tlv::Error::Unknown))?;
-                        let permissions = Permissions::from_byte(perms[0])
+                            .ok_or(tlv::ErrorContainer::new(
+                                StepNumber::Res as u8,
+                                tlv::Error::MissingTlvItem(Type::Permissions),
+                            ))?;
+                        if perms.is_empty() {
+                            return Err(tlv::ErrorContainer::new(
+                                StepNumber::Res as u8,
+                                tlv::Error::MissingTlvItem(Type::Permissions),
+                            ));
+                        }
+                        let permissions = Permissions::from_bytes(&perms)
                             .map_err(|_| tlv::ErrorContainer::new(StepNumber::Res as u8, tlv::Error::Unknown))?;
                         Ok(HandlerType::Add {
                             pairing_id,
@@ -83,7 +96,10 @@ impl TlvHandlerExt for Pairings {
                     x if x == HandlerNumber::Remove as u8 => {
                         let pairing_id = decoded
                             .remove(&(Type::Identifier as u8))
-                            .ok_or(tlv::ErrorContainer::new(StepNumber::Res as u8, tlv::Error::Unknown))?;
+                            .ok_or(tlv::ErrorContainer::new(
+                                StepNumber::Res as u8,
+                                tlv::Error::MissingTlvItem(Type::Identifier),
+                            ))?;
                         Ok(HandlerType::Remove { pairing_id })
                     },
                     x if x == HandlerNumber::List as u8 => Ok(HandlerType::List),
@@ -99,6 +115,7 @@ impl TlvHandlerExt for Pairings {
         &mut self,
         handler: HandlerType,
         controller_id: pointer::ControllerId,
+        _: ConnectionInfo,
         config: pointer::Config,
         storage: pointer::Storage,
         event_emitter: pointer::EventEmitter,
@@ -120,12 +137,20 @@ impl TlvHandlerExt for Pairings {
                 )
                 .await
                 {
-                    Ok(res) => Ok(res),
+                    Ok((res, added)) => {
+                        if added {
+                            self.metrics.record_pairing_added();
+                        }
+                        Ok(res)
+                    },
                     Err(err) => Err(tlv::ErrorContainer::new(StepNumber::Res as u8, err)),
                 },
                 HandlerType::Remove { pairing_id } => {
                     match handle_remove(controller_id, storage, event_emitter, pairing_id).await {
-                        Ok(res) => Ok(res),
+                        Ok(res) => {
+                            self.metrics.record_pairings_removed(1);
+                            Ok(res)
+                        },
                         Err(err) => Err(tlv::ErrorContainer::new(StepNumber::Res as u8, err)),
                     }
                 },
@@ -147,64 +172,71 @@ async fn handle_add(
     pairing_id: Vec<u8>,
     ltpk: Vec<u8>,
     permissions: Permissions,
-) -> Result<tlv::Container, tlv::Error> {
+) -> Result<(tlv::Container, bool), tlv::Error> {
     info!("pairings M1: received add pairing request");
 
     check_admin(&controller_id, &storage).await?;
 
     let uuid_str = str::from_utf8(&pairing_id)?;
     let pairing_uuid = Uuid::parse_str(uuid_str)?;
+    let public_key: [u8; PUBLIC_KEY_LENGTH] = ltpk.as_slice().try_into().map_err(|_| tlv::Error::InvalidPublicKey)?;
 
     let mut s = storage.lock().await;
-    match s.load_pairing(&pairing_uuid).await {
+    // updating the permissions of an existing pairing doesn't add a pairing
+    let added = match s.load_pairing(&pairing_uuid).await {
         Ok(mut pairing) => {
             let pairing_key = ed25519_dalek::VerifyingKey::from_bytes(&pairing.public_key)?;
-            let mut key_bytes = [0u8; PUBLIC_KEY_LENGTH];
-            key_bytes.copy_from_slice(&ltpk[..PUBLIC_KEY_LENGTH]);
-            let ltpk_key = ed25519_dalek::VerifyingKey::from_bytes(&key_bytes)?;
+            let ltpk_key = ed25519_dalek::VerifyingKey::from_bytes(&public_key)?;
             if pairing_key != ltpk_key {
-                return Err(tlv::Error::Unknown);
+                return Err(tlv::Error::KeyMismatch);
+            }
+            // controllers re-add pairings they already know, which needn't hit the storage again
+            if pairing.permissions != permissions {
+                pairing.permissions = permissions;
+                s.save_pairing(&pairing).await?;
             }
-            pairing.permissions = permissions;
-            s.save_pairing(&pairing).await?;
 
             drop(s);
 
-            event_emitter
-                .lock()
-                .await
-                .emit(&Event::ControllerPaired { id: pairing.id })
-                .await;
+            event::emit(&event_emitter, &Event::ControllerPaired { id: pairing.id }).await;
+
+            false
         },
         Err(_) => {
-            if let Some(max_peers) = config.lock().await.max_peers {
-                if s.count_pairings().await? + 1 > max_peers {
-                    return Err(tlv::Error::MaxPeers);
-                }
+            let pairing_count = s.count_pairings().await?;
+            // the config precedes the storage in the lock order, and the storage isn't blocked while waiting for the
+            // approval
+            drop(s);
+
+            let c = config.lock().await;
+            if exceeds_max_peers(pairing_count, c.max_peers) {
+                return Err(tlv::Error::MaxPeers);
             }
+            let pairing_approver = c.pairing_approver.clone();
+            drop(c);
 
-            let mut public_key = [0; 32];
-            public_key.clone_from_slice(&ltpk);
             let pairing = Pairing {
                 id: pairing_uuid,
                 permissions,
                 public_key,
             };
+
+            approve_pairing(pairing_approver, &pairing).await?;
+
+            let mut s = storage.lock().await;
             s.save_pairing(&pairing).await?;
 
             drop(s);
 
-            event_emitter
-                .lock()
-                .await
-                .emit(&Event::ControllerPaired { id: pairing.id })
-                .await;
+            event::emit(&event_emitter, &Event::ControllerPaired { id: pairing.id }).await;
+
+            true
         },
-    }
+    };
 
     info!("pairings M2: sending add pairing response");
 
-    Ok(vec![Value::State(StepNumber::Res as u8)])
+    Ok((vec![Value::State(StepNumber::Res as u8)], added))
 }
 
 async fn handle_remove(
@@ -219,13 +251,16 @@ async fn handle_remove(
 
     let uuid_str = str::from_utf8(&pairing_id)?;
     let pairing_uuid = Uuid::parse_str(uuid_str)?;
-    storage.lock().await.delete_pairing(&pairing_uuid).await?;
 
-    event_emitter
-        .lock()
-        .await
-        .emit(&Event::ControllerUnpaired { id: pairing_uuid })
-        .await;
+    let mut s = storage.lock().await;
+    s.delete_pairing(&pairing_uuid).await?;
+    let pairing_count = s.count_pairings().await?;
+    drop(s);
+
+    event::emit(&event_emitter, &Event::ControllerUnpaired { id: pairing_uuid }).await;
+    if pairing_count == 0 {
+        event::emit(&event_emitter, &Event::PairedStateChanged { paired: false }).await;
+    }
 
     info!("pairings M2: sending remove pairing response");
 
@@ -241,32 +276,119 @@ async fn handle_list(
     check_admin(&controller_id, &storage).await?;
 
     let pairings = storage.lock().await.list_pairings().await?;
+
+    info!("pairings M2: sending list pairings response");
+
+    Ok(list_pairings_response(&pairings))
+}
+
+/// Builds the list pairings response, with a `Separator` item between the entries of the pairings.
+fn list_pairings_response(pairings: &[Pairing]) -> tlv::Container {
     let mut list = vec![Value::State(StepNumber::Res as u8)];
     for (i, pairing) in pairings.iter().enumerate() {
-        list.push(Value::Identifier(pairing.id.hyphenated().to_string()));
-        list.push(Value::PublicKey(pairing.public_key.to_vec()));
-        list.push(Value::Permissions(pairing.permissions.clone()));
-        if i < pairings.len() {
+        if i > 0 {
             list.push(Value::Separator);
         }
+        list.push(Value::Identifier(pairing.id.hyphenated().to_string()));
+        list.push(Value::PublicKey(pairing.public_key.to_vec()));
+        list.push(Value::Permissions(pairing.permissions));
     }
 
-    info!("pairings M2: sending list pairings response");
+    list
+}
 
-    Ok(list)
+/// Returns whether adding a new pairing to `pairing_count` existing ones would exceed the optional `max_peers` limit.
+/// Adding the pairing that reaches the limit exactly is allowed.
+pub(crate) fn exceeds_max_peers(pairing_count: usize, max_peers: Option<usize>) -> bool {
+    max_peers.map_or(false, |max_peers| pairing_count + 1 > max_peers)
 }
 
-async fn check_admin(controller_id: &pointer::ControllerId, storage: &pointer::Storage) -> Result<(), tlv::Error> {
+/// Asks the [`PairingApprover`](PairingApprover) of the config, if any, whether a new pairing may be
+/// saved.
+pub(crate) async fn approve_pairing(
+    pairing_approver: Option<PairingApprover>,
+    pairing: &Pairing,
+) -> Result<(), tlv::Error> {
+    if let Some(pairing_approver) = pairing_approver {
+        pairing_approver.approve(pairing).await.map_err(|e| {
+            info!("pairing of controller {} rejected: {}", pairing.id, e);
+            tlv::Error::Authentication
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Verifies that the controller of the current session has a pairing, regardless of its permissions, and returns the
+/// pairing.
+async fn check_paired(
+    controller_id: &pointer::ControllerId,
+    storage: &pointer::Storage,
+) -> Result<Pairing, tlv::Error> {
     let controller_id: Uuid = controller_id
         .read()
         .unwrap()
         .deref()
         .ok_or(tlv::Error::Authentication)?;
-    match storage.lock().await.load_pairing(&controller_id).await {
-        Err(_) => Err(tlv::Error::Authentication),
-        Ok(controller) => match controller.permissions {
-            Permissions::Admin => Ok(()),
-            _ => Err(tlv::Error::Authentication),
-        },
+    storage
+        .lock()
+        .await
+        .load_pairing(&controller_id)
+        .await
+        .map_err(|_| tlv::Error::Authentication)
+}
+
+/// Verifies that the controller of the current session has a pairing with admin permissions.
+async fn check_admin(controller_id: &pointer::ControllerId, storage: &pointer::Storage) -> Result<(), tlv::Error> {
+    let controller = check_paired(controller_id, storage).await?;
+    if controller.permissions.contains(Permissions::ADMIN) {
+        Ok(())
+    } else {
+        Err(tlv::Error::Authentication)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tlv::Encodable;
+
+    #[test]
+    fn test_exceeds_max_peers() {
+        assert!(!exceeds_max_peers(0, None));
+        assert!(!exceeds_max_peers(100, None));
+        assert!(exceeds_max_peers(0, Some(0)));
+        assert!(!exceeds_max_peers(0, Some(1)));
+        assert!(exceeds_max_peers(1, Some(1)));
+        assert!(!exceeds_max_peers(15, Some(16)));
+        assert!(exceeds_max_peers(16, Some(16)));
+    }
+
+    #[test]
+    fn test_list_pairings_response_separates_entries() {
+        let pairing = |id| Pairing {
+            id,
+            permissions: Permissions::ADMIN,
+            public_key: [7; 32],
+        };
+        let separators = |list: &tlv::Container| list.iter().filter(|v| matches!(v, Value::Separator)).count();
+
+        let list = list_pairings_response(&[pairing(Uuid::new_v4())]);
+        assert_eq!(separators(&list), 0);
+
+        let ids = [Uuid::new_v4(), Uuid::new_v4()];
+        let list = list_pairings_response(&[pairing(ids[0]), pairing(ids[1])]);
+        assert_eq!(separators(&list), 1);
+        assert!(!matches!(list.last(), Some(Value::Separator)));
+
+        let entries = tlv::decode_list(&list.encode());
+        assert_eq!(entries.len(), 2);
+        for (entry, id) in entries.iter().zip(ids) {
+            assert_eq!(
+                entry.get(&(Type::Identifier as u8)),
+                Some(&id.hyphenated().to_string().into_bytes())
+            );
+        }
     }
 }
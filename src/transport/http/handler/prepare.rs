@@ -0,0 +1,103 @@
+use futures::future::{BoxFuture, FutureExt};
+use hyper::{body::Buf, Body, Response, StatusCode, Uri};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::{
+    pointer,
+    transport::http::{handler::JsonHandlerExt, json_response, Status},
+    ConnectionInfo,
+    Result,
+};
+
+/// A timed write announced by a `PUT /prepare` request. Writes of characteristics with the `TimedWrite` permission have
+/// to carry the same process ID and arrive before the deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreparedWrite {
+    pub pid: u64,
+    pub deadline: Instant,
+}
+
+impl PreparedWrite {
+    /// Returns whether a write with the given process ID, arriving at `now`, is covered by the prepared write.
+    pub fn admits(&self, pid: u64, now: Instant) -> bool { self.pid == pid && now <= self.deadline }
+}
+
+#[derive(Debug, Deserialize)]
+struct PrepareRequest {
+    /// Time in milliseconds the prepared write is valid for.
+    ttl: u64,
+    pid: u64,
+}
+
+pub struct Prepare {
+    prepared_write: pointer::PreparedWrite,
+}
+
+impl Prepare {
+    pub fn new(prepared_write: pointer::PreparedWrite) -> Prepare { Prepare { prepared_write } }
+}
+
+impl JsonHandlerExt for Prepare {
+    fn handle(
+        &mut self,
+        _: Uri,
+        body: Body,
+        _: pointer::ControllerId,
+        _: ConnectionInfo,
+        _: pointer::EventSubscriptions,
+        _: pointer::Config,
+        _: pointer::Storage,
+        _: pointer::AccessoryDatabase,
+        _: pointer::EventEmitter,
+    ) -> BoxFuture<Result<Response<Body>>> {
+        let prepared_write = self.prepared_write.clone();
+
+        async move {
+            let aggregated_body = hyper::body::aggregate(body).await?;
+            let request: PrepareRequest = serde_json::from_slice(aggregated_body.chunk())?;
+
+            debug!("preparing timed write {} with a TTL of {} ms", request.pid, request.ttl);
+
+            let deadline = match Instant::now().checked_add(Duration::from_millis(request.ttl)) {
+                Some(deadline) => deadline,
+                None => {
+                    debug!("rejecting timed write {}, its TTL is out of range", request.pid);
+                    let body = serde_json::to_vec(&json!({ "status": Status::InvalidValueInRequest as i32 }))?;
+                    return json_response(body, StatusCode::BAD_REQUEST);
+                },
+            };
+
+            // a new prepare request replaces the pending one of the connection
+            *prepared_write.lock().await = Some(PreparedWrite {
+                pid: request.pid,
+                deadline,
+            });
+
+            let body = serde_json::to_vec(&json!({ "status": Status::Success as i32 }))?;
+            json_response(body, StatusCode::OK)
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepared_write_admits() {
+        let now = Instant::now();
+        let prepared_write = PreparedWrite {
+            pid: 42,
+            deadline: now + Duration::from_millis(100),
+        };
+
+        assert!(prepared_write.admits(42, now));
+        assert!(prepared_write.admits(42, now + Duration::from_millis(100)));
+        assert!(!prepared_write.admits(43, now));
+        assert!(!prepared_write.admits(42, now + Duration::from_millis(101)));
+    }
+}
@@ -0,0 +1,82 @@
+use futures::future::{BoxFuture, FutureExt};
+use hyper::{body::Buf, Body, Response, StatusCode, Uri};
+use serde::Deserialize;
+use tracing::{debug, info};
+
+use crate::{
+    camera::SnapshotRequest,
+    pointer,
+    transport::http::{handler::JsonHandlerExt, image_response},
+    ConnectionInfo,
+    Error,
+    Result,
+};
+
+#[derive(Debug, Deserialize)]
+struct ResourceRequest {
+    #[serde(rename = "resource-type")]
+    resource_type: String,
+    #[serde(flatten)]
+    snapshot: SnapshotRequest,
+}
+
+pub struct Resource;
+
+impl Resource {
+    pub fn new() -> Resource { Resource }
+}
+
+impl JsonHandlerExt for Resource {
+    fn handle(
+        &mut self,
+        _: Uri,
+        body: Body,
+        _: pointer::ControllerId,
+        _: ConnectionInfo,
+        _: pointer::EventSubscriptions,
+        config: pointer::Config,
+        _: pointer::Storage,
+        _: pointer::AccessoryDatabase,
+        _: pointer::EventEmitter,
+    ) -> BoxFuture<Result<Response<Body>>> {
+        async move {
+            let aggregated_body = hyper::body::aggregate(body).await?;
+            let request: ResourceRequest = serde_json::from_slice(aggregated_body.chunk())?;
+
+            info!("received resource request: {:?}", &request);
+
+            // images are the only resource type defined for IP accessories
+            if request.resource_type != "image" {
+                return Err(Error::HttpStatus(StatusCode::BAD_REQUEST));
+            }
+
+            let snapshot_handler = config.lock().await.snapshot_handler.clone();
+            let snapshot_handler = snapshot_handler.ok_or(Error::HttpStatus(StatusCode::NOT_FOUND))?;
+
+            let image = snapshot_handler.take_snapshot(request.snapshot).await?;
+
+            debug!("sending snapshot of {} bytes", image.len());
+
+            image_response(image, StatusCode::OK)
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resource_request() {
+        let request: ResourceRequest =
+            serde_json::from_str(r#"{"aid":2,"resource-type":"image","image-width":640,"image-height":360}"#).unwrap();
+
+        assert_eq!(request.resource_type, "image");
+        assert_eq!(request.snapshot, SnapshotRequest {
+            aid: Some(2),
+            width: 640,
+            height: 360,
+        });
+    }
+}
@@ -7,13 +7,13 @@ use hyper::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    characteristic::{Format, Perm, Unit},
+    characteristic::{CharacteristicError, Format, Perm, Unit},
     Error,
     HapType,
     Result,
 };
 
-mod handler;
+pub(crate) mod handler;
 
 pub(crate) mod server;
 
@@ -31,12 +31,36 @@ pub enum Status {
     OperationTimedOut = -70408,
     ResourceDoesNotExist = -70409,
     InvalidValueInRequest = -70410,
+    InsufficientAuthorization = -70411,
+}
+
+impl Status {
+    /// Derives the status to answer a characteristic read or write with from the error it failed with. Callbacks can
+    /// choose the status by failing with a [`CharacteristicError`](CharacteristicError).
+    pub(crate) fn from_error(error: &Error) -> Status {
+        match error {
+            Error::ValueOnRead(e) | Error::ValueOnUpdate(e) => match e.downcast_ref::<CharacteristicError>() {
+                Some(CharacteristicError::InsufficientPrivileges) => Status::InsufficientPrivileges,
+                Some(CharacteristicError::ServiceCommunicationFailure) => Status::ServiceCommunicationFailure,
+                Some(CharacteristicError::ResourceBusy) => Status::ResourceBusy,
+                Some(CharacteristicError::OutOfResource) => Status::OutOfResource,
+                Some(CharacteristicError::OperationTimedOut) => Status::OperationTimedOut,
+                Some(CharacteristicError::ResourceDoesNotExist) => Status::ResourceDoesNotExist,
+                Some(CharacteristicError::InvalidValueInRequest) => Status::InvalidValueInRequest,
+                None => Status::ServiceCommunicationFailure,
+            },
+            Error::InvalidValue(_) | Error::DisallowedValue(_) => Status::InvalidValueInRequest,
+            Error::AccessoryNotFound | Error::CharacteristicNotFound => Status::ResourceDoesNotExist,
+            _ => Status::ServiceCommunicationFailure,
+        }
+    }
 }
 
 #[derive(Debug)]
 enum ContentType {
     PairingTLV8,
     HapJson,
+    ImageJpeg,
 }
 
 impl ContentType {
@@ -44,6 +68,7 @@ impl ContentType {
         match self {
             ContentType::PairingTLV8 => "application/pairing+tlv8".into(),
             ContentType::HapJson => "application/hap+json".into(),
+            ContentType::ImageJpeg => "image/jpeg".into(),
         }
     }
 }
@@ -77,9 +102,22 @@ pub struct ReadResponseObject {
     pub step_value: Option<serde_json::Value>,
     #[serde(rename = "maxLen", skip_serializing_if = "Option::is_none")]
     pub max_len: Option<u16>,
+    #[serde(rename = "maxDataLen", skip_serializing_if = "Option::is_none")]
+    pub max_data_len: Option<u32>,
+    #[serde(rename = "valid-values", skip_serializing_if = "Option::is_none")]
+    pub valid_values: Option<Vec<serde_json::Value>>,
+    #[serde(rename = "valid-values-range", skip_serializing_if = "Option::is_none")]
+    pub valid_values_range: Option<[serde_json::Value; 2]>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub status: Option<i32>,
 }
+#[derive(Debug, Deserialize)]
+pub struct WriteRequestBody {
+    pub characteristics: Vec<WriteObject>,
+    /// Process ID of a timed write, matching the `pid` of the preceding `PUT /prepare` request.
+    pub pid: Option<u64>,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct WriteObject {
@@ -90,6 +128,9 @@ pub struct WriteObject {
     #[serde(rename = "authData")]
     pub auth_data: Option<String>,
     pub remote: Option<bool>,
+    /// Whether the controller requests the value of the characteristic in the response to the write.
+    #[serde(rename = "r")]
+    pub response: Option<bool>,
 }
 
 #[derive(Debug, Serialize)]
@@ -97,6 +138,8 @@ pub struct WriteResponseObject {
     pub iid: u64,
     pub aid: u64,
     pub status: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Serialize)]
@@ -114,11 +157,17 @@ pub fn json_response(body: Vec<u8>, status: StatusCode) -> Result<Response<Body>
     response(body, status, ContentType::HapJson)
 }
 
+pub fn image_response(body: Vec<u8>, status: StatusCode) -> Result<Response<Body>> {
+    response(body, status, ContentType::ImageJpeg)
+}
+
 pub fn status_response(status: StatusCode) -> Result<Response<Body>> {
-    Response::builder()
-        .status(status)
-        .body(Body::empty())
-        .map_err(Error::from)
+    let mut builder = Response::builder().status(status);
+    // iOS expects every response but a `204 No Content` to be framed by a content length, even without a body
+    if status != StatusCode::NO_CONTENT {
+        builder = builder.header(CONTENT_LENGTH, 0);
+    }
+    builder.body(Body::empty()).map_err(Error::from)
 }
 
 pub fn event_response(event_objects: Vec<EventObject>) -> Result<Vec<u8>> {
@@ -126,7 +175,7 @@ pub fn event_response(event_objects: Vec<EventObject>) -> Result<Vec<u8>> {
         characteristics: event_objects,
     })?;
     let response = format!(
-        "EVENT/1.0 200 OK\nContent-Type: {}\nContent-Length: {}\n\n{}",
+        "EVENT/1.0 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
         ContentType::HapJson.to_string(),
         body.len(),
         body,
@@ -134,6 +183,8 @@ pub fn event_response(event_objects: Vec<EventObject>) -> Result<Vec<u8>> {
     Ok(response.as_bytes().to_vec())
 }
 
+/// Builds a response with a complete body, so it's sent with a `Content-Length` header instead of chunked transfer
+/// encoding, which the HAP clients of iOS don't accept.
 fn response(body: Vec<u8>, status: StatusCode, content_type: ContentType) -> Result<Response<Body>> {
     Response::builder()
         .status(status)
@@ -142,3 +193,46 @@ fn response(body: Vec<u8>, status: StatusCode, content_type: ContentType) -> Res
         .body(body.into())
         .map_err(Error::from)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_from_error() {
+        assert!(matches!(
+            Status::from_error(&Error::ValueOnUpdate(CharacteristicError::ResourceBusy.into())),
+            Status::ResourceBusy
+        ));
+        assert!(matches!(
+            Status::from_error(&Error::ValueOnRead("device unreachable".into())),
+            Status::ServiceCommunicationFailure
+        ));
+        assert!(matches!(
+            Status::from_error(&Error::InvalidValue(Format::Bool)),
+            Status::InvalidValueInRequest
+        ));
+    }
+
+    #[test]
+    fn test_responses_have_content_length() {
+        let response = json_response(b"{}".to_vec(), StatusCode::OK).unwrap();
+        assert_eq!(response.headers()[CONTENT_LENGTH], "2");
+
+        let response = status_response(StatusCode::NOT_FOUND).unwrap();
+        assert_eq!(response.headers()[CONTENT_LENGTH], "0");
+
+        let response = status_response(StatusCode::NO_CONTENT).unwrap();
+        assert!(response.headers().get(CONTENT_LENGTH).is_none());
+    }
+
+    #[test]
+    fn test_event_response() {
+        let event = event_response(vec![]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(event).unwrap(),
+            "EVENT/1.0 200 OK\r\nContent-Type: application/hap+json\r\nContent-Length: 22\r\n\r\n{\"characteristics\":[]}"
+        );
+    }
+}
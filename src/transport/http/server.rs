@@ -1,20 +1,27 @@
 use futures::{
     channel::oneshot,
-    future::{self, BoxFuture, Future, FutureExt, TryFutureExt},
+    future::{self, BoxFuture, Either, Future, FutureExt, TryFutureExt},
     lock::Mutex,
 };
 use hyper::{server::conn::Http, service::Service, Body, Method, Request, Response, StatusCode};
 use log::{debug, error, info};
+use serde_json::json;
 use std::{
-    net::SocketAddr,
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+        Mutex as StdMutex,
+    },
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 use tokio::net::TcpListener;
 
 use crate::{
-    event::Event,
+    event::{self, Event},
     pointer,
     transport::{
         http::{
@@ -26,15 +33,20 @@ use crate::{
                 pair_setup::PairSetup,
                 pair_verify::PairVerify,
                 pairings::Pairings,
+                prepare::Prepare,
+                resource::Resource,
                 HandlerExt,
                 JsonHandler,
                 TlvHandler,
             },
+            json_response,
             status_response,
             EventObject,
+            Status,
         },
         tcp::{EncryptedStream, Session, StreamWrapper},
     },
+    ConnectionInfo,
     Error,
     Result,
 };
@@ -45,12 +57,128 @@ struct Handlers {
     pub accessories: Arc<Mutex<Box<dyn HandlerExt + Send + Sync>>>,
     pub get_characteristics: Arc<Mutex<Box<dyn HandlerExt + Send + Sync>>>,
     pub put_characteristics: Arc<Mutex<Box<dyn HandlerExt + Send + Sync>>>,
+    pub prepare: Arc<Mutex<Box<dyn HandlerExt + Send + Sync>>>,
     pub pairings: Arc<Mutex<Box<dyn HandlerExt + Send + Sync>>>,
     pub identify: Arc<Mutex<Box<dyn HandlerExt + Send + Sync>>>,
+    pub resource: Arc<Mutex<Box<dyn HandlerExt + Send + Sync>>>,
+}
+
+/// Book-keeping of the open connections, enforcing the `max_connections` and `connection_idle_timeout` limits of the
+/// config.
+#[derive(Clone, Default)]
+struct Connections(Arc<StdMutex<HashMap<u64, OpenConnection>>>);
+
+struct OpenConnection {
+    peer_address: SocketAddr,
+    secured: Arc<AtomicBool>,
+    last_activity: Instant,
+    close_sender: oneshot::Sender<()>,
+}
+
+impl Connections {
+    /// Registers a new connection, closing connections to stay within `max_connections`. Connections without an
+    /// encrypted session are closed first, as they don't belong to a paired controller (yet), then the least recently
+    /// used ones.
+    fn open(
+        &self,
+        connection: ConnectionInfo,
+        secured: Arc<AtomicBool>,
+        close_sender: oneshot::Sender<()>,
+        max_connections: Option<usize>,
+    ) {
+        let mut connections = self.0.lock().expect("accessing connections");
+
+        if let Some(max_connections) = max_connections {
+            while !connections.is_empty() && connections.len() >= max_connections {
+                let evicted = connections
+                    .iter()
+                    .min_by_key(|(_, c)| (c.secured.load(Ordering::SeqCst), c.last_activity))
+                    .map(|(&id, _)| id)
+                    .expect("connections aren't empty");
+
+                info!(
+                    "closing connection of session {} to stay within the limit of {} connections",
+                    evicted, max_connections
+                );
+
+                if let Some(c) = connections.remove(&evicted) {
+                    let _ = c.close_sender.send(());
+                }
+            }
+        }
+
+        connections.insert(connection.session_id, OpenConnection {
+            peer_address: connection.peer_address,
+            secured,
+            last_activity: Instant::now(),
+            close_sender,
+        });
+    }
+
+    /// Records activity on a connection, i.e. a request or an event notification.
+    fn touch(&self, session_id: u64) {
+        if let Some(c) = self.0.lock().expect("accessing connections").get_mut(&session_id) {
+            c.last_activity = Instant::now();
+        }
+    }
+
+    /// Returns when the connection becomes idle, or `None` if it's closed.
+    fn idle_deadline(&self, session_id: u64, idle_timeout: Duration) -> Option<Instant> {
+        self.0
+            .lock()
+            .expect("accessing connections")
+            .get(&session_id)
+            .map(|c| c.last_activity + idle_timeout)
+    }
+
+    /// Removes a connection, closing it if it's still open.
+    fn close(&self, session_id: u64) {
+        if let Some(c) = self.0.lock().expect("accessing connections").remove(&session_id) {
+            let _ = c.close_sender.send(());
+        }
+    }
+
+    /// Closes the connections that haven't established an encrypted session yet, i.e. that are idle before or in the
+    /// middle of a pair setup or pair verify, optionally only those of the given peer. Returns the number of closed
+    /// connections.
+    fn close_unsecured(&self, peer_ip: Option<IpAddr>) -> usize {
+        let mut connections = self.0.lock().expect("accessing connections");
+
+        let unsecured: Vec<u64> = connections
+            .iter()
+            .filter(|(_, c)| !c.secured.load(Ordering::SeqCst))
+            .filter(|(_, c)| peer_ip.map_or(true, |ip| c.peer_address.ip() == ip))
+            .map(|(&id, _)| id)
+            .collect();
+
+        for session_id in &unsecured {
+            if let Some(c) = connections.remove(session_id) {
+                info!("resetting session {} of {}", session_id, c.peer_address);
+                let _ = c.close_sender.send(());
+            }
+        }
+
+        unsecured.len()
+    }
+
+    /// Closes all open connections. Returns the number of closed connections.
+    fn close_all(&self) -> usize {
+        let connections = std::mem::take(&mut *self.0.lock().expect("accessing connections"));
+        let count = connections.len();
+
+        for (_, c) in connections {
+            let _ = c.close_sender.send(());
+        }
+
+        count
+    }
 }
 
 struct Api {
     controller_id: pointer::ControllerId,
+    connection: ConnectionInfo,
+    connections: Connections,
+    secured: Arc<AtomicBool>,
     event_subscriptions: pointer::EventSubscriptions,
     config: pointer::Config,
     storage: pointer::Storage,
@@ -62,28 +190,47 @@ struct Api {
 impl Api {
     fn new(
         controller_id: pointer::ControllerId,
+        connection: ConnectionInfo,
+        connections: Connections,
+        secured: Arc<AtomicBool>,
         event_subscriptions: pointer::EventSubscriptions,
         config: pointer::Config,
         storage: pointer::Storage,
         accessory_database: pointer::AccessoryDatabase,
         event_emitter: pointer::EventEmitter,
         session_sender: oneshot::Sender<Session>,
+        pair_setup_in_progress: pointer::PairSetupInProgress,
+        metrics: pointer::Metrics,
     ) -> Self {
+        let session_sender = Arc::new(Mutex::new(Some(session_sender)));
+        let prepared_write = Arc::new(Mutex::new(None));
+
         Api {
             controller_id,
+            connection,
+            connections,
+            secured,
             event_subscriptions,
             config,
             storage,
             accessory_database,
             event_emitter,
             handlers: Handlers {
-                pair_setup: Arc::new(Mutex::new(Box::new(TlvHandler::from(PairSetup::new())))),
+                pair_setup: Arc::new(Mutex::new(Box::new(TlvHandler::from(PairSetup::new(
+                    session_sender.clone(),
+                    pair_setup_in_progress,
+                    metrics.clone(),
+                ))))),
                 pair_verify: Arc::new(Mutex::new(Box::new(TlvHandler::from(PairVerify::new(session_sender))))),
                 accessories: Arc::new(Mutex::new(Box::new(JsonHandler::from(Accessories::new())))),
                 get_characteristics: Arc::new(Mutex::new(Box::new(JsonHandler::from(GetCharacteristics::new())))),
-                put_characteristics: Arc::new(Mutex::new(Box::new(JsonHandler::from(UpdateCharacteristics::new())))),
-                pairings: Arc::new(Mutex::new(Box::new(TlvHandler::from(Pairings::new())))),
+                put_characteristics: Arc::new(Mutex::new(Box::new(JsonHandler::from(UpdateCharacteristics::new(
+                    prepared_write.clone(),
+                ))))),
+                prepare: Arc::new(Mutex::new(Box::new(JsonHandler::from(Prepare::new(prepared_write))))),
+                pairings: Arc::new(Mutex::new(Box::new(TlvHandler::from(Pairings::new(metrics))))),
                 identify: Arc::new(Mutex::new(Box::new(JsonHandler::from(Identify::new())))),
+                resource: Arc::new(Mutex::new(Box::new(JsonHandler::from(Resource::new())))),
             },
         }
     }
@@ -99,22 +246,33 @@ impl Service<Request<Body>> for Api {
     }
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
+        self.connections.touch(self.connection.session_id);
+
         let (parts, body) = req.into_parts();
         let method = parts.method;
         let uri = parts.uri;
 
+        // apart from pairing and identifying unpaired accessories, requests require an encrypted session
+        let requires_secured_session = !matches!(uri.path(), "/pair-setup" | "/pair-verify" | "/identify");
+        if requires_secured_session && !self.secured.load(Ordering::SeqCst) {
+            return future::ready(connection_authorization_required_response()).boxed();
+        }
+
         let mut handler: Option<Arc<Mutex<Box<dyn HandlerExt + Send + Sync>>>> = match (method, uri.path()) {
             (Method::POST, "/pair-setup") => Some(self.handlers.pair_setup.clone()),
             (Method::POST, "/pair-verify") => Some(self.handlers.pair_verify.clone()),
             (Method::GET, "/accessories") => Some(self.handlers.accessories.clone()),
             (Method::GET, "/characteristics") => Some(self.handlers.get_characteristics.clone()),
             (Method::PUT, "/characteristics") => Some(self.handlers.put_characteristics.clone()),
+            (Method::PUT, "/prepare") => Some(self.handlers.prepare.clone()),
             (Method::POST, "/pairings") => Some(self.handlers.pairings.clone()),
             (Method::POST, "/identify") => Some(self.handlers.identify.clone()),
+            (Method::POST, "/resource") => Some(self.handlers.resource.clone()),
             _ => None,
         };
 
         let controller_id = self.controller_id.clone();
+        let connection = self.connection;
         let event_subscriptions = self.event_subscriptions.clone();
         let config = self.config.clone();
         let storage = self.storage.clone();
@@ -131,6 +289,7 @@ impl Service<Request<Body>> for Api {
                             uri,
                             body,
                             controller_id,
+                            connection,
                             event_subscriptions,
                             config,
                             storage,
@@ -147,6 +306,36 @@ impl Service<Request<Body>> for Api {
     }
 }
 
+/// Binds a TCP listener to `socket_addr` and records the address it's actually bound to, which has a different port if
+/// the configured port is `0`. The mDNS responder announces that port instead of the configured one.
+async fn bind_listener(
+    socket_addr: SocketAddr,
+    local_addr: &StdMutex<Option<SocketAddr>>,
+    mdns_responder: &pointer::MdnsResponder,
+) -> Result<TcpListener> {
+    info!("binding TCP listener on {}", &socket_addr);
+
+    let listener = TcpListener::bind(socket_addr)
+        .await
+        .map_err(|e| Error::Bind(socket_addr, e))?;
+    let bound_addr = listener.local_addr()?;
+    if bound_addr != socket_addr {
+        info!("TCP listener bound on {}", &bound_addr);
+    }
+
+    *local_addr.lock().expect("accessing local_addr") = Some(bound_addr);
+    mdns_responder.lock().await.set_port(bound_addr.port());
+
+    Ok(listener)
+}
+
+/// Returns the `470 Connection Authorization Required` response for requests on connections that haven't completed pair
+/// verify.
+fn connection_authorization_required_response() -> Result<Response<Body>> {
+    let body = serde_json::to_vec(&json!({ "status": Status::InsufficientPrivileges as i32 }))?;
+    json_response(body, StatusCode::from_u16(470).expect("valid status code"))
+}
+
 #[derive(Clone)]
 pub struct Server {
     config: pointer::Config,
@@ -154,6 +343,13 @@ pub struct Server {
     accessory_database: pointer::AccessoryDatabase,
     event_emitter: pointer::EventEmitter,
     mdns_responder: pointer::MdnsResponder,
+    pair_setup_in_progress: pointer::PairSetupInProgress,
+    metrics: pointer::Metrics,
+    listener: Arc<Mutex<Option<TcpListener>>>,
+    local_addr: Arc<StdMutex<Option<SocketAddr>>>,
+    connections: Connections,
+    // session IDs stay unique across runs, as connections outlive the run handle that accepted them
+    next_session_id: Arc<AtomicU64>,
 }
 
 impl Server {
@@ -163,6 +359,8 @@ impl Server {
         accessory_database: pointer::AccessoryDatabase,
         event_emitter: pointer::EventEmitter,
         mdns_responder: pointer::MdnsResponder,
+        pair_setup_in_progress: pointer::PairSetupInProgress,
+        metrics: pointer::Metrics,
     ) -> Self {
         Server {
             config,
@@ -170,33 +368,82 @@ impl Server {
             accessory_database,
             event_emitter,
             mdns_responder,
+            pair_setup_in_progress,
+            metrics,
+            listener: Arc::new(Mutex::new(None)),
+            local_addr: Arc::new(StdMutex::new(None)),
+            connections: Connections::default(),
+            next_session_id: Arc::new(AtomicU64::new(1)),
         }
     }
 
+    /// Binds the TCP listener to the host and port of the config, so binding errors surface before the server is run.
+    pub async fn bind(&self) -> Result<()> {
+        let c = self.config.lock().await;
+        let socket_addr = SocketAddr::new(c.host, c.port);
+        drop(c);
+
+        let listener = bind_listener(socket_addr, &self.local_addr, &self.mdns_responder).await?;
+        *self.listener.lock().await = Some(listener);
+
+        Ok(())
+    }
+
+    /// Returns the address the TCP listener was last bound to, if it was bound yet.
+    pub fn local_addr(&self) -> Option<SocketAddr> { *self.local_addr.lock().expect("accessing local_addr") }
+
+    /// Closes the connections without an encrypted session, optionally only those of the given peer, dropping their
+    /// pair setup and pair verify state. Returns the number of closed connections.
+    pub fn reset_sessions(&self, peer_ip: Option<IpAddr>) -> usize { self.connections.close_unsecured(peer_ip) }
+
+    /// Closes all open connections, e.g. once the server stopped accepting new ones on shutdown. Returns the number of
+    /// closed connections.
+    pub fn close_connections(&self) -> usize { self.connections.close_all() }
+
     pub fn run_handle(&self) -> BoxFuture<Result<()>> {
         let config = self.config.clone();
         let storage = self.storage.clone();
         let accessory_database = self.accessory_database.clone();
         let event_emitter = self.event_emitter.clone();
         let mdns_responder = self.mdns_responder.clone();
+        let pair_setup_in_progress = self.pair_setup_in_progress.clone();
+        let metrics = self.metrics.clone();
+        let listener = self.listener.clone();
+        let local_addr = self.local_addr.clone();
+        let connections = self.connections.clone();
+        let next_session_id = self.next_session_id.clone();
 
         async move {
             let config_lock = config.lock().await;
             let socket_addr = SocketAddr::new(config_lock.host, config_lock.port);
+            let event_coalescing_window = config_lock.event_coalescing_window;
+            let max_connections = config_lock.max_connections;
+            let connection_idle_timeout = config_lock.connection_idle_timeout;
             drop(config_lock);
 
-            info!("binding TCP listener on {}", &socket_addr);
-            let listener = TcpListener::bind(socket_addr).await?;
+            // the listener bound on creation is dropped when the server shuts down, so running it again binds anew
+            let bound_listener = listener.lock().await.take();
+            let listener = match bound_listener {
+                Some(listener) => listener,
+                None => bind_listener(socket_addr, &local_addr, &mdns_responder).await?,
+            };
 
             mdns_responder.lock().await.update_records().await;
 
             loop {
-                let (stream, _socket_addr) = listener.accept().await?;
+                let (stream, peer_address) = listener.accept().await?;
+
+                let session_id = next_session_id.fetch_add(1, Ordering::SeqCst);
+                metrics.record_connection_opened();
+                let connection = ConnectionInfo {
+                    peer_address,
+                    session_id,
+                };
 
-                debug!("incoming TCP stream from {}", stream.peer_addr()?);
+                debug!("incoming TCP stream from {} (session {})", peer_address, session_id);
 
                 let (
-                    encrypted_stream,
+                    mut encrypted_stream,
                     stream_incoming,
                     stream_outgoing,
                     session_sender,
@@ -206,22 +453,103 @@ impl Server {
                 let stream_wrapper =
                     StreamWrapper::new(stream_incoming, stream_outgoing.clone(), incoming_waker, outgoing_waker);
                 let event_subscriptions = Arc::new(Mutex::new(vec![]));
+                let pending_events = Arc::new(Mutex::new(HashMap::new()));
+
+                let (close_sender, close_receiver) = oneshot::channel();
+                connections.open(
+                    connection,
+                    encrypted_stream.secured.clone(),
+                    close_sender,
+                    max_connections,
+                );
+
+                if let Some(idle_timeout) = connection_idle_timeout {
+                    let connections = connections.clone();
+                    tokio::spawn(async move {
+                        while let Some(deadline) = connections.idle_deadline(session_id, idle_timeout) {
+                            if deadline <= Instant::now() {
+                                info!("closing idle connection of session {}", session_id);
+                                connections.close(session_id);
+                                break;
+                            }
+                            tokio::time::sleep_until(deadline.into()).await;
+                        }
+                    });
+                }
 
                 let api = Api::new(
                     encrypted_stream.controller_id.clone(),
+                    connection,
+                    connections.clone(),
+                    encrypted_stream.secured.clone(),
                     event_subscriptions.clone(),
                     config.clone(),
                     storage.clone(),
                     accessory_database.clone(),
                     event_emitter.clone(),
                     session_sender,
+                    pair_setup_in_progress.clone(),
+                    metrics.clone(),
                 );
 
+                let session_established = encrypted_stream.session_established();
+                let session_event_emitter = event_emitter.clone();
+                let connected = tokio::spawn(async move {
+                    if let Ok(controller_id) = session_established.await {
+                        event::emit(&session_event_emitter, &Event::ControllerConnected { controller_id }).await;
+                    }
+                });
+
+                let connection_controller_id = encrypted_stream.controller_id.clone();
+                let connection_subscriptions = event_subscriptions.clone();
+                let connection_event_emitter = event_emitter.clone();
+                let connections_ = connections.clone();
+                let connection_metrics = metrics.clone();
+                let event_metrics = metrics.clone();
+                let event_connections = connections.clone();
+
                 event_emitter.lock().await.add_listener(Box::new(move |event| {
                     let event_subscriptions_ = event_subscriptions.clone();
+                    let pending_events_ = pending_events.clone();
                     let stream_outgoing_ = stream_outgoing.clone();
+                    let metrics_ = event_metrics.clone();
+                    let connections_ = event_connections.clone();
                     async move {
                         match *event {
+                            Event::CharacteristicValueChanged { aid, iid, ref value }
+                                if event_coalescing_window.is_some() =>
+                            {
+                                if !event_subscriptions_.lock().await.contains(&(aid, iid)) {
+                                    return;
+                                }
+
+                                let mut pending = pending_events_.lock().await;
+                                let flush_scheduled = !pending.is_empty();
+                                pending.insert((aid, iid), value.clone());
+                                drop(pending);
+
+                                // the first change within a window schedules the flush of all changes of that window
+                                if !flush_scheduled {
+                                    let window = event_coalescing_window.unwrap_or_default();
+                                    tokio::spawn(async move {
+                                        tokio::time::sleep(window).await;
+
+                                        let events = pending_events_
+                                            .lock()
+                                            .await
+                                            .drain()
+                                            .map(|((aid, iid), value)| EventObject { aid, iid, value })
+                                            .collect();
+                                        let event_res = event_response(events).expect("couldn't create event response");
+                                        if stream_outgoing_.unbounded_send(event_res).is_ok() {
+                                            metrics_.record_event_notification_sent();
+                                            connections_.touch(session_id);
+                                        } else {
+                                            event_subscriptions_.lock().await.clear();
+                                        }
+                                    });
+                                }
+                            },
                             Event::CharacteristicValueChanged { aid, iid, ref value } => {
                                 let mut dropped_subscriptions = vec![];
                                 for (i, &(s_aid, s_iid)) in event_subscriptions_.lock().await.iter().enumerate() {
@@ -233,16 +561,23 @@ impl Server {
                                         };
                                         let event_res =
                                             event_response(vec![event]).expect("couldn't create event response");
-                                        if stream_outgoing_.unbounded_send(event_res).is_err() {
+                                        if stream_outgoing_.unbounded_send(event_res).is_ok() {
+                                            metrics_.record_event_notification_sent();
+                                            connections_.touch(session_id);
+                                        } else {
                                             dropped_subscriptions.push(i);
                                         }
                                     }
                                 }
                                 let mut ev = event_subscriptions_.lock().await;
-                                for s in dropped_subscriptions {
+                                for s in dropped_subscriptions.into_iter().rev() {
                                     ev.remove(s);
                                 }
                             },
+                            Event::AccessoryRemoved { aid } => {
+                                event_subscriptions_.lock().await.retain(|&(s_aid, _)| s_aid != aid);
+                                pending_events_.lock().await.retain(|&(p_aid, _), _| p_aid != aid);
+                            },
                             _ => {},
                         }
                     }
@@ -255,11 +590,41 @@ impl Server {
                 http.http1_keep_alive(true);
                 http.http1_preserve_header_case(true);
 
-                tokio::spawn(encrypted_stream.map_err(|e| error!("{:?}", e)).map(|_| ()));
+                // closing a connection drops the encrypted stream and with it the TCP stream, which in turn ends the
+                // HTTP connection
+                tokio::spawn(future::select(encrypted_stream, close_receiver).map(|res| {
+                    if let Either::Left((Err(e), _)) = res {
+                        error!("{:?}", e);
+                    }
+                }));
                 tokio::spawn(
                     http.serve_connection(stream_wrapper, api)
                         .map_err(|e| error!("{:?}", e))
-                        .map(|_| ()),
+                        .then(move |_| async move {
+                            connections_.close(session_id);
+                            connection_metrics.record_connection_closed();
+
+                            // a closed connection drops all of its event subscriptions
+                            let subscriptions = std::mem::take(&mut *connection_subscriptions.lock().await);
+                            for (aid, iid) in subscriptions {
+                                event::emit(&connection_event_emitter, &Event::CharacteristicUnsubscribed {
+                                    aid,
+                                    iid,
+                                })
+                                .await;
+                            }
+
+                            let controller_id = *connection_controller_id.read().expect("reading controller_id");
+                            if let Some(controller_id) = controller_id {
+                                // the controller ID is set right before the session is reported as established, so
+                                // the task emitting `ControllerConnected` is about to finish and has to go first
+                                let _ = connected.await;
+                                event::emit(&connection_event_emitter, &Event::ControllerDisconnected {
+                                    controller_id,
+                                })
+                                .await;
+                            }
+                        }),
                 );
             }
 
@@ -269,3 +634,127 @@ impl Server {
         .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection_info(session_id: u64, peer_address: &str) -> ConnectionInfo {
+        ConnectionInfo {
+            peer_address: peer_address.parse().unwrap(),
+            session_id,
+        }
+    }
+
+    #[test]
+    fn test_least_recently_used_connection_is_closed() {
+        let connections = Connections::default();
+        let secured = Arc::new(AtomicBool::new(false));
+
+        let (sender_1, mut receiver_1) = oneshot::channel();
+        let (sender_2, mut receiver_2) = oneshot::channel();
+        let (sender_3, mut receiver_3) = oneshot::channel();
+
+        connections.open(
+            connection_info(1, "192.168.1.2:50001"),
+            secured.clone(),
+            sender_1,
+            Some(2),
+        );
+        connections.open(
+            connection_info(2, "192.168.1.2:50002"),
+            secured.clone(),
+            sender_2,
+            Some(2),
+        );
+        std::thread::sleep(Duration::from_millis(1));
+        connections.touch(1);
+        connections.open(
+            connection_info(3, "192.168.1.2:50003"),
+            secured.clone(),
+            sender_3,
+            Some(2),
+        );
+
+        assert_eq!(receiver_1.try_recv(), Ok(None));
+        assert_eq!(receiver_2.try_recv(), Ok(Some(())));
+        assert_eq!(receiver_3.try_recv(), Ok(None));
+        assert!(connections.idle_deadline(2, Duration::from_secs(1)).is_none());
+        assert!(connections.idle_deadline(3, Duration::from_secs(1)).is_some());
+    }
+
+    #[test]
+    fn test_unsecured_connections_are_closed_first() {
+        let connections = Connections::default();
+
+        let (sender_1, mut receiver_1) = oneshot::channel();
+        let (sender_2, mut receiver_2) = oneshot::channel();
+        let (sender_3, mut receiver_3) = oneshot::channel();
+
+        connections.open(
+            connection_info(1, "192.168.1.2:50001"),
+            Arc::new(AtomicBool::new(true)),
+            sender_1,
+            Some(2),
+        );
+        std::thread::sleep(Duration::from_millis(1));
+        connections.open(
+            connection_info(2, "192.168.1.3:50002"),
+            Arc::new(AtomicBool::new(false)),
+            sender_2,
+            Some(2),
+        );
+        connections.open(
+            connection_info(3, "192.168.1.4:50003"),
+            Arc::new(AtomicBool::new(false)),
+            sender_3,
+            Some(2),
+        );
+
+        // the secured connection is the least recently used one, but kept
+        assert_eq!(receiver_1.try_recv(), Ok(None));
+        assert_eq!(receiver_2.try_recv(), Ok(Some(())));
+        assert_eq!(receiver_3.try_recv(), Ok(None));
+    }
+
+    #[test]
+    fn test_unsecured_connections_are_reset() {
+        let connections = Connections::default();
+
+        let (sender_1, mut receiver_1) = oneshot::channel();
+        let (sender_2, mut receiver_2) = oneshot::channel();
+        let (sender_3, mut receiver_3) = oneshot::channel();
+
+        connections.open(
+            connection_info(1, "192.168.1.2:50001"),
+            Arc::new(AtomicBool::new(true)),
+            sender_1,
+            None,
+        );
+        connections.open(
+            connection_info(2, "192.168.1.2:50002"),
+            Arc::new(AtomicBool::new(false)),
+            sender_2,
+            None,
+        );
+        connections.open(
+            connection_info(3, "192.168.1.3:50003"),
+            Arc::new(AtomicBool::new(false)),
+            sender_3,
+            None,
+        );
+
+        assert_eq!(connections.close_unsecured(Some("192.168.1.2".parse().unwrap())), 1);
+        assert_eq!(receiver_1.try_recv(), Ok(None));
+        assert_eq!(receiver_2.try_recv(), Ok(Some(())));
+        assert_eq!(receiver_3.try_recv(), Ok(None));
+
+        assert_eq!(connections.close_unsecured(None), 1);
+        assert_eq!(receiver_1.try_recv(), Ok(None));
+        assert_eq!(receiver_3.try_recv(), Ok(Some(())));
+
+        // on shutdown, the secured connections are closed as well
+        assert_eq!(connections.close_all(), 1);
+        assert_eq!(receiver_1.try_recv(), Ok(Some(())));
+    }
+}
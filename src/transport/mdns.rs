@@ -1,25 +1,34 @@
 use libmdns::{Responder, Service};
-use log::debug;
+use log::{debug, warn};
+use std::net::IpAddr;
 
-use crate::pointer;
+use crate::{pointer, Config};
 
 /// An mDNS Responder. Used to announce the Accessory's name and HAP TXT records to potential controllers.
 pub struct MdnsResponder {
     config: pointer::Config,
     responder: Responder,
+    allowed_ips: Vec<IpAddr>,
+    port: Option<u16>,
     service: Option<Service>,
+    announced_name: Option<String>,
     task: Option<Box<dyn futures::Future<Output = ()> + Unpin + std::marker::Send>>,
 }
 
 impl MdnsResponder {
-    /// Creates a new mDNS Responder.
+    /// Creates a new mDNS Responder announcing the addresses selected by the server's `Config`, see `announced_ips`.
     pub async fn new(config: pointer::Config) -> Self {
-        let (responder, task) = libmdns::Responder::with_default_handle().expect("creating mDNS responder");
+        let allowed_ips = announced_ips(&*config.lock().await);
+        let (responder, task) =
+            libmdns::Responder::with_default_handle_and_ip_list(allowed_ips.clone()).expect("creating mDNS responder");
 
         MdnsResponder {
             config,
             responder,
+            allowed_ips,
+            port: None,
             service: None,
+            announced_name: None,
             task: Some(task),
         }
     }
@@ -32,26 +41,49 @@ impl MdnsResponder {
 
         let c = self.config.lock().await;
 
-        let name = c.name.clone();
-        let port = c.port;
+        let name = c.bonjour_name().to_string();
+        let port = self.port.unwrap_or(c.port);
+        let ttl = c.mdns_ttl;
         let tr = c.txt_records();
 
         drop(c);
 
-        self.service = Some(self.responder.register("_hap._tcp".into(), name, port, &[
-            &tr[0], &tr[1], &tr[2], &tr[3], &tr[4], &tr[5], &tr[6], &tr[7],
-        ]));
+        self.service = Some(self.responder.register_with_ttl(
+            "_hap._tcp".into(),
+            name.clone(),
+            port,
+            &[&tr[0], &tr[1], &tr[2], &tr[3], &tr[4], &tr[5], &tr[6], &tr[7], &tr[8]],
+            ttl,
+        ));
+        self.announced_name = Some(name);
 
         debug!("setting mDNS records: {:?}", &tr);
     }
 
+    /// Sets the port the TCP listener is bound to, which is announced instead of the port of the `Config`. Takes effect
+    /// on the next update of the records.
+    pub fn set_port(&mut self, port: u16) { self.port = Some(port); }
+
+    /// Stops announcing the accessory. Dropping the registered service makes the responder send goodbye packets to
+    /// flush it from the caches of the controllers.
+    pub fn unregister(&mut self) {
+        debug!("removing mDNS records");
+
+        self.service = None;
+        self.announced_name = None;
+    }
+
+    /// Returns the service instance name the accessory is currently announced with, if it's announced.
+    pub fn announced_name(&self) -> Option<&str> { self.announced_name.as_deref() }
+
     /// Returns the mDNS task to throw on a scheduler.
     pub fn run_handle(&mut self) -> Box<dyn futures::Future<Output = ()> + Unpin + std::marker::Send> {
         match self.task.take() {
             Some(task) => task,
             // if the task handle is gone, recreate the whole responder
             None => {
-                let (responder, task) = libmdns::Responder::with_default_handle().expect("creating mDNS responder");
+                let (responder, task) = libmdns::Responder::with_default_handle_and_ip_list(self.allowed_ips.clone())
+                    .expect("creating mDNS responder");
                 self.responder = responder;
 
                 task
@@ -59,3 +91,106 @@ impl MdnsResponder {
         }
     }
 }
+
+/// Returns the IP addresses to announce for a server configured by `config`. An empty list makes the responder
+/// announce the IPv4 and IPv6 addresses of all network interfaces.
+fn announced_ips(config: &Config) -> Vec<IpAddr> {
+    let interfaces = if config.host.is_unspecified() && config.mdns_ips.is_empty() && !config.mdns_interfaces.is_empty()
+    {
+        match if_addrs::get_if_addrs() {
+            Ok(interfaces) => interfaces
+                .into_iter()
+                .map(|iface| (iface.name.clone(), iface.ip()))
+                .collect(),
+            Err(e) => {
+                warn!("error listing network interfaces: {:?}", e);
+                vec![]
+            },
+        }
+    } else {
+        vec![]
+    };
+
+    select_ips(config.host, &config.mdns_ips, &config.mdns_interfaces, &interfaces)
+}
+
+/// Selects the IP addresses to announce: the explicitly configured `mdns_ips`, else a specified `host`, else the
+/// addresses of the `interfaces` named in `mdns_interfaces`.
+fn select_ips(
+    host: IpAddr,
+    mdns_ips: &[IpAddr],
+    mdns_interfaces: &[String],
+    interfaces: &[(String, IpAddr)],
+) -> Vec<IpAddr> {
+    if !mdns_ips.is_empty() {
+        return mdns_ips.to_vec();
+    }
+
+    if !host.is_unspecified() {
+        return vec![host];
+    }
+
+    let ips: Vec<IpAddr> = interfaces
+        .iter()
+        .filter(|(name, _)| mdns_interfaces.contains(name))
+        .map(|(_, ip)| *ip)
+        .collect();
+
+    if ips.is_empty() && !mdns_interfaces.is_empty() {
+        // announcing nothing would make the accessory undiscoverable, so fall back to all interfaces
+        warn!(
+            "none of the mDNS interfaces {:?} has an address, announcing all interfaces",
+            mdns_interfaces
+        );
+    }
+
+    ips
+}
+
+/// Derives the instance name to re-announce with after a name conflict, following the renaming scheme of RFC 6762
+/// section 9: `"Acme Lightbulb"` becomes `"Acme Lightbulb (2)"`, `"Acme Lightbulb (2)"` becomes
+/// `"Acme Lightbulb (3)"` and so on. A suffix that can't be incremented anymore is kept and gets a `(2)` appended.
+pub(crate) fn conflict_free_name(name: &str) -> String {
+    if let Some(base) = name.strip_suffix(')') {
+        if let Some((base, n)) = base.rsplit_once(" (") {
+            if let Some(n) = n.parse::<u32>().ok().and_then(|n| n.checked_add(1)) {
+                return format!("{} ({})", base, n);
+            }
+        }
+    }
+
+    format!("{} (2)", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_ips() {
+        let unspecified: IpAddr = "0.0.0.0".parse().unwrap();
+        let host: IpAddr = "192.168.1.10".parse().unwrap();
+        let other: IpAddr = "10.0.0.10".parse().unwrap();
+        let interfaces = vec![("eth0".to_string(), host), ("wlan0".to_string(), other)];
+
+        assert_eq!(select_ips(unspecified, &[], &[], &interfaces), Vec::<IpAddr>::new());
+        assert_eq!(select_ips(host, &[], &["wlan0".into()], &interfaces), vec![host]);
+        assert_eq!(select_ips(host, &[other], &[], &interfaces), vec![other]);
+        assert_eq!(select_ips(unspecified, &[], &["wlan0".into()], &interfaces), vec![
+            other
+        ]);
+        assert_eq!(
+            select_ips(unspecified, &[], &["eth1".into()], &interfaces),
+            Vec::<IpAddr>::new()
+        );
+    }
+
+    #[test]
+    fn test_conflict_free_name() {
+        assert_eq!(conflict_free_name("Acme Lightbulb"), "Acme Lightbulb (2)");
+        assert_eq!(conflict_free_name("Acme Lightbulb (2)"), "Acme Lightbulb (3)");
+        assert_eq!(conflict_free_name("Acme Lightbulb (9)"), "Acme Lightbulb (10)");
+        assert_eq!(conflict_free_name("Acme (Kitchen)"), "Acme (Kitchen) (2)");
+        assert_eq!(conflict_free_name("Acme (4294967295)"), "Acme (4294967295) (2)");
+    }
+}
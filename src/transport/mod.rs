@@ -1,5 +1,6 @@
 use hkdf::Hkdf;
 use sha2::Sha512;
+use std::net::SocketAddr;
 
 use crate::{Error, Result};
 
@@ -8,6 +9,18 @@ pub(crate) mod http;
 pub(crate) mod mdns;
 pub(crate) mod tcp;
 
+/// Metadata of the TCP connection a request was received on. Writes that need additional authorization pass it to the
+/// [`AuthorizationValidator`](crate::characteristic::AuthorizationValidator) as part of their
+/// [`AuthorizationRequest`](crate::characteristic::AuthorizationRequest).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Socket address of the controller.
+    pub peer_address: SocketAddr,
+    /// ID of the connection, unique per run of the server. Stays the same for all requests of a connection and
+    /// therefore identifies its encrypted session.
+    pub session_id: u64,
+}
+
 pub(crate) fn hkdf_extract_and_expand(salt: &[u8], ikm: &[u8], info: &[u8]) -> Result<[u8; 32]> {
     let mut okm = [0u8; 32];
 
@@ -16,7 +16,12 @@ use std::{
     future::Future,
     io::{self, ErrorKind},
     pin::Pin,
-    sync::{Arc, Mutex, RwLock},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        Mutex,
+        RwLock,
+    },
     task::{Context, Poll, Waker},
 };
 use tokio::{
@@ -24,6 +29,7 @@ use tokio::{
     net::TcpStream,
 };
 use uuid::Uuid;
+use zeroize::Zeroizing;
 
 use crate::Result;
 
@@ -159,8 +165,9 @@ impl AsyncWrite for StreamWrapper {
 
 #[derive(Debug)]
 pub struct Session {
-    pub controller_id: Uuid,
-    pub shared_secret: [u8; 32],
+    /// ID of the paired controller. `None` for transient pair setup sessions, which aren't backed by a pairing.
+    pub controller_id: Option<Uuid>,
+    pub shared_secret: Zeroizing<Vec<u8>>,
 }
 
 #[derive(Debug)]
@@ -172,7 +179,11 @@ pub struct EncryptedStream {
     outgoing_waker: Arc<Mutex<Option<Waker>>>,
     session_receiver: oneshot::Receiver<Session>,
     pub controller_id: Arc<RwLock<Option<Uuid>>>,
-    shared_secret: Option<[u8; 32]>,
+    /// Whether the stream is encrypted, i.e. a pair verify or transient pair setup was completed.
+    pub secured: Arc<AtomicBool>,
+    /// Notified with the ID of the controller once an encrypted session of a paired controller is set up.
+    session_notifier: Option<oneshot::Sender<Uuid>>,
+    shared_secret: Option<Zeroizing<Vec<u8>>>,
     decrypt_count: u64,
     encrypt_count: u64,
     encrypted_buf: BytesMut,
@@ -212,6 +223,8 @@ impl EncryptedStream {
                 outgoing_waker: outgoing_waker.clone(),
                 session_receiver: receiver,
                 controller_id: Arc::new(RwLock::new(None)),
+                secured: Arc::new(AtomicBool::new(false)),
+                session_notifier: None,
                 shared_secret: None,
                 decrypt_count: 0,
                 encrypt_count: 0,
@@ -231,6 +244,16 @@ impl EncryptedStream {
         )
     }
 
+    /// Returns a receiver that resolves to the ID of the controller once an encrypted session of a paired controller
+    /// is set up on the stream. Transient pair setup sessions aren't reported. The receiver is cancelled if the stream
+    /// is dropped before a session is set up.
+    pub fn session_established(&mut self) -> oneshot::Receiver<Uuid> {
+        let (sender, receiver) = oneshot::channel();
+        self.session_notifier = Some(sender);
+
+        receiver
+    }
+
     fn read_decrypted(&mut self, buf: &mut ReadBuf) -> Poll<std::result::Result<(), io::Error>> {
         debug!("reading from decrypted buffer");
 
@@ -257,7 +280,7 @@ impl EncryptedStream {
 
         if self.missing_data_for_decrypted_buf {
             let decrypted = decrypt_chunk(
-                &self.shared_secret.expect("missing shared secret"),
+                self.shared_secret.as_ref().expect("missing shared secret"),
                 &self.encrypted_buf[..2],
                 &self.encrypted_buf[2..(self.packet_len - 14)],
                 &self.encrypted_buf[(self.packet_len - 14)..(self.packet_len + 2)],
@@ -424,9 +447,14 @@ impl AsyncRead for EncryptedStream {
         if encrypted_stream.shared_secret.is_none() {
             match encrypted_stream.session_receiver.try_recv() {
                 Ok(Some(session)) => {
-                    *encrypted_stream.controller_id.write().expect("setting controller_id") =
-                        Some(session.controller_id);
+                    *encrypted_stream.controller_id.write().expect("setting controller_id") = session.controller_id;
                     encrypted_stream.shared_secret = Some(session.shared_secret);
+                    encrypted_stream.secured.store(true, Ordering::SeqCst);
+                    if let (Some(controller_id), Some(notifier)) =
+                        (session.controller_id, encrypted_stream.session_notifier.take())
+                    {
+                        let _ = notifier.send(controller_id);
+                    }
                 },
                 _ => {
                     return AsyncRead::poll_read(Pin::new(&mut encrypted_stream.stream), cx, buf);
@@ -451,12 +479,12 @@ impl AsyncWrite for EncryptedStream {
     fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<std::result::Result<usize, Error>> {
         let encrypted_stream = Pin::into_inner(self);
 
-        if let Some(shared_secret) = encrypted_stream.shared_secret {
+        if let Some(ref shared_secret) = encrypted_stream.shared_secret {
             let mut write_buf = BytesMut::from(buf);
 
             while write_buf.len() > 1024 {
                 let (aad, chunk, auth_tag) =
-                    encrypt_chunk(&shared_secret, &write_buf[..1024], &mut encrypted_stream.encrypt_count)
+                    encrypt_chunk(shared_secret, &write_buf[..1024], &mut encrypted_stream.encrypt_count)
                         .map_err(|_| io::Error::new(io::ErrorKind::Other, "encryption failed"))?;
 
                 let data = [&aad[..], &chunk[..], &auth_tag[..]].concat();
@@ -465,7 +493,7 @@ impl AsyncWrite for EncryptedStream {
                 write_buf.advance(1024);
             }
 
-            let (aad, chunk, auth_tag) = encrypt_chunk(&shared_secret, &write_buf, &mut encrypted_stream.encrypt_count)
+            let (aad, chunk, auth_tag) = encrypt_chunk(shared_secret, &write_buf, &mut encrypted_stream.encrypt_count)
                 .map_err(|_| io::Error::new(io::ErrorKind::Other, "encryption failed"))?;
 
             let data = [&aad[..], &chunk[..], &auth_tag[..]].concat();
@@ -487,21 +515,11 @@ impl AsyncWrite for EncryptedStream {
     }
 }
 
-fn decrypt_chunk(
-    shared_secret: &[u8; 32],
-    aad: &[u8],
-    data: &[u8],
-    auth_tag: &[u8],
-    count: &mut u64,
-) -> Result<Vec<u8>> {
-    let read_key = compute_read_key(shared_secret)?;
-    let aead = ChaCha20Poly1305::new(GenericArray::from_slice(&read_key));
-
-    let mut nonce = vec![0; 4];
-    let mut suffix = vec![0; 8];
-    LittleEndian::write_u64(&mut suffix, *count);
-    nonce.extend(suffix);
-    *count += 1;
+fn decrypt_chunk(shared_secret: &[u8], aad: &[u8], data: &[u8], auth_tag: &[u8], count: &mut u64) -> Result<Vec<u8>> {
+    let read_key = Zeroizing::new(compute_read_key(shared_secret)?);
+    let aead = ChaCha20Poly1305::new(GenericArray::from_slice(read_key.as_slice()));
+
+    let nonce = next_nonce(count)?;
 
     let mut buffer = Vec::new();
     buffer.extend_from_slice(data);
@@ -510,15 +528,11 @@ fn decrypt_chunk(
     Ok(buffer)
 }
 
-fn encrypt_chunk(shared_secret: &[u8; 32], data: &[u8], count: &mut u64) -> Result<([u8; 2], Vec<u8>, [u8; 16])> {
-    let write_key = compute_write_key(shared_secret)?;
-    let aead = ChaCha20Poly1305::new(GenericArray::from_slice(&write_key));
+fn encrypt_chunk(shared_secret: &[u8], data: &[u8], count: &mut u64) -> Result<([u8; 2], Vec<u8>, [u8; 16])> {
+    let write_key = Zeroizing::new(compute_write_key(shared_secret)?);
+    let aead = ChaCha20Poly1305::new(GenericArray::from_slice(write_key.as_slice()));
 
-    let mut nonce = vec![0; 4];
-    let mut suffix = vec![0; 8];
-    LittleEndian::write_u64(&mut suffix, *count);
-    nonce.extend(suffix);
-    *count += 1;
+    let nonce = next_nonce(count)?;
 
     let mut aad = [0; 2];
     LittleEndian::write_u16(&mut aad, data.len() as u16);
@@ -530,14 +544,76 @@ fn encrypt_chunk(shared_secret: &[u8; 32], data: &[u8], count: &mut u64) -> Resu
     Ok((aad, buffer, auth_tag.into()))
 }
 
-fn compute_read_key(shared_secret: &[u8; 32]) -> Result<[u8; 32]> {
+/// Returns the nonce of the next frame, i.e. 4 zero bytes followed by the frame counter as 64 bit little-endian
+/// integer, and advances the counter.
+///
+/// HAP doesn't define rekeying an encrypted session, so instead of wrapping around and reusing a nonce, this fails once
+/// the counter is exhausted, which closes the connection. The controller then derives fresh session keys by running
+/// pair verify on a new connection.
+pub(crate) fn next_nonce(count: &mut u64) -> Result<[u8; 12]> {
+    let mut nonce = [0; 12];
+    LittleEndian::write_u64(&mut nonce[4..], *count);
+    *count = count.checked_add(1).ok_or(crate::Error::NonceCounterExhausted)?;
+
+    Ok(nonce)
+}
+
+fn compute_read_key(shared_secret: &[u8]) -> Result<[u8; 32]> {
     compute_key(shared_secret, b"Control-Write-Encryption-Key")
 }
 
-fn compute_write_key(shared_secret: &[u8; 32]) -> Result<[u8; 32]> {
+fn compute_write_key(shared_secret: &[u8]) -> Result<[u8; 32]> {
     compute_key(shared_secret, b"Control-Read-Encryption-Key")
 }
 
-fn compute_key(shared_secret: &[u8; 32], info: &[u8]) -> Result<[u8; 32]> {
+fn compute_key(shared_secret: &[u8], info: &[u8]) -> Result<[u8; 32]> {
     super::hkdf_extract_and_expand(b"Control-Salt", shared_secret, info)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nonce_counter() {
+        let mut count = 0x0102;
+
+        assert_eq!(next_nonce(&mut count).unwrap(), [0, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(count, 0x0103);
+    }
+
+    #[test]
+    fn test_exhausted_nonce_counter_isnt_reused() {
+        let mut count = u64::MAX - 1;
+
+        assert!(next_nonce(&mut count).is_ok());
+        assert!(matches!(
+            next_nonce(&mut count),
+            Err(crate::Error::NonceCounterExhausted)
+        ));
+        assert!(matches!(
+            next_nonce(&mut count),
+            Err(crate::Error::NonceCounterExhausted)
+        ));
+        assert_eq!(count, u64::MAX);
+    }
+
+    #[test]
+    fn test_encrypted_chunk_roundtrip() {
+        let shared_secret = [7; 32];
+        let mut encrypt_count = 0;
+        let mut decrypt_count = 0;
+
+        // the read key of one side is the write key of the other, so a chunk is decrypted with the write key here
+        let (aad, chunk, auth_tag) = encrypt_chunk(&shared_secret, b"hello", &mut encrypt_count).unwrap();
+        let write_key = compute_write_key(&shared_secret).unwrap();
+        let aead = ChaCha20Poly1305::new(GenericArray::from_slice(&write_key));
+        let nonce = next_nonce(&mut decrypt_count).unwrap();
+        let mut buffer = chunk;
+        aead.decrypt_in_place_detached(Nonce::from_slice(&nonce), &aad, &mut buffer, Tag::from_slice(&auth_tag))
+            .unwrap();
+
+        assert_eq!(buffer, b"hello");
+        assert_eq!(encrypt_count, 1);
+    }
+}